@@ -0,0 +1,121 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use futures::{stream, Stream, TryStreamExt};
+use tokio::task;
+
+use crate::lsm::Lsm;
+
+pub const SCAN_BATCH_SIZE: usize = 128;
+
+/*
+ * Lsm的异步封装：所有文件IO都放到tokio的阻塞线程池中执行，底层同步实现不变
+ */
+#[derive(Clone)]
+pub struct AsyncLsm {
+    inner: Arc<RwLock<Lsm>>,
+}
+
+/*
+ * future被丢弃时设置取消标记，尚未开始执行的阻塞任务会直接跳过（已经开始的任务无法中断）
+ */
+struct CancelGuard {
+    cancelled: Arc<AtomicBool>,
+    done: bool,
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if !self.done {
+            self.cancelled.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+impl AsyncLsm {
+    pub fn new(lsm: Lsm) -> Self {
+        return AsyncLsm {
+            inner: Arc::new(RwLock::new(lsm)),
+        };
+    }
+
+    pub async fn get(&self, key: String) -> io::Result<Option<String>> {
+        return self.run(move |lsm| lsm.read().unwrap().get(&key)).await;
+    }
+
+    pub async fn insert(&self, key: String, val: String) -> io::Result<()> {
+        return self
+            .run(move |lsm| lsm.write().unwrap().insert(&key, &val))
+            .await;
+    }
+
+    pub async fn remove(&self, key: String) -> io::Result<()> {
+        return self.run(move |lsm| lsm.write().unwrap().remove(&key)).await;
+    }
+
+    /*
+     * 分批读取[start, end)范围内的数据，每批只归并读取SCAN_BATCH_SIZE条，stream被丢弃后不会再读取后续批次
+     */
+    pub fn scan(
+        &self,
+        start: String,
+        end: String,
+    ) -> impl Stream<Item = io::Result<(String, String)>> {
+        let lsm = self.clone();
+        return stream::try_unfold(Some(start), move |start| {
+            let lsm = lsm.clone();
+            let end = end.clone();
+            async move {
+                let start = match start {
+                    Some(s) => s,
+                    None => return Ok::<_, io::Error>(None),
+                };
+                let batch = lsm
+                    .run(move |inner| inner.read().unwrap().scan(&start, &end, SCAN_BATCH_SIZE))
+                    .await?;
+                // 下一批从上一批最后一个key之后开始
+                let next = if batch.len() < SCAN_BATCH_SIZE {
+                    None
+                } else {
+                    Some(format!("{}\u{0}", batch.last().unwrap().0))
+                };
+                return Ok(Some((
+                    stream::iter(batch.into_iter().map(Ok::<_, io::Error>)),
+                    next,
+                )));
+            }
+        })
+        .try_flatten();
+    }
+
+    async fn run<T, F>(&self, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&RwLock<Lsm>) -> io::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let mut guard = CancelGuard {
+            cancelled: cancelled.clone(),
+            done: false,
+        };
+        let inner = self.inner.clone();
+        let res = task::spawn_blocking(move || {
+            if cancelled.load(Ordering::SeqCst) {
+                return Err(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    "operation cancelled",
+                ));
+            }
+            return f(&inner);
+        })
+        .await
+        .map_err(io::Error::other)?;
+        guard.done = true;
+        return res;
+    }
+}
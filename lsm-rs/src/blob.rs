@@ -0,0 +1,266 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufWriter, Write},
+    sync::Arc,
+};
+
+use chrono::Utc;
+
+use crate::{
+    env::{Env, WritableFile},
+    reader::Reader,
+    writer::Writer,
+};
+
+pub const BLOB_THRESHOLD: usize = 128;
+pub const BLOB_GC_LIVE_RATIO: f64 = 0.5;
+// key_size(u32) value_size(u32)
+const BLOB_HEADER_SIZE: usize = 8;
+
+/*
+ * sstable中指向blob文件的指针
+ */
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct BlobPointer {
+    pub file_id: u64,
+    pub offset: u64,
+    pub size: u32,
+}
+
+/*
+ * sstable中的值：小value直接内联，大value只保存blob指针
+ */
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Value {
+    Inline(String),
+    Blob(BlobPointer),
+}
+
+/*
+ * 只追加写的blob文件写入器，一次minor compaction对应一个blob文件
+ * 记录格式：key_size(u32) value_size(u32) key value
+ */
+pub struct BlobWriter {
+    env: Arc<dyn Env>,
+    file_id: u64,
+    path: String,
+    writer: Option<BufWriter<Box<dyn WritableFile>>>,
+    offset: u64,
+}
+
+impl BlobWriter {
+    pub fn append(&mut self, key: &String, value: &String) -> io::Result<BlobPointer> {
+        if self.writer.is_none() {
+            self.writer = Some(BufWriter::new(self.env.create(&self.path)?));
+        }
+        let writer = self.writer.as_mut().unwrap();
+        let key_bytes = key.as_bytes();
+        let val_bytes = value.as_bytes();
+        let val_size = Writer::size("value", val_bytes)?;
+        writer.write_all(&Writer::size("key", key_bytes)?.to_le_bytes())?;
+        writer.write_all(&val_size.to_le_bytes())?;
+        writer.write_all(key_bytes)?;
+        writer.write_all(val_bytes)?;
+        let pointer = BlobPointer {
+            file_id: self.file_id,
+            offset: self.offset,
+            size: val_size,
+        };
+        self.offset += (BLOB_HEADER_SIZE + key_bytes.len() + val_bytes.len()) as u64;
+        return Ok(pointer);
+    }
+
+    /*
+     * 返回文件id和写入的总字节数，没有写入任何大value时不会产生文件
+     */
+    pub fn finish(self) -> io::Result<Option<(u64, u64)>> {
+        match self.writer {
+            Some(mut writer) => {
+                writer.flush()?;
+                writer.get_mut().sync()?;
+                return Ok(Some((self.file_id, self.offset)));
+            }
+            None => return Ok(None),
+        }
+    }
+}
+
+pub struct BlobStore {
+    env: Arc<dyn Env>,
+    path: String,
+    pub threshold: usize,
+    pub gc_live_ratio: f64,
+    file: Box<dyn WritableFile>,
+    // file_id -> (总字节数, 已失效字节数)
+    files: HashMap<u64, (u64, u64)>,
+}
+
+impl BlobStore {
+    pub fn new(env: Arc<dyn Env>, base_path: &str, threshold: usize, gc_live_ratio: f64) -> Self {
+        let path = format!("{}/blob", base_path);
+        env.create_dir_all(&path).unwrap();
+        let index_path = format!("{}/blob.index", path);
+        let mut store = Self {
+            file: env.append(&index_path).unwrap(),
+            env,
+            path,
+            threshold,
+            gc_live_ratio,
+            files: HashMap::new(),
+        };
+        store.init(&index_path).unwrap();
+        return store;
+    }
+
+    /*
+     * 回放blob.index：add/discard/remove三种记录，忽略断电截断的最后一行
+     */
+    fn init(&mut self, index_path: &str) -> io::Result<()> {
+        let buf = self.env.map(index_path)?;
+        let content = String::from_utf8_lossy(&buf);
+        let mut lines: Vec<&str> = content.split('\n').collect();
+        lines.pop();
+        for line in lines {
+            let columns: Vec<&str> = line.split_whitespace().collect();
+            let file_id = match columns.get(1).map(|c| c.parse::<u64>()) {
+                Some(Ok(file_id)) => file_id,
+                _ => continue,
+            };
+            match (
+                columns[0],
+                columns.get(2).and_then(|c| c.parse::<u64>().ok()),
+            ) {
+                ("add", Some(total)) => {
+                    self.files.insert(file_id, (total, 0));
+                }
+                ("discard", Some(size)) => {
+                    if let Some(stat) = self.files.get_mut(&file_id) {
+                        stat.1 += size;
+                    }
+                }
+                ("remove", _) => {
+                    self.files.remove(&file_id);
+                }
+                _ => {}
+            }
+        }
+        return self.rewrite(index_path);
+    }
+
+    /*
+     * 与sstable索引相同，重启时用当前状态重写，避免新记录追加在截断的行后面
+     */
+    fn rewrite(&mut self, index_path: &str) -> io::Result<()> {
+        let mut content = String::new();
+        for (file_id, (total, discard)) in self.files.iter() {
+            content.push_str(&format!("add {} {}\n", file_id, total));
+            if *discard > 0 {
+                content.push_str(&format!("discard {} {}\n", file_id, discard));
+            }
+        }
+        let tmp_path = format!("{}.tmp", index_path);
+        let mut file = self.env.create(&tmp_path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync()?;
+        self.env.rename(&tmp_path, index_path)?;
+        self.file = self.env.append(index_path)?;
+        return Ok(());
+    }
+
+    pub fn writer(&self) -> BlobWriter {
+        let file_id = Utc::now().timestamp_nanos_opt().unwrap() as u64;
+        return BlobWriter {
+            env: self.env.clone(),
+            file_id,
+            path: self.file_path(file_id),
+            writer: None,
+            offset: 0,
+        };
+    }
+
+    pub fn add(&mut self, file_id: u64, total: u64) -> io::Result<()> {
+        self.append(format!("add {} {}\n", file_id, total).as_bytes())?;
+        self.files.insert(file_id, (total, 0));
+        return Ok(());
+    }
+
+    /*
+     * 合并时丢弃的旧指针对应的value记为失效
+     */
+    pub fn discard(&mut self, pointer: &BlobPointer) -> io::Result<()> {
+        if let Some(stat) = self.files.get_mut(&pointer.file_id) {
+            let size = pointer.size as u64;
+            stat.1 += size;
+            self.append(format!("discard {} {}\n", pointer.file_id, size).as_bytes())?;
+        }
+        return Ok(());
+    }
+
+    pub fn remove(&mut self, file_id: u64) -> io::Result<()> {
+        self.append(format!("remove {}\n", file_id).as_bytes())?;
+        self.files.remove(&file_id);
+        return self.env.remove_file(&self.file_path(file_id));
+    }
+
+    pub fn read(&self, pointer: &BlobPointer) -> io::Result<String> {
+        let path = self.file_path(pointer.file_id);
+        let header = self.env.read_at(&path, pointer.offset, 4)?;
+        let key_size = Reader::take_size(&header, &mut 0)? as u64;
+        let buf = self.env.read_at(
+            &path,
+            pointer.offset + BLOB_HEADER_SIZE as u64 + key_size,
+            pointer.size as usize,
+        )?;
+        return String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+    }
+
+    /*
+     * 存活比例低于阈值的blob文件（只按value字节数统计）
+     */
+    pub fn gc_candidates(&self) -> Vec<u64> {
+        let mut candidates: Vec<u64> = self
+            .files
+            .iter()
+            .filter(|(_, (total, discard))| {
+                // 合并失败重试时同一个指针可能被重复记为失效
+                *total > 0
+                    && (total.saturating_sub(*discard) as f64 / *total as f64) < self.gc_live_ratio
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        candidates.sort();
+        return candidates;
+    }
+
+    /*
+     * 读取blob文件中的全部记录，用于垃圾回收时判断存活
+     */
+    pub fn scan(&self, file_id: u64) -> io::Result<Vec<(String, BlobPointer, String)>> {
+        let buf = self.env.map(&self.file_path(file_id))?;
+        let mut res = vec![];
+        let mut offset: usize = 0;
+        while offset < buf.len() {
+            let start = offset as u64;
+            let (key, val) = Reader::read_blob_record(&buf, &mut offset)?;
+            res.push((
+                key,
+                BlobPointer {
+                    file_id,
+                    offset: start,
+                    size: val.len() as u32,
+                },
+                val,
+            ));
+        }
+        return Ok(res);
+    }
+
+    fn append(&mut self, record: &[u8]) -> io::Result<()> {
+        self.file.write_all(record)?;
+        return self.file.sync();
+    }
+
+    fn file_path(&self, file_id: u64) -> String {
+        return format!("{}/{}.blob", self.path, file_id);
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Cursor, Read, Seek, SeekFrom, Write},
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
+
+use memmap::MmapOptions;
+
+pub trait WritableFile: Write + Send + Sync {
+    fn sync(&mut self) -> io::Result<()>;
+}
+
+/*
+ * 存储层访问文件系统的唯一入口，便于在测试中替换为可注入故障的内存实现
+ */
+pub trait Env: Send + Sync {
+    fn create_dir_all(&self, path: &str) -> io::Result<()>;
+
+    // 追加写入，文件不存在时创建
+    fn append(&self, path: &str) -> io::Result<Box<dyn WritableFile>>;
+
+    // 创建并清空文件
+    fn create(&self, path: &str) -> io::Result<Box<dyn WritableFile>>;
+
+    fn open(&self, path: &str) -> io::Result<Box<dyn Read + Send>>;
+
+    // 读取整个文件内容
+    fn map(&self, path: &str) -> io::Result<Box<dyn Deref<Target = [u8]>>>;
+
+    fn read_at(&self, path: &str, offset: u64, size: usize) -> io::Result<Vec<u8>>;
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()>;
+
+    fn remove_file(&self, path: &str) -> io::Result<()>;
+
+    // 目录下的文件完整路径，按名称排序
+    fn list_dir(&self, path: &str) -> io::Result<Vec<String>>;
+}
+
+/*
+ * 基于本地文件系统的实现
+ */
+pub struct DiskEnv;
+
+impl WritableFile for File {
+    fn sync(&mut self) -> io::Result<()> {
+        return self.sync_all();
+    }
+}
+
+impl Env for DiskEnv {
+    fn create_dir_all(&self, path: &str) -> io::Result<()> {
+        return fs::create_dir_all(path);
+    }
+
+    fn append(&self, path: &str) -> io::Result<Box<dyn WritableFile>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        return Ok(Box::new(file));
+    }
+
+    fn create(&self, path: &str) -> io::Result<Box<dyn WritableFile>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(path)?;
+        return Ok(Box::new(file));
+    }
+
+    fn open(&self, path: &str) -> io::Result<Box<dyn Read + Send>> {
+        return Ok(Box::new(File::open(path)?));
+    }
+
+    fn map(&self, path: &str) -> io::Result<Box<dyn Deref<Target = [u8]>>> {
+        let file = File::open(path)?;
+        // 空文件无法mmap
+        if file.metadata()?.len() == 0 {
+            return Ok(Box::new(Vec::new()));
+        }
+        return Ok(Box::new(unsafe { MmapOptions::new().map(&file)? }));
+    }
+
+    fn read_at(&self, path: &str, offset: u64, size: usize) -> io::Result<Vec<u8>> {
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buf: Vec<u8> = vec![0; size];
+        file.read_exact(&mut buf)?;
+        return Ok(buf);
+    }
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        return fs::rename(from, to);
+    }
+
+    fn remove_file(&self, path: &str) -> io::Result<()> {
+        return fs::remove_file(path);
+    }
+
+    fn list_dir(&self, path: &str) -> io::Result<Vec<String>> {
+        let mut paths: Vec<String> = vec![];
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                paths.push(format!("{}/{}", path, entry.file_name().to_string_lossy()));
+            }
+        }
+        paths.sort();
+        return Ok(paths);
+    }
+}
+
+/*
+ * 内存文件系统，用于确定性的故障注入测试：
+ * 1.写入失败：按概率直接返回错误，不写入任何数据
+ * 2.fsync失败：按概率返回错误，数据停留在未持久化状态
+ * 3.断电：crash()只保留已持久化的数据，加上未持久化尾部的随机前缀
+ * 目录操作（创建、重命名、删除）视为立即持久化
+ */
+#[derive(Clone)]
+pub struct MemEnv {
+    state: Arc<Mutex<MemState>>,
+}
+
+struct MemState {
+    files: BTreeMap<String, MemFile>,
+    seed: u64,
+    write_error_rate: u32,
+    sync_error_rate: u32,
+}
+
+#[derive(Clone, Default)]
+struct MemFile {
+    data: Vec<u8>,
+    synced: usize,
+}
+
+impl MemState {
+    // xorshift，保证同样的种子和调用序列得到同样的故障
+    fn next(&mut self) -> u64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        return self.seed;
+    }
+
+    fn hit(&mut self, rate: u32) -> bool {
+        return rate > 0 && (self.next() % 100) < rate as u64;
+    }
+
+    fn file(&mut self, path: &str) -> io::Result<&mut MemFile> {
+        return self.files.get_mut(path).ok_or(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("file not found: {}", path),
+        ));
+    }
+}
+
+struct MemWritableFile {
+    env: MemEnv,
+    path: String,
+}
+
+impl Write for MemWritableFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.env.state.lock().unwrap();
+        let rate = state.write_error_rate;
+        if state.hit(rate) {
+            return Err(io::Error::other("injected write error"));
+        }
+        state.file(&self.path)?.data.extend_from_slice(buf);
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+impl WritableFile for MemWritableFile {
+    fn sync(&mut self) -> io::Result<()> {
+        let mut state = self.env.state.lock().unwrap();
+        let rate = state.sync_error_rate;
+        if state.hit(rate) {
+            return Err(io::Error::other("injected sync error"));
+        }
+        let file = state.file(&self.path)?;
+        file.synced = file.data.len();
+        return Ok(());
+    }
+}
+
+impl MemEnv {
+    pub fn new(seed: u64) -> Self {
+        return MemEnv {
+            state: Arc::new(Mutex::new(MemState {
+                files: BTreeMap::new(),
+                seed: seed.max(1),
+                write_error_rate: 0,
+                sync_error_rate: 0,
+            })),
+        };
+    }
+
+    /*
+     * 故障概率，单位为百分比
+     */
+    pub fn set_faults(&self, write_error_rate: u32, sync_error_rate: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.write_error_rate = write_error_rate;
+        state.sync_error_rate = sync_error_rate;
+    }
+
+    /*
+     * 模拟断电，返回重启后看到的文件系统。原实例不受影响，崩溃前遗留的后台线程继续写入也不会影响新实例
+     */
+    pub fn crash(&self) -> MemEnv {
+        let mut state = self.state.lock().unwrap();
+        let mut files: BTreeMap<String, MemFile> = BTreeMap::new();
+        let paths: Vec<String> = state.files.keys().cloned().collect();
+        for path in paths {
+            let file = state.files.get(&path).unwrap().clone();
+            let unsynced = file.data.len() - file.synced;
+            let kept = file.synced + (state.next() % (unsynced as u64 + 1)) as usize;
+            let mut data = file.data;
+            data.truncate(kept);
+            files.insert(path, MemFile { data, synced: kept });
+        }
+        let seed = state.next();
+        return MemEnv {
+            state: Arc::new(Mutex::new(MemState {
+                files,
+                seed: seed.max(1),
+                write_error_rate: state.write_error_rate,
+                sync_error_rate: state.sync_error_rate,
+            })),
+        };
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        return Ok(self.state.lock().unwrap().file(path)?.data.clone());
+    }
+}
+
+impl Env for MemEnv {
+    fn create_dir_all(&self, _path: &str) -> io::Result<()> {
+        return Ok(());
+    }
+
+    fn append(&self, path: &str) -> io::Result<Box<dyn WritableFile>> {
+        self.state
+            .lock()
+            .unwrap()
+            .files
+            .entry(path.to_string())
+            .or_default();
+        return Ok(Box::new(MemWritableFile {
+            env: self.clone(),
+            path: path.to_string(),
+        }));
+    }
+
+    fn create(&self, path: &str) -> io::Result<Box<dyn WritableFile>> {
+        self.state
+            .lock()
+            .unwrap()
+            .files
+            .insert(path.to_string(), MemFile::default());
+        return Ok(Box::new(MemWritableFile {
+            env: self.clone(),
+            path: path.to_string(),
+        }));
+    }
+
+    fn open(&self, path: &str) -> io::Result<Box<dyn Read + Send>> {
+        return Ok(Box::new(Cursor::new(self.read(path)?)));
+    }
+
+    fn map(&self, path: &str) -> io::Result<Box<dyn Deref<Target = [u8]>>> {
+        return Ok(Box::new(self.read(path)?));
+    }
+
+    fn read_at(&self, path: &str, offset: u64, size: usize) -> io::Result<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        let data = &state.file(path)?.data;
+        let start = offset as usize;
+        if start + size > data.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("read out of range: {}", path),
+            ));
+        }
+        return Ok(data[start..start + size].to_vec());
+    }
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let file = state.files.remove(from).ok_or(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("file not found: {}", from),
+        ))?;
+        state.files.insert(to.to_string(), file);
+        return Ok(());
+    }
+
+    fn remove_file(&self, path: &str) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        return state.files.remove(path).map(|_| ()).ok_or(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("file not found: {}", path),
+        ));
+    }
+
+    fn list_dir(&self, path: &str) -> io::Result<Vec<String>> {
+        let prefix = format!("{}/", path);
+        return Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .files
+            .keys()
+            .filter(|p| p.starts_with(&prefix) && !p[prefix.len()..].contains('/'))
+            .cloned()
+            .collect());
+    }
+}
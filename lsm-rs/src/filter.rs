@@ -0,0 +1,237 @@
+use std::{convert::TryInto, io, sync::Arc};
+
+use crate::reader::Reader;
+
+pub const BLOOM_BITS_PER_KEY: usize = 10;
+
+/*
+ * 前缀提取器：以某个前缀开头的所有key都必须提取出这个前缀，且前缀本身的提取结果为自身，
+ * 否则按前缀过滤sstable时会漏掉数据
+ */
+pub trait PrefixExtractor: Send + Sync {
+    // 不在提取范围内的key返回None，这些key只参与整key过滤
+    fn extract<'a>(&self, key: &'a str) -> Option<&'a str>;
+
+    // 记录在过滤器文件中，配置变化后旧文件的前缀过滤器不再使用
+    fn name(&self) -> String;
+
+    // 查询的前缀能否使用前缀过滤器
+    fn in_domain(&self, prefix: &str) -> bool {
+        return self.extract(prefix) == Some(prefix);
+    }
+}
+
+/*
+ * 固定字节数的前缀，长度不足的key不参与前缀过滤
+ */
+pub struct FixedPrefix(pub usize);
+
+impl PrefixExtractor for FixedPrefix {
+    fn extract<'a>(&self, key: &'a str) -> Option<&'a str> {
+        return key.get(..self.0);
+    }
+
+    fn name(&self) -> String {
+        return format!("fixed:{}", self.0);
+    }
+}
+
+/*
+ * 截取到第count个分隔符（包含分隔符），如count为2时 user:123:name -> user:123:
+ */
+pub struct DelimitedPrefix {
+    pub delimiter: char,
+    pub count: usize,
+}
+
+impl PrefixExtractor for DelimitedPrefix {
+    fn extract<'a>(&self, key: &'a str) -> Option<&'a str> {
+        return self
+            .count
+            .checked_sub(1)
+            .and_then(|n| key.match_indices(self.delimiter).nth(n))
+            .map(|(i, d)| &key[..i + d.len()]);
+    }
+
+    fn name(&self) -> String {
+        return format!("delimited:{}:{}", self.delimiter, self.count);
+    }
+}
+
+/*
+ * 布隆过滤器，使用双重哈希模拟k个哈希函数
+ * 哈希结果会持久化，不能使用标准库中不保证稳定的哈希算法
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct BloomFilter {
+    k: u8,
+    bits: Vec<u8>,
+}
+
+impl BloomFilter {
+    pub fn new(hashes: &[u64]) -> Self {
+        let nbits = (hashes.len() * BLOOM_BITS_PER_KEY).max(64);
+        // k = ln2 * bits_per_key
+        let k = ((BLOOM_BITS_PER_KEY as f64 * 0.69) as u8).clamp(1, 30);
+        let mut filter = BloomFilter {
+            k,
+            bits: vec![0; nbits.div_ceil(8)],
+        };
+        for hash in hashes {
+            for bit in filter.bit_positions(*hash) {
+                filter.bits[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+        return filter;
+    }
+
+    pub fn may_contain(&self, key: &str) -> bool {
+        return self
+            .bit_positions(Self::hash(key))
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0);
+    }
+
+    // FNV-1a
+    pub fn hash(key: &str) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for b in key.as_bytes() {
+            hash ^= *b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        return hash;
+    }
+
+    fn bit_positions(&self, hash: u64) -> impl Iterator<Item = usize> {
+        let nbits = (self.bits.len() * 8) as u64;
+        let h1 = hash & 0xffffffff;
+        let h2 = (hash >> 32) | 1;
+        return (0..self.k as u64)
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % nbits) as usize);
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(self.k);
+        buf.extend_from_slice(&(self.bits.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&self.bits);
+    }
+
+    fn decode(buf: &[u8], offset: &mut usize) -> io::Result<Self> {
+        let k = Reader::take(buf, offset, 1)?[0];
+        let len = u32::from_le_bytes(Reader::take(buf, offset, 4)?.try_into().unwrap()) as usize;
+        let bits = Reader::take(buf, offset, len)?.to_vec();
+        if k == 0 || bits.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid bloom filter",
+            ));
+        }
+        return Ok(BloomFilter { k, bits });
+    }
+}
+
+/*
+ * 每个sstable对应一个过滤器文件：整key过滤器用于点查，前缀过滤器用于前缀扫描
+ * 格式：name_size(u8) name key_filter has_prefix_filter(u8) [prefix_filter]
+ * filter：k(u8) bits_size(u32) bits
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    prefix_name: String,
+    key_filter: BloomFilter,
+    prefix_filter: Option<BloomFilter>,
+}
+
+impl Filter {
+    pub fn may_contain_key(&self, key: &str) -> bool {
+        return self.key_filter.may_contain(key);
+    }
+
+    /*
+     * 提取器与构建时不一致或前缀不在提取范围内时无法判断，视为可能包含
+     */
+    pub fn may_contain_prefix(&self, extractor: &dyn PrefixExtractor, prefix: &str) -> bool {
+        return match &self.prefix_filter {
+            Some(filter) if extractor.name() == self.prefix_name && extractor.in_domain(prefix) => {
+                filter.may_contain(prefix)
+            }
+            _ => true,
+        };
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf: Vec<u8> = Vec::new();
+        buf.push(self.prefix_name.len() as u8);
+        buf.extend_from_slice(self.prefix_name.as_bytes());
+        self.key_filter.encode(&mut buf);
+        match &self.prefix_filter {
+            Some(filter) => {
+                buf.push(1);
+                filter.encode(&mut buf);
+            }
+            None => buf.push(0),
+        }
+        return buf;
+    }
+
+    pub fn decode(buf: &[u8]) -> io::Result<Self> {
+        let mut offset = 0;
+        let name_size = Reader::take(buf, &mut offset, 1)?[0] as usize;
+        let prefix_name = Reader::to_string(Reader::take(buf, &mut offset, name_size)?)?;
+        let key_filter = BloomFilter::decode(buf, &mut offset)?;
+        let prefix_filter = match Reader::take(buf, &mut offset, 1)?[0] {
+            0 => None,
+            _ => Some(BloomFilter::decode(buf, &mut offset)?),
+        };
+        return Ok(Filter {
+            prefix_name,
+            key_filter,
+            prefix_filter,
+        });
+    }
+}
+
+/*
+ * 写sstable时按key顺序逐条加入，相邻key的前缀相同时只记录一次
+ */
+pub struct FilterBuilder {
+    extractor: Option<Arc<dyn PrefixExtractor>>,
+    key_hashes: Vec<u64>,
+    prefix_hashes: Vec<u64>,
+    last_prefix: Option<String>,
+}
+
+impl FilterBuilder {
+    pub fn new(extractor: Option<Arc<dyn PrefixExtractor>>) -> Self {
+        return FilterBuilder {
+            extractor,
+            key_hashes: vec![],
+            prefix_hashes: vec![],
+            last_prefix: None,
+        };
+    }
+
+    pub fn add(&mut self, key: &str) {
+        self.key_hashes.push(BloomFilter::hash(key));
+        if let Some(prefix) = self.extractor.as_ref().and_then(|e| e.extract(key)) {
+            if self.last_prefix.as_deref() != Some(prefix) {
+                self.prefix_hashes.push(BloomFilter::hash(prefix));
+                self.last_prefix = Some(prefix.to_string());
+            }
+        }
+    }
+
+    pub fn finish(self) -> Filter {
+        return Filter {
+            prefix_name: self
+                .extractor
+                .as_ref()
+                .map(|e| e.name())
+                .unwrap_or_default(),
+            key_filter: BloomFilter::new(&self.key_hashes),
+            prefix_filter: self
+                .extractor
+                .as_ref()
+                .map(|_| BloomFilter::new(&self.prefix_hashes)),
+        };
+    }
+}
@@ -1,14 +1,15 @@
 use std::{
     cmp::Ordering,
-    collections::{BTreeSet, HashMap},
-    fs::{self, File, OpenOptions},
-    io::{self, BufRead, BufReader, Seek, SeekFrom, Write},
+    collections::{BTreeSet, HashMap, HashSet},
+    io::{self, Write},
     sync::Arc,
 };
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Serialize, Deserialize)]
+use crate::env::{Env, WritableFile};
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Position {
     pub level: usize,
     pub path: String,
@@ -16,16 +17,22 @@ pub struct Position {
     pub end_key: String,
 }
 
+/*
+ * 全序：区间有重叠的sstable（level 0）也必须能同时存在于BTreeSet中
+ */
 impl Ord for Position {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match (
-            self.start_key.cmp(&other.start_key),
-            self.end_key.cmp(&other.end_key),
-        ) {
-            (Ordering::Less, Ordering::Less) => Ordering::Less,
-            (Ordering::Greater, Ordering::Greater) => Ordering::Greater,
-            _ => Ordering::Equal,
-        }
+    fn cmp(&self, other: &Self) -> Ordering {
+        return self
+            .start_key
+            .cmp(&other.start_key)
+            .then_with(|| self.end_key.cmp(&other.end_key))
+            .then_with(|| self.path.cmp(&other.path));
+    }
+}
+
+impl PartialOrd for Position {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
     }
 }
 
@@ -38,99 +45,188 @@ impl Position {
             end_key,
         }
     }
+
+    fn overlap(&self, start_key: &String, end_key: &String) -> bool {
+        return &self.start_key <= end_key && &self.end_key >= start_key;
+    }
 }
 
-#[derive(Debug)]
+/*
+ * 索引文件每行一条记录：level path start_key end_key [-被替换的path ...] [+已持久化的日志]，重写后日志单独成行
+ * 一行即一次原子变更，断电截断的最后一行（没有换行符）会被忽略
+ */
 pub struct Index {
     level: usize,
-    file: File,
+    env: Arc<dyn Env>,
+    file: Box<dyn WritableFile>,
     path_indexes: HashMap<String, (Arc<Position>, bool)>,
     key_indexes: Vec<BTreeSet<Arc<Position>>>,
+    flushed_logs: HashSet<String>,
+    // 写入失败后文件状态未知（未持久化的记录之后可能被持久化），拒绝之后的所有变更直到重启
+    failed: bool,
 }
 
 impl Index {
-    pub fn new(base_path: &String, level: usize) -> Self {
+    pub fn new(env: Arc<dyn Env>, base_path: &String, level: usize) -> Self {
         let index_dir = format!("{}/index", base_path);
-        fs::create_dir_all(&index_dir).unwrap();
+        env.create_dir_all(&index_dir).unwrap();
+        let index_path = format!("{}/{}.index", index_dir, "sstable");
         let mut index = Self {
             level,
-            file: OpenOptions::new()
-                .create(true)
-                .read(true)
-                .append(true)
-                .open(format!("{}/{}.index", index_dir, "sstable"))
-                .unwrap(),
+            file: env.append(&index_path).unwrap(),
+            env,
             path_indexes: HashMap::new(),
             key_indexes: vec![BTreeSet::new(); level],
+            flushed_logs: HashSet::new(),
+            failed: false,
         };
-        index.init().unwrap();
+        index.init(&index_path).unwrap();
         return index;
     }
 
-    fn init(&mut self) -> io::Result<()> {
-        self.file.seek(SeekFrom::Start(0))?;
-        let mut reader: BufReader<&File> = BufReader::new(&self.file);
-        let mut buf = String::new();
-        while let Ok(size) = reader.read_line(&mut buf) {
-            if size == 0 {
-                break;
-            } else {
-                let columns: Vec<&str> = buf.split_whitespace().collect();
-                let level: usize = columns[0].parse::<usize>().unwrap();
-                let path: &str = columns[1];
-                let start_key: String = columns[2].to_string();
-                let end_key: String = columns[3].to_string();
-                if columns.len() == 4 {
-                    let position = Arc::new(Position {
+    fn init(&mut self, index_path: &str) -> io::Result<()> {
+        let buf = self.env.map(index_path)?;
+        let content = String::from_utf8_lossy(&buf);
+        let mut lines: Vec<&str> = content.split('\n').collect();
+        // 最后一段要么为空，要么是不完整的记录
+        lines.pop();
+        for line in lines {
+            let columns: Vec<&str> = line.split_whitespace().collect();
+            let position = match columns.first().map(|c| c.parse::<usize>()) {
+                Some(Ok(level)) if columns.len() >= 4 && level < self.level => {
+                    Some(Arc::new(Position::new(
                         level,
-                        path: path.to_string(),
-                        start_key,
-                        end_key,
-                    });
-                    self.key_indexes[level].insert(position.clone());
-                    self.path_indexes
-                        .insert(path.to_string(), (position.clone(), false));
+                        columns[1].to_string(),
+                        columns[2].to_string(),
+                        columns[3].to_string(),
+                    )))
+                }
+                _ => None,
+            };
+            // 先删除被替换的文件，单文件合并时新旧路径相同
+            let skip = if position.is_some() { 4 } else { 0 };
+            for column in columns[skip..].iter() {
+                if let Some(path) = column.strip_prefix('-') {
+                    self.remove(path);
+                } else if let Some(log) = column.strip_prefix('+') {
+                    self.flushed_logs.insert(log.to_string());
                 }
-                buf.clear();
             }
+            if let Some(position) = position {
+                self.insert(position);
+            }
+        }
+        return self.rewrite(index_path);
+    }
+
+    /*
+     * 用当前状态重写索引文件：丢弃断电截断的最后一行（否则之后追加的记录会和它拼在一起），同时压缩历史记录
+     * 先写临时文件并fsync，再通过重命名原子替换
+     */
+    fn rewrite(&mut self, index_path: &str) -> io::Result<()> {
+        let mut content = String::new();
+        for positions in self.key_indexes.iter() {
+            for p in positions.iter() {
+                content.push_str(&format!(
+                    "{} {} {} {}\n",
+                    p.level, p.path, p.start_key, p.end_key
+                ));
+            }
+        }
+        for log in self.flushed_logs.iter() {
+            content.push_str(&format!("+{}\n", log));
         }
-        self.file.seek(SeekFrom::End(0))?;
+        let tmp_path = format!("{}.tmp", index_path);
+        let mut file = self.env.create(&tmp_path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync()?;
+        self.env.rename(&tmp_path, index_path)?;
+        self.file = self.env.append(index_path)?;
         return Ok(());
     }
 
+    /*
+     * 日志删除后不再需要记录其已持久化，下次重写索引文件时清除
+     */
+    pub fn forget_flushed(&mut self, log: &String) {
+        self.flushed_logs.remove(log);
+    }
+
+    /*
+     * minor compaction产生的新文件，同时记录对应的日志已经持久化
+     */
     pub fn add(
         &mut self,
         current_level: usize,
         path: &String,
         start_key: &String,
         end_key: &String,
+        flushed_log: &String,
     ) -> io::Result<()> {
-        self.file.write_all(
-            format!("{} {} {} {}\n", current_level, path, start_key, end_key).as_bytes(),
+        self.append(
+            format!(
+                "{} {} {} {} +{}\n",
+                current_level, path, start_key, end_key, flushed_log
+            )
+            .as_bytes(),
         )?;
-        let position = Arc::new(Position::new(
+        self.insert(Arc::new(Position::new(
             current_level,
             path.to_string(),
             start_key.to_string(),
             end_key.to_string(),
-        ));
-        self.key_indexes[current_level].insert(position.clone());
-        self.path_indexes
-            .insert(position.path.clone(), (position, false));
+        )));
+        self.flushed_logs.insert(flushed_log.to_string());
         return Ok(());
     }
 
-    pub fn clear(&mut self, useless_positions: &Vec<Arc<Position>>) -> io::Result<()> {
+    /*
+     * major compaction的结果：新文件替换掉参与合并的文件
+     */
+    pub fn replace(
+        &mut self,
+        current_level: usize,
+        path: &String,
+        start_key: &String,
+        end_key: &String,
+        useless_positions: &Vec<Arc<Position>>,
+    ) -> io::Result<()> {
+        let mut record = format!("{} {} {} {}", current_level, path, start_key, end_key);
         for position in useless_positions {
-            if let Some(p) = self.path_indexes.remove(&position.path) {
-                self.key_indexes[p.0.level].remove(&p.0);
-            }
+            record.push_str(&format!(" -{}", position.path));
         }
+        record.push('\n');
+        self.append(record.as_bytes())?;
+        for position in useless_positions {
+            self.remove(&position.path);
+        }
+        self.insert(Arc::new(Position::new(
+            current_level,
+            path.to_string(),
+            start_key.to_string(),
+            end_key.to_string(),
+        )));
         return Ok(());
     }
 
+    pub fn is_flushed(&self, log: &String) -> bool {
+        return self.flushed_logs.contains(log);
+    }
+
+    pub fn count_in_level(&self, level: usize) -> usize {
+        return self.key_indexes[level].len();
+    }
+
+    /*
+     * level 0：只能选择最旧的文件，否则合并到下一层后会被更旧的数据遮蔽
+     */
     pub fn get_random_position_in_level(&mut self, level: usize) -> Option<Arc<Position>> {
-        for position in self.key_indexes[level].iter() {
+        let mut positions: Vec<&Arc<Position>> = self.key_indexes[level].iter().collect();
+        if level == 0 {
+            positions.sort_by(|a, b| a.path.cmp(&b.path));
+            positions.truncate(1);
+        }
+        for position in positions {
             if let Some(p) = self.path_indexes.get_mut(&position.path) {
                 if !p.1 {
                     p.1 = true;
@@ -147,7 +243,9 @@ impl Index {
         position: Arc<Position>,
     ) -> Vec<Arc<Position>> {
         let mut res = vec![];
-        let positions = self.key_indexes[level].range(position.clone()..=position);
+        let positions = self.key_indexes[level]
+            .iter()
+            .filter(|p| p.overlap(&position.start_key, &position.end_key));
         for kp in positions {
             if let Some(pp) = self.path_indexes.get_mut(&kp.path) {
                 if !pp.1 {
@@ -160,26 +258,100 @@ impl Index {
     }
 
     /*
-     * level 0：可能会出现区间重复的sstable
+     * 按从新到旧的顺序返回命中的sstable
+     * level 0：可能会出现区间重复的sstable，文件名的时间戳越大越新
      * level 1..n：start_key == end_key时，只会命中一个sstable（合并方式保证每一层sstable文件没有交集）
      */
     pub fn get_hit_path_in_db(&self, start_key: &String, end_key: &String) -> Vec<Arc<Position>> {
-        let position = Arc::new(Position::new(
-            0,
-            start_key.to_string(),
-            end_key.to_string(),
-            String::default(),
-        ));
         let mut res = vec![];
         for i in 0..self.level {
             let mut positions: Vec<Arc<Position>> = self.key_indexes[i]
-                .range(position.clone()..=position.clone())
-                .map(|p| p.clone())
+                .iter()
+                .filter(|p| p.overlap(start_key, end_key))
+                .cloned()
+                .collect();
+            if i == 0 {
+                positions.sort_by(|a, b| b.path.cmp(&a.path));
+            }
+            res.append(&mut positions);
+        }
+        return res;
+    }
+
+    /*
+     * 范围查询：返回与[start_key, end_key)有交集的所有sstable，按从旧到新排列
+     * （层级越高越旧，level 0内文件名的时间戳越大越新）
+     */
+    pub fn get_positions_in_range(
+        &self,
+        start_key: &String,
+        end_key: &String,
+    ) -> Vec<Arc<Position>> {
+        let mut res: Vec<Arc<Position>> = vec![];
+        for i in (0..self.level).rev() {
+            let mut positions: Vec<Arc<Position>> = self.key_indexes[i]
+                .iter()
+                .filter(|p| &p.start_key < end_key && &p.end_key >= start_key)
+                .cloned()
+                .collect();
+            positions.sort_by(|a, b| a.path.cmp(&b.path));
+            res.append(&mut positions);
+        }
+        return res;
+    }
+
+    /*
+     * 前缀扫描：以prefix开头的key是从prefix开始的一段连续区间，
+     * 文件的start_key小于prefix或者本身以prefix开头时才可能包含，顺序同get_positions_in_range
+     */
+    pub fn get_positions_with_prefix(&self, prefix: &str) -> Vec<Arc<Position>> {
+        let mut res: Vec<Arc<Position>> = vec![];
+        for i in (0..self.level).rev() {
+            let mut positions: Vec<Arc<Position>> = self.key_indexes[i]
+                .iter()
+                .filter(|p| {
+                    p.end_key.as_str() >= prefix
+                        && (p.start_key.as_str() < prefix || p.start_key.starts_with(prefix))
+                })
+                .cloned()
                 .collect();
-            if !positions.is_empty() {
-                res.append(&mut positions);
+            positions.sort_by(|a, b| a.path.cmp(&b.path));
+            res.append(&mut positions);
+        }
+        return res;
+    }
+
+    /*
+     * 合并失败时释放参与合并的文件，避免之后的合并跳过它们导致同一层出现重叠
+     */
+    pub fn release(&mut self, positions: &Vec<Arc<Position>>) {
+        for position in positions {
+            if let Some(p) = self.path_indexes.get_mut(&position.path) {
+                p.1 = false;
             }
         }
+    }
+
+    fn append(&mut self, record: &[u8]) -> io::Result<()> {
+        if self.failed {
+            return Err(io::Error::other(
+                "index is read only after a failed write, restart required",
+            ));
+        }
+        let res = self.file.write_all(record).and_then(|_| self.file.sync());
+        self.failed = res.is_err();
         return res;
     }
+
+    fn insert(&mut self, position: Arc<Position>) {
+        self.key_indexes[position.level].insert(position.clone());
+        self.path_indexes
+            .insert(position.path.clone(), (position, false));
+    }
+
+    fn remove(&mut self, path: &str) {
+        if let Some(p) = self.path_indexes.remove(path) {
+            self.key_indexes[p.0.level].remove(&p.0);
+        }
+    }
 }
@@ -1,21 +1,70 @@
+#![allow(clippy::needless_return)]
+
+mod async_lsm;
+mod blob;
+mod env;
+mod filter;
 mod index;
 mod log;
 mod lsm;
 mod memtable;
 mod reader;
 mod sstable;
+mod transaction;
 mod writer;
 
+pub use crate::{
+    async_lsm::{AsyncLsm, SCAN_BATCH_SIZE},
+    blob::{BLOB_GC_LIVE_RATIO, BLOB_THRESHOLD},
+    env::{DiskEnv, Env, MemEnv, WritableFile},
+    filter::{DelimitedPrefix, FixedPrefix, PrefixExtractor},
+    lsm::{Lsm, LsmOptions},
+    transaction::{Conflict, Transaction},
+};
+
 #[cfg(test)]
 mod tests {
 
-    use std::{io, thread, time::Duration};
+    use std::{
+        collections::HashMap,
+        io::{self, Read},
+        ops::Deref,
+        sync::{Arc, RwLock},
+        thread,
+        time::Duration,
+    };
+
+    use futures::TryStreamExt;
+    use rb_tree::RBMap;
+
+    use crate::{
+        async_lsm::{AsyncLsm, SCAN_BATCH_SIZE},
+        blob::{BlobStore, Value, BLOB_THRESHOLD},
+        env::{Env, MemEnv, WritableFile},
+        filter::{DelimitedPrefix, FixedPrefix, PrefixExtractor},
+        log::Log,
+        lsm::{Lsm, LsmOptions},
+        sstable::SSTable,
+        transaction::Conflict,
+        writer::Writer,
+    };
 
-    use crate::lsm::Lsm;
+    fn options(env: &MemEnv, mem_table_capicaty: usize, blob_threshold: usize) -> LsmOptions {
+        return LsmOptions {
+            env: Arc::new(env.clone()),
+            blob_threshold,
+            ..LsmOptions::new(mem_table_capicaty, 3, 2)
+        };
+    }
+
+    fn open(env: &MemEnv, mem_table_capicaty: usize, blob_threshold: usize) -> Lsm {
+        return Lsm::new_with_options("store", options(env, mem_table_capicaty, blob_threshold));
+    }
 
     #[test]
     fn it_works() -> io::Result<()> {
-        let mut lsm = Lsm::new("store", 2, 7, 1);
+        let env = MemEnv::new(1);
+        let mut lsm = open(&env, 2, BLOB_THRESHOLD);
         lsm.insert(&"key1".to_string(), &"value1".to_string())?;
         lsm.insert(&"key2".to_string(), &"value2".to_string())?;
         lsm.insert(&"key3".to_string(), &"value3".to_string())?;
@@ -32,8 +81,628 @@ mod tests {
             lsm.get(&"key2".to_string()).unwrap(),
             Some("value2".to_string())
         );
-        println!("-----done-----");
-        thread::sleep(Duration::from_secs(120));
+
+        // 重启后数据不丢失
+        let lsm = open(&env.crash(), 2, BLOB_THRESHOLD);
+        assert_eq!(lsm.get(&"key1".to_string()).unwrap(), None);
+        assert_eq!(
+            lsm.get(&"key3".to_string()).unwrap(),
+            Some("value3_changed".to_string())
+        );
+        return Ok(());
+    }
+
+    #[test]
+    fn transaction() -> io::Result<()> {
+        let mut lsm = open(&MemEnv::new(1), 1024, BLOB_THRESHOLD);
+        lsm.insert(&"balance".to_string(), &"100".to_string())?;
+
+        // 快照之后被其他写入修改，提交失败
+        let mut txn = lsm.begin();
+        let balance = txn.get(&lsm, &"balance".to_string())?.unwrap();
+        lsm.insert(&"balance".to_string(), &"50".to_string())?;
+        txn.insert(&"balance".to_string(), &format!("{}0", balance));
+        let err = lsm.commit(txn).unwrap_err();
+        assert!(Conflict::is_conflict(&err));
+        assert_eq!(lsm.get(&"balance".to_string())?, Some("50".to_string()));
+
+        // 无冲突时整批提交
+        let mut txn = lsm.begin();
+        txn.get(&lsm, &"balance".to_string())?;
+        txn.insert(&"balance".to_string(), &"60".to_string());
+        txn.remove(&"other".to_string());
+        assert_eq!(
+            txn.get(&lsm, &"balance".to_string())?,
+            Some("60".to_string())
+        );
+        lsm.commit(txn)?;
+        assert_eq!(lsm.get(&"balance".to_string())?, Some("60".to_string()));
+        assert_eq!(lsm.get(&"other".to_string())?, None);
+
+        // 没有提交也没有回滚就被丢弃的事务同样释放快照，不再记录之后的修改
+        let mut txn = lsm.begin();
+        txn.get(&lsm, &"balance".to_string())?;
+        lsm.insert(&"balance".to_string(), &"70".to_string())?;
+        assert_eq!(lsm.tracker.tracked_keys(), 1);
+        drop(txn);
+        assert_eq!(lsm.tracker.tracked_keys(), 0);
+        lsm.insert(&"balance".to_string(), &"80".to_string())?;
+        assert_eq!(lsm.tracker.tracked_keys(), 0);
+        let txn = lsm.begin();
+        lsm.rollback(txn);
+        lsm.insert(&"balance".to_string(), &"90".to_string())?;
+        assert_eq!(lsm.tracker.tracked_keys(), 0);
+        return Ok(());
+    }
+
+    #[test]
+    fn blob() -> io::Result<()> {
+        let env: Arc<dyn Env> = Arc::new(MemEnv::new(1));
+        let path = "store";
+        let blob = Arc::new(RwLock::new(BlobStore::new(env.clone(), path, 8, 0.5)));
+        let sstable = SSTable::new(env.clone(), path, 2, 1, blob.clone(), None);
+        let large = "large_value_in_blob".to_string();
+
+        let mut table: RBMap<String, Option<String>> = RBMap::new();
+        table.insert("small".to_string(), Some("value".to_string()));
+        table.insert("large".to_string(), Some(large.clone()));
+        let immut_tables = Arc::new(RwLock::new(vec![("store/log/0.log".to_string(), table)]));
+        sstable.save(immut_tables)?;
+
+        // 小value内联，大value只在sstable中保存指针
+        assert_eq!(
+            sstable.get_entry(&"small".to_string())?,
+            Some(Value::Inline("value".to_string()))
+        );
+        let pointer = match sstable.get_entry(&"large".to_string())? {
+            Some(Value::Blob(pointer)) => pointer,
+            _ => panic!("large value should be stored in blob file"),
+        };
+        assert_eq!(sstable.get(&"large".to_string())?, Some(large.clone()));
+
+        // 失效比例超过阈值后成为垃圾回收的候选
+        assert!(blob.read().unwrap().gc_candidates().is_empty());
+        blob.write().unwrap().discard(&pointer)?;
+        assert_eq!(blob.read().unwrap().gc_candidates(), vec![pointer.file_id]);
+        let records = blob.read().unwrap().scan(pointer.file_id)?;
+        assert_eq!(records, vec![("large".to_string(), pointer.clone(), large)]);
+
+        // 重启后从blob.index恢复统计
+        let reopened = BlobStore::new(env.clone(), path, 8, 0.5);
+        assert_eq!(reopened.gc_candidates(), vec![pointer.file_id]);
+        blob.write().unwrap().remove(pointer.file_id)?;
+        assert!(BlobStore::new(env, path, 8, 0.5).gc_candidates().is_empty());
+        return Ok(());
+    }
+
+    /*
+     * 等待后台线程把memtable持久化到sstable
+     */
+    fn wait_for_flush(env: &MemEnv, blob_files: usize) {
+        for _ in 0..500 {
+            let sstables = env.list_dir("store/sstable/0").unwrap();
+            let blobs = env.list_dir("store/blob").unwrap();
+            if !sstables.is_empty()
+                && blobs.iter().filter(|p| p.ends_with(".blob")).count() >= blob_files
+            {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("memtable is not flushed");
+    }
+
+    #[test]
+    fn large_value() -> io::Result<()> {
+        let env = MemEnv::new(1);
+        let mut lsm = open(&env, 0, BLOB_THRESHOLD);
+        let large: String = (0..5000).map(|i| (b'a' + (i % 26) as u8) as char).collect();
+        let long_key = "k".repeat(300);
+        lsm.insert(&"large".to_string(), &large)?;
+        lsm.insert(&long_key, &large[..200].to_string())?;
+        assert_eq!(lsm.get(&"large".to_string())?, Some(large.clone()));
+        wait_for_flush(&env, 1);
+
+        // 重启后从sstable中的指针读取blob文件中的value
+        let env = env.crash();
+        let mut lsm = open(&env, 0, BLOB_THRESHOLD);
+        assert_eq!(lsm.get(&"large".to_string())?, Some(large.clone()));
+        assert_eq!(lsm.get(&long_key)?, Some(large[..200].to_string()));
+
+        // 覆盖后旧value失效，垃圾回收不影响最新的值
+        let updated = large.to_uppercase();
+        lsm.insert(&"large".to_string(), &updated)?;
+        lsm.gc_blobs()?;
+        assert_eq!(lsm.get(&"large".to_string())?, Some(updated.clone()));
+        assert_eq!(lsm.get(&long_key)?, Some(large[..200].to_string()));
+        let lsm = open(&env.crash(), 0, BLOB_THRESHOLD);
+        assert_eq!(lsm.get(&"large".to_string())?, Some(updated));
+        return Ok(());
+    }
+
+    #[test]
+    fn prefix_scan_after_gc() -> io::Result<()> {
+        // p:a和p:b写入同一个blob文件，较大的p:a被覆盖后该文件存活比例过低，
+        // 回收时p:b重新写入，sstable中仍然留有指向已删除文件的旧指针
+        let env = MemEnv::new(1);
+        let mut lsm = open(&env, 0, BLOB_THRESHOLD);
+        let value = |c: char| c.to_string().repeat(BLOB_THRESHOLD + 1);
+        let mut txn = lsm.begin();
+        txn.insert(&"p:a".to_string(), &value('a').repeat(2));
+        txn.insert(&"p:b".to_string(), &value('b'));
+        lsm.commit(txn)?;
+        wait_for_flush(&env, 1);
+        lsm.insert(&"p:a".to_string(), &value('c'))?;
+        wait_for_flush(&env, 2);
+        // 合并之后才会记录失效的value
+        let mut i = 0;
+        while lsm.gc_blobs()? == 0 {
+            assert!(i < 500, "blob file is not collected");
+            lsm.insert(&format!("q:{}", i), &i.to_string())?;
+            thread::sleep(Duration::from_millis(10));
+            i += 1;
+        }
+        let expected = vec![
+            ("p:a".to_string(), value('c')),
+            ("p:b".to_string(), value('b')),
+        ];
+        assert_eq!(lsm.prefix_scan("p:", 10)?, expected);
+        assert_eq!(
+            lsm.scan(&"p:".to_string(), &"p;".to_string(), 10)?,
+            expected
+        );
+        return Ok(());
+    }
+
+    #[test]
+    fn prefix_filter() -> io::Result<()> {
+        let env: Arc<dyn Env> = Arc::new(MemEnv::new(1));
+        let path = "store";
+        let extractor: Arc<dyn PrefixExtractor> = Arc::new(DelimitedPrefix {
+            delimiter: ':',
+            count: 2,
+        });
+        assert_eq!(extractor.extract("user:123:name"), Some("user:123:"));
+        assert_eq!(extractor.extract("user:123"), None);
+        let blob = Arc::new(RwLock::new(BlobStore::new(env.clone(), path, 128, 0.5)));
+        let sstable = SSTable::new(
+            env.clone(),
+            path,
+            3,
+            4,
+            blob.clone(),
+            Some(extractor.clone()),
+        );
+
+        let mut immut_tables = vec![];
+        for (i, user) in ["user:1:", "user:2:"].iter().enumerate() {
+            let mut table: RBMap<String, Option<String>> = RBMap::new();
+            table.insert("user:0:visits".to_string(), Some(format!("{}", i)));
+            table.insert(format!("{}age", user), Some(format!("{}", 20 + i)));
+            table.insert(format!("{}name", user), Some(format!("name{}", i)));
+            immut_tables.push((format!("store/log/{}.log", i), table));
+        }
+        sstable.save(Arc::new(RwLock::new(immut_tables)))?;
+
+        // 两个文件的key区间重叠，只能靠过滤器排除
+        let positions = sstable
+            .index
+            .read()
+            .unwrap()
+            .get_positions_with_prefix("user:1:");
+        assert_eq!(positions.len(), 2);
+        let filters: Vec<_> = positions
+            .iter()
+            .map(|p| sstable.filter(&p.path).unwrap())
+            .collect();
+        assert!(filters[0].may_contain_prefix(extractor.as_ref(), "user:1:"));
+        assert!(!filters[1].may_contain_prefix(extractor.as_ref(), "user:1:"));
+        assert!(filters[1].may_contain_key("user:2:name"));
+        assert!(!filters[1].may_contain_key("user:1:name"));
+        // 提取器配置变化或前缀不在提取范围内时不过滤
+        assert!(filters[1].may_contain_prefix(&FixedPrefix(7), "user:1:"));
+        assert!(filters[1].may_contain_prefix(extractor.as_ref(), "user:"));
+        assert_eq!(
+            sstable
+                .prefix_scan("user:1:")?
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "user:1:age".to_string(),
+                    Some(Value::Inline("20".to_string()))
+                ),
+                (
+                    "user:1:name".to_string(),
+                    Some(Value::Inline("name0".to_string()))
+                )
+            ]
+        );
+
+        // memtable中的前缀索引，重启后数据回放到immut table
+        let env = MemEnv::new(1);
+        let mut lsm = Lsm::new_with_options(
+            path,
+            LsmOptions {
+                prefix_extractor: Some(extractor.clone()),
+                ..options(&env, 1024, BLOB_THRESHOLD)
+            },
+        );
+        for user in 0..10 {
+            lsm.insert(&format!("user:{}:name", user), &format!("name{}", user))?;
+            lsm.insert(&format!("user:{}:age", user), &format!("{}", user))?;
+        }
+        lsm.remove(&"user:3:age".to_string())?;
+        let expected = vec![("user:3:name".to_string(), "name3".to_string())];
+        assert_eq!(lsm.prefix_scan("user:3:", 10)?, expected);
+        assert_eq!(lsm.prefix_scan("user:1", 10)?.len(), 2);
+        let lsm = Lsm::new_with_options(
+            path,
+            LsmOptions {
+                prefix_extractor: Some(extractor),
+                ..options(&env.crash(), 1024, BLOB_THRESHOLD)
+            },
+        );
+        assert_eq!(lsm.prefix_scan("user:3:", 10)?, expected);
+        return Ok(());
+    }
+
+    /*
+     * 无法创建sstable文件的环境，用于模拟后台持久化失败
+     */
+    struct NoSSTableEnv(MemEnv);
+
+    impl Env for NoSSTableEnv {
+        fn create_dir_all(&self, path: &str) -> io::Result<()> {
+            return self.0.create_dir_all(path);
+        }
+
+        fn append(&self, path: &str) -> io::Result<Box<dyn WritableFile>> {
+            return self.0.append(path);
+        }
+
+        fn create(&self, path: &str) -> io::Result<Box<dyn WritableFile>> {
+            if path.ends_with(".sst") {
+                return Err(io::Error::other("no space left"));
+            }
+            return self.0.create(path);
+        }
+
+        fn open(&self, path: &str) -> io::Result<Box<dyn Read + Send>> {
+            return self.0.open(path);
+        }
+
+        fn map(&self, path: &str) -> io::Result<Box<dyn Deref<Target = [u8]>>> {
+            return self.0.map(path);
+        }
+
+        fn read_at(&self, path: &str, offset: u64, size: usize) -> io::Result<Vec<u8>> {
+            return self.0.read_at(path, offset, size);
+        }
+
+        fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+            return self.0.rename(from, to);
+        }
+
+        fn remove_file(&self, path: &str) -> io::Result<()> {
+            return self.0.remove_file(path);
+        }
+
+        fn list_dir(&self, path: &str) -> io::Result<Vec<String>> {
+            return self.0.list_dir(path);
+        }
+    }
+
+    #[test]
+    fn flush_error() -> io::Result<()> {
+        let mut lsm = Lsm::new_with_options(
+            "store",
+            LsmOptions {
+                env: Arc::new(NoSSTableEnv(MemEnv::new(1))),
+                ..LsmOptions::new(0, 3, 2)
+            },
+        );
+        // 后台持久化的错误由之后的写入返回，这次写入不生效
+        let mut i = 0;
+        let err = loop {
+            match lsm.insert(&format!("key{}", i), &i.to_string()) {
+                Ok(_) => {
+                    assert!(i < 500, "flush error is not reported");
+                    thread::sleep(Duration::from_millis(10));
+                    i += 1;
+                }
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(err.to_string(), "no space left");
+        assert_eq!(lsm.get(&format!("key{}", i))?, None);
+        // 持久化失败的数据仍然保留在immut table中
+        for j in 0..i {
+            assert_eq!(lsm.get(&format!("key{}", j))?, Some(j.to_string()));
+        }
+        return Ok(());
+    }
+
+    #[test]
+    fn truncated_sstable() -> io::Result<()> {
+        // 每层超过1个文件就合并，所有文件都包含key a，保证之后的合并需要读取文件内容
+        let env: Arc<dyn Env> = Arc::new(MemEnv::new(1));
+        let blob = Arc::new(RwLock::new(BlobStore::new(
+            env.clone(),
+            "store",
+            BLOB_THRESHOLD,
+            0.5,
+        )));
+        let sstable = SSTable::new(env.clone(), "store", 3, 1, blob, None);
+        let table = |i: usize| {
+            let mut table: RBMap<String, Option<String>> = RBMap::new();
+            table.insert("a".to_string(), Some(format!("{}", i)));
+            table.insert(format!("k{}", i), Some(format!("{}", i)));
+            return Arc::new(RwLock::new(vec![(format!("store/log/{}.log", i), table)]));
+        };
+        sstable.save(table(0))?;
+        sstable.save(table(1))?;
+
+        // 截断所有sstable文件的最后一条记录
+        for level in 0..3 {
+            for path in env.list_dir(&format!("store/sstable/{}", level))? {
+                if path.ends_with(".sst") {
+                    let buf = env.map(&path)?.to_vec();
+                    env.create(&path)?.write_all(&buf[..buf.len() - 1])?;
+                }
+            }
+        }
+        // 合并读到不完整的记录时返回错误，不会panic，之后的持久化仍然可以继续
+        let err = sstable.save(table(2)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(sstable.save(table(3)).is_err());
+        return Ok(());
+    }
+
+    #[test]
+    fn torn_log_tail() -> io::Result<()> {
+        let env = MemEnv::new(1);
+        let mut lsm = open(&env, 1024, BLOB_THRESHOLD);
+        lsm.insert(&"a".to_string(), &"1".to_string())?;
+        lsm.insert(&"b".to_string(), &"2".to_string())?;
+        lsm.insert(&"c".to_string(), &"3".to_string())?;
+        drop(lsm);
+
+        // cache.log最后一个批次被截断，只丢弃这个批次
+        let path = "store/log/cache.log";
+        let buf = env.map(path)?.to_vec();
+        env.create(path)?.write_all(&buf[..buf.len() - 3])?;
+        let lsm = open(&env, 1024, BLOB_THRESHOLD);
+        assert_eq!(lsm.get(&"a".to_string())?, Some("1".to_string()));
+        assert_eq!(lsm.get(&"b".to_string())?, Some("2".to_string()));
+        assert_eq!(lsm.get(&"c".to_string())?, None);
+        drop(lsm);
+
+        // 归档的日志只包含完整的批次，再次重启严格回放也能通过
+        let log = Log::new(Arc::new(env.clone()), "store");
+        let saved_logs = log.saved_logs()?;
+        assert_eq!(saved_logs.len(), 1);
+        assert_eq!(log.build_map(&saved_logs[0])?.len(), 2);
+        let lsm = open(&env, 1024, BLOB_THRESHOLD);
+        assert_eq!(lsm.get(&"b".to_string())?, Some("2".to_string()));
+        return Ok(());
+    }
+
+    #[test]
+    fn corrupt_log() -> io::Result<()> {
+        let write = |env: &MemEnv| -> io::Result<()> {
+            let mut lsm = open(env, 1024, BLOB_THRESHOLD);
+            lsm.insert(&"a".to_string(), &"1".to_string())?;
+            lsm.insert(&"b".to_string(), &"2".to_string())?;
+            return Ok(());
+        };
+        let flip = |env: &MemEnv, path: &str| -> io::Result<()> {
+            let mut buf = env.map(path)?.to_vec();
+            buf[20] ^= 0xff;
+            return env.create(path)?.write_all(&buf);
+        };
+
+        // cache.log中间的批次损坏，后面还有完整的批次，不是断电截断
+        let env = MemEnv::new(1);
+        write(&env)?;
+        flip(&env, "store/log/cache.log")?;
+        let mut log = Log::new(Arc::new(env.clone()), "store");
+        assert_eq!(
+            log.recover_cache_file().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        // 已归档的日志已经fsync，任何损坏都返回错误
+        let env = MemEnv::new(1);
+        write(&env)?;
+        drop(open(&env, 1024, BLOB_THRESHOLD));
+        let log = Log::new(Arc::new(env.clone()), "store");
+        let saved_log_path = log.saved_logs()?.pop().unwrap();
+        flip(&env, &saved_log_path)?;
+        assert_eq!(
+            log.build_map(&saved_log_path).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        // 没有分帧的旧格式日志也返回错误
+        let env = MemEnv::new(1);
+        let mut log = Log::new(Arc::new(env.clone()), "store");
+        let mut buf: Vec<u8> = Vec::new();
+        Writer::write_by_seek(&mut buf, &"a".to_string(), Some(&"1".to_string()))?;
+        Writer::write_by_seek(&mut buf, &"b".to_string(), Some(&"2".to_string()))?;
+        env.create("store/log/cache.log")?.write_all(&buf)?;
+        assert!(log.recover_cache_file().is_err());
+        return Ok(());
+    }
+
+    #[test]
+    fn scan() -> io::Result<()> {
+        // 每次写入都会持久化，数据分布在memtable和多个sstable中，同一个key的多个版本互相覆盖
+        let env = MemEnv::new(1);
+        let mut lsm = open(&env, 0, 16);
+        for i in 0..40 {
+            lsm.insert(&format!("key{:02}", i), &format!("value{}", i))?;
+        }
+        for i in (0..40).step_by(3) {
+            lsm.insert(
+                &format!("key{:02}", i),
+                &format!("large_value_updated_{}", i),
+            )?;
+        }
+        for i in (0..40).step_by(5) {
+            lsm.remove(&format!("key{:02}", i))?;
+        }
+        let expected = |i: usize| match i {
+            i if i % 5 == 0 => None,
+            i if i % 3 == 0 => Some(format!("large_value_updated_{}", i)),
+            i => Some(format!("value{}", i)),
+        };
+        let entries = lsm.scan(&"key10".to_string(), &"key30".to_string(), 100)?;
+        assert_eq!(
+            entries,
+            (10..30)
+                .filter_map(|i| expected(i).map(|v| (format!("key{:02}", i), v)))
+                .collect::<Vec<_>>()
+        );
+        let entries = lsm.scan(&"key".to_string(), &"key~".to_string(), 5)?;
+        assert_eq!(
+            entries.iter().map(|e| e.0.as_str()).collect::<Vec<_>>(),
+            vec!["key01", "key02", "key03", "key04", "key06"]
+        );
+        assert!(lsm
+            .scan(&"key05".to_string(), &"key06".to_string(), 10)?
+            .is_empty());
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn async_lsm() -> io::Result<()> {
+        let lsm = AsyncLsm::new(open(&MemEnv::new(1), 1024, BLOB_THRESHOLD));
+        let count = SCAN_BATCH_SIZE * 2 + 10;
+        for i in 0..count {
+            lsm.insert(format!("key{:04}", i), format!("value{}", i))
+                .await?;
+        }
+        lsm.remove("key0001".to_string()).await?;
+        assert_eq!(
+            lsm.get("key0002".to_string()).await?,
+            Some("value2".to_string())
+        );
+        assert_eq!(lsm.get("key0001".to_string()).await?, None);
+
+        // 跨越多个批次的流式扫描
+        let entries: Vec<(String, String)> = lsm
+            .scan("key0000".to_string(), "key9999".to_string())
+            .try_collect()
+            .await?;
+        assert_eq!(entries.len(), count - 1);
+        assert_eq!(entries[0].0, "key0000");
+        assert_eq!(entries[1].0, "key0002");
+        assert!(entries.windows(2).all(|w| w[0].0 < w[1].0));
+
+        let entries: Vec<(String, String)> = lsm
+            .scan("key0010".to_string(), "key0020".to_string())
+            .try_collect()
+            .await?;
+        assert_eq!(entries.len(), 10);
+        return Ok(());
+    }
+
+    /*
+     * 随机注入写入失败、fsync失败，并在随机时刻断电重启：
+     * 确认成功的写入必须在重启后可见，失败的写入可能生效也可能不生效
+     */
+    #[test]
+    fn crash_recovery() -> io::Result<()> {
+        for seed in 1..=20_u64 {
+            let mut rng = seed;
+            let mut next = move || {
+                rng ^= rng << 13;
+                rng ^= rng >> 7;
+                rng ^= rng << 17;
+                return rng;
+            };
+            let mut env = MemEnv::new(seed);
+            // memtable容量为0时每次写入都会触发持久化和合并，短value内联，长value写入blob
+            let mut lsm = open(&env, 0, 12);
+            let mut expected: HashMap<String, Vec<Option<String>>> = HashMap::new();
+            // 每个事务写入各自独有的一组key，value相同
+            let mut txns: Vec<(Vec<String>, Option<String>)> = vec![];
+            for round in 0..4 {
+                env.set_faults(10, 10);
+                let ops = next() % 60;
+                for i in 0..ops {
+                    if next() % 4 == 0 {
+                        let keys: Vec<String> = (0..2 + next() % 3)
+                            .map(|k| format!("txn{}_{}_{}", round, i, k))
+                            .collect();
+                        let val = match next() % 2 {
+                            0 => format!("t{}_{}", round, i),
+                            _ => format!("large_txn_value_{}_{}", round, i),
+                        };
+                        let mut txn = lsm.begin();
+                        for key in keys.iter() {
+                            txn.insert(key, &val);
+                        }
+                        let committed = lsm.commit(txn).is_ok();
+                        txns.push((keys, if committed { Some(val) } else { None }));
+                        continue;
+                    }
+                    let key = format!("key{}", next() % 16);
+                    let val = match next() % 4 {
+                        0 => None,
+                        1 => Some(format!("v{}_{}", round, i)),
+                        _ => Some(format!("large_value_{}_{}", round, i)),
+                    };
+                    let res = match &val {
+                        Some(v) => lsm.insert(&key, v),
+                        None => lsm.remove(&key),
+                    };
+                    let allowed = expected.entry(key).or_insert(vec![None]);
+                    if res.is_ok() {
+                        *allowed = vec![val];
+                    } else {
+                        allowed.push(val);
+                    }
+                    if next() % 8 == 0 {
+                        lsm.gc_blobs().unwrap_or(0);
+                    }
+                }
+
+                env.set_faults(0, 0);
+                env = env.crash();
+                lsm = open(&env, 0, 12);
+                for (key, allowed) in expected.iter_mut() {
+                    let actual = lsm.get(key)?;
+                    assert!(
+                        allowed.contains(&actual),
+                        "seed {} round {} {}: {:?} not in {:?}",
+                        seed,
+                        round,
+                        key,
+                        actual,
+                        allowed
+                    );
+                    *allowed = vec![actual];
+                }
+                // 事务要么全部可见，要么全部不可见，确认提交的事务必须可见
+                for (keys, committed) in txns.iter_mut() {
+                    let actual: Vec<Option<String>> =
+                        keys.iter().map(|k| lsm.get(k)).collect::<io::Result<_>>()?;
+                    assert!(
+                        actual.iter().all(|v| v == &actual[0]),
+                        "seed {} round {}: partial transaction {:?}",
+                        seed,
+                        round,
+                        actual
+                    );
+                    if committed.is_some() {
+                        assert_eq!(&actual[0], committed, "seed {} round {}", seed, round);
+                    }
+                    *committed = actual[0].clone();
+                }
+            }
+        }
         return Ok(());
     }
 }
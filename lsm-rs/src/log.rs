@@ -1,56 +1,232 @@
-use std::{
-    fs::{self, File, OpenOptions},
-    io::{self, BufReader},
-};
+use std::{convert::TryInto, io, sync::Arc};
 
 use chrono::Utc;
 use rb_tree::RBMap;
 
-use crate::{reader::Reader, writer::Writer};
+use crate::{
+    blob::Value,
+    env::{Env, WritableFile},
+    reader::Reader,
+    writer::Writer,
+};
+
+// 每个批次以魔数开头，用来区分没有分帧的旧格式日志和断电截断的尾部
+const BATCH_MAGIC: u32 = 0x314c4157;
+const BATCH_HEADER_SIZE: usize = 16;
+
+// 日志回放的结果
+type Table = RBMap<String, Option<String>>;
 
 pub struct Log {
+    env: Arc<dyn Env>,
+    log_base_path: String,
     cache_file_path: String,
-    cache_file: File,
+    cache_file: Box<dyn WritableFile>,
 }
 
 impl Log {
-    pub fn new(base_path: &str) -> Log {
+    pub fn new(env: Arc<dyn Env>, base_path: &str) -> Log {
         let log_base_path = format!("{}/log", base_path);
-        fs::create_dir_all(&log_base_path).unwrap();
+        env.create_dir_all(&log_base_path).unwrap();
         let cache_file_path = format!("{}/cache.log", &log_base_path);
-        let cache_file = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .append(true)
-            .open(&cache_file_path)
-            .unwrap();
+        let cache_file = env.append(&cache_file_path).unwrap();
         return Log {
+            env,
+            log_base_path,
             cache_file_path,
             cache_file,
         };
     }
 
+    /*
+     * 单条写入作为只有一条记录的批次，返回成功即代表已持久化
+     */
     pub fn append(&mut self, key: &String, value: Option<&String>) -> io::Result<()> {
-        return Writer::write_by_seek(&mut self.cache_file, key, value);
+        let mut payload: Vec<u8> = Vec::new();
+        Writer::write_by_seek(&mut payload, key, value)?;
+        return self.append_frame(1, &payload);
+    }
+
+    pub fn append_batch(&mut self, batch: &RBMap<String, Option<String>>) -> io::Result<()> {
+        let mut payload: Vec<u8> = Vec::new();
+        for (k, v) in batch.iter() {
+            Writer::write_by_seek(&mut payload, k, v.as_ref())?;
+        }
+        return self.append_frame(batch.len() as u32, &payload);
+    }
+
+    /*
+     * 批次格式：magic(u32) count(u32) payload_size(u32) checksum(u32) payload
+     * 先编码到内存再一次写入并fsync，避免批次中间穿插其他写入；回放时只有完整且校验通过的批次才会生效
+     */
+    fn append_frame(&mut self, count: u32, payload: &[u8]) -> io::Result<()> {
+        let mut buf: Vec<u8> = Vec::with_capacity(BATCH_HEADER_SIZE + payload.len());
+        buf.extend_from_slice(&BATCH_MAGIC.to_le_bytes());
+        buf.extend_from_slice(&count.to_le_bytes());
+        buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&crc32(payload).to_le_bytes());
+        buf.extend_from_slice(payload);
+        self.cache_file.write_all(&buf)?;
+        return self.cache_file.sync();
+    }
+
+    /*
+     * 回放已归档的日志：归档前已经fsync，任何不完整或者校验失败的批次都视为损坏
+     */
+    pub fn build_map(&self, path: &str) -> io::Result<Table> {
+        let buf = self.env.map(path)?;
+        return Self::replay(path, &buf, false).map(|(map, _)| map);
+    }
+
+    /*
+     * 回放cache.log并归档：只有尾部断电截断的批次可以丢弃（整个批次都不生效），
+     * 有效部分先写入临时文件再重命名为归档日志，之后cache.log从空文件开始
+     */
+    pub fn recover_cache_file(&mut self) -> io::Result<Option<(String, Table)>> {
+        let buf = self.env.map(&self.cache_file_path)?;
+        let (map, valid) = Self::replay(&self.cache_file_path, &buf, true)?;
+        if map.is_empty() {
+            drop(buf);
+            self.reset_cache_file()?;
+            return Ok(None);
+        }
+        let saved_log_path = self.saved_log_path();
+        let tmp_path = saved_log_path.replace(".log", ".tmp");
+        let mut file = self.env.create(&tmp_path)?;
+        file.write_all(&buf[..valid])?;
+        file.sync()?;
+        drop(buf);
+        self.env.rename(&tmp_path, &saved_log_path)?;
+        self.reset_cache_file()?;
+        return Ok(Some((saved_log_path, map)));
+    }
+
+    /*
+     * 返回回放的结果和有效部分的长度；allow_torn_tail时，延伸到文件末尾的不完整批次按断电截断处理
+     */
+    fn replay(path: &str, buf: &[u8], allow_torn_tail: bool) -> io::Result<(Table, usize)> {
+        let mut offset = 0;
+        let mut map: Table = RBMap::new();
+        while offset < buf.len() {
+            let start = offset;
+            match Self::read_batch(buf, &mut offset) {
+                Ok(batch) => {
+                    for (k, v) in batch {
+                        map.insert(k, v);
+                    }
+                }
+                // 断电后未写完的尾部可能被截断，也可能是未写入的零
+                Err(e)
+                    if allow_torn_tail
+                        && (e.kind() == io::ErrorKind::UnexpectedEof
+                            || offset == buf.len()
+                            || buf[start..].iter().all(|b| *b == 0)) =>
+                {
+                    return Ok((map, start));
+                }
+                Err(e) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("corrupt log {} at offset {}: {}", path, start, e),
+                    ));
+                }
+            }
+        }
+        return Ok((map, offset));
     }
 
-    pub fn build_map(path: &String) -> io::Result<RBMap<String, Option<String>>> {
-        let mut reader: BufReader<File> = BufReader::new(OpenOptions::new().read(true).open(path)?);
-        let mut map: RBMap<String, Option<String>> = RBMap::new();
-        while let Ok(Some((k, v))) = Reader::read_by_seek(&mut reader) {
-            map.insert(k, v);
+    fn read_batch(buf: &[u8], offset: &mut usize) -> io::Result<Vec<(String, Option<String>)>> {
+        let magic = &buf[*offset..buf.len().min(*offset + 4)];
+        if magic != &BATCH_MAGIC.to_le_bytes()[..magic.len()] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid log batch magic",
+            ));
+        }
+        let header = Reader::take(buf, offset, BATCH_HEADER_SIZE)?;
+        let count = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+        let size = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+        let checksum = u32::from_le_bytes(header[12..16].try_into().unwrap());
+        let payload = Reader::take(buf, offset, size)?;
+        if crc32(payload) != checksum {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "log batch checksum mismatch",
+            ));
+        }
+        let mut batch = Vec::with_capacity(count);
+        let mut payload_offset = 0;
+        while let Some((k, v)) = Reader::read_by_mmap(payload, &mut payload_offset)? {
+            // 日志中只会出现内联value
+            batch.push((
+                k,
+                v.map(|v| match v {
+                    Value::Inline(s) => s,
+                    Value::Blob(_) => unreachable!(),
+                }),
+            ));
+        }
+        if batch.len() != count {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "log batch record count mismatch",
+            ));
         }
-        return Ok(map);
+        return Ok(batch);
+    }
+
+    /*
+     * 所有已归档的日志（不含cache.log），按归档时间排序
+     */
+    pub fn saved_logs(&self) -> io::Result<Vec<String>> {
+        return Ok(self
+            .env
+            .list_dir(&self.log_base_path)?
+            .into_iter()
+            .filter(|p| p != &self.cache_file_path && p.ends_with(".log"))
+            .collect());
+    }
+
+    pub fn remove(&self, path: &str) -> io::Result<()> {
+        return self.env.remove_file(path);
     }
 
+    pub fn reset_cache_file(&mut self) -> io::Result<()> {
+        self.cache_file = self.env.create(&self.cache_file_path)?;
+        return Ok(());
+    }
+
+    /*
+     * 归档前再fsync一次：之前fsync失败的写入也可能留在文件中，归档后的日志必须是完整的
+     */
     pub fn save_cache_file(&mut self) -> io::Result<String> {
-        let saved_log_path: String = format!("{}.log", Utc::now().timestamp_nanos());
-        fs::rename(&self.cache_file_path, &saved_log_path)?;
-        self.cache_file = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .append(true)
-            .open(&self.cache_file_path)?;
+        self.cache_file.sync()?;
+        let saved_log_path = self.saved_log_path();
+        self.env.rename(&self.cache_file_path, &saved_log_path)?;
+        self.cache_file = self.env.append(&self.cache_file_path)?;
         return Ok(saved_log_path);
     }
+
+    fn saved_log_path(&self) -> String {
+        return format!(
+            "{}/{}.log",
+            self.log_base_path,
+            Utc::now().timestamp_nanos_opt().unwrap()
+        );
+    }
+}
+
+/*
+ * CRC-32（IEEE），用于检测日志批次是否被截断或损坏
+ */
+fn crc32(buf: &[u8]) -> u32 {
+    let mut crc: u32 = 0xffffffff;
+    for b in buf {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb88320 & mask);
+        }
+    }
+    return !crc;
 }
@@ -1,27 +1,154 @@
-use std::{io, mem::size_of_val, thread};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+    io,
+    mem::size_of_val,
+    sync::{Arc, Mutex, RwLock},
+    thread,
+};
 
-use crate::{log::Log, memtable::MemTable, sstable::SSTable};
+use rb_tree::RBMap;
+
+use crate::{
+    blob::{BlobStore, Value, BLOB_GC_LIVE_RATIO, BLOB_THRESHOLD},
+    env::{DiskEnv, Env},
+    filter::PrefixExtractor,
+    log::Log,
+    memtable::MemTable,
+    sstable::{Entries, SSTable},
+    transaction::Tracker,
+};
 
 pub struct Lsm {
     mem_table: MemTable,
     log: Log,
     sstable: SSTable,
+    pub(crate) tracker: Tracker,
+    // 后台持久化失败的错误，由下一次写入返回给调用方
+    flush_error: Arc<Mutex<Option<io::Error>>>,
+}
+
+/*
+ * 打开Lsm时的配置，所有文件操作都通过env完成，测试中可以替换为内存文件系统
+ * blob_threshold：value超过该字节数时单独存入blob文件
+ * blob_gc_live_ratio：blob文件存活比例低于该值时会被垃圾回收
+ * prefix_extractor：配置后sstable和memtable会额外按key前缀建立过滤器和索引，用于加速prefix_scan
+ */
+#[derive(Clone)]
+pub struct LsmOptions {
+    pub env: Arc<dyn Env>,
+    pub mem_table_capicaty: usize,
+    pub level: usize,
+    pub level_capicatiy: usize,
+    pub blob_threshold: usize,
+    pub blob_gc_live_ratio: f64,
+    pub prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+}
+
+impl LsmOptions {
+    pub fn new(mem_table_capicaty: usize, level: usize, level_capicatiy: usize) -> Self {
+        return LsmOptions {
+            env: Arc::new(DiskEnv),
+            mem_table_capicaty,
+            level,
+            level_capicatiy,
+            blob_threshold: BLOB_THRESHOLD,
+            blob_gc_live_ratio: BLOB_GC_LIVE_RATIO,
+            prefix_extractor: None,
+        };
+    }
 }
 
 impl Lsm {
     pub fn new(path: &str, mem_table_capicaty: usize, level: usize, level_capicatiy: usize) -> Lsm {
-        return Lsm {
-            mem_table: MemTable::new(mem_table_capicaty),
-            log: Log::new(path),
-            sstable: SSTable::new(path, level, level_capicatiy),
+        return Self::new_with_options(
+            path,
+            LsmOptions::new(mem_table_capicaty, level, level_capicatiy),
+        );
+    }
+
+    pub fn new_with_blob(
+        path: &str,
+        mem_table_capicaty: usize,
+        level: usize,
+        level_capicatiy: usize,
+        blob_threshold: usize,
+        blob_gc_live_ratio: f64,
+    ) -> Lsm {
+        return Self::new_with_options(
+            path,
+            LsmOptions {
+                blob_threshold,
+                blob_gc_live_ratio,
+                ..LsmOptions::new(mem_table_capicaty, level, level_capicatiy)
+            },
+        );
+    }
+
+    pub fn new_with_options(path: &str, options: LsmOptions) -> Lsm {
+        let env = options.env;
+        let blob = Arc::new(RwLock::new(BlobStore::new(
+            env.clone(),
+            path,
+            options.blob_threshold,
+            options.blob_gc_live_ratio,
+        )));
+        let mut lsm = Lsm {
+            mem_table: MemTable::new(options.mem_table_capicaty, options.prefix_extractor.clone()),
+            log: Log::new(env.clone(), path),
+            sstable: SSTable::new(
+                env,
+                path,
+                options.level,
+                options.level_capicatiy,
+                blob,
+                options.prefix_extractor,
+            ),
+            tracker: Tracker::new(),
+            flush_error: Arc::new(Mutex::new(None)),
         };
+        lsm.recover().unwrap();
+        return lsm;
+    }
+
+    /*
+     * 重启恢复：已持久化到sstable的日志直接删除，其余日志按顺序回放为immut table
+     */
+    fn recover(&mut self) -> io::Result<()> {
+        for saved_log_path in self.log.saved_logs()? {
+            let mut index = self.sstable.index.write().unwrap();
+            if index.is_flushed(&saved_log_path) {
+                if self.log.remove(&saved_log_path).is_ok() {
+                    index.forget_flushed(&saved_log_path);
+                }
+            } else {
+                drop(index);
+                let table = self.log.build_map(&saved_log_path)?;
+                self.mem_table
+                    .immut_tables
+                    .write()
+                    .unwrap()
+                    .push((saved_log_path, table));
+            }
+        }
+        // cache.log尾部可能有断电截断的不完整批次，回放后归档有效部分，不再继续追加
+        if let Some((saved_log_path, table)) = self.log.recover_cache_file()? {
+            self.mem_table
+                .immut_tables
+                .write()
+                .unwrap()
+                .push((saved_log_path, table));
+        }
+        return Ok(());
     }
 
     pub fn insert(&mut self, key: &String, val: &String) -> io::Result<()> {
+        self.take_flush_error()?;
         //FIXME 写日志成功但是写缓存失败，如果确保在此刻重启后日志里的数据无效？(无解)
         self.log.append(key, Some(val))?;
         self.mem_table
             .insert(key.to_string(), Some(val.to_string()));
+        self.tracker.record(key);
         return self.check_capacity();
     }
 
@@ -34,9 +161,115 @@ impl Lsm {
         }
     }
 
+    /*
+     * 返回[start, end)范围内最多limit条数据，按key有序
+     * 各个memtable和sstable按key归并，取到limit条后停止，不会读取整个范围
+     */
+    pub fn scan(
+        &self,
+        start: &String,
+        end: &String,
+        limit: usize,
+    ) -> io::Result<Vec<(String, String)>> {
+        let tables = self.mem_table.immut_tables.read().unwrap();
+        // 从新到旧：当前memtable、immut table、sstable
+        let mut sources: Vec<Entries> = vec![MemTable::range(&self.mem_table.table, start, end)];
+        for table in tables.iter().rev().map(|p| &p.1) {
+            sources.push(MemTable::range(table, start, end));
+        }
+        sources.append(&mut self.sstable.scan(start, end)?);
+
+        let mut heap: BinaryHeap<(Reverse<String>, Reverse<usize>, Option<Value>)> =
+            BinaryHeap::new();
+        for i in 0..sources.len() {
+            Self::advance(&mut sources, i, &mut heap)?;
+        }
+        let mut res: Vec<(String, String)> = vec![];
+        while res.len() < limit {
+            let (Reverse(key), Reverse(i), val) = match heap.pop() {
+                Some(entry) => entry,
+                None => break,
+            };
+            // 同一个key只保留最新的一条，较旧的记录直接跳过
+            while let Some((Reverse(k), Reverse(j), _)) = heap.peek() {
+                if k != &key {
+                    break;
+                }
+                let j = *j;
+                heap.pop();
+                Self::advance(&mut sources, j, &mut heap)?;
+            }
+            Self::advance(&mut sources, i, &mut heap)?;
+            match val {
+                Some(Value::Inline(v)) => res.push((key, v)),
+                Some(Value::Blob(pointer)) => {
+                    let v = self.sstable.blob.read().unwrap().read(&pointer)?;
+                    res.push((key, v));
+                }
+                None => {}
+            }
+        }
+        return Ok(res);
+    }
+
+    fn advance(
+        sources: &mut [Entries],
+        i: usize,
+        heap: &mut BinaryHeap<(Reverse<String>, Reverse<usize>, Option<Value>)>,
+    ) -> io::Result<()> {
+        if let Some(entry) = sources[i].next() {
+            let (k, v) = entry?;
+            heap.push((Reverse(k), Reverse(i), v));
+        }
+        return Ok(());
+    }
+
+    /*
+     * 返回以prefix开头的最多limit条数据，按key有序
+     * 每个key只保留最新的版本之后才读取blob，被遮蔽的旧指针可能已经被垃圾回收
+     */
+    pub fn prefix_scan(&self, prefix: &str, limit: usize) -> io::Result<Vec<(String, String)>> {
+        let mut entries: BTreeMap<String, Option<Value>> = self.sstable.prefix_scan(prefix)?;
+        let mut mem_entries: BTreeMap<String, Option<String>> = BTreeMap::new();
+        self.mem_table.prefix_scan(prefix, &mut mem_entries);
+        for (k, v) in mem_entries {
+            entries.insert(k, v.map(Value::Inline));
+        }
+        let mut res: Vec<(String, String)> = vec![];
+        for (key, val) in entries {
+            if res.len() >= limit {
+                break;
+            }
+            match val {
+                Some(Value::Inline(v)) => res.push((key, v)),
+                Some(Value::Blob(pointer)) => {
+                    let v = self.sstable.blob.read().unwrap().read(&pointer)?;
+                    res.push((key, v));
+                }
+                None => {}
+            }
+        }
+        return Ok(res);
+    }
+
     pub fn remove(&mut self, key: &String) -> io::Result<()> {
+        self.take_flush_error()?;
         self.log.append(key, None)?;
         self.mem_table.insert(key.to_string(), None);
+        self.tracker.record(key);
+        return self.check_capacity();
+    }
+
+    /*
+     * 整批写入：日志一次性追加，写完全部缓存后再检查容量，保证同一批数据落在同一个memtable中
+     */
+    pub(crate) fn write_batch(&mut self, batch: &RBMap<String, Option<String>>) -> io::Result<()> {
+        self.take_flush_error()?;
+        self.log.append_batch(batch)?;
+        for (k, v) in batch.iter() {
+            self.mem_table.insert(k.to_string(), v.clone());
+            self.tracker.record(k);
+        }
         return self.check_capacity();
     }
 
@@ -44,23 +277,53 @@ impl Lsm {
         if size_of_val(&self.mem_table.table) > self.mem_table.capicaty {
             let saved_log_path: String = self.log.save_cache_file()?;
             self.mem_table.save_table(&saved_log_path);
-            let sstable_path = self.sstable.path.clone();
-            let sstable_level = self.sstable.level.clone();
-            let sstable_level_capacity = self.sstable.level_capacity.clone();
-            let index = self.sstable.index.clone();
+            let sstable = self.sstable.clone();
             let table = self.mem_table.immut_tables.clone();
+            let flush_error = self.flush_error.clone();
             thread::spawn(move || {
-                SSTable::save(
-                    sstable_path,
-                    sstable_level,
-                    sstable_level_capacity,
-                    saved_log_path,
-                    table,
-                    index,
-                )
-                .unwrap();
+                // 失败时immut table和日志都会保留，下次持久化时按顺序重试
+                if let Err(e) = sstable.save(table) {
+                    flush_error.lock().unwrap().get_or_insert(e);
+                }
             });
         }
         return Ok(());
     }
+
+    /*
+     * 返回并清除后台持久化的错误，在写入之前检查，出错时本次写入不生效
+     */
+    fn take_flush_error(&self) -> io::Result<()> {
+        return match self.flush_error.lock().unwrap().take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        };
+    }
+
+    /*
+     * blob垃圾回收：存活比例过低的blob文件中，仍被sstable引用的value重新走一遍写入流程，
+     * 新值会遮蔽旧指针（之后由合并丢弃），因此旧blob文件可以直接删除
+     */
+    pub fn gc_blobs(&mut self) -> io::Result<usize> {
+        self.take_flush_error()?;
+        let candidates = self.sstable.blob.read().unwrap().gc_candidates();
+        for file_id in candidates.iter() {
+            let records = self.sstable.blob.read().unwrap().scan(*file_id)?;
+            for (key, pointer, val) in records {
+                // memtable中已有更新的值
+                if self.mem_table.get(&key).0 {
+                    continue;
+                }
+                if let Some(Value::Blob(p)) = self.sstable.get_entry(&key)? {
+                    if p == pointer {
+                        self.log.append(&key, Some(&val))?;
+                        self.mem_table.insert(key, Some(val));
+                    }
+                }
+            }
+            self.sstable.blob.write().unwrap().remove(*file_id)?;
+        }
+        self.check_capacity()?;
+        return Ok(candidates.len());
+    }
 }
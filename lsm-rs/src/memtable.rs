@@ -1,26 +1,44 @@
 use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
     mem,
     sync::{Arc, RwLock},
 };
 
 use rb_tree::RBMap;
 
+use crate::{
+    blob::Value,
+    filter::PrefixExtractor,
+    sstable::{Entries, ImmutTables},
+};
+
 pub struct MemTable {
     pub table: RBMap<String, Option<String>>,
-    pub immut_tables: Arc<RwLock<Vec<(String, RBMap<String, Option<String>>)>>>,
+    pub immut_tables: ImmutTables,
     pub capicaty: usize,
+    prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    // 前缀 -> 当前table中以该前缀开头的key，immut table存在时间很短，不单独建立索引
+    prefix_index: HashMap<String, BTreeSet<String>>,
 }
 
 impl MemTable {
-    pub fn new(capicaty: usize) -> Self {
+    pub fn new(capicaty: usize, prefix_extractor: Option<Arc<dyn PrefixExtractor>>) -> Self {
         return MemTable {
             table: RBMap::new(),
             immut_tables: Arc::new(RwLock::new(Vec::new())), // 必须为有序结构，保证查询时的最新值
             capicaty,
+            prefix_extractor,
+            prefix_index: HashMap::new(),
         };
     }
 
     pub fn insert(&mut self, key: String, value: Option<String>) {
+        if let Some(prefix) = self.prefix_extractor.as_ref().and_then(|e| e.extract(&key)) {
+            self.prefix_index
+                .entry(prefix.to_string())
+                .or_default()
+                .insert(key.clone());
+        }
         self.table.insert(key, value);
     }
 
@@ -56,5 +74,53 @@ impl MemTable {
             saved_log_path.to_string(),
             mem::replace(&mut self.table, RBMap::new()),
         ));
+        self.prefix_index.clear();
+    }
+
+    /*
+     * table中[start, end)范围内的数据，按key有序
+     */
+    pub fn range<'a>(
+        table: &'a RBMap<String, Option<String>>,
+        start: &'a String,
+        end: &'a String,
+    ) -> Entries<'a> {
+        return Box::new(
+            table
+                .iter()
+                .skip_while(move |(k, _)| *k < start)
+                .take_while(move |(k, _)| *k < end)
+                .map(|(k, v)| Ok((k.to_string(), v.clone().map(Value::Inline)))),
+        );
+    }
+
+    /*
+     * 将以prefix开头的数据按从旧到新的顺序覆盖写入res，前缀在提取范围内时当前table只读取索引中的key
+     */
+    pub fn prefix_scan(&self, prefix: &str, res: &mut BTreeMap<String, Option<String>>) {
+        let tables = self.immut_tables.read().unwrap();
+        for table in tables.iter().map(|p| &p.1) {
+            for (k, v) in table.iter() {
+                if k.starts_with(prefix) {
+                    res.insert(k.to_string(), v.clone());
+                }
+            }
+        }
+        match &self.prefix_extractor {
+            Some(extractor) if extractor.in_domain(prefix) => {
+                if let Some(keys) = self.prefix_index.get(prefix) {
+                    for k in keys {
+                        res.insert(k.to_string(), self.table.get(k).cloned().flatten());
+                    }
+                }
+            }
+            _ => {
+                for (k, v) in self.table.iter() {
+                    if k.starts_with(prefix) {
+                        res.insert(k.to_string(), v.clone());
+                    }
+                }
+            }
+        }
     }
 }
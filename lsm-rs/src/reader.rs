@@ -1,52 +1,60 @@
 use std::{
-    fs::File,
+    convert::TryInto,
     io::{self, Error, ErrorKind, Read},
-    path::Path,
 };
 
-use memmap::{Mmap, MmapOptions};
+use crate::{
+    blob::{BlobPointer, Value},
+    env::Env,
+    writer::{BLOB_FLAG, DELETE_FLAG},
+};
 
 pub struct Reader;
 
 impl Reader {
-    pub fn search_by_key(path: &Path, key: &String) -> io::Result<Option<String>> {
-        let buf: Mmap = unsafe { MmapOptions::new().map(&File::open(path)?)? };
+    /*
+     * 返回文件中key对应的记录，外层None表示文件中不存在该key，内层None表示已删除
+     */
+    pub fn search_by_key(
+        env: &dyn Env,
+        path: &str,
+        key: &String,
+    ) -> io::Result<Option<Option<Value>>> {
+        let buf = env.map(path)?;
         let mut offset = 0;
         while let Some((k, v)) = Self::read_by_mmap(&buf, &mut offset)? {
             if &k == key {
-                return Ok(v);
+                return Ok(Some(v));
             }
         }
         return Ok(None);
     }
 
+    /*
+     * 记录不完整（断电导致的尾部截断）时返回UnexpectedEof
+     */
     pub fn read_by_mmap(
-        buf: &Mmap,
+        buf: &[u8],
         offset: &mut usize,
-    ) -> Result<Option<(String, Option<String>)>, Error> {
+    ) -> Result<Option<(String, Option<Value>)>, Error> {
         if *offset < buf.len() {
-            let is_delete = buf[*offset] == 1_u8;
-            *offset += 1;
-            let kv: (String, Option<String>);
-            if is_delete {
-                let key_size = buf[*offset];
-                *offset += 1;
-                let start = *offset;
-                *offset += key_size as usize;
-                let key = String::from_utf8(buf[start..*offset].to_vec()).unwrap();
+            let flag = Self::take(buf, offset, 1)?[0];
+            let kv: (String, Option<Value>);
+            if flag == DELETE_FLAG {
+                let key_size = Self::take_size(buf, offset)?;
+                let key = Self::to_string(Self::take(buf, offset, key_size)?)?;
                 kv = (key, None);
+            } else if flag == BLOB_FLAG {
+                let key_size = Self::take_size(buf, offset)?;
+                let key = Self::to_string(Self::take(buf, offset, key_size)?)?;
+                let pointer = Self::parse_pointer(Self::take(buf, offset, 20)?);
+                kv = (key, Some(Value::Blob(pointer)));
             } else {
-                let key_size = buf[*offset] as usize;
-                *offset += 1;
-                let val_size = buf[*offset] as usize;
-                *offset += 1;
-                let start = *offset;
-                *offset += key_size;
-                let key = String::from_utf8(buf[start..*offset].to_vec()).unwrap();
-                let start = *offset;
-                *offset += val_size;
-                let val = String::from_utf8(buf[start..*offset].to_vec()).unwrap();
-                kv = (key, Some(val));
+                let key_size = Self::take_size(buf, offset)?;
+                let val_size = Self::take_size(buf, offset)?;
+                let key = Self::to_string(Self::take(buf, offset, key_size)?)?;
+                let val = Self::to_string(Self::take(buf, offset, val_size)?)?;
+                kv = (key, Some(Value::Inline(val)));
             }
             return Ok(Some(kv));
         } else {
@@ -54,42 +62,86 @@ impl Reader {
         }
     }
 
-    pub fn read_by_seek(reader: &mut dyn Read) -> Result<Option<(String, Option<String>)>, Error> {
-        let mut buf: Vec<u8> = vec![0; 1];
-        match reader.read_exact(&mut buf) {
-            Ok(_) => {
-                let is_delete = buf[0] == 1_u8;
-                if is_delete {
-                    buf = vec![0; 1];
-                    reader.read_exact(&mut buf).unwrap();
-                    let key_size = buf[0];
-
-                    buf = vec![0; key_size as usize];
-                    reader.read_exact(&mut buf).unwrap();
-                    let key = String::from_utf8(buf.clone()).unwrap();
+    /*
+     * blob文件记录：key_size(u32) value_size(u32) key value
+     */
+    pub fn read_blob_record(buf: &[u8], offset: &mut usize) -> io::Result<(String, String)> {
+        let key_size = Self::take_size(buf, offset)?;
+        let val_size = Self::take_size(buf, offset)?;
+        let key = Self::to_string(Self::take(buf, offset, key_size)?)?;
+        let val = Self::to_string(Self::take(buf, offset, val_size)?)?;
+        return Ok((key, val));
+    }
 
-                    return Ok(Some((key, None)));
-                } else {
-                    buf = vec![0; 2];
-                    reader.read_exact(&mut buf).unwrap();
-                    let key_size = buf[0];
-                    let value_size = buf[1];
+    pub fn take<'a>(buf: &'a [u8], offset: &mut usize, size: usize) -> io::Result<&'a [u8]> {
+        if *offset + size > buf.len() {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "incomplete record"));
+        }
+        let start = *offset;
+        *offset += size;
+        return Ok(&buf[start..*offset]);
+    }
 
-                    buf = vec![0; key_size as usize];
-                    reader.read_exact(&mut buf).unwrap();
-                    let key = String::from_utf8(buf.clone()).unwrap();
+    // u32长度字段
+    pub fn take_size(buf: &[u8], offset: &mut usize) -> io::Result<usize> {
+        return Ok(u32::from_le_bytes(Self::take(buf, offset, 4)?.try_into().unwrap()) as usize);
+    }
 
-                    buf = vec![0; value_size as usize];
-                    reader.read_exact(&mut buf).unwrap();
-                    let val = String::from_utf8(buf.clone()).unwrap();
+    pub fn to_string(buf: &[u8]) -> io::Result<String> {
+        return String::from_utf8(buf.to_vec()).map_err(|e| Error::new(ErrorKind::InvalidData, e));
+    }
 
-                    return Ok(Some((key, Some(val))));
-                }
-            }
-            Err(error) => match error.kind() {
-                ErrorKind::UnexpectedEof => return Ok(None),
-                _ => panic!("read file error: {}", error),
-            },
+    /*
+     * 文件在记录边界结束时返回None，记录不完整或者内容无效时返回错误
+     */
+    pub fn read_by_seek(reader: &mut dyn Read) -> Result<Option<(String, Option<Value>)>, Error> {
+        let mut flag = [0; 1];
+        match reader.read_exact(&mut flag) {
+            Ok(_) => {}
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(error) => return Err(error),
         }
+        if flag[0] == DELETE_FLAG {
+            let key_size = Self::read_size(reader)?;
+            let key = Self::to_string(&Self::read_exact(reader, key_size)?)?;
+            return Ok(Some((key, None)));
+        } else if flag[0] == BLOB_FLAG {
+            let key_size = Self::read_size(reader)?;
+            let key = Self::to_string(&Self::read_exact(reader, key_size)?)?;
+            let pointer = Self::parse_pointer(&Self::read_exact(reader, 20)?);
+            return Ok(Some((key, Some(Value::Blob(pointer)))));
+        } else {
+            let key_size = Self::read_size(reader)?;
+            let val_size = Self::read_size(reader)?;
+            let key = Self::to_string(&Self::read_exact(reader, key_size)?)?;
+            let val = Self::to_string(&Self::read_exact(reader, val_size)?)?;
+            return Ok(Some((key, Some(Value::Inline(val)))));
+        }
+    }
+
+    // 记录中间遇到文件结尾说明记录不完整
+    fn read_exact(reader: &mut dyn Read, size: usize) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0; size];
+        reader.read_exact(&mut buf).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => Error::new(ErrorKind::UnexpectedEof, "incomplete record"),
+            _ => e,
+        })?;
+        return Ok(buf);
+    }
+
+    fn read_size(reader: &mut dyn Read) -> io::Result<usize> {
+        let buf = Self::read_exact(reader, 4)?;
+        return Ok(u32::from_le_bytes(buf.try_into().unwrap()) as usize);
+    }
+
+    /*
+     * 指针格式：file_id(u64) offset(u64) size(u32)
+     */
+    fn parse_pointer(buf: &[u8]) -> BlobPointer {
+        return BlobPointer {
+            file_id: u64::from_le_bytes(buf[0..8].try_into().unwrap()),
+            offset: u64::from_le_bytes(buf[8..16].try_into().unwrap()),
+            size: u32::from_le_bytes(buf[16..20].try_into().unwrap()),
+        };
     }
 }
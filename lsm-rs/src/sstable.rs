@@ -1,127 +1,325 @@
 use std::{
     cmp::Reverse,
-    collections::BinaryHeap,
-    fs::{self, File, OpenOptions},
-    io::{self, BufReader, BufWriter},
-    path::Path,
-    sync::{Arc, RwLock},
+    collections::{BTreeMap, BinaryHeap, HashMap},
+    io::{self, BufReader, BufWriter, Read, Write},
+    ops::Deref,
+    sync::{Arc, Mutex, RwLock},
 };
 
 use chrono::Utc;
 use rb_tree::RBMap;
 
 use crate::{
+    blob::{BlobStore, Value},
+    env::Env,
+    filter::{Filter, FilterBuilder, PrefixExtractor},
     index::{Index, Position},
     reader::Reader,
     writer::Writer,
 };
 
+pub type ImmutTables = Arc<RwLock<Vec<(String, RBMap<String, Option<String>>)>>>;
+
+// 按key有序的记录，删除标记为None
+pub type Entries<'a> = Box<dyn Iterator<Item = io::Result<(String, Option<Value>)>> + 'a>;
+
+/*
+ * 逐条读取sstable文件中[start, end)范围内的记录
+ */
+struct FileEntries {
+    buf: Box<dyn Deref<Target = [u8]>>,
+    offset: usize,
+    start: String,
+    end: String,
+    done: bool,
+}
+
+impl Iterator for FileEntries {
+    type Item = io::Result<(String, Option<Value>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            match Reader::read_by_mmap(&self.buf, &mut self.offset) {
+                Ok(Some((k, _))) if k < self.start => continue,
+                Ok(Some((k, v))) if k < self.end => return Some(Ok((k, v))),
+                Ok(_) => self.done = true,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        return None;
+    }
+}
+
+#[derive(Clone)]
 pub struct SSTable {
     pub path: String,
     pub level: usize,
     pub level_capacity: usize,
     pub index: Arc<RwLock<Index>>,
+    pub blob: Arc<RwLock<BlobStore>>,
+    env: Arc<dyn Env>,
+    prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    // sstable路径 -> 过滤器，首次使用时从过滤器文件加载，文件缺失或损坏时为None（不过滤）
+    filters: Arc<RwLock<HashMap<String, Option<Arc<Filter>>>>>,
+    // 持久化和合并同一时间只允许一个线程执行
+    compaction_lock: Arc<Mutex<()>>,
 }
 
 impl SSTable {
-    pub fn new(base_path: &str, level: usize, level_capacity: usize) -> Self {
+    pub fn new(
+        env: Arc<dyn Env>,
+        base_path: &str,
+        level: usize,
+        level_capacity: usize,
+        blob: Arc<RwLock<BlobStore>>,
+        prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    ) -> Self {
         let path = format!("{}/sstable", base_path);
-        fs::create_dir_all(&path).unwrap();
+        env.create_dir_all(&path).unwrap();
         for i in 0..level {
-            fs::create_dir_all(format!("{}/{}", &path, i)).unwrap()
+            env.create_dir_all(&format!("{}/{}", &path, i)).unwrap()
         }
-        let index = Arc::new(RwLock::new(Index::new(&path, level)));
+        let index = Arc::new(RwLock::new(Index::new(env.clone(), &path, level)));
         return Self {
             path,
             level,
             level_capacity,
             index,
+            blob,
+            env,
+            prefix_extractor,
+            filters: Arc::new(RwLock::new(HashMap::new())),
+            compaction_lock: Arc::new(Mutex::new(())),
         };
     }
 
     pub fn get(&self, key: &String) -> io::Result<Option<String>> {
-        let positions = self.index.read().unwrap().get_hit_path_in_db(key, key);
+        return match self.get_entry(key)? {
+            Some(Value::Inline(v)) => Ok(Some(v)),
+            // 大value透明地从blob文件中读取
+            Some(Value::Blob(pointer)) => self.blob.read().unwrap().read(&pointer).map(Some),
+            None => Ok(None),
+        };
+    }
+
+    /*
+     * 返回sstable中最新的一条记录（不解析blob指针）
+     */
+    pub fn get_entry(&self, key: &String) -> io::Result<Option<Value>> {
+        // 读取期间持有索引的读锁，避免合并完成后删除正在读取的文件
+        let index = self.index.read().unwrap();
+        let positions = index.get_hit_path_in_db(key, key);
         for path in positions.iter().map(|p| &p.path) {
-            let val = Reader::search_by_key(&Path::new(&path), key);
-            if val.is_ok() && val.as_ref().unwrap().is_some() {
-                return val;
+            if let Some(filter) = self.filter(path) {
+                if !filter.may_contain_key(key) {
+                    continue;
+                }
+            }
+            if let Ok(Some(entry)) = Reader::search_by_key(self.env.as_ref(), path, key) {
+                return Ok(entry);
             }
         }
         return Ok(None);
     }
 
-    pub fn save(
-        sstable_path: String,
-        level: usize,
-        level_capacity: usize,
-        saved_log_path: String,
-        immut_tables: Arc<RwLock<Vec<(String, RBMap<String, Option<String>>)>>>,
-        index: Arc<RwLock<Index>>,
-    ) -> io::Result<()> {
-        // minor compaction（持久化immut_tables）
-        let file_path = format!("{}/0/{}.sst", sstable_path, Utc::now().timestamp_nanos());
-        let file = &mut OpenOptions::new()
-            .create(true)
-            .truncate(true)
-            .write(true)
-            .open(&file_path)?;
+    /*
+     * 范围扫描：返回所有相交sstable的有序迭代器，按从新到旧排列，由调用方归并
+     * 文件内容在持有索引读锁时读入，之后合并删除文件也不影响迭代
+     */
+    pub fn scan(&self, start: &String, end: &String) -> io::Result<Vec<Entries<'static>>> {
+        let index = self.index.read().unwrap();
+        let positions = index.get_positions_in_range(start, end);
+        let mut res: Vec<Entries<'static>> = vec![];
+        for path in positions.iter().rev().map(|p| &p.path) {
+            res.push(Box::new(FileEntries {
+                buf: self.env.map(path)?,
+                offset: 0,
+                start: start.to_string(),
+                end: end.to_string(),
+                done: false,
+            }));
+        }
+        return Ok(res);
+    }
+
+    /*
+     * 前缀扫描：前缀过滤器排除的sstable直接跳过，其余同scan
+     */
+    pub fn prefix_scan(&self, prefix: &str) -> io::Result<BTreeMap<String, Option<Value>>> {
+        let index = self.index.read().unwrap();
+        let positions = index.get_positions_with_prefix(prefix);
+        let mut res: BTreeMap<String, Option<Value>> = BTreeMap::new();
+        for path in positions.iter().map(|p| &p.path) {
+            if let (Some(filter), Some(extractor)) = (self.filter(path), &self.prefix_extractor) {
+                if !filter.may_contain_prefix(extractor.as_ref(), prefix) {
+                    continue;
+                }
+            }
+            let buf = self.env.map(path)?;
+            let mut offset = 0;
+            while let Some((k, v)) = Reader::read_by_mmap(&buf, &mut offset)? {
+                if k.as_str() < prefix {
+                    continue;
+                } else if !k.starts_with(prefix) {
+                    break;
+                }
+                // 文件从旧到新排列，较新的记录覆盖旧的；blob指针由调用方在去重之后再读取
+                res.insert(k, v);
+            }
+        }
+        return Ok(res);
+    }
+
+    pub fn filter(&self, path: &String) -> Option<Arc<Filter>> {
+        if let Some(filter) = self.filters.read().unwrap().get(path) {
+            return filter.clone();
+        }
+        let filter = self
+            .env
+            .map(&Self::filter_path(path))
+            .and_then(|buf| Filter::decode(&buf))
+            .ok()
+            .map(Arc::new);
+        self.filters
+            .write()
+            .unwrap()
+            .insert(path.to_string(), filter.clone());
+        return filter;
+    }
+
+    /*
+     * 过滤器文件必须在sstable写入索引之前持久化
+     */
+    fn write_filter(&self, path: &String, filter: Filter) -> io::Result<()> {
+        let mut file = self.env.create(&Self::filter_path(path))?;
+        file.write_all(&filter.encode())?;
+        file.sync()?;
+        self.filters
+            .write()
+            .unwrap()
+            .insert(path.to_string(), Some(Arc::new(filter)));
+        return Ok(());
+    }
+
+    fn remove_filter(&self, path: &String) {
+        self.filters.write().unwrap().remove(path);
+        self.env.remove_file(&Self::filter_path(path)).unwrap_or(());
+    }
+
+    fn filter_path(path: &String) -> String {
+        return format!("{}.filter", path);
+    }
+
+    /*
+     * 按顺序持久化所有immut table，前一个失败时不再持久化后面的，
+     * 保证已持久化的日志总是最早的一段，重启回放剩余日志时不会覆盖更新的数据
+     */
+    pub fn save(&self, immut_tables: ImmutTables) -> io::Result<()> {
+        let _guard = self.compaction_lock.lock().unwrap();
+        loop {
+            let saved_log_path = match immut_tables.read().unwrap().first() {
+                Some(pair) => pair.0.clone(),
+                None => break,
+            };
+            self.flush(&saved_log_path, &immut_tables)?;
+        }
+
+        // major compcation（校验每层文件并合并）
+        return self.compaction();
+    }
+
+    /*
+     * minor compaction（持久化immut_tables）
+     * 顺序：写入并fsync sstable -> 记录索引 -> 移除内存表 -> 删除日志，任意一步崩溃都不会丢数据
+     */
+    fn flush(&self, saved_log_path: &String, immut_tables: &ImmutTables) -> io::Result<()> {
+        let file_path = format!(
+            "{}/0/{}.sst",
+            self.path,
+            Utc::now().timestamp_nanos_opt().unwrap()
+        );
+        let mut buf: Vec<u8> = Vec::new();
         let mut start_key: Option<String> = None;
         let mut end_key: Option<String> = None;
         let mut start_tmp: Option<&String> = None;
         let mut end_tmp: Option<&String> = None;
+        let mut filter_builder = FilterBuilder::new(self.prefix_extractor.clone());
+        let (threshold, mut blob_writer) = {
+            let locked_blob = self.blob.read().unwrap();
+            (locked_blob.threshold, locked_blob.writer())
+        };
         if let Some(table) = immut_tables
             .read()
             .unwrap()
             .iter()
-            .filter(|pair| pair.0 == saved_log_path)
+            .filter(|pair| &pair.0 == saved_log_path)
             .map(|pair| &pair.1)
             .next()
         {
             for (k, v) in table.iter() {
-                Writer::write_by_seek(file, k, v.as_ref())?;
-                if start_key.is_none() {
+                match v {
+                    // 超过阈值的value写入blob文件，sstable中只保留指针
+                    Some(val) if val.len() > threshold => {
+                        let pointer = blob_writer.append(k, val)?;
+                        Writer::write_pointer(&mut buf, k, &pointer)?;
+                    }
+                    _ => Writer::write_by_seek(&mut buf, k, v.as_ref())?,
+                }
+                filter_builder.add(k);
+                if start_tmp.is_none() {
                     start_tmp.replace(k);
                 }
                 end_tmp.replace(k);
             }
-            start_key.replace(start_tmp.unwrap().clone());
-            end_key.replace(end_tmp.unwrap().clone());
+            if let (Some(start), Some(end)) = (start_tmp, end_tmp) {
+                start_key.replace(start.clone());
+                end_key.replace(end.clone());
+            }
+        }
+        if let Some((file_id, total)) = blob_writer.finish()? {
+            self.blob.write().unwrap().add(file_id, total)?;
+        }
+        if let (Some(start_key), Some(end_key)) = (start_key, end_key) {
+            let mut file = self.env.create(&file_path)?;
+            file.write_all(&buf)?;
+            file.sync()?;
+            self.write_filter(&file_path, filter_builder.finish())?;
+            self.index
+                .write()
+                .unwrap()
+                .add(0, &file_path, &start_key, &end_key, saved_log_path)?;
         }
         if let Ok(mut tables) = immut_tables.write() {
             let index = tables
                 .iter()
-                .position(|pair| pair.0 == saved_log_path)
+                .position(|pair| &pair.0 == saved_log_path)
                 .unwrap();
             tables.remove(index);
         }
-        fs::remove_file(saved_log_path)?;
-        if start_key.is_some() && end_key.is_some() {
-            index
-                .write()
-                .unwrap()
-                .add(0, &file_path, &start_key.unwrap(), &end_key.unwrap())?;
+        // 索引中已记录该日志已持久化，删除失败时重启后会再次删除
+        if self.env.remove_file(saved_log_path).is_ok() {
+            self.index.write().unwrap().forget_flushed(saved_log_path);
         }
-
-        // major compcation（校验每层文件并合并）
-        return Self::compaction(&sstable_path, &level, &level_capacity, index);
+        return Ok(());
     }
 
-    fn compaction(
-        sstable_path: &String,
-        level: &usize,
-        level_capacity: &usize,
-        index: Arc<RwLock<Index>>,
-    ) -> io::Result<()> {
-        for i in 0..*level {
-            if fs::read_dir(format!("{}/{}", sstable_path, i))?.count() > *level_capacity {
-                let mut write_index = index.write().unwrap();
+    fn compaction(&self) -> io::Result<()> {
+        for i in 0..self.level - 1 {
+            if self.index.read().unwrap().count_in_level(i) > self.level_capacity {
+                let mut write_index = self.index.write().unwrap();
                 if let Some(position) = write_index.get_random_position_in_level(i) {
                     let merge_level = i + 1;
                     let mut positions: Vec<Arc<Position>> =
                         write_index.get_hit_positions_in_level(merge_level, position.clone());
                     drop(write_index);
                     positions.insert(0, position);
-                    Self::merge(sstable_path, merge_level, positions, index.clone())?;
+                    if let Err(e) = self.merge(merge_level, &positions) {
+                        self.index.write().unwrap().release(&positions);
+                        return Err(e);
+                    }
                 }
             } else if i == 0 {
                 break;
@@ -130,55 +328,36 @@ impl SSTable {
         return Ok(());
     }
 
-    fn merge(
-        path: &String,
-        merge_level: usize,
-        positions: Vec<Arc<Position>>,
-        index: Arc<RwLock<Index>>,
-    ) -> io::Result<()> {
+    fn merge(&self, merge_level: usize, positions: &Vec<Arc<Position>>) -> io::Result<()> {
         if positions.len() == 1 {
-            let new_path = format!(
-                "{}/{}/{}.sst",
-                path,
-                merge_level,
-                Utc::now().timestamp_nanos()
-            );
+            // 单文件合并只修改索引中的层级，文件路径不变
             let position = &positions[0];
-            if let Ok(mut locked_index) = index.write() {
-                // 单文件合并直接修改文件名
-                fs::rename(&position.path, &new_path)?;
-                locked_index.add(
-                    merge_level,
-                    &new_path,
-                    &position.start_key,
-                    &position.end_key,
-                )?;
-                locked_index.clear(&positions)?;
-            }
+            self.index.write().unwrap().replace(
+                merge_level,
+                &position.path,
+                &position.start_key,
+                &position.end_key,
+                positions,
+            )?;
         } else {
-            let mut readers: Vec<BufReader<File>> = positions
-                .iter()
-                .map(|p| BufReader::new(OpenOptions::new().read(true).open(&p.path).unwrap()))
-                .collect();
+            let mut readers: Vec<BufReader<Box<dyn Read + Send>>> = vec![];
+            for p in positions.iter() {
+                readers.push(BufReader::new(self.env.open(&p.path)?));
+            }
             let tmp_file_path = format!(
                 "{}/{}/{}.tmp",
-                path,
+                self.path,
                 merge_level,
-                Utc::now().timestamp_nanos()
-            );
-            let mut writer = BufWriter::new(
-                OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .truncate(true)
-                    .open(&tmp_file_path)?,
+                Utc::now().timestamp_nanos_opt().unwrap()
             );
+            let mut writer = BufWriter::new(self.env.create(&tmp_file_path)?);
+            let mut filter_builder = FilterBuilder::new(self.prefix_extractor.clone());
 
             let mut start_key: Option<String> = None;
             let mut end_key: Option<String> = None;
 
             // 归并所有文件（文件已按层级从小到大排列）
-            let mut heap: BinaryHeap<(Reverse<String>, Reverse<usize>, Option<String>)> =
+            let mut heap: BinaryHeap<(Reverse<String>, Reverse<usize>, Option<Value>)> =
                 BinaryHeap::new();
             for (i, reader) in readers.iter_mut().enumerate() {
                 if let Some((k, v)) = Reader::read_by_seek(reader)? {
@@ -186,7 +365,7 @@ impl SSTable {
                 }
             }
             // 每个文件取一条数据，保证每个文件的第一条数据以key和按照文件层级顺序排列
-            let mut pre: Option<(Reverse<String>, Reverse<usize>, Option<String>)> = None;
+            let mut pre: Option<(Reverse<String>, Reverse<usize>, Option<Value>)> = None;
             while !heap.is_empty() {
                 let tmp = heap.pop().unwrap();
                 if let Some((k, v)) = Reader::read_by_seek(&mut readers[tmp.1 .0])? {
@@ -197,25 +376,32 @@ impl SSTable {
                         start_key.replace((tmp.0).0.clone());
                     }
                     end_key.replace((tmp.0).0.clone());
-                    Writer::write_by_seek(&mut writer, &((tmp.0).0), tmp.2.as_ref())?;
+                    Writer::write_value(&mut writer, &((tmp.0).0), tmp.2.as_ref())?;
+                    filter_builder.add(&(tmp.0).0);
                     pre = Some(tmp);
+                } else if let Some(Value::Blob(pointer)) = &tmp.2 {
+                    // 被覆盖的旧指针不再引用blob中的value，只记录失效大小，不重写value
+                    self.blob.write().unwrap().discard(pointer)?;
                 }
             }
+            writer.flush()?;
+            writer.get_mut().sync()?;
+            drop(writer);
 
             let new_file_path = tmp_file_path.replace(".tmp", ".sst");
-            fs::rename(&tmp_file_path, &new_file_path)?;
-
-            if let Ok(mut locked_index) = index.write() {
-                locked_index.add(
-                    merge_level,
-                    &new_file_path,
-                    &start_key.unwrap(),
-                    &end_key.unwrap(),
-                )?;
-                locked_index.clear(&positions)?;
-            }
+            self.write_filter(&new_file_path, filter_builder.finish())?;
+            self.env.rename(&tmp_file_path, &new_file_path)?;
+
+            self.index.write().unwrap().replace(
+                merge_level,
+                &new_file_path,
+                &start_key.unwrap(),
+                &end_key.unwrap(),
+                positions,
+            )?;
             for position in positions.iter() {
-                fs::remove_file(&position.path)?;
+                self.env.remove_file(&position.path).unwrap_or(());
+                self.remove_filter(&position.path);
             }
         }
         return Ok(());
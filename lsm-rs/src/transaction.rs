@@ -0,0 +1,191 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    fmt::{self, Display},
+    io,
+    sync::{Arc, Mutex},
+};
+
+use rb_tree::RBMap;
+
+use crate::lsm::Lsm;
+
+/*
+ * 乐观事务：begin时记录快照序号，读写都缓存在事务内部，
+ * commit时检查读写过的key在快照之后是否被修改过，有冲突则整体失败
+ */
+pub struct Transaction {
+    snapshot: Snapshot,
+    reads: HashSet<String>,
+    writes: RBMap<String, Option<String>>,
+}
+
+/*
+ * 活跃事务持有的快照，事务提交、回滚或者直接被丢弃时释放
+ */
+struct Snapshot {
+    seq: u64,
+    tracker: Tracker,
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        self.tracker.release(self.seq);
+    }
+}
+
+#[derive(Debug)]
+pub struct Conflict {
+    pub key: String,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "transaction conflict on key: {}", self.key);
+    }
+}
+
+impl Error for Conflict {}
+
+impl Conflict {
+    pub fn is_conflict(error: &io::Error) -> bool {
+        return error.get_ref().map(|e| e.is::<Conflict>()).unwrap_or(false);
+    }
+}
+
+impl Transaction {
+    fn new(snapshot: Snapshot) -> Self {
+        return Transaction {
+            snapshot,
+            reads: HashSet::new(),
+            writes: RBMap::new(),
+        };
+    }
+
+    pub fn get(&mut self, lsm: &Lsm, key: &String) -> io::Result<Option<String>> {
+        // 优先读取事务内未提交的写入
+        if let Some(v) = self.writes.get(key) {
+            return Ok(v.clone());
+        }
+        self.reads.insert(key.to_string());
+        return lsm.get(key);
+    }
+
+    pub fn insert(&mut self, key: &String, val: &String) {
+        self.writes.insert(key.to_string(), Some(val.to_string()));
+    }
+
+    pub fn remove(&mut self, key: &String) {
+        self.writes.insert(key.to_string(), None);
+    }
+}
+
+/*
+ * 记录活跃事务的快照以及快照之后每个key最后一次修改的序号，用于提交时的冲突检测
+ * 事务中的快照也持有同一份状态，以便在事务被丢弃时释放
+ */
+#[derive(Clone)]
+pub struct Tracker {
+    state: Arc<Mutex<TrackerState>>,
+}
+
+struct TrackerState {
+    seq: u64,
+    snapshots: BTreeMap<u64, usize>,
+    key_seqs: HashMap<String, u64>,
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        return Tracker {
+            state: Arc::new(Mutex::new(TrackerState {
+                seq: 0,
+                snapshots: BTreeMap::new(),
+                key_seqs: HashMap::new(),
+            })),
+        };
+    }
+
+    pub fn begin(&self) -> Transaction {
+        let mut state = self.state.lock().unwrap();
+        let seq = state.seq;
+        *state.snapshots.entry(seq).or_insert(0) += 1;
+        return Transaction::new(Snapshot {
+            seq,
+            tracker: self.clone(),
+        });
+    }
+
+    pub fn record(&self, key: &String) {
+        let mut state = self.state.lock().unwrap();
+        state.seq += 1;
+        // 没有活跃事务时不会有人关心这次修改
+        if !state.snapshots.is_empty() {
+            let seq = state.seq;
+            state.key_seqs.insert(key.to_string(), seq);
+        }
+    }
+
+    pub fn check(&self, txn: &Transaction) -> io::Result<()> {
+        let state = self.state.lock().unwrap();
+        for key in txn.reads.iter().chain(txn.writes.keys()) {
+            if let Some(seq) = state.key_seqs.get(key) {
+                if *seq > txn.snapshot.seq {
+                    return Err(io::Error::other(Conflict {
+                        key: key.to_string(),
+                    }));
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    fn release(&self, snapshot: u64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(count) = state.snapshots.get_mut(&snapshot) {
+            *count -= 1;
+            if *count == 0 {
+                state.snapshots.remove(&snapshot);
+            }
+        }
+        // 清理不可能再和任何活跃事务冲突的记录
+        match state.snapshots.keys().next() {
+            Some(oldest) => {
+                let oldest = *oldest;
+                state.key_seqs.retain(|_, seq| *seq > oldest);
+            }
+            None => state.key_seqs.clear(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn tracked_keys(&self) -> usize {
+        return self.state.lock().unwrap().key_seqs.len();
+    }
+}
+
+impl Lsm {
+    pub fn begin(&mut self) -> Transaction {
+        return self.tracker.begin();
+    }
+
+    pub fn commit(&mut self, txn: Transaction) -> io::Result<()> {
+        let checked = self.tracker.check(&txn);
+        let Transaction {
+            snapshot, writes, ..
+        } = txn;
+        drop(snapshot);
+        checked?;
+        if writes.is_empty() {
+            return Ok(());
+        }
+        return self.write_batch(&writes);
+    }
+
+    /*
+     * 释放事务的快照，直接丢弃事务也有同样的效果
+     */
+    pub fn rollback(&mut self, txn: Transaction) {
+        drop(txn);
+    }
+}
@@ -1,6 +1,18 @@
 use std::io;
 use std::io::Write;
 
+use crate::blob::{BlobPointer, Value};
+
+pub const PUT_FLAG: u8 = 0;
+pub const DELETE_FLAG: u8 = 1;
+pub const BLOB_FLAG: u8 = 2;
+
+/*
+ * 记录格式（长度均为u32）：
+ * PUT：flag key_size value_size key value
+ * DELETE：flag key_size key
+ * BLOB：flag key_size key pointer
+ */
 pub struct Writer;
 
 impl Writer {
@@ -10,30 +22,58 @@ impl Writer {
         val: Option<&String>,
     ) -> io::Result<()> {
         let key_bytes = key.as_bytes();
-        if key_bytes.len() > 255 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                format!("key size invalid! key bytes len:{}", key_bytes.len(),),
-            ));
-        }
         if let Some(v) = val {
             let val_bytes = v.as_bytes();
-            if val_bytes.len() > 255 {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    format!("value size invalid! value bytes len:{}", val_bytes.len()),
-                ));
-            }
-            writer.write_all(&(false as u8).to_le_bytes())?;
-            writer.write_all(&(key_bytes.len() as u8).to_le_bytes())?;
-            writer.write_all(&(val_bytes.len() as u8).to_le_bytes())?;
+            writer.write_all(&PUT_FLAG.to_le_bytes())?;
+            writer.write_all(&Self::size("key", key_bytes)?.to_le_bytes())?;
+            writer.write_all(&Self::size("value", val_bytes)?.to_le_bytes())?;
             writer.write_all(key_bytes)?;
             writer.write_all(val_bytes)?;
         } else {
-            writer.write_all(&(true as u8).to_le_bytes())?;
-            writer.write_all(&(key_bytes.len() as u8).to_le_bytes())?;
+            writer.write_all(&DELETE_FLAG.to_le_bytes())?;
+            writer.write_all(&Self::size("key", key_bytes)?.to_le_bytes())?;
             writer.write_all(key_bytes)?;
         }
         return Ok(());
     }
+
+    pub fn write_pointer(
+        writer: &mut dyn Write,
+        key: &String,
+        pointer: &BlobPointer,
+    ) -> io::Result<()> {
+        let key_bytes = key.as_bytes();
+        writer.write_all(&BLOB_FLAG.to_le_bytes())?;
+        writer.write_all(&Self::size("key", key_bytes)?.to_le_bytes())?;
+        writer.write_all(key_bytes)?;
+        writer.write_all(&pointer.file_id.to_le_bytes())?;
+        writer.write_all(&pointer.offset.to_le_bytes())?;
+        writer.write_all(&pointer.size.to_le_bytes())?;
+        return Ok(());
+    }
+
+    pub fn write_value(
+        writer: &mut dyn Write,
+        key: &String,
+        val: Option<&Value>,
+    ) -> io::Result<()> {
+        return match val {
+            Some(Value::Inline(v)) => Self::write_by_seek(writer, key, Some(v)),
+            Some(Value::Blob(pointer)) => Self::write_pointer(writer, key, pointer),
+            None => Self::write_by_seek(writer, key, None),
+        };
+    }
+
+    /*
+     * 长度字段为u32，超出时返回错误
+     */
+    pub fn size(name: &str, bytes: &[u8]) -> io::Result<u32> {
+        if bytes.len() > u32::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} size invalid! {} bytes len:{}", name, name, bytes.len()),
+            ));
+        }
+        return Ok(bytes.len() as u32);
+    }
 }
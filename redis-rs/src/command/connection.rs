@@ -0,0 +1,102 @@
+use crate::{
+    common::{error::Result, utils},
+    server::{
+        resp::{Reply, RESP2, RESP3},
+        server::REDIS_VERSION,
+    },
+};
+
+use super::table::Context;
+
+/*
+ * PING [message]
+ */
+pub fn ping(ctx: &mut Context) -> Result<Reply> {
+    // RESP2的订阅模式下回复数组，与消息的格式一致
+    if ctx.client.subscriptions() > 0 && ctx.client.protocol == RESP2 && ctx.args.len() <= 2 {
+        let message = ctx.args.get(1).cloned().unwrap_or_default();
+        return Ok(Reply::Array(vec![
+            Reply::bulk("pong"),
+            Reply::Bulk(message),
+        ]));
+    }
+    return Ok(match ctx.args.len() {
+        1 => Reply::Status("PONG".to_string()),
+        2 => Reply::bulk(&ctx.args[1]),
+        _ => Reply::error("wrong number of arguments for 'ping' command"),
+    });
+}
+
+pub fn echo(ctx: &mut Context) -> Result<Reply> {
+    return Ok(Reply::bulk(&ctx.args[1]));
+}
+
+pub fn quit(ctx: &mut Context) -> Result<Reply> {
+    ctx.client.close = true;
+    return Ok(Reply::ok());
+}
+
+/*
+ * SELECT index，切换当前连接使用的db
+ */
+pub fn select(ctx: &mut Context) -> Result<Reply> {
+    ctx.client.db = ctx.db_index_arg(1)?;
+    return Ok(Reply::ok());
+}
+
+/*
+ * HELLO [protover [AUTH username password] [SETNAME clientname]]
+ */
+pub fn hello(ctx: &mut Context) -> Result<Reply> {
+    let argc = ctx.args.len();
+    if argc > 1 {
+        match utils::parse_str::<_, u8>(&ctx.arg(1)) {
+            Some(v) if v == RESP2 || v == RESP3 => {
+                ctx.client.protocol = v;
+                // 订阅者的消息由发布者编码
+                if let Some(subscriber) = &ctx.client.subscriber {
+                    subscriber.set_protocol(v);
+                }
+            }
+            Some(_) => {
+                return Ok(Reply::Error(
+                    "NOPROTO unsupported protocol version".to_string(),
+                ))
+            }
+            None => {
+                return Ok(Reply::error(
+                    "Protocol version is not an integer or out of range",
+                ))
+            }
+        }
+    }
+    let mut i = 2;
+    while i < argc {
+        match ctx.arg(i).to_lowercase().as_str() {
+            // 没有配置密码，任意用户名密码都可以通过
+            "auth" if i + 2 < argc => i += 3,
+            "setname" if i + 1 < argc => {
+                ctx.client.name = Some(ctx.arg(i + 1));
+                i += 2;
+            }
+            _ => {
+                return Ok(Reply::error(format!(
+                    "Syntax error in HELLO option '{}'",
+                    ctx.arg(i)
+                )))
+            }
+        }
+    }
+    return Ok(Reply::Map(vec![
+        (Reply::bulk("server"), Reply::bulk("redis")),
+        (Reply::bulk("version"), Reply::bulk(REDIS_VERSION)),
+        (
+            Reply::bulk("proto"),
+            Reply::Integer(ctx.client.protocol as i64),
+        ),
+        (Reply::bulk("id"), Reply::Integer(ctx.client.id as i64)),
+        (Reply::bulk("mode"), Reply::bulk("standalone")),
+        (Reply::bulk("role"), Reply::bulk("master")),
+        (Reply::bulk("modules"), Reply::Array(vec![])),
+    ]));
+}
@@ -0,0 +1,296 @@
+use std::sync::Arc;
+
+use crate::{
+    common::error::{Error, ErrorKind, Result},
+    db::{
+        db::Db,
+        notify::{NOTIFY_GENERIC, NOTIFY_HASH},
+    },
+    server::resp::Reply,
+    types::{
+        hash::{HashEntry, HashObject},
+        object::{Object, ObjectValue},
+        strings,
+    },
+};
+
+use super::{keys::ScanOptions, table::Context};
+
+/*
+ * HSET key field value [field value ...]，返回新增的字段数
+ */
+pub fn hset(ctx: &mut Context) -> Result<Reply> {
+    if !ctx.args.len().is_multiple_of(2) {
+        return Ok(Reply::error("wrong number of arguments for 'hset' command"));
+    }
+    let key = ctx.key(1);
+    let hash = hash_or_create(ctx.db, &key)?;
+    let mut created = 0;
+    for pair in ctx.args[2..].chunks(2) {
+        if hash.set(&pair[0], &pair[1]) {
+            created += 1;
+        }
+    }
+    ctx.db.notify(NOTIFY_HASH, "hset", &key);
+    return Ok(Reply::Integer(created));
+}
+
+pub fn hsetnx(ctx: &mut Context) -> Result<Reply> {
+    let key = ctx.key(1);
+    let hash = hash_or_create(ctx.db, &key)?;
+    if hash.contains(&ctx.args[2]) {
+        return Ok(Reply::Integer(0));
+    }
+    hash.set(&ctx.args[2], &ctx.args[3]);
+    ctx.db.notify(NOTIFY_HASH, "hset", &key);
+    return Ok(Reply::Integer(1));
+}
+
+pub fn hget(ctx: &mut Context) -> Result<Reply> {
+    let value = match lookup_hash(ctx.db, &ctx.key(1))? {
+        Some(hash) => hash.get(&ctx.args[2]),
+        None => None,
+    };
+    return Ok(value.map(Reply::Bulk).unwrap_or(Reply::Null));
+}
+
+pub fn hmget(ctx: &mut Context) -> Result<Reply> {
+    let fields = &ctx.args[2..];
+    let values = match lookup_hash(ctx.db, &ctx.key(1))? {
+        Some(hash) => fields.iter().map(|f| hash.get(f)).collect(),
+        None => vec![None; fields.len()],
+    };
+    return Ok(Reply::Array(
+        values
+            .into_iter()
+            .map(|v| v.map(Reply::Bulk).unwrap_or(Reply::Null))
+            .collect(),
+    ));
+}
+
+/*
+ * HDEL key field [field ...]，返回删除的字段数
+ */
+pub fn hdel(ctx: &mut Context) -> Result<Reply> {
+    let key = ctx.key(1);
+    let (removed, empty) = match lookup_hash_mut(ctx.db, &key)? {
+        Some(hash) => {
+            let removed = ctx.args[2..].iter().filter(|f| hash.remove(f)).count();
+            (removed, hash.is_empty())
+        }
+        None => (0, false),
+    };
+    if removed > 0 {
+        ctx.db.notify(NOTIFY_HASH, "hdel", &key);
+    }
+    // redis中不存在空的哈希
+    if empty {
+        ctx.db.delete(&key)?;
+        ctx.db.notify(NOTIFY_GENERIC, "del", &key);
+    }
+    return Ok(Reply::Integer(removed as i64));
+}
+
+pub fn hexists(ctx: &mut Context) -> Result<Reply> {
+    let exists = match lookup_hash(ctx.db, &ctx.key(1))? {
+        Some(hash) => hash.contains(&ctx.args[2]),
+        None => false,
+    };
+    return Ok(Reply::Integer(exists as i64));
+}
+
+pub fn hlen(ctx: &mut Context) -> Result<Reply> {
+    let len = match lookup_hash(ctx.db, &ctx.key(1))? {
+        Some(hash) => hash.len(),
+        None => 0,
+    };
+    return Ok(Reply::Integer(len as i64));
+}
+
+/*
+ * RESP3下回复map，RESP2下为field value交替的数组
+ */
+pub fn hgetall(ctx: &mut Context) -> Result<Reply> {
+    let entries = entries(ctx.db, &ctx.key(1))?;
+    return Ok(Reply::Map(
+        entries
+            .into_iter()
+            .map(|(f, v)| (Reply::Bulk(f), Reply::Bulk(v)))
+            .collect(),
+    ));
+}
+
+pub fn hkeys(ctx: &mut Context) -> Result<Reply> {
+    let entries = entries(ctx.db, &ctx.key(1))?;
+    return Ok(Reply::Array(
+        entries.into_iter().map(|(f, _)| Reply::Bulk(f)).collect(),
+    ));
+}
+
+pub fn hvals(ctx: &mut Context) -> Result<Reply> {
+    let entries = entries(ctx.db, &ctx.key(1))?;
+    return Ok(Reply::Array(
+        entries.into_iter().map(|(_, v)| Reply::Bulk(v)).collect(),
+    ));
+}
+
+/*
+ * HINCRBY key field increment
+ */
+pub fn hincrby(ctx: &mut Context) -> Result<Reply> {
+    let delta = ctx.integer_arg(3)?;
+    let key = ctx.key(1);
+    let value = hash_or_create(ctx.db, &key)?.incr_by(&ctx.args[2], delta)?;
+    ctx.db.notify(NOTIFY_HASH, "hincrby", &key);
+    return Ok(Reply::Integer(value));
+}
+
+pub fn hincrbyfloat(ctx: &mut Context) -> Result<Reply> {
+    let delta = strings::parse_float(&ctx.args[3]).ok_or_else(|| {
+        return Error::new(ErrorKind::Invalid, "value is not a valid float".to_string());
+    })?;
+    let key = ctx.key(1);
+    let value = hash_or_create(ctx.db, &key)?.incr_by_float(&ctx.args[2], delta)?;
+    ctx.db.notify(NOTIFY_HASH, "hincrbyfloat", &key);
+    let args = ctx.args;
+    ctx.rewrite_args(&[
+        b"HSET".as_ref(),
+        &args[1],
+        &args[2],
+        value.to_string().as_bytes(),
+    ]);
+    return Ok(Reply::bulk(value.to_string()));
+}
+
+/*
+ * HSCAN key cursor [MATCH pattern] [COUNT count]
+ */
+pub fn hscan(ctx: &mut Context) -> Result<Reply> {
+    let options = ScanOptions::parse(ctx, 2)?;
+    let (cursor, entries) = match lookup_hash(ctx.db, &ctx.key(1))? {
+        Some(hash) => hash.scan(options.cursor, options.count),
+        None => (0, vec![]),
+    };
+    let mut items = vec![];
+    for (field, value) in entries {
+        if options.matches(&field) {
+            items.push(Reply::Bulk(field));
+            items.push(Reply::Bulk(value));
+        }
+    }
+    return Ok(ScanOptions::reply(cursor, items));
+}
+
+fn entries(db: &mut Db, key: &[u8]) -> Result<Vec<HashEntry>> {
+    return match lookup_hash(db, key)? {
+        Some(hash) => Ok(hash.entries()),
+        None => Ok(vec![]),
+    };
+}
+
+/*
+ * key不存在时返回None，不是哈希时返回WRONGTYPE
+ */
+fn lookup_hash<'a>(db: &'a mut Db, key: &[u8]) -> Result<Option<&'a HashObject>> {
+    return match db.lookup_key(key)? {
+        Some(obj) => Ok(Some(obj.as_hash()?)),
+        None => Ok(None),
+    };
+}
+
+fn lookup_hash_mut<'a>(db: &'a mut Db, key: &[u8]) -> Result<Option<&'a mut HashObject>> {
+    return match db.lookup_key_mut(key)? {
+        Some(obj) => Ok(Some(obj.as_hash_mut()?)),
+        None => Ok(None),
+    };
+}
+
+fn hash_or_create<'a>(db: &'a mut Db, key: &[u8]) -> Result<&'a mut HashObject> {
+    if db.lookup_key(key)?.is_none() {
+        let obj = Object::new(ObjectValue::Hash(Arc::new(HashObject::new())))?;
+        db.set_object(key, obj, false)?;
+    }
+    return db.lookup_key_mut(key)?.unwrap().as_hash_mut();
+}
+
+#[test]
+fn test_hash_commands() {
+    use crate::server::server::{call, start_test_server};
+    use std::net::TcpStream;
+
+    let mut stream = TcpStream::connect(start_test_server()).unwrap();
+    let mut call = |args: &[&str]| call(&mut stream, args);
+
+    assert_eq!(call(&["HSET", "h", "a", "1", "b", "2"]), Reply::Integer(2));
+    assert_eq!(call(&["HSET", "h", "a", "3", "c", "4"]), Reply::Integer(1));
+    assert_eq!(
+        call(&["HSET", "h", "a", "1", "b"]),
+        Reply::error("wrong number of arguments for 'hset' command")
+    );
+    assert_eq!(call(&["HGET", "h", "a"]), Reply::bulk("3"));
+    assert_eq!(call(&["HGET", "h", "x"]), Reply::Null);
+    assert_eq!(
+        call(&["HMGET", "h", "a", "x"]),
+        Reply::Array(vec![Reply::bulk("3"), Reply::Null])
+    );
+    assert_eq!(call(&["HSETNX", "h", "a", "5"]), Reply::Integer(0));
+    assert_eq!(call(&["HSETNX", "h", "d", "5"]), Reply::Integer(1));
+    assert_eq!(call(&["HLEN", "h"]), Reply::Integer(4));
+    assert_eq!(call(&["HEXISTS", "h", "d"]), Reply::Integer(1));
+    assert_eq!(call(&["HDEL", "h", "d", "x"]), Reply::Integer(1));
+    assert_eq!(
+        call(&["HKEYS", "h"]),
+        Reply::Array(vec![Reply::bulk("a"), Reply::bulk("b"), Reply::bulk("c")])
+    );
+    assert_eq!(
+        call(&["HVALS", "h"]),
+        Reply::Array(vec![Reply::bulk("3"), Reply::bulk("2"), Reply::bulk("4")])
+    );
+    // RESP2下HGETALL为扁平数组
+    assert_eq!(
+        call(&["HGETALL", "h"]),
+        Reply::Array(
+            ["a", "3", "b", "2", "c", "4"]
+                .iter()
+                .map(Reply::bulk)
+                .collect()
+        )
+    );
+    assert_eq!(call(&["HINCRBY", "h", "a", "7"]), Reply::Integer(10));
+    assert_eq!(call(&["HINCRBYFLOAT", "h", "n", "1.5"]), Reply::bulk("1.5"));
+    assert_eq!(
+        call(&["HINCRBY", "h", "n", "1"]),
+        Reply::error("hash value is not an integer")
+    );
+    assert_eq!(
+        call(&["HSCAN", "h", "0", "MATCH", "[ab]"]),
+        ScanOptions::reply(0, ["a", "10", "b", "2"].iter().map(Reply::bulk).collect())
+    );
+    assert_eq!(call(&["HSCAN", "h", "x"]), Reply::error("invalid cursor"));
+    assert_eq!(call(&["HDEL", "h", "a", "b", "c", "n"]), Reply::Integer(4));
+    assert_eq!(call(&["EXISTS", "h"]), Reply::Integer(0));
+
+    // dict编码下通过游标迭代出全部字段
+    for i in 0..200 {
+        call(&["HSET", "big", &i.to_string(), "v"]);
+    }
+    let mut cursor = "0".to_string();
+    let mut fields = std::collections::HashSet::new();
+    loop {
+        match call(&["HSCAN", "big", &cursor, "COUNT", "50"]) {
+            Reply::Array(reply) => {
+                if let (Reply::Bulk(next), Reply::Array(items)) = (&reply[0], &reply[1]) {
+                    cursor = String::from_utf8(next.clone()).unwrap();
+                    for pair in items.chunks(2) {
+                        fields.insert(format!("{:?}", pair[0]));
+                    }
+                }
+            }
+            reply => panic!("unexpected reply: {:?}", reply),
+        }
+        if cursor == "0" {
+            break;
+        }
+    }
+    assert_eq!(fields.len(), 200);
+}
@@ -0,0 +1,813 @@
+use chrono::Local;
+
+use crate::{
+    common::error::{Error, ErrorKind, Result},
+    common::utils,
+    db::notify::NOTIFY_GENERIC,
+    server::resp::Reply,
+};
+
+use super::table::Context;
+
+/*
+ * DEL key [key ...]，返回删除的key数量
+ */
+pub fn del(ctx: &mut Context) -> Result<Reply> {
+    let mut count = 0;
+    for i in 1..ctx.args.len() {
+        let key = ctx.key(i);
+        if ctx.db.exist(&key)? && ctx.db.delete(&key)? {
+            ctx.db.notify(NOTIFY_GENERIC, "del", &key);
+            count += 1;
+        }
+    }
+    return Ok(Reply::Integer(count));
+}
+
+/*
+ * EXISTS key [key ...]，重复的key会重复计数
+ */
+pub fn exists(ctx: &mut Context) -> Result<Reply> {
+    let mut count = 0;
+    for i in 1..ctx.args.len() {
+        if ctx.db.exist(&ctx.key(i))? {
+            count += 1;
+        }
+    }
+    return Ok(Reply::Integer(count));
+}
+
+/*
+ * UNLINK key [key ...]，没有后台释放，与DEL相同
+ */
+pub fn unlink(ctx: &mut Context) -> Result<Reply> {
+    return del(ctx);
+}
+
+/*
+ * EXPIRE key seconds [NX|XX|GT|LT]
+ */
+pub fn expire(ctx: &mut Context) -> Result<Reply> {
+    return expire_generic(ctx, Local::now().timestamp_millis(), 1000);
+}
+
+pub fn pexpire(ctx: &mut Context) -> Result<Reply> {
+    return expire_generic(ctx, Local::now().timestamp_millis(), 1);
+}
+
+pub fn expireat(ctx: &mut Context) -> Result<Reply> {
+    return expire_generic(ctx, 0, 1000);
+}
+
+pub fn pexpireat(ctx: &mut Context) -> Result<Reply> {
+    return expire_generic(ctx, 0, 1);
+}
+
+/*
+ * EXPIRE家族：过期时间为basetime + when * unit毫秒。
+ * GT和LT把没有过期时间的key视为永不过期
+ */
+fn expire_generic(ctx: &mut Context, basetime: i64, unit: i64) -> Result<Reply> {
+    let (mut nx, mut xx, mut gt, mut lt) = (false, false, false, false);
+    for i in 3..ctx.args.len() {
+        match ctx.arg(i).to_uppercase().as_str() {
+            "NX" => nx = true,
+            "XX" => xx = true,
+            "GT" => gt = true,
+            "LT" => lt = true,
+            _ => return Ok(Reply::error(format!("Unsupported option {}", ctx.arg(i)))),
+        }
+    }
+    if nx && (xx || gt || lt) {
+        return Ok(Reply::error(
+            "NX and XX, GT or LT options at the same time are not compatible",
+        ));
+    }
+    if gt && lt {
+        return Ok(Reply::error(
+            "GT and LT options at the same time are not compatible",
+        ));
+    }
+    let expire_time = ctx
+        .integer_arg(2)?
+        .checked_mul(unit)
+        .and_then(|millis| millis.checked_add(basetime))
+        .ok_or_else(|| {
+            return Error::new(
+                ErrorKind::Invalid,
+                format!(
+                    "invalid expire time in '{}' command",
+                    ctx.arg(0).to_lowercase()
+                ),
+            );
+        })?;
+
+    let key = ctx.key(1);
+    if !ctx.db.exist(&key)? {
+        return Ok(Reply::Integer(0));
+    }
+    let allowed = match ctx.db.get_expire(&key)? {
+        Some(current) => !nx && (!gt || expire_time > current) && (!lt || expire_time < current),
+        None => !xx && !gt,
+    };
+    if !allowed {
+        return Ok(Reply::Integer(0));
+    }
+    return set_expire(ctx, expire_time);
+}
+
+pub fn ttl(ctx: &mut Context) -> Result<Reply> {
+    return ttl_generic(ctx, 1000);
+}
+
+pub fn pttl(ctx: &mut Context) -> Result<Reply> {
+    return ttl_generic(ctx, 1);
+}
+
+/*
+ * 剩余的生存时间，key不存在时返回-2，没有过期时间时返回-1
+ */
+fn ttl_generic(ctx: &mut Context, unit: i64) -> Result<Reply> {
+    let key = ctx.key(1);
+    if !ctx.db.exist(&key)? {
+        return Ok(Reply::Integer(-2));
+    }
+    return Ok(Reply::Integer(match ctx.db.get_expire(&key)? {
+        Some(time) => {
+            let ttl = (time - Local::now().timestamp_millis()).max(0);
+            (ttl + unit / 2) / unit
+        }
+        None => -1,
+    }));
+}
+
+/*
+ * PERSIST key，移除过期时间，key不存在或者没有过期时间时返回0
+ */
+pub fn persist(ctx: &mut Context) -> Result<Reply> {
+    let key = ctx.key(1);
+    if !ctx.db.exist(&key)? || ctx.db.get_expire(&key)?.is_none() {
+        return Ok(Reply::Integer(0));
+    }
+    ctx.db.delete_expire(&key)?;
+    ctx.db.notify(NOTIFY_GENERIC, "persist", &key);
+    return Ok(Reply::Integer(1));
+}
+
+/*
+ * TYPE key，不存在时返回none
+ */
+pub fn r#type(ctx: &mut Context) -> Result<Reply> {
+    return Ok(Reply::Status(match ctx.db.peek_key(&ctx.key(1))? {
+        Some(obj) => obj.get_type().to_lowercase(),
+        None => "none".to_string(),
+    }));
+}
+
+/*
+ * KEYS pattern
+ */
+pub fn keys(ctx: &mut Context) -> Result<Reply> {
+    let keys = ctx.db.keys(&ctx.args[1])?;
+    return Ok(Reply::Array(
+        keys.iter().map(|key| Reply::bulk(key.as_bytes())).collect(),
+    ));
+}
+
+/*
+ * SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]，
+ * 游标按key的哈希值递增，rehash不影响已经返回的位置
+ */
+pub fn scan(ctx: &mut Context) -> Result<Reply> {
+    let options = ScanOptions::parse_keys(ctx, 1)?;
+    let (cursor, entries) = ctx.db.scan(options.cursor, options.count);
+    let mut items = vec![];
+    for (key, obj) in entries {
+        if !options.matches(key.as_bytes()) {
+            continue;
+        }
+        if let Some(type_name) = &options.type_name {
+            if !obj.get_type().eq_ignore_ascii_case(type_name) {
+                continue;
+            }
+        }
+        if ctx.db.exist(key.as_bytes())? {
+            items.push(Reply::bulk(key.as_bytes()));
+        }
+    }
+    return Ok(ScanOptions::reply(cursor, items));
+}
+
+pub fn randomkey(ctx: &mut Context) -> Result<Reply> {
+    return Ok(match ctx.db.random_key()? {
+        Some(key) => Reply::bulk(key.as_bytes()),
+        None => Reply::Null,
+    });
+}
+
+/*
+ * RENAME key newkey，过期时间跟随key
+ */
+pub fn rename(ctx: &mut Context) -> Result<Reply> {
+    let (src, dst) = (ctx.key(1), ctx.key(2));
+    if !ctx.db.rename(&src, &dst)? {
+        return Ok(Reply::error("no such key"));
+    }
+    notify_rename(ctx, &src, &dst);
+    return Ok(Reply::ok());
+}
+
+/*
+ * RENAMENX key newkey，newkey已存在时不做修改并返回0
+ */
+pub fn renamenx(ctx: &mut Context) -> Result<Reply> {
+    let (src, dst) = (ctx.key(1), ctx.key(2));
+    if !ctx.db.exist(&src)? {
+        return Ok(Reply::error("no such key"));
+    }
+    if ctx.db.exist(&dst)? {
+        return Ok(Reply::Integer(0));
+    }
+    ctx.db.rename(&src, &dst)?;
+    notify_rename(ctx, &src, &dst);
+    return Ok(Reply::Integer(1));
+}
+
+fn notify_rename(ctx: &mut Context, src: &[u8], dst: &[u8]) {
+    ctx.db.notify(NOTIFY_GENERIC, "rename_from", src);
+    ctx.db.notify(NOTIFY_GENERIC, "rename_to", dst);
+}
+
+/*
+ * COPY source destination [DB destination-db] [REPLACE]，过期时间一起复制，
+ * value写时复制，不会立即拷贝数据
+ */
+pub fn copy(ctx: &mut Context) -> Result<Reply> {
+    let (src, dst) = (ctx.key(1), ctx.key(2));
+    let mut target = ctx.client.db;
+    let mut replace = false;
+    let mut i = 3;
+    while i < ctx.args.len() {
+        match ctx.arg(i).to_uppercase().as_str() {
+            "REPLACE" => replace = true,
+            "DB" if i + 1 < ctx.args.len() => {
+                target = ctx.db_index_arg(i + 1)?;
+                i += 1;
+            }
+            _ => return Err(syntax_error()),
+        }
+        i += 1;
+    }
+    if target == ctx.client.db && src == dst {
+        return Ok(Reply::error("source and destination objects are the same"));
+    }
+
+    let obj = match ctx.db.peek_key(&src)? {
+        Some(obj) => obj.clone(),
+        None => return Ok(Reply::Integer(0)),
+    };
+    let expire_time = ctx.db.get_expire(&src)?;
+    let db = match target == ctx.client.db {
+        true => &mut *ctx.db,
+        false => ctx.db_pair(ctx.client.db, target).1,
+    };
+    if !replace && db.exist(&dst)? {
+        return Ok(Reply::Integer(0));
+    }
+    db.set_object(&dst, obj, false)?;
+    if let Some(time) = expire_time {
+        db.set_expire(&dst, time)?;
+    }
+    db.notify(NOTIFY_GENERIC, "copy_to", &dst);
+    ctx.server.touch_watched_key(target, &ctx.args[2]);
+    return Ok(Reply::Integer(1));
+}
+
+/*
+ * MOVE key db，把key连同过期时间移动到另一个db，目标db中已存在时不移动
+ */
+pub fn r#move(ctx: &mut Context) -> Result<Reply> {
+    let key = ctx.key(1);
+    let target = ctx.db_index_arg(2)?;
+    if target == ctx.client.db {
+        return Ok(Reply::error("source and destination objects are the same"));
+    }
+    let (src, dst) = ctx.db_pair(ctx.client.db, target);
+    let obj = match src.peek_key(&key)? {
+        Some(obj) => obj.clone(),
+        None => return Ok(Reply::Integer(0)),
+    };
+    if dst.exist(&key)? {
+        return Ok(Reply::Integer(0));
+    }
+    let expire_time = src.get_expire(&key)?;
+    dst.set_object(&key, obj, false)?;
+    if let Some(time) = expire_time {
+        dst.set_expire(&key, time)?;
+    }
+    src.delete(&key)?;
+    src.notify(NOTIFY_GENERIC, "move_from", &key);
+    dst.notify(NOTIFY_GENERIC, "move_to", &key);
+    ctx.server.touch_watched_key(target, &ctx.args[1]);
+    return Ok(Reply::Integer(1));
+}
+
+/*
+ * SWAPDB index1 index2，交换两个db的全部数据，连接所选的db编号不变
+ */
+pub fn swapdb(ctx: &mut Context) -> Result<Reply> {
+    let parse_index = |ctx: &Context, index: usize, name: &str| {
+        return ctx.integer_arg(index).map_err(|_| {
+            return Error::new(ErrorKind::Invalid, format!("invalid {} DB index", name));
+        });
+    };
+    parse_index(ctx, 1, "first")?;
+    parse_index(ctx, 2, "second")?;
+    let first = ctx.db_index_arg(1)?;
+    let second = ctx.db_index_arg(2)?;
+    if first != second {
+        let server = ctx.server;
+        let (first_db, second_db) = ctx.db_pair(first, second);
+        server.touch_watched_db(first, first_db, Some(second_db));
+        server.touch_watched_db(second, second_db, Some(first_db));
+        std::mem::swap(first_db, second_db);
+    }
+    return Ok(Reply::ok());
+}
+
+/*
+ * OBJECT ENCODING|IDLETIME|FREQ key，查看对象内部信息，不更新访问时间
+ */
+pub fn object(ctx: &mut Context) -> Result<Reply> {
+    let subcommand = ctx.arg(1).to_lowercase();
+    if !matches!(subcommand.as_str(), "encoding" | "idletime" | "freq") || ctx.args.len() != 3 {
+        return Ok(Reply::error(format!(
+            "unknown subcommand '{}'. Try OBJECT HELP.",
+            ctx.arg(1)
+        )));
+    }
+    let lfu = ctx.server.config().maxmemory_policy.is_lfu();
+    let obj = match ctx.db.peek_key(&ctx.key(2))? {
+        Some(obj) => obj,
+        None => return Ok(Reply::Null),
+    };
+    return Ok(match subcommand.as_str() {
+        "encoding" => Reply::bulk(obj.get_encoding()),
+        "idletime" if lfu => Reply::error(
+            "An LFU maxmemory policy is selected, idle time not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.",
+        ),
+        "idletime" => Reply::Integer(obj.idle_time() / 1000),
+        "freq" if !lfu => Reply::error(
+            "An LFU maxmemory policy is not selected, access frequency not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.",
+        ),
+        _ => Reply::Integer(obj.lfu_counter() as i64),
+    });
+}
+
+/*
+ * 过期时间已经过去时直接删除key，统一以PEXPIREAT写入AOF
+ */
+fn set_expire(ctx: &mut Context, expire_time: i64) -> Result<Reply> {
+    let key = ctx.key(1);
+    if !ctx.db.exist(&key)? {
+        return Ok(Reply::Integer(0));
+    }
+    if expire_time <= Local::now().timestamp_millis() {
+        ctx.db.delete(&key)?;
+        ctx.db.notify(NOTIFY_GENERIC, "del", &key);
+        ctx.rewrite_args(&[b"DEL".as_slice(), &key]);
+        return Ok(Reply::Integer(1));
+    }
+    ctx.db.set_expire_time(&key, &expire_time.to_string())?;
+    ctx.db.notify(NOTIFY_GENERIC, "expire", &key);
+    ctx.rewrite_args(&[
+        b"PEXPIREAT".as_slice(),
+        &key,
+        expire_time.to_string().as_bytes(),
+    ]);
+    return Ok(Reply::Integer(1));
+}
+
+/*
+ * SCAN家族共用的参数：cursor [MATCH pattern] [COUNT count]
+ */
+pub struct ScanOptions {
+    pub cursor: u64,
+    pub pattern: Option<Vec<u8>>,
+    pub count: usize,
+    // 只有SCAN支持TYPE
+    pub type_name: Option<String>,
+}
+
+impl ScanOptions {
+    /*
+     * index为cursor所在的位置
+     */
+    pub fn parse(ctx: &Context, index: usize) -> Result<Self> {
+        return Self::parse_generic(ctx, index, false);
+    }
+
+    /*
+     * SCAN的参数，额外支持TYPE type
+     */
+    pub fn parse_keys(ctx: &Context, index: usize) -> Result<Self> {
+        return Self::parse_generic(ctx, index, true);
+    }
+
+    fn parse_generic(ctx: &Context, index: usize, allow_type: bool) -> Result<Self> {
+        let cursor = ctx
+            .arg(index)
+            .parse::<u64>()
+            .map_err(|_| Error::new(ErrorKind::Invalid, "invalid cursor".to_string()))?;
+        let mut options = Self {
+            cursor,
+            pattern: None,
+            count: 10,
+            type_name: None,
+        };
+        let mut i = index + 1;
+        while i < ctx.args.len() {
+            match ctx.arg(i).to_uppercase().as_str() {
+                "MATCH" if i + 1 < ctx.args.len() => {
+                    options.pattern = Some(ctx.args[i + 1].clone());
+                }
+                "COUNT" if i + 1 < ctx.args.len() => {
+                    let count = ctx.integer_arg(i + 1)?;
+                    if count < 1 {
+                        return Err(syntax_error());
+                    }
+                    options.count = count as usize;
+                }
+                "TYPE" if allow_type && i + 1 < ctx.args.len() => {
+                    let type_name = ctx.arg(i + 1).to_lowercase();
+                    if !matches!(
+                        type_name.as_str(),
+                        "string" | "list" | "hash" | "set" | "zset"
+                    ) {
+                        return Err(Error::new(
+                            ErrorKind::Invalid,
+                            format!("unknown type name '{}'", ctx.arg(i + 1)),
+                        ));
+                    }
+                    options.type_name = Some(type_name);
+                }
+                _ => return Err(syntax_error()),
+            }
+            i += 2;
+        }
+        return Ok(options);
+    }
+
+    pub fn matches(&self, key: &[u8]) -> bool {
+        return match &self.pattern {
+            Some(pattern) => utils::glob_match(pattern, key),
+            None => true,
+        };
+    }
+
+    /*
+     * 回复：[下一次的游标, [元素...]]
+     */
+    pub fn reply(cursor: u64, items: Vec<Reply>) -> Reply {
+        return Reply::Array(vec![Reply::bulk(cursor.to_string()), Reply::Array(items)]);
+    }
+}
+
+fn syntax_error() -> Error {
+    return Error::new(ErrorKind::Invalid, "syntax error".to_string());
+}
+
+#[test]
+fn test_object() {
+    use crate::{
+        db::evict::EvictionPolicy,
+        server::{
+            config::Config,
+            server::{call, start_test_server, start_test_server_with},
+        },
+    };
+    use std::net::TcpStream;
+
+    let mut stream = TcpStream::connect(start_test_server()).unwrap();
+
+    call(&mut stream, &["SET", "int", "123"]);
+    call(&mut stream, &["SET", "str", "hello"]);
+    call(&mut stream, &["SET", "raw", &"x".repeat(100)]);
+    call(&mut stream, &["RPUSH", "list", "a"]);
+    call(&mut stream, &["SADD", "set", "1"]);
+    call(&mut stream, &["ZADD", "zset", "1", "a"]);
+    for (key, encoding) in [
+        ("int", "int"),
+        ("str", "embstr"),
+        ("raw", "raw"),
+        ("list", "listpack"),
+        ("set", "intset"),
+        ("zset", "skiplist"),
+    ] {
+        assert_eq!(
+            call(&mut stream, &["OBJECT", "ENCODING", key]),
+            Reply::bulk(encoding)
+        );
+    }
+    assert_eq!(
+        call(&mut stream, &["OBJECT", "ENCODING", "none"]),
+        Reply::Null
+    );
+    assert_eq!(
+        call(&mut stream, &["OBJECT", "IDLETIME", "str"]),
+        Reply::Integer(0)
+    );
+    assert!(matches!(
+        call(&mut stream, &["OBJECT", "FREQ", "str"]),
+        Reply::Error(_)
+    ));
+    assert!(matches!(
+        call(&mut stream, &["OBJECT", "FOO", "str"]),
+        Reply::Error(_)
+    ));
+
+    // LFU策略下可以查看访问计数
+    let config = Config {
+        maxmemory_policy: EvictionPolicy::AllKeysLfu,
+        ..Config::default()
+    };
+    let mut stream = TcpStream::connect(start_test_server_with(config)).unwrap();
+    call(&mut stream, &["SET", "k", "v"]);
+    assert!(matches!(
+        call(&mut stream, &["OBJECT", "FREQ", "k"]),
+        Reply::Integer(n) if n >= 5
+    ));
+    assert!(matches!(
+        call(&mut stream, &["OBJECT", "IDLETIME", "k"]),
+        Reply::Error(_)
+    ));
+
+    // noeviction超出maxmemory后拒绝写入，但仍然可以读取和删除
+    let config = Config {
+        maxmemory: 1,
+        ..Config::default()
+    };
+    let mut stream = TcpStream::connect(start_test_server_with(config)).unwrap();
+    assert_eq!(call(&mut stream, &["SET", "k", "v"]), Reply::ok());
+    assert!(matches!(
+        call(&mut stream, &["SET", "k2", "v"]),
+        Reply::Error(e) if e.starts_with("OOM")
+    ));
+    assert_eq!(call(&mut stream, &["GET", "k"]), Reply::bulk("v"));
+    assert_eq!(call(&mut stream, &["DEL", "k"]), Reply::Integer(1));
+    assert_eq!(call(&mut stream, &["SET", "k2", "v"]), Reply::ok());
+
+    // allkeys-random淘汰旧的key以写入新的key，每个命令执行前都会淘汰到maxmemory以下
+    let config = Config {
+        maxmemory: 1,
+        maxmemory_policy: EvictionPolicy::AllKeysRandom,
+        ..Config::default()
+    };
+    let mut stream = TcpStream::connect(start_test_server_with(config)).unwrap();
+    assert_eq!(call(&mut stream, &["SET", "k", "v"]), Reply::ok());
+    assert_eq!(call(&mut stream, &["SET", "k2", "v"]), Reply::ok());
+    assert_eq!(call(&mut stream, &["EXISTS", "k", "k2"]), Reply::Integer(0));
+}
+
+#[test]
+fn test_databases() {
+    use crate::server::server::{call, start_test_server};
+    use std::net::TcpStream;
+
+    let addr = start_test_server();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut other = TcpStream::connect(addr).unwrap();
+
+    call(&mut stream, &["SET", "a", "0"]);
+    assert_eq!(call(&mut stream, &["SELECT", "1"]), Reply::ok());
+    assert_eq!(call(&mut stream, &["GET", "a"]), Reply::Null);
+    call(&mut stream, &["SET", "b", "1"]);
+    call(
+        &mut stream,
+        &[
+            "PEXPIREAT",
+            "b",
+            &(Local::now().timestamp_millis() + 100_000).to_string(),
+        ],
+    );
+    assert_eq!(call(&mut stream, &["DBSIZE"]), Reply::Integer(1));
+    assert!(
+        matches!(call(&mut stream, &["SELECT", "16"]), Reply::Error(e) if e.contains("out of range"))
+    );
+    assert!(matches!(
+        call(&mut stream, &["SELECT", "x"]),
+        Reply::Error(_)
+    ));
+    // 每个连接单独选择db
+    assert_eq!(call(&mut other, &["GET", "a"]), Reply::bulk("0"));
+
+    // 目标db中已存在时不移动
+    assert!(
+        matches!(call(&mut stream, &["MOVE", "b", "1"]), Reply::Error(e) if e.contains("same"))
+    );
+    assert_eq!(
+        call(&mut stream, &["MOVE", "missing", "0"]),
+        Reply::Integer(0)
+    );
+    assert_eq!(call(&mut stream, &["MOVE", "b", "0"]), Reply::Integer(1));
+    assert_eq!(call(&mut stream, &["EXISTS", "b"]), Reply::Integer(0));
+    assert_eq!(call(&mut other, &["EXISTS", "b"]), Reply::Integer(1));
+    call(&mut stream, &["SET", "a", "1"]);
+    assert_eq!(call(&mut stream, &["MOVE", "a", "0"]), Reply::Integer(0));
+
+    // SWAPDB之后连接仍然使用原来的编号
+    assert_eq!(call(&mut stream, &["SWAPDB", "0", "1"]), Reply::ok());
+    assert_eq!(call(&mut other, &["GET", "a"]), Reply::bulk("1"));
+    assert_eq!(call(&mut stream, &["GET", "a"]), Reply::bulk("0"));
+    assert_eq!(call(&mut stream, &["DBSIZE"]), Reply::Integer(2));
+    assert!(
+        matches!(call(&mut stream, &["SWAPDB", "x", "1"]), Reply::Error(e) if e.contains("invalid first DB index"))
+    );
+    assert!(
+        matches!(call(&mut stream, &["SWAPDB", "0", "99"]), Reply::Error(e) if e.contains("out of range"))
+    );
+
+    assert_eq!(call(&mut stream, &["FLUSHDB", "ASYNC"]), Reply::ok());
+    assert_eq!(call(&mut stream, &["DBSIZE"]), Reply::Integer(0));
+    assert_eq!(call(&mut other, &["DBSIZE"]), Reply::Integer(1));
+    assert!(matches!(
+        call(&mut stream, &["FLUSHALL", "NOW"]),
+        Reply::Error(_)
+    ));
+    assert_eq!(call(&mut stream, &["FLUSHALL"]), Reply::ok());
+    assert_eq!(call(&mut other, &["DBSIZE"]), Reply::Integer(0));
+}
+
+#[test]
+fn test_binary_keys() {
+    use crate::server::server::{call_bytes, start_test_server};
+    use std::net::TcpStream;
+
+    let mut stream = TcpStream::connect(start_test_server()).unwrap();
+    let mut call = |args: &[&[u8]]| call_bytes(&mut stream, args);
+
+    // 不同的非UTF-8 key不能互相覆盖
+    assert_eq!(call(&[b"SET", b"\xff", b"a"]), Reply::ok());
+    assert_eq!(call(&[b"SET", b"\xfe", b"b"]), Reply::ok());
+    assert_eq!(call(&[b"GET", b"\xff"]), Reply::bulk("a"));
+    assert_eq!(call(&[b"GET", b"\xfe"]), Reply::bulk("b"));
+    assert_eq!(call(&[b"DBSIZE"]), Reply::Integer(2));
+    assert_eq!(call(&[b"RENAME", b"\xff", b"\xfd"]), Reply::ok());
+    assert_eq!(call(&[b"EXISTS", b"\xff"]), Reply::Integer(0));
+    assert_eq!(call(&[b"GET", b"\xfd"]), Reply::bulk("a"));
+    assert_eq!(
+        call(&[b"KEYS", b"\xfd"]),
+        Reply::Array(vec![Reply::bulk(b"\xfd")])
+    );
+}
+
+#[test]
+fn test_keyspace() {
+    use crate::server::server::{call, start_test_server};
+    use std::{collections::HashSet, net::TcpStream};
+
+    let mut stream = TcpStream::connect(start_test_server()).unwrap();
+    let mut call = |args: &[&str]| call(&mut stream, args);
+    let bulks = |reply: Reply| match reply {
+        Reply::Array(items) => items
+            .into_iter()
+            .map(|item| match item {
+                Reply::Bulk(v) => String::from_utf8(v).unwrap(),
+                _ => panic!("not a bulk"),
+            })
+            .collect::<HashSet<_>>(),
+        _ => panic!("not an array"),
+    };
+
+    assert_eq!(call(&["RANDOMKEY"]), Reply::Null);
+    call(&["SET", "foo", "1"]);
+    call(&["SET", "foobar", "2"]);
+    call(&["RPUSH", "list", "a"]);
+    call(&["ZADD", "zset", "1", "a"]);
+    assert_eq!(
+        bulks(call(&["KEYS", "foo*"])),
+        HashSet::from(["foo".to_string(), "foobar".to_string()])
+    );
+    assert_eq!(call(&["TYPE", "list"]), Reply::Status("list".to_string()));
+    assert_eq!(call(&["TYPE", "zset"]), Reply::Status("zset".to_string()));
+    assert_eq!(
+        call(&["TYPE", "missing"]),
+        Reply::Status("none".to_string())
+    );
+    assert!(matches!(call(&["RANDOMKEY"]), Reply::Bulk(_)));
+
+    // TTL家族和NX/XX/GT/LT
+    assert_eq!(call(&["TTL", "missing"]), Reply::Integer(-2));
+    assert_eq!(call(&["TTL", "foo"]), Reply::Integer(-1));
+    assert_eq!(call(&["EXPIRE", "foo", "100", "XX"]), Reply::Integer(0));
+    assert_eq!(call(&["EXPIRE", "foo", "100", "GT"]), Reply::Integer(0));
+    assert_eq!(call(&["EXPIRE", "foo", "100", "NX"]), Reply::Integer(1));
+    assert_eq!(call(&["EXPIRE", "foo", "200", "NX"]), Reply::Integer(0));
+    assert_eq!(call(&["EXPIRE", "foo", "50", "GT"]), Reply::Integer(0));
+    assert_eq!(call(&["EXPIRE", "foo", "200", "gt"]), Reply::Integer(1));
+    assert_eq!(call(&["TTL", "foo"]), Reply::Integer(200));
+    assert_eq!(call(&["PEXPIRE", "foo", "50000", "LT"]), Reply::Integer(1));
+    assert!(matches!(call(&["PTTL", "foo"]), Reply::Integer(ttl) if ttl > 49000 && ttl <= 50000));
+    assert!(
+        matches!(call(&["EXPIRE", "foo", "1", "NX", "GT"]), Reply::Error(e) if e.contains("not compatible"))
+    );
+    assert!(
+        matches!(call(&["EXPIRE", "foo", "1", "GT", "LT"]), Reply::Error(e) if e.contains("not compatible"))
+    );
+    assert!(
+        matches!(call(&["EXPIRE", "foo", "1", "YY"]), Reply::Error(e) if e.contains("Unsupported option"))
+    );
+    assert!(
+        matches!(call(&["EXPIRE", "foo", &i64::MAX.to_string()]), Reply::Error(e) if e.contains("invalid expire time"))
+    );
+    let at = Local::now().timestamp() + 1000;
+    assert_eq!(
+        call(&["EXPIREAT", "foo", &at.to_string()]),
+        Reply::Integer(1)
+    );
+    assert!(matches!(call(&["TTL", "foo"]), Reply::Integer(ttl) if ttl > 990));
+    assert_eq!(call(&["PERSIST", "foo"]), Reply::Integer(1));
+    assert_eq!(call(&["PERSIST", "foo"]), Reply::Integer(0));
+    assert_eq!(call(&["EXPIREAT", "foobar", "1"]), Reply::Integer(1));
+    assert_eq!(call(&["EXISTS", "foobar"]), Reply::Integer(0));
+
+    // RENAME带上过期时间
+    call(&["EXPIRE", "foo", "100"]);
+    assert_eq!(call(&["RENAME", "foo", "bar"]), Reply::ok());
+    assert_eq!(call(&["TTL", "bar"]), Reply::Integer(100));
+    assert_eq!(call(&["EXISTS", "foo"]), Reply::Integer(0));
+    assert!(
+        matches!(call(&["RENAME", "foo", "bar"]), Reply::Error(e) if e.contains("no such key"))
+    );
+    assert_eq!(call(&["RENAMENX", "bar", "list"]), Reply::Integer(0));
+    assert_eq!(call(&["RENAMENX", "bar", "foo"]), Reply::Integer(1));
+
+    // COPY：目标已存在时需要REPLACE，复制后互不影响
+    assert_eq!(call(&["COPY", "list", "list2"]), Reply::Integer(1));
+    call(&["RPUSH", "list2", "b"]);
+    assert_eq!(call(&["LLEN", "list"]), Reply::Integer(1));
+    assert_eq!(call(&["COPY", "list", "list2"]), Reply::Integer(0));
+    assert_eq!(
+        call(&["COPY", "list", "list2", "REPLACE"]),
+        Reply::Integer(1)
+    );
+    assert_eq!(call(&["LLEN", "list2"]), Reply::Integer(1));
+    assert_eq!(call(&["COPY", "foo", "foo", "DB", "1"]), Reply::Integer(1));
+    assert!(matches!(call(&["COPY", "foo", "foo"]), Reply::Error(e) if e.contains("same")));
+    call(&["SELECT", "1"]);
+    assert_eq!(call(&["TTL", "foo"]), Reply::Integer(100));
+    call(&["SELECT", "0"]);
+
+    assert_eq!(
+        call(&["UNLINK", "list", "list2", "missing"]),
+        Reply::Integer(2)
+    );
+
+    // SCAN期间新增的key引起rehash，一直存在的key都会被返回
+    for i in 0..200 {
+        call(&["SET", &format!("key:{}", i), "v"]);
+    }
+    let mut seen = HashSet::new();
+    let mut cursor = "0".to_string();
+    let mut round = 0;
+    loop {
+        let reply = call(&["SCAN", &cursor, "MATCH", "key:*", "COUNT", "20"]);
+        let (next, keys) = match reply {
+            Reply::Array(mut items) => {
+                let keys = bulks(items.pop().unwrap());
+                (items.pop().unwrap(), keys)
+            }
+            _ => panic!("not an array"),
+        };
+        seen.extend(keys);
+        for i in 0..50 {
+            call(&["SET", &format!("new:{}:{}", round, i), "v"]);
+        }
+        round += 1;
+        cursor = match next {
+            Reply::Bulk(v) => String::from_utf8(v).unwrap(),
+            _ => panic!("not a bulk"),
+        };
+        if cursor == "0" {
+            break;
+        }
+    }
+    assert!((0..200).all(|i| seen.contains(&format!("key:{}", i))));
+    assert!(seen.iter().all(|key| key.starts_with("key:")));
+
+    match call(&["SCAN", "0", "COUNT", "10000", "TYPE", "zset"]) {
+        Reply::Array(mut items) => assert_eq!(
+            bulks(items.pop().unwrap()),
+            HashSet::from(["zset".to_string()])
+        ),
+        _ => panic!("not an array"),
+    }
+    assert!(matches!(
+        call(&["SCAN", "0", "TYPE", "stream2"]),
+        Reply::Error(_)
+    ));
+    assert!(matches!(
+        call(&["HSCAN", "h", "0", "TYPE", "hash"]),
+        Reply::Error(_)
+    ));
+}
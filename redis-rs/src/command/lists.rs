@@ -0,0 +1,538 @@
+use std::sync::Arc;
+
+use crate::{
+    common::error::Result,
+    db::{
+        db::Db,
+        notify::{NOTIFY_GENERIC, NOTIFY_LIST},
+    },
+    server::{blocking::BlockedOp, resp::Reply},
+    types::{
+        list::ListObject,
+        object::{Object, ObjectValue},
+    },
+};
+
+use super::table::Context;
+
+pub fn lpush(ctx: &mut Context) -> Result<Reply> {
+    return push(ctx, true);
+}
+
+pub fn rpush(ctx: &mut Context) -> Result<Reply> {
+    return push(ctx, false);
+}
+
+pub fn lpop(ctx: &mut Context) -> Result<Reply> {
+    return pop(ctx, true);
+}
+
+pub fn rpop(ctx: &mut Context) -> Result<Reply> {
+    return pop(ctx, false);
+}
+
+/*
+ * LRANGE key start stop
+ */
+pub fn lrange(ctx: &mut Context) -> Result<Reply> {
+    let start = ctx.integer_arg(2)?;
+    let stop = ctx.integer_arg(3)?;
+    let values = match lookup_list(ctx.db, &ctx.key(1))? {
+        Some(list) => list.range(start, stop),
+        None => vec![],
+    };
+    return Ok(Reply::Array(values.into_iter().map(Reply::Bulk).collect()));
+}
+
+pub fn lindex(ctx: &mut Context) -> Result<Reply> {
+    let index = ctx.integer_arg(2)?;
+    return Ok(match lookup_list(ctx.db, &ctx.key(1))? {
+        Some(list) => list.get(index).map(Reply::Bulk).unwrap_or(Reply::Null),
+        None => Reply::Null,
+    });
+}
+
+/*
+ * LSET key index element
+ */
+pub fn lset(ctx: &mut Context) -> Result<Reply> {
+    let index = ctx.integer_arg(2)?;
+    let key = ctx.key(1);
+    let reply = match lookup_list_mut(ctx.db, &key)? {
+        Some(list) => match list.set(index, &ctx.args[3]) {
+            true => Reply::ok(),
+            false => return Ok(Reply::error("index out of range")),
+        },
+        None => return Ok(Reply::error("no such key")),
+    };
+    ctx.db.notify(NOTIFY_LIST, "lset", &key);
+    return Ok(reply);
+}
+
+/*
+ * LINSERT key BEFORE|AFTER pivot element，找不到pivot时返回-1，key不存在时返回0
+ */
+pub fn linsert(ctx: &mut Context) -> Result<Reply> {
+    let before = match ctx.arg(2).to_uppercase().as_str() {
+        "BEFORE" => true,
+        "AFTER" => false,
+        _ => return Ok(Reply::error("syntax error")),
+    };
+    let key = ctx.key(1);
+    let len = match lookup_list_mut(ctx.db, &key)? {
+        Some(list) => match list.insert(&ctx.args[3], &ctx.args[4], before) {
+            Some(len) => len,
+            None => return Ok(Reply::Integer(-1)),
+        },
+        None => return Ok(Reply::Integer(0)),
+    };
+    ctx.db.notify(NOTIFY_LIST, "linsert", &key);
+    return Ok(Reply::Integer(len as i64));
+}
+
+/*
+ * LREM key count element
+ */
+pub fn lrem(ctx: &mut Context) -> Result<Reply> {
+    let count = ctx.integer_arg(2)?;
+    let key = ctx.key(1);
+    let removed = match lookup_list_mut(ctx.db, &key)? {
+        Some(list) => list.remove_value(count, &ctx.args[3]),
+        None => 0,
+    };
+    if removed > 0 {
+        ctx.db.notify(NOTIFY_LIST, "lrem", &key);
+    }
+    delete_if_empty(ctx.db, &key)?;
+    return Ok(Reply::Integer(removed as i64));
+}
+
+/*
+ * LTRIM key start stop
+ */
+pub fn ltrim(ctx: &mut Context) -> Result<Reply> {
+    let start = ctx.integer_arg(2)?;
+    let stop = ctx.integer_arg(3)?;
+    let key = ctx.key(1);
+    if let Some(list) = lookup_list_mut(ctx.db, &key)? {
+        list.trim(start, stop);
+        ctx.db.notify(NOTIFY_LIST, "ltrim", &key);
+    }
+    delete_if_empty(ctx.db, &key)?;
+    return Ok(Reply::ok());
+}
+
+pub fn llen(ctx: &mut Context) -> Result<Reply> {
+    let len = match lookup_list(ctx.db, &ctx.key(1))? {
+        Some(list) => list.len(),
+        None => 0,
+    };
+    return Ok(Reply::Integer(len as i64));
+}
+
+/*
+ * LMOVE source destination LEFT|RIGHT LEFT|RIGHT
+ */
+pub fn lmove(ctx: &mut Context) -> Result<Reply> {
+    let (from_left, to_left) = match (parse_direction(&ctx.arg(3)), parse_direction(&ctx.arg(4))) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return Ok(Reply::error("syntax error")),
+    };
+    let value = move_element(ctx.db, &ctx.key(1), &ctx.key(2), from_left, to_left)?;
+    return Ok(value.map(Reply::Bulk).unwrap_or(Reply::Null));
+}
+
+/*
+ * BLMOVE source destination LEFT|RIGHT LEFT|RIGHT timeout
+ */
+pub fn blmove(ctx: &mut Context) -> Result<Reply> {
+    let (from_left, to_left) = match (parse_direction(&ctx.arg(3)), parse_direction(&ctx.arg(4))) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return Ok(Reply::error("syntax error")),
+    };
+    let timeout = ctx.timeout_arg(5)?;
+    let op = BlockedOp::Move {
+        destination: ctx.args[2].clone(),
+        from_left,
+        to_left,
+    };
+    return match move_element(ctx.db, &ctx.key(1), &ctx.key(2), from_left, to_left)? {
+        Some(value) => {
+            ctx.rewrite_args(&op.command(&ctx.args[1]));
+            Ok(Reply::Bulk(value))
+        }
+        None => Ok(ctx.block(vec![ctx.args[1].clone()], timeout, op)),
+    };
+}
+
+pub fn blpop(ctx: &mut Context) -> Result<Reply> {
+    return blocking_pop(ctx, true);
+}
+
+pub fn brpop(ctx: &mut Context) -> Result<Reply> {
+    return blocking_pop(ctx, false);
+}
+
+/*
+ * 从source的一端弹出元素并推入destination的一端，source为空时返回None
+ */
+pub fn move_element(
+    db: &mut Db,
+    source: &[u8],
+    destination: &[u8],
+    from_left: bool,
+    to_left: bool,
+) -> Result<Option<Vec<u8>>> {
+    // 先检查目标的类型，避免弹出元素后无法写入
+    if let Some(obj) = db.lookup_key(destination)? {
+        obj.as_list()?;
+    }
+    let value = match lookup_list_mut(db, source)? {
+        Some(list) => list.pop(from_left),
+        None => None,
+    };
+    let value = match value {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    db.notify(NOTIFY_LIST, pop_event(from_left), source);
+    delete_if_empty(db, source)?;
+    list_or_create(db, destination)?.push(&value, to_left);
+    db.notify(NOTIFY_LIST, push_event(to_left), destination);
+    return Ok(Some(value));
+}
+
+/*
+ * LPUSH/RPUSH key element [element ...]，返回推入后的长度
+ */
+fn push(ctx: &mut Context, front: bool) -> Result<Reply> {
+    let key = ctx.key(1);
+    let list = list_or_create(ctx.db, &key)?;
+    for value in ctx.args[2..].iter() {
+        list.push(value, front);
+    }
+    let len = list.len();
+    ctx.db.notify(NOTIFY_LIST, push_event(front), &key);
+    return Ok(Reply::Integer(len as i64));
+}
+
+/*
+ * LPOP/RPOP key [count]，指定count时返回数组
+ */
+fn pop(ctx: &mut Context, front: bool) -> Result<Reply> {
+    let count = match ctx.args.len() {
+        2 => None,
+        3 => match ctx.integer_arg(2)? {
+            count if count < 0 => {
+                return Ok(Reply::error("value is out of range, must be positive"))
+            }
+            count => Some(count as usize),
+        },
+        _ => return Ok(Reply::error("syntax error")),
+    };
+    let key = ctx.key(1);
+    let list = match lookup_list_mut(ctx.db, &key)? {
+        Some(list) => list,
+        None if count.is_some() => return Ok(Reply::NullArray),
+        None => return Ok(Reply::Null),
+    };
+    let reply = match count {
+        Some(count) => Reply::Array(
+            (0..count)
+                .map_while(|_| list.pop(front))
+                .map(Reply::Bulk)
+                .collect(),
+        ),
+        None => list.pop(front).map(Reply::Bulk).unwrap_or(Reply::Null),
+    };
+    ctx.db.notify(NOTIFY_LIST, pop_event(front), &key);
+    delete_if_empty(ctx.db, &key)?;
+    return Ok(reply);
+}
+
+/*
+ * BLPOP/BRPOP key [key ...] timeout，从第一个非空的列表弹出，都为空时阻塞。
+ * 传播为对应的LPOP/RPOP
+ */
+fn blocking_pop(ctx: &mut Context, front: bool) -> Result<Reply> {
+    let last = ctx.args.len() - 1;
+    let timeout = ctx.timeout_arg(last)?;
+    let op = BlockedOp::Pop { front };
+    for i in 1..last {
+        let key = ctx.key(i);
+        let value = match lookup_list_mut(ctx.db, &key)? {
+            Some(list) => list.pop(front),
+            None => None,
+        };
+        if let Some(value) = value {
+            ctx.db.notify(NOTIFY_LIST, pop_event(front), &key);
+            delete_if_empty(ctx.db, &key)?;
+            ctx.rewrite_args(&op.command(&ctx.args[i]));
+            return Ok(Reply::Array(vec![
+                Reply::bulk(&ctx.args[i]),
+                Reply::Bulk(value),
+            ]));
+        }
+    }
+    return Ok(ctx.block(ctx.args[1..last].to_vec(), timeout, op));
+}
+
+fn push_event(front: bool) -> &'static str {
+    return if front { "lpush" } else { "rpush" };
+}
+
+fn pop_event(front: bool) -> &'static str {
+    return if front { "lpop" } else { "rpop" };
+}
+
+fn parse_direction(direction: &str) -> Option<bool> {
+    return match direction.to_uppercase().as_str() {
+        "LEFT" => Some(true),
+        "RIGHT" => Some(false),
+        _ => None,
+    };
+}
+
+/*
+ * key不存在时返回None，不是列表时返回WRONGTYPE
+ */
+fn lookup_list<'a>(db: &'a mut Db, key: &[u8]) -> Result<Option<&'a ListObject>> {
+    return match db.lookup_key(key)? {
+        Some(obj) => Ok(Some(obj.as_list()?)),
+        None => Ok(None),
+    };
+}
+
+fn lookup_list_mut<'a>(db: &'a mut Db, key: &[u8]) -> Result<Option<&'a mut ListObject>> {
+    return match db.lookup_key_mut(key)? {
+        Some(obj) => Ok(Some(obj.as_list_mut()?)),
+        None => Ok(None),
+    };
+}
+
+fn list_or_create<'a>(db: &'a mut Db, key: &[u8]) -> Result<&'a mut ListObject> {
+    if db.lookup_key(key)?.is_none() {
+        let obj = Object::new(ObjectValue::List(Arc::new(ListObject::new())))?;
+        db.set_object(key, obj, false)?;
+    }
+    return db.lookup_key_mut(key)?.unwrap().as_list_mut();
+}
+
+/*
+ * 列表为空时删除key，redis中不存在空列表
+ */
+fn delete_if_empty(db: &mut Db, key: &[u8]) -> Result<()> {
+    let empty = match db.lookup_key(key)? {
+        Some(obj) => obj.as_list().map(|list| list.is_empty()).unwrap_or(false),
+        None => false,
+    };
+    if empty {
+        db.delete(key)?;
+        db.notify(NOTIFY_GENERIC, "del", key);
+    }
+    return Ok(());
+}
+
+#[test]
+fn test_list_commands() {
+    use crate::server::server::{call, start_test_server};
+    use std::net::TcpStream;
+
+    let mut stream = TcpStream::connect(start_test_server()).unwrap();
+    let mut call = |args: &[&str]| call(&mut stream, args);
+    let bulks = |values: &[&str]| Reply::Array(values.iter().map(Reply::bulk).collect());
+
+    assert_eq!(call(&["RPUSH", "l", "a", "b", "c"]), Reply::Integer(3));
+    assert_eq!(call(&["LPUSH", "l", "z"]), Reply::Integer(4));
+    assert_eq!(
+        call(&["LRANGE", "l", "0", "-1"]),
+        bulks(&["z", "a", "b", "c"])
+    );
+    assert_eq!(call(&["LLEN", "l"]), Reply::Integer(4));
+    assert_eq!(call(&["LINDEX", "l", "-1"]), Reply::bulk("c"));
+    assert_eq!(call(&["LINDEX", "l", "10"]), Reply::Null);
+    assert_eq!(call(&["LSET", "l", "0", "y"]), Reply::ok());
+    assert_eq!(
+        call(&["LSET", "l", "10", "y"]),
+        Reply::error("index out of range")
+    );
+    assert_eq!(call(&["LSET", "x", "0", "y"]), Reply::error("no such key"));
+    assert_eq!(
+        call(&["LINSERT", "l", "BEFORE", "b", "a"]),
+        Reply::Integer(5)
+    );
+    assert_eq!(
+        call(&["LINSERT", "l", "AFTER", "q", "a"]),
+        Reply::Integer(-1)
+    );
+    assert_eq!(call(&["LREM", "l", "0", "a"]), Reply::Integer(2));
+    assert_eq!(call(&["LRANGE", "l", "0", "-1"]), bulks(&["y", "b", "c"]));
+    assert_eq!(call(&["LTRIM", "l", "1", "-1"]), Reply::ok());
+    assert_eq!(call(&["LPOP", "l"]), Reply::bulk("b"));
+    assert_eq!(call(&["RPUSH", "l", "d", "e"]), Reply::Integer(3));
+    assert_eq!(call(&["RPOP", "l", "2"]), bulks(&["e", "d"]));
+    assert_eq!(
+        call(&["LPOP", "l", "-1"]),
+        Reply::error("value is out of range, must be positive")
+    );
+
+    // LMOVE，source为空后被删除
+    assert_eq!(
+        call(&["LMOVE", "l", "m", "LEFT", "RIGHT"]),
+        Reply::bulk("c")
+    );
+    assert_eq!(call(&["EXISTS", "l"]), Reply::Integer(0));
+    assert_eq!(call(&["LMOVE", "l", "m", "LEFT", "RIGHT"]), Reply::Null);
+    assert_eq!(call(&["LPOP", "l"]), Reply::Null);
+    assert_eq!(call(&["LPOP", "l", "1"]), Reply::NullArray);
+    assert_eq!(call(&["RPUSH", "m", "d"]), Reply::Integer(2));
+    assert_eq!(
+        call(&["LMOVE", "m", "m", "LEFT", "RIGHT"]),
+        Reply::bulk("c")
+    );
+    assert_eq!(call(&["LRANGE", "m", "0", "-1"]), bulks(&["d", "c"]));
+    assert_eq!(
+        call(&["LMOVE", "m", "m", "UP", "RIGHT"]),
+        Reply::error("syntax error")
+    );
+
+    // 类型错误
+    assert_eq!(call(&["SET", "s", "v"]), Reply::ok());
+    let wrong_type = Reply::Error(
+        "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+    );
+    assert_eq!(call(&["LPUSH", "s", "v"]), wrong_type);
+    assert_eq!(call(&["LMOVE", "m", "s", "LEFT", "LEFT"]), wrong_type);
+    assert_eq!(call(&["LLEN", "m"]), Reply::Integer(2));
+    assert_eq!(call(&["GET", "m"]), wrong_type);
+}
+
+#[test]
+fn test_blocking_commands() {
+    use crate::server::server::{call, read_reply, start_test_server};
+    use std::{io::Write, net::TcpStream, thread, time::Duration};
+
+    let addr = start_test_server();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let bulks = |values: &[&str]| Reply::Array(values.iter().map(Reply::bulk).collect());
+    // 发送命令但不等待回复
+    let send = |stream: &mut TcpStream, args: &[&str]| {
+        let mut buf = vec![];
+        Reply::Array(args.iter().map(Reply::bulk).collect())
+            .encode(crate::server::resp::RESP2, &mut buf);
+        stream.write_all(&buf).unwrap();
+    };
+    // 等到有count个客户端被阻塞
+    let wait_blocked = |stream: &mut TcpStream, count: usize| {
+        let expected = format!("blocked_clients:{}", count);
+        for _ in 0..200 {
+            if let Reply::Bulk(info) = call(stream, &["INFO", "clients"]) {
+                if String::from_utf8_lossy(&info).contains(&expected) {
+                    return;
+                }
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("clients are not blocked");
+    };
+
+    // 有数据时直接返回，按key的顺序取第一个非空的list
+    call(&mut stream, &["RPUSH", "b", "1", "2"]);
+    assert_eq!(
+        call(&mut stream, &["BLPOP", "a", "b", "0"]),
+        bulks(&["b", "1"])
+    );
+    assert_eq!(
+        call(&mut stream, &["BRPOP", "a", "b", "0"]),
+        bulks(&["b", "2"])
+    );
+    assert_eq!(call(&mut stream, &["EXISTS", "b"]), Reply::Integer(0));
+
+    // 超时
+    assert_eq!(call(&mut stream, &["BLPOP", "a", "0.05"]), Reply::NullArray);
+    assert_eq!(
+        call(&mut stream, &["BLMOVE", "a", "d", "LEFT", "LEFT", "0.05"]),
+        Reply::Null
+    );
+    assert!(
+        matches!(call(&mut stream, &["BLPOP", "a", "-1"]), Reply::Error(e) if e.contains("negative"))
+    );
+    assert!(
+        matches!(call(&mut stream, &["BLPOP", "a", "x"]), Reply::Error(e) if e.contains("not a float"))
+    );
+    call(&mut stream, &["SET", "s", "v"]);
+    assert!(
+        matches!(call(&mut stream, &["BLPOP", "s", "0"]), Reply::Error(e) if e.starts_with("WRONGTYPE"))
+    );
+
+    // 多个客户端阻塞在同一个key上，按阻塞的先后被唤醒
+    let mut first = TcpStream::connect(addr).unwrap();
+    let mut second = TcpStream::connect(addr).unwrap();
+    send(&mut first, &["BLPOP", "q", "0"]);
+    wait_blocked(&mut stream, 1);
+    send(&mut second, &["BRPOP", "other", "q", "0"]);
+    wait_blocked(&mut stream, 2);
+    assert_eq!(
+        call(&mut stream, &["RPUSH", "q", "x", "y", "z"]),
+        Reply::Integer(3)
+    );
+    assert_eq!(read_reply(&mut first), bulks(&["q", "x"]));
+    assert_eq!(read_reply(&mut second), bulks(&["q", "z"]));
+    assert_eq!(
+        call(&mut stream, &["LRANGE", "q", "0", "-1"]),
+        bulks(&["y"])
+    );
+    wait_blocked(&mut stream, 0);
+
+    // BLMOVE被唤醒后把元素移到目标list
+    send(&mut first, &["BLMOVE", "src", "dst", "RIGHT", "LEFT", "0"]);
+    wait_blocked(&mut stream, 1);
+    call(&mut stream, &["LPUSH", "src", "e"]);
+    assert_eq!(read_reply(&mut first), Reply::bulk("e"));
+    assert_eq!(
+        call(&mut stream, &["LRANGE", "dst", "0", "-1"]),
+        bulks(&["e"])
+    );
+
+    // BZPOPMIN
+    send(&mut first, &["BZPOPMIN", "z", "0"]);
+    wait_blocked(&mut stream, 1);
+    call(&mut stream, &["ZADD", "z", "2", "b", "1", "a"]);
+    assert_eq!(
+        read_reply(&mut first),
+        Reply::Array(vec![Reply::bulk("z"), Reply::bulk("a"), Reply::bulk("1")])
+    );
+    assert_eq!(
+        call(&mut stream, &["BZPOPMAX", "z", "0"]),
+        Reply::Array(vec![Reply::bulk("z"), Reply::bulk("b"), Reply::bulk("2")])
+    );
+
+    // 事务和脚本中不阻塞
+    call(&mut stream, &["MULTI"]);
+    call(&mut stream, &["BLPOP", "empty", "0"]);
+    call(&mut stream, &["BLMOVE", "empty", "d", "LEFT", "LEFT", "0"]);
+    assert_eq!(
+        call(&mut stream, &["EXEC"]),
+        Reply::Array(vec![Reply::NullArray, Reply::Null])
+    );
+    assert_eq!(
+        call(
+            &mut stream,
+            &[
+                "EVAL",
+                "return redis.call('BLPOP', KEYS[1], 0)",
+                "1",
+                "empty"
+            ]
+        ),
+        Reply::Null
+    );
+
+    // 阻塞的客户端断开连接后不再等待
+    send(&mut first, &["BLPOP", "gone", "0"]);
+    wait_blocked(&mut stream, 1);
+    drop(first);
+    wait_blocked(&mut stream, 0);
+    call(&mut stream, &["LPUSH", "gone", "v"]);
+    assert_eq!(call(&mut stream, &["LLEN", "gone"]), Reply::Integer(1));
+}
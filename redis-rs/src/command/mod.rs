@@ -0,0 +1,13 @@
+pub mod connection;
+pub mod hashes;
+pub mod keys;
+pub mod lists;
+pub mod pubsub;
+pub mod replication;
+pub mod scripting;
+pub mod server;
+pub mod sets;
+pub mod strings;
+pub mod table;
+pub mod transaction;
+pub mod zsets;
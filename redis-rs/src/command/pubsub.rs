@@ -0,0 +1,514 @@
+use crate::{common::error::Result, server::resp::Reply};
+
+use super::table::Context;
+
+/*
+ * SUBSCRIBE channel [channel ...]，每个频道回复一条确认，带上当前订阅的总数
+ */
+pub fn subscribe(ctx: &mut Context) -> Result<Reply> {
+    let limit = ctx.server.config().client_output_buffer_limit_pubsub;
+    let subscriber = ctx.client.subscriber(limit)?;
+    let mut pubsub = ctx.server.pubsub().lock().unwrap();
+    let mut replies = vec![];
+    for channel in &ctx.args[1..] {
+        if !ctx.client.channels.contains(channel) {
+            ctx.client.channels.push(channel.clone());
+            pubsub.subscribe(channel, &subscriber);
+        }
+        replies.push(confirm("subscribe", Reply::bulk(channel), ctx));
+    }
+    drop(pubsub);
+    return Ok(reply_each(ctx, replies));
+}
+
+/*
+ * UNSUBSCRIBE [channel ...]，没有参数时取消所有频道
+ */
+pub fn unsubscribe(ctx: &mut Context) -> Result<Reply> {
+    let channels = match ctx.args.len() {
+        1 => ctx.client.channels.clone(),
+        _ => ctx.args[1..].to_vec(),
+    };
+    let mut pubsub = ctx.server.pubsub().lock().unwrap();
+    let mut replies = vec![];
+    for channel in channels {
+        pubsub.unsubscribe(&channel, ctx.client.id);
+        ctx.client.channels.retain(|c| *c != channel);
+        replies.push(confirm("unsubscribe", Reply::Bulk(channel), ctx));
+    }
+    drop(pubsub);
+    if replies.is_empty() {
+        return Ok(confirm("unsubscribe", Reply::Null, ctx));
+    }
+    return Ok(reply_each(ctx, replies));
+}
+
+/*
+ * PSUBSCRIBE pattern [pattern ...]，glob风格的模式
+ */
+pub fn psubscribe(ctx: &mut Context) -> Result<Reply> {
+    let limit = ctx.server.config().client_output_buffer_limit_pubsub;
+    let subscriber = ctx.client.subscriber(limit)?;
+    let mut pubsub = ctx.server.pubsub().lock().unwrap();
+    let mut replies = vec![];
+    for pattern in &ctx.args[1..] {
+        if !ctx.client.patterns.contains(pattern) {
+            ctx.client.patterns.push(pattern.clone());
+            pubsub.psubscribe(pattern, &subscriber);
+        }
+        replies.push(confirm("psubscribe", Reply::bulk(pattern), ctx));
+    }
+    drop(pubsub);
+    return Ok(reply_each(ctx, replies));
+}
+
+pub fn punsubscribe(ctx: &mut Context) -> Result<Reply> {
+    let patterns = match ctx.args.len() {
+        1 => ctx.client.patterns.clone(),
+        _ => ctx.args[1..].to_vec(),
+    };
+    let mut pubsub = ctx.server.pubsub().lock().unwrap();
+    let mut replies = vec![];
+    for pattern in patterns {
+        pubsub.punsubscribe(&pattern, ctx.client.id);
+        ctx.client.patterns.retain(|p| *p != pattern);
+        replies.push(confirm("punsubscribe", Reply::Bulk(pattern), ctx));
+    }
+    drop(pubsub);
+    if replies.is_empty() {
+        return Ok(confirm("punsubscribe", Reply::Null, ctx));
+    }
+    return Ok(reply_each(ctx, replies));
+}
+
+/*
+ * PUBLISH channel message，返回收到消息的订阅者个数
+ */
+pub fn publish(ctx: &mut Context) -> Result<Reply> {
+    let receivers = ctx
+        .server
+        .pubsub()
+        .lock()
+        .unwrap()
+        .publish(&ctx.args[1], &ctx.args[2]);
+    return Ok(Reply::Integer(receivers as i64));
+}
+
+/*
+ * PUBSUB CHANNELS [pattern] | NUMSUB [channel ...] | NUMPAT
+ */
+pub fn pubsub(ctx: &mut Context) -> Result<Reply> {
+    let pubsub = ctx.server.pubsub().lock().unwrap();
+    let argc = ctx.args.len();
+    return Ok(match ctx.arg(1).to_lowercase().as_str() {
+        "channels" if argc <= 3 => {
+            let pattern = ctx.args.get(2).map(|p| p.as_slice());
+            Reply::Array(
+                pubsub
+                    .channels(pattern)
+                    .into_iter()
+                    .map(Reply::Bulk)
+                    .collect(),
+            )
+        }
+        "numsub" => Reply::Array(
+            ctx.args[2..]
+                .iter()
+                .flat_map(|channel| {
+                    return [
+                        Reply::bulk(channel),
+                        Reply::Integer(pubsub.numsub(channel) as i64),
+                    ];
+                })
+                .collect(),
+        ),
+        "numpat" if argc == 2 => Reply::Integer(pubsub.numpat() as i64),
+        _ => Reply::error(format!(
+            "unknown subcommand or wrong number of arguments for '{}'. Try PUBSUB HELP.",
+            ctx.arg(1)
+        )),
+    });
+}
+
+/*
+ * 订阅相关命令的确认：[类型, 频道或模式, 当前订阅的总数]，RESP3下为push类型
+ */
+fn confirm(kind: &str, name: Reply, ctx: &Context) -> Reply {
+    return Reply::Push(vec![
+        Reply::bulk(kind),
+        name,
+        Reply::Integer(ctx.client.subscriptions() as i64),
+    ]);
+}
+
+/*
+ * 多个频道时每个频道单独回复，最后一条作为命令的返回值
+ */
+fn reply_each(ctx: &mut Context, mut replies: Vec<Reply>) -> Reply {
+    let last = replies.pop().unwrap();
+    for reply in replies {
+        ctx.client.add_reply(&reply);
+    }
+    return last;
+}
+
+#[test]
+fn test_pubsub() {
+    use crate::server::server::{call, read_replies, start_test_server};
+    use std::{io::Write, net::TcpStream};
+
+    let send = |stream: &mut TcpStream, args: &[&str]| {
+        let mut buf = vec![];
+        Reply::Array(args.iter().map(Reply::bulk).collect())
+            .encode(crate::server::resp::RESP2, &mut buf);
+        stream.write_all(&buf).unwrap();
+    };
+    let message = |items: &[&str]| Reply::Push(items.iter().map(Reply::bulk).collect());
+    let count = |kind: &str, name: &str, count: i64| {
+        return Reply::Array(vec![
+            Reply::bulk(kind),
+            Reply::bulk(name),
+            Reply::Integer(count),
+        ]);
+    };
+
+    let addr = start_test_server();
+    let mut subscriber = TcpStream::connect(addr).unwrap();
+    let mut publisher = TcpStream::connect(addr).unwrap();
+
+    send(&mut subscriber, &["SUBSCRIBE", "news", "sports"]);
+    assert_eq!(
+        read_replies(&mut subscriber, 2),
+        vec![
+            count("subscribe", "news", 1),
+            count("subscribe", "sports", 2)
+        ]
+    );
+    assert_eq!(
+        call(&mut subscriber, &["PSUBSCRIBE", "n*"]),
+        count("psubscribe", "n*", 3)
+    );
+    // RESP2的订阅模式下不能执行普通命令
+    assert!(
+        matches!(call(&mut subscriber, &["GET", "a"]), Reply::Error(e) if e.contains("only (P|S)SUBSCRIBE"))
+    );
+    assert_eq!(
+        call(&mut subscriber, &["PING"]),
+        Reply::Array(vec![Reply::bulk("pong"), Reply::bulk("")])
+    );
+
+    assert_eq!(
+        call(&mut publisher, &["PUBLISH", "news", "hello"]),
+        Reply::Integer(2)
+    );
+    assert_eq!(
+        call(&mut publisher, &["PUBLISH", "nothing", "x"]),
+        Reply::Integer(1)
+    );
+    assert_eq!(
+        call(&mut publisher, &["PUBLISH", "other", "x"]),
+        Reply::Integer(0)
+    );
+    let replies = read_replies(&mut subscriber, 3);
+    assert!(replies.contains(&Reply::Array(vec![
+        Reply::bulk("message"),
+        Reply::bulk("news"),
+        Reply::bulk("hello")
+    ])));
+    assert!(replies.contains(&Reply::Array(vec![
+        Reply::bulk("pmessage"),
+        Reply::bulk("n*"),
+        Reply::bulk("news"),
+        Reply::bulk("hello")
+    ])));
+    assert_eq!(
+        replies[2],
+        Reply::Array(vec![
+            Reply::bulk("pmessage"),
+            Reply::bulk("n*"),
+            Reply::bulk("nothing"),
+            Reply::bulk("x")
+        ])
+    );
+
+    // 订阅状态的查询
+    match call(&mut publisher, &["PUBSUB", "CHANNELS"]) {
+        Reply::Array(mut channels) => {
+            channels.sort_by_key(|c| format!("{:?}", c));
+            assert_eq!(channels, vec![Reply::bulk("news"), Reply::bulk("sports")]);
+        }
+        reply => panic!("unexpected reply {:?}", reply),
+    }
+    assert_eq!(
+        call(&mut publisher, &["PUBSUB", "CHANNELS", "s*"]),
+        Reply::Array(vec![Reply::bulk("sports")])
+    );
+    assert_eq!(
+        call(&mut publisher, &["PUBSUB", "NUMSUB", "news", "none"]),
+        Reply::Array(vec![
+            Reply::bulk("news"),
+            Reply::Integer(1),
+            Reply::bulk("none"),
+            Reply::Integer(0)
+        ])
+    );
+    assert_eq!(
+        call(&mut publisher, &["PUBSUB", "NUMPAT"]),
+        Reply::Integer(1)
+    );
+
+    // 取消全部订阅后回到普通模式
+    send(&mut subscriber, &["UNSUBSCRIBE"]);
+    assert_eq!(read_replies(&mut subscriber, 2).len(), 2);
+    assert_eq!(
+        call(&mut subscriber, &["PUNSUBSCRIBE"]),
+        count("punsubscribe", "n*", 0)
+    );
+    assert_eq!(
+        call(&mut subscriber, &["UNSUBSCRIBE"]),
+        Reply::Array(vec![
+            Reply::bulk("unsubscribe"),
+            Reply::Null,
+            Reply::Integer(0)
+        ])
+    );
+    assert_eq!(call(&mut subscriber, &["SET", "a", "1"]), Reply::ok());
+    assert_eq!(
+        call(&mut publisher, &["PUBLISH", "news", "x"]),
+        Reply::Integer(0)
+    );
+
+    // RESP3下可以同时执行普通命令，消息为push类型
+    let mut resp3 = TcpStream::connect(addr).unwrap();
+    call(&mut resp3, &["HELLO", "3"]);
+    assert_eq!(
+        call(&mut resp3, &["SUBSCRIBE", "news"]),
+        Reply::Push(vec![
+            Reply::bulk("subscribe"),
+            Reply::bulk("news"),
+            Reply::Integer(1)
+        ])
+    );
+    assert_eq!(call(&mut resp3, &["GET", "a"]), Reply::bulk("1"));
+    call(&mut publisher, &["PUBLISH", "news", "hi"]);
+    assert_eq!(
+        read_replies(&mut resp3, 1)[0],
+        message(&["message", "news", "hi"])
+    );
+
+    // 不能在事务中订阅
+    call(&mut publisher, &["MULTI"]);
+    assert!(
+        matches!(call(&mut publisher, &["SUBSCRIBE", "x"]), Reply::Error(e) if e.contains("transaction"))
+    );
+    call(&mut publisher, &["DISCARD"]);
+}
+
+#[test]
+fn test_pubsub_output_buffer_limit() {
+    use crate::server::{
+        config::Config,
+        pubsub::OutputBufferLimit,
+        server::{call, start_test_server_with},
+    };
+    use std::net::TcpStream;
+
+    let addr = start_test_server_with(Config {
+        client_output_buffer_limit_pubsub: OutputBufferLimit {
+            hard: 1024 * 1024,
+            soft: 0,
+            soft_seconds: 0,
+        },
+        ..Config::default()
+    });
+    let mut subscriber = TcpStream::connect(addr).unwrap();
+    let mut publisher = TcpStream::connect(addr).unwrap();
+    call(&mut subscriber, &["SUBSCRIBE", "news"]);
+
+    // 订阅者不读取，缓冲区超过限制后被断开
+    let payload = "x".repeat(256 * 1024);
+    let mut published = 0;
+    while call(&mut publisher, &["PUBSUB", "NUMSUB", "news"])
+        != Reply::Array(vec![Reply::bulk("news"), Reply::Integer(0)])
+    {
+        assert!(published < 1000, "slow subscriber not disconnected");
+        call(&mut publisher, &["PUBLISH", "news", &payload]);
+        published += 1;
+    }
+    assert_eq!(
+        call(&mut publisher, &["PUBLISH", "news", "x"]),
+        Reply::Integer(0)
+    );
+}
+
+#[test]
+fn test_keyspace_notifications() {
+    use crate::db::notify;
+    use crate::server::{
+        config::Config,
+        server::{call, read_replies, start_test_server_with},
+    };
+    use std::{net::TcpStream, thread, time::Duration};
+
+    let addr = start_test_server_with(Config {
+        notify_keyspace_events: notify::parse_flags("KEA").unwrap(),
+        ..Config::default()
+    });
+    let mut subscriber = TcpStream::connect(addr).unwrap();
+    let mut client = TcpStream::connect(addr).unwrap();
+    call(&mut subscriber, &["PSUBSCRIBE", "__key*@0__:*"]);
+    // 一条命令产生的消息可能在同一次read中返回，必须一起读取
+    let mut expect = |events: &[(&str, &str)]| {
+        let mut messages = vec![];
+        for (key, event) in events {
+            for (channel, data) in [
+                (format!("__keyspace@0__:{}", key), event.to_string()),
+                (format!("__keyevent@0__:{}", event), key.to_string()),
+            ]
+            .iter()
+            {
+                messages.push(Reply::Array(vec![
+                    Reply::bulk("pmessage"),
+                    Reply::bulk("__key*@0__:*"),
+                    Reply::bulk(channel),
+                    Reply::bulk(data),
+                ]));
+            }
+        }
+        assert_eq!(read_replies(&mut subscriber, messages.len()), messages);
+    };
+
+    call(&mut client, &["SET", "k", "v"]);
+    expect(&[("k", "set")]);
+    call(&mut client, &["RPUSH", "l", "a"]);
+    expect(&[("l", "rpush")]);
+    call(&mut client, &["LPOP", "l"]);
+    expect(&[("l", "lpop"), ("l", "del")]);
+    call(&mut client, &["RENAME", "k", "k2"]);
+    expect(&[("k", "rename_from"), ("k2", "rename_to")]);
+
+    // 没有修改的写命令不产生事件，其他db的事件发到其他频道
+    call(&mut client, &["SREM", "s", "a"]);
+    call(&mut client, &["SELECT", "1"]);
+    call(&mut client, &["DEL", "k2"]);
+    call(&mut client, &["SELECT", "0"]);
+    call(&mut client, &["DEL", "k2"]);
+    expect(&[("k2", "del")]);
+
+    // 访问时惰性删除
+    call(&mut client, &["SET", "k", "v", "PX", "50"]);
+    expect(&[("k", "set"), ("k", "expire")]);
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(call(&mut client, &["GET", "k"]), Reply::Null);
+    expect(&[("k", "expired")]);
+
+    // 后台任务主动删除
+    call(&mut client, &["SET", "k", "v", "PX", "50"]);
+    expect(&[("k", "set"), ("k", "expire")]);
+    expect(&[("k", "expired")]);
+}
+
+#[test]
+fn test_keyspace_notification_flags() {
+    use crate::db::notify;
+    use crate::server::{
+        config::Config,
+        server::{call, read_replies, start_test_server_with},
+    };
+    use std::net::TcpStream;
+
+    // 只开启键事件通知中的new和过期事件
+    let addr = start_test_server_with(Config {
+        notify_keyspace_events: notify::parse_flags("Exn").unwrap(),
+        ..Config::default()
+    });
+    let mut subscriber = TcpStream::connect(addr).unwrap();
+    let mut client = TcpStream::connect(addr).unwrap();
+    call(&mut subscriber, &["SUBSCRIBE", "__keyevent@0__:new"]);
+    call(&mut client, &["SET", "k", "v"]);
+    call(&mut client, &["SET", "k", "v2"]);
+    call(&mut client, &["HSET", "h", "f", "v"]);
+    call(&mut client, &["PUBLISH", "__keyevent@0__:new", "end"]);
+    let messages: Vec<Reply> = ["k", "h", "end"]
+        .iter()
+        .map(|key| {
+            return Reply::Array(vec![
+                Reply::bulk("message"),
+                Reply::bulk("__keyevent@0__:new"),
+                Reply::bulk(key),
+            ]);
+        })
+        .collect();
+    assert_eq!(read_replies(&mut subscriber, 3), messages);
+}
+
+#[test]
+fn test_config_keyspace_events() {
+    use crate::server::server::{call, read_replies, start_test_server};
+    use std::net::TcpStream;
+
+    let addr = start_test_server();
+    let mut subscriber = TcpStream::connect(addr).unwrap();
+    let mut client = TcpStream::connect(addr).unwrap();
+    assert_eq!(
+        call(&mut client, &["CONFIG", "GET", "notify-keyspace-events"]),
+        Reply::Array(vec![Reply::bulk("notify-keyspace-events"), Reply::bulk("")])
+    );
+    call(&mut subscriber, &["SUBSCRIBE", "__keyevent@0__:set"]);
+    // 默认不发布，运行时开启之后才有通知
+    call(&mut client, &["SET", "a", "v"]);
+    assert_eq!(
+        call(
+            &mut client,
+            &["CONFIG", "SET", "notify-keyspace-events", "KEA"]
+        ),
+        Reply::ok()
+    );
+    assert_eq!(
+        call(&mut client, &["CONFIG", "GET", "notify-*"]),
+        Reply::Array(vec![
+            Reply::bulk("notify-keyspace-events"),
+            Reply::bulk("AKE")
+        ])
+    );
+    call(&mut client, &["SET", "b", "v"]);
+    assert_eq!(
+        read_replies(&mut subscriber, 1),
+        vec![Reply::Array(vec![
+            Reply::bulk("message"),
+            Reply::bulk("__keyevent@0__:set"),
+            Reply::bulk("b"),
+        ])]
+    );
+
+    assert!(matches!(
+        call(&mut client, &["CONFIG", "SET", "notify-keyspace-events", "Kw"]),
+        Reply::Error(e) if e.contains("Invalid argument")
+    ));
+    assert!(matches!(
+        call(&mut client, &["CONFIG", "SET", "port", "6380"]),
+        Reply::Error(e) if e.contains("Unsupported CONFIG parameter")
+    ));
+    assert!(matches!(
+        call(&mut client, &["CONFIG", "GET"]),
+        Reply::Error(_)
+    ));
+    assert_eq!(
+        call(&mut client, &["CONFIG", "GET", "appendonly"]),
+        Reply::Array(vec![Reply::bulk("appendonly"), Reply::bulk("no")])
+    );
+    call(
+        &mut client,
+        &["CONFIG", "SET", "notify-keyspace-events", ""],
+    );
+    call(&mut client, &["SET", "c", "v"]);
+    call(&mut client, &["PUBLISH", "__keyevent@0__:set", "end"]);
+    assert_eq!(
+        read_replies(&mut subscriber, 1),
+        vec![Reply::Array(vec![
+            Reply::bulk("message"),
+            Reply::bulk("__keyevent@0__:set"),
+            Reply::bulk("end"),
+        ])]
+    );
+}
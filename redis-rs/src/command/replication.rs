@@ -0,0 +1,313 @@
+use crate::{
+    common::error::Result,
+    db::{db::Db, rdb},
+    server::resp::Reply,
+};
+
+use super::table::Context;
+
+/*
+ * REPLICAOF host port | REPLICAOF NO ONE，SLAVEOF是它的别名
+ */
+pub fn replicaof(ctx: &mut Context) -> Result<Reply> {
+    let host = ctx.arg(1);
+    let port = ctx.arg(2);
+    if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+        if ctx.server.replication().lock().unwrap().unset_master() {
+            for db in ctx.dbs() {
+                db.set_replica(false);
+            }
+            println!("[replication] master mode enabled");
+        }
+        return Ok(Reply::ok());
+    }
+    let port = ctx.integer_arg(2)?;
+    if !(0..=u16::MAX as i64).contains(&port) {
+        return Ok(Reply::error("Invalid master port"));
+    }
+    if !ctx
+        .server
+        .replication()
+        .lock()
+        .unwrap()
+        .set_master(&host, port as u16)
+    {
+        return Ok(Reply::Status(
+            "OK Already connected to specified master".to_string(),
+        ));
+    }
+    for db in ctx.dbs() {
+        db.set_replica(true);
+    }
+    println!("[replication] replica of {}:{} enabled", host, port);
+    return Ok(Reply::ok());
+}
+
+/*
+ * REPLCONF listening-port <port> | capa <capability> | ack <offset> | getack *
+ * 从节点在握手和复制过程中使用，ACK不需要回复
+ */
+pub fn replconf(ctx: &mut Context) -> Result<Reply> {
+    if ctx.args.len().is_multiple_of(2) {
+        return Ok(Reply::error("syntax error"));
+    }
+    for i in (1..ctx.args.len()).step_by(2) {
+        match ctx.arg(i).to_lowercase().as_str() {
+            "listening-port" => {
+                let port = ctx.integer_arg(i + 1)?;
+                if !(0..=u16::MAX as i64).contains(&port) {
+                    return Ok(Reply::error("Invalid listening port"));
+                }
+                ctx.client.replica_listening_port = port as u16;
+            }
+            "ack" => {
+                let offset = ctx.integer_arg(i + 1)?;
+                ctx.server
+                    .replication()
+                    .lock()
+                    .unwrap()
+                    .ack(ctx.client.id, offset);
+                ctx.client.skip_reply = true;
+            }
+            // 复制线程收到GETACK后立即发送ACK
+            "capa" | "ip-address" | "getack" => {}
+            _ => {
+                return Ok(Reply::error(format!(
+                    "Unrecognized REPLCONF option: {}",
+                    ctx.arg(i)
+                )))
+            }
+        }
+    }
+    return Ok(Reply::ok());
+}
+
+/*
+ * PSYNC replid offset：可以从积压缓冲区补齐时回复+CONTINUE并发送缺失的数据，
+ * 否则回复+FULLRESYNC replid offset并发送rdb。
+ * 之后连接上的回复都经过输出缓冲区，复制流由Replication直接写入
+ */
+pub fn psync(ctx: &mut Context) -> Result<Reply> {
+    let offset = ctx.integer_arg(2)?;
+    let replid = ctx.arg(1);
+    return sync_replica(ctx, Some((replid, offset)));
+}
+
+/*
+ * SYNC：旧版本的全量同步，没有+FULLRESYNC回复
+ */
+pub fn sync(ctx: &mut Context) -> Result<Reply> {
+    return sync_replica(ctx, None);
+}
+
+fn sync_replica(ctx: &mut Context, psync: Option<(String, i64)>) -> Result<Reply> {
+    let server = ctx.server;
+    let mut replication = server.replication().lock().unwrap();
+    if replication.is_replica() && !replication.link_is_up() {
+        return Ok(Reply::error(
+            "NOMASTERLINK Can't SYNC while not connected with my master",
+        ));
+    }
+    let limit = server.config().client_output_buffer_limit_replica;
+    let output = ctx.client.subscriber(limit)?;
+    let partial = psync
+        .as_ref()
+        .and_then(|(replid, offset)| replication.partial_data(replid, *offset));
+    match partial {
+        Some(data) => {
+            output.push_reply(&Reply::Status(format!("CONTINUE {}", replication.replid())));
+            output.push(&data);
+            println!(
+                "[replication] partial resync with replica {}, {} bytes",
+                ctx.client.id,
+                data.len()
+            );
+        }
+        None => {
+            // 在db锁内生成快照，rdb与复制流的offset一致
+            let (replid, offset) = replication.prepare_full_sync();
+            let dbs: Vec<Db> = ctx.dbs().into_iter().map(|db| db.clone()).collect();
+            let rdb = rdb::dump(&dbs, vec![])?;
+            if psync.is_some() {
+                output.push_reply(&Reply::Status(format!("FULLRESYNC {} {}", replid, offset)));
+            }
+            output.push(format!("${}\r\n", rdb.len()).as_bytes());
+            output.push(&rdb);
+            println!(
+                "[replication] full resync with replica {}, {} bytes, offset {}",
+                ctx.client.id,
+                rdb.len(),
+                offset
+            );
+        }
+    }
+    replication.add_replica(
+        ctx.client.id,
+        ctx.client.peer_ip(),
+        ctx.client.replica_listening_port,
+        output,
+    );
+    ctx.client.skip_reply = true;
+    return Ok(Reply::ok());
+}
+
+#[cfg(test)]
+fn wait_until<F: FnMut() -> bool>(mut condition: F) {
+    for _ in 0..500 {
+        if condition() {
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    panic!("condition not met in time");
+}
+
+#[test]
+fn test_replication() {
+    use crate::server::server::{call, start_test_server};
+    use std::net::TcpStream;
+
+    let master_addr = start_test_server();
+    let mut master = TcpStream::connect(master_addr).unwrap();
+    let mut replica = TcpStream::connect(start_test_server()).unwrap();
+    call(&mut master, &["SET", "a", "1"]);
+    call(&mut master, &["SELECT", "1"]);
+    call(&mut master, &["RPUSH", "l", "x", "y"]);
+    assert_eq!(
+        call(
+            &mut replica,
+            &["REPLICAOF", "127.0.0.1", &master_addr.port().to_string()]
+        ),
+        Reply::ok()
+    );
+    assert_eq!(
+        call(
+            &mut replica,
+            &["REPLICAOF", "127.0.0.1", &master_addr.port().to_string()]
+        ),
+        Reply::Status("OK Already connected to specified master".to_string())
+    );
+    let info = |stream: &mut TcpStream| match call(stream, &["INFO", "replication"]) {
+        Reply::Bulk(info) => String::from_utf8(info).unwrap(),
+        reply => panic!("unexpected reply: {:?}", reply),
+    };
+    wait_until(|| info(&mut replica).contains("master_link_status:up"));
+
+    // 全量同步得到主节点的全部db
+    assert_eq!(call(&mut replica, &["GET", "a"]), Reply::bulk("1"));
+    call(&mut replica, &["SELECT", "1"]);
+    assert_eq!(
+        call(&mut replica, &["LRANGE", "l", "0", "-1"]),
+        Reply::Array(vec![Reply::bulk("x"), Reply::bulk("y")])
+    );
+    assert!(info(&mut master).contains("connected_slaves:1"));
+
+    // 之后的写命令和事务通过复制流传播
+    call(&mut master, &["LPOP", "l"]);
+    call(&mut master, &["MULTI"]);
+    call(&mut master, &["INCR", "n"]);
+    call(&mut master, &["INCR", "n"]);
+    call(&mut master, &["EXEC"]);
+    wait_until(|| call(&mut replica, &["GET", "n"]) == Reply::bulk("2"));
+    assert_eq!(
+        call(&mut replica, &["LRANGE", "l", "0", "-1"]),
+        Reply::Array(vec![Reply::bulk("y")])
+    );
+    assert_eq!(
+        call(&mut replica, &["SET", "x", "1"]),
+        Reply::Error("READONLY You can't write against a read only replica.".to_string())
+    );
+    assert_eq!(call(&mut replica, &["GET", "x"]), Reply::Null);
+
+    // 从节点上过期的key读不到，主节点过期后传播DEL才真正删除
+    call(&mut master, &["SET", "e", "v", "PX", "100"]);
+    wait_until(|| call(&mut replica, &["DBSIZE"]) == Reply::Integer(3));
+    std::thread::sleep(std::time::Duration::from_millis(150));
+    assert_eq!(call(&mut replica, &["GET", "e"]), Reply::Null);
+    wait_until(|| call(&mut replica, &["DBSIZE"]) == Reply::Integer(2));
+
+    // 成为主节点后可以写入
+    assert_eq!(call(&mut replica, &["REPLICAOF", "NO", "ONE"]), Reply::ok());
+    assert_eq!(call(&mut replica, &["SET", "x", "1"]), Reply::ok());
+    assert!(info(&mut replica).contains("role:master"));
+    wait_until(|| info(&mut master).contains("connected_slaves:0"));
+}
+
+#[test]
+fn test_psync() {
+    use crate::server::server::{call, start_test_server};
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    // n为None时读取一行（不含\r\n），否则读取n个字节
+    fn read(stream: &mut TcpStream, buf: &mut Vec<u8>, n: Option<usize>) -> Vec<u8> {
+        loop {
+            match n {
+                Some(n) if buf.len() >= n => return buf.drain(..n).collect(),
+                None => {
+                    if let Some(end) = buf.windows(2).position(|w| w == b"\r\n") {
+                        let line = buf.drain(..end + 2).collect::<Vec<u8>>();
+                        return line[..end].to_vec();
+                    }
+                }
+                _ => {}
+            }
+            let mut chunk = [0; 1024];
+            let len = stream.read(&mut chunk).unwrap();
+            assert!(len > 0, "connection closed");
+            buf.extend_from_slice(&chunk[..len]);
+        }
+    }
+    let psync = |stream: &mut TcpStream, replid: &str, offset: &str| {
+        let mut request = vec![];
+        Reply::Array(vec![
+            Reply::bulk("PSYNC"),
+            Reply::bulk(replid),
+            Reply::bulk(offset),
+        ])
+        .encode(crate::server::resp::RESP2, &mut request);
+        stream.write_all(&request).unwrap();
+    };
+
+    let addr = start_test_server();
+    let mut client = TcpStream::connect(addr).unwrap();
+    call(&mut client, &["SET", "a", "1"]);
+    let mut first = TcpStream::connect(addr).unwrap();
+    let mut buf = vec![];
+    psync(&mut first, "?", "-1");
+    let line = String::from_utf8(read(&mut first, &mut buf, None)).unwrap();
+    let parts: Vec<&str> = line.split(' ').collect();
+    assert_eq!(parts[0], "+FULLRESYNC");
+    assert_eq!(parts[2], "0");
+    let replid = parts[1].to_string();
+    let len = String::from_utf8(read(&mut first, &mut buf, None)).unwrap();
+    let len: usize = len.strip_prefix('$').unwrap().parse().unwrap();
+    let rdb = read(&mut first, &mut buf, Some(len));
+    let dbs = crate::db::rdb::restore(&rdb, "store", 16).unwrap();
+    assert_eq!(dbs[0].dict.dict_size(), 1);
+
+    // 全量同步之后的写命令从SELECT开始
+    call(&mut client, &["SET", "b", "2"]);
+    let stream = b"*2\r\n$6\r\nSELECT\r\n$1\r\n0\r\n*3\r\n$3\r\nSET\r\n$1\r\nb\r\n$1\r\n2\r\n";
+    assert_eq!(read(&mut first, &mut buf, Some(stream.len())), stream);
+
+    // 断线重连的从节点从积压缓冲区补齐
+    let mut second = TcpStream::connect(addr).unwrap();
+    let mut buf = vec![];
+    psync(&mut second, &replid, "24");
+    assert_eq!(
+        read(&mut second, &mut buf, None),
+        format!("+CONTINUE {}", replid).into_bytes()
+    );
+    assert_eq!(
+        read(&mut second, &mut buf, Some(stream.len() - 23)),
+        &stream[23..]
+    );
+
+    // offset超出积压缓冲区时全量同步
+    let mut third = TcpStream::connect(addr).unwrap();
+    let mut buf = vec![];
+    psync(&mut third, &replid, "1000");
+    let line = String::from_utf8(read(&mut third, &mut buf, None)).unwrap();
+    assert_eq!(line, format!("+FULLRESYNC {} {}", replid, stream.len()));
+}
@@ -0,0 +1,379 @@
+use std::sync::Arc;
+
+use crate::{
+    common::error::Result,
+    script::{
+        parser::FuncBody,
+        script::{self, Host},
+    },
+    server::{client::Client, resp::Reply, resp::RESP2},
+};
+
+use super::table::{Context, CMD_DENYOOM, CMD_NOSCRIPT, CMD_WRITE};
+
+/*
+ * EVAL script numkeys [key ...] [arg ...]
+ */
+pub fn eval(ctx: &mut Context) -> Result<Reply> {
+    let (sha, chunk) = match ctx.server.scripts().load(&ctx.args[1]) {
+        Ok(loaded) => loaded,
+        Err(reply) => return Ok(reply),
+    };
+    return run_script(ctx, &sha, &chunk);
+}
+
+/*
+ * EVALSHA sha1 numkeys [key ...] [arg ...]，执行SCRIPT LOAD或者EVAL缓存的脚本
+ */
+pub fn evalsha(ctx: &mut Context) -> Result<Reply> {
+    let sha = ctx.arg(1).to_ascii_lowercase();
+    return match ctx.server.scripts().get(&sha) {
+        Some(chunk) => run_script(ctx, &sha, &chunk),
+        None => Ok(Reply::Error(
+            "NOSCRIPT No matching script. Please use EVAL.".to_string(),
+        )),
+    };
+}
+
+/*
+ * SCRIPT LOAD|EXISTS|FLUSH|KILL
+ */
+pub fn script(ctx: &mut Context) -> Result<Reply> {
+    let scripts = ctx.server.scripts();
+    let argc = ctx.args.len();
+    return Ok(match ctx.arg(1).to_lowercase().as_str() {
+        "load" if argc == 3 => match scripts.load(&ctx.args[2]) {
+            Ok((sha, _)) => Reply::Bulk(sha.into_bytes()),
+            Err(reply) => reply,
+        },
+        "exists" if argc >= 3 => Reply::Array(
+            (2..argc)
+                .map(|i| Reply::Integer(scripts.exists(&ctx.arg(i)) as i64))
+                .collect(),
+        ),
+        // 缓存中只有编译结果，同步清空即可
+        "flush" if argc == 2 => {
+            scripts.flush();
+            Reply::ok()
+        }
+        "flush" if argc == 3 && matches!(ctx.arg(2).to_lowercase().as_str(), "async" | "sync") => {
+            scripts.flush();
+            Reply::ok()
+        }
+        "flush" if argc == 3 => Reply::error("SCRIPT FLUSH only support SYNC|ASYNC option"),
+        // 执行脚本时持有db锁，这里只会在没有脚本执行时到达
+        "kill" if argc == 2 => scripts.kill(),
+        _ => Reply::error(format!(
+            "unknown subcommand or wrong number of arguments for '{}'. Try SCRIPT HELP.",
+            ctx.arg(1)
+        )),
+    });
+}
+
+fn run_script(ctx: &mut Context, sha: &str, chunk: &Arc<FuncBody>) -> Result<Reply> {
+    let numkeys = ctx.integer_arg(2)?;
+    if numkeys < 0 {
+        return Ok(Reply::error("Number of keys can't be negative"));
+    }
+    let numkeys = numkeys as usize;
+    if numkeys > ctx.args.len() - 3 {
+        return Ok(Reply::error(
+            "Number of keys can't be greater than number of args",
+        ));
+    }
+    let keys = ctx.args[3..3 + numkeys].to_vec();
+    let argv = ctx.args[3 + numkeys..].to_vec();
+
+    let mut client = Client::fake();
+    client.db = ctx.client.db;
+    client.protocol = RESP2;
+    let wrapped = ctx.client.multi_propagated;
+    let mut host = ScriptHost {
+        ctx,
+        client,
+        wrapped,
+        multi: false,
+    };
+    host.ctx.server.scripts().start();
+    let reply = script::run(sha, chunk, &keys, &argv, &mut host);
+    host.ctx.server.scripts().finish();
+    // 脚本中的写命令在AOF和复制流中用MULTI/EXEC包围，保证原子性
+    if host.multi {
+        host.ctx
+            .server
+            .propagate(host.client.db, &[b"EXEC".to_vec()]);
+    }
+    return Ok(reply);
+}
+
+/*
+ * 脚本通过redis.call执行的命令：使用伪客户端，借用EVAL持有的db，
+ * 因此整个脚本在同一把锁内执行，是原子的
+ */
+struct ScriptHost<'c, 'a> {
+    ctx: &'c mut Context<'a>,
+    client: Client,
+    // EXEC已经传播了MULTI
+    wrapped: bool,
+    // 脚本自己传播了MULTI，结束时需要传播EXEC
+    multi: bool,
+}
+
+impl Host for ScriptHost<'_, '_> {
+    fn call(&mut self, args: Vec<Vec<u8>>) -> Reply {
+        let server = self.ctx.server;
+        let cmd = match server.commands().lookup(&args[0]) {
+            Some(cmd) => cmd,
+            None => return Reply::error("Unknown Redis command called from script"),
+        };
+        if !cmd.check_arity(args.len()) {
+            return Reply::error("Wrong number of args calling Redis command from script");
+        }
+        if cmd.has_flag(CMD_NOSCRIPT) {
+            return Reply::error("This Redis command is not allowed from script");
+        }
+        let is_replica = server.replication().lock().unwrap().is_replica();
+        if is_replica && cmd.has_flag(CMD_WRITE) {
+            return Reply::Error(
+                "READONLY You can't write against a read only replica.".to_string(),
+            );
+        }
+        let maxmemory = server.config().maxmemory;
+        if maxmemory > 0 && !is_replica && cmd.has_flag(CMD_DENYOOM) {
+            let used: usize = self.ctx.dbs().iter().map(|db| db.used_memory()).sum();
+            if used > maxmemory {
+                return Reply::Error(
+                    "OOM command not allowed when used memory > 'maxmemory'.".to_string(),
+                );
+            }
+        }
+        if cmd.has_flag(CMD_WRITE) {
+            if !self.wrapped && !self.multi {
+                server.propagate(self.client.db, &[b"MULTI".to_vec()]);
+                self.multi = true;
+            }
+            server.scripts().set_wrote();
+        }
+        let mut dbs = self.ctx.dbs();
+        return server.call_with(cmd, &mut self.client, &mut dbs, &args);
+    }
+
+    fn interrupted(&mut self) -> bool {
+        return self.ctx.server.scripts().killed();
+    }
+}
+
+#[test]
+fn test_eval() {
+    use crate::server::server::{call, start_test_server};
+    use std::net::TcpStream;
+
+    let addr = start_test_server();
+    let mut stream = TcpStream::connect(addr).unwrap();
+
+    assert_eq!(
+        call(&mut stream, &["EVAL", "return 1 + 2", "0"]),
+        Reply::Integer(3)
+    );
+    assert_eq!(
+        call(
+            &mut stream,
+            &[
+                "EVAL",
+                "return {KEYS[1], ARGV[1], ARGV[2]}",
+                "1",
+                "k",
+                "a",
+                "b"
+            ]
+        ),
+        Reply::Array(vec![Reply::bulk("k"), Reply::bulk("a"), Reply::bulk("b")])
+    );
+    // 返回值的转换：小数截断，true->1，false->nil，数组到第一个nil为止
+    assert_eq!(
+        call(
+            &mut stream,
+            &["EVAL", "return {3.99, true, false, 'x'}", "0"]
+        ),
+        Reply::Array(vec![
+            Reply::Integer(3),
+            Reply::Integer(1),
+            Reply::Null,
+            Reply::bulk("x")
+        ])
+    );
+    assert_eq!(
+        call(&mut stream, &["EVAL", "return {1, nil, 3}", "0"]),
+        Reply::Array(vec![Reply::Integer(1)])
+    );
+    assert_eq!(
+        call(
+            &mut stream,
+            &["EVAL", "return redis.status_reply('FINE')", "0"]
+        ),
+        Reply::Status("FINE".to_string())
+    );
+    assert_eq!(
+        call(
+            &mut stream,
+            &["EVAL", "return redis.error_reply('MY err')", "0"]
+        ),
+        Reply::Error("MY err".to_string())
+    );
+
+    // redis.call的回复转换成脚本中的值
+    let script = "redis.call('SET', KEYS[1], ARGV[1]) \
+                  local v = redis.call('INCRBY', KEYS[1], 5) \
+                  return {v, redis.call('GET', KEYS[1]), redis.call('GET', 'missing'), redis.call('PING')['ok']}";
+    assert_eq!(
+        call(&mut stream, &["EVAL", script, "1", "n", "10"]),
+        Reply::Array(vec![
+            Reply::Integer(15),
+            Reply::bulk("15"),
+            Reply::Null,
+            Reply::bulk("PONG"),
+        ])
+    );
+
+    // redis.call出错时中止脚本，redis.pcall返回错误
+    call(&mut stream, &["LPUSH", "list", "a"]);
+    let reply = call(
+        &mut stream,
+        &["EVAL", "return redis.call('GET', 'list')", "0"],
+    );
+    assert!(matches!(reply, Reply::Error(e) if e.starts_with("WRONGTYPE")));
+    assert_eq!(
+        call(
+            &mut stream,
+            &[
+                "EVAL",
+                "local r = redis.pcall('GET', 'list') return type(r.err)",
+                "0"
+            ]
+        ),
+        Reply::bulk("string")
+    );
+    let reply = call(&mut stream, &["EVAL", "return redis.call('NOPE')", "0"]);
+    assert!(matches!(reply, Reply::Error(e) if e.contains("Unknown Redis command")));
+    let reply = call(&mut stream, &["EVAL", "return redis.call('GET')", "0"]);
+    assert!(matches!(reply, Reply::Error(e) if e.contains("Wrong number of args")));
+    let reply = call(
+        &mut stream,
+        &["EVAL", "return redis.call('EVAL', 'return 1', 0)", "0"],
+    );
+    assert!(matches!(reply, Reply::Error(e) if e.contains("not allowed from script")));
+
+    // 运行时错误和编译错误
+    let reply = call(&mut stream, &["EVAL", "local t = nil\nreturn t.x", "0"]);
+    assert!(
+        matches!(&reply, Reply::Error(e) if e.starts_with("ERR user_script:2: attempt to index"))
+    );
+    let reply = call(&mut stream, &["EVAL", "x = 1", "0"]);
+    assert!(
+        matches!(reply, Reply::Error(e) if e.contains("Script attempted to create global variable 'x'"))
+    );
+    let reply = call(&mut stream, &["EVAL", "return (", "0"]);
+    assert!(matches!(reply, Reply::Error(e) if e.starts_with("ERR Error compiling script")));
+    let reply = call(&mut stream, &["EVAL", "return 1", "-1"]);
+    assert!(matches!(reply, Reply::Error(e) if e.contains("can't be negative")));
+    let reply = call(&mut stream, &["EVAL", "return 1", "2", "a"]);
+    assert!(matches!(reply, Reply::Error(e) if e.contains("greater than number of args")));
+
+    // 脚本缓存
+    let sha = "1b936e3fe509bcbc9cd0664897bbe8fd0cac101b";
+    let reply = call(&mut stream, &["EVALSHA", sha, "0"]);
+    assert!(matches!(reply, Reply::Error(e) if e.starts_with("NOSCRIPT")));
+    assert_eq!(
+        call(&mut stream, &["SCRIPT", "LOAD", "return 'hello'"]),
+        Reply::bulk(sha)
+    );
+    assert_eq!(
+        call(&mut stream, &["EVALSHA", &sha.to_uppercase(), "0"]),
+        Reply::bulk("hello")
+    );
+    assert_eq!(
+        call(&mut stream, &["SCRIPT", "EXISTS", sha, "0000"]),
+        Reply::Array(vec![Reply::Integer(1), Reply::Integer(0)])
+    );
+    assert_eq!(
+        call(&mut stream, &["SCRIPT", "FLUSH", "ASYNC"]),
+        Reply::ok()
+    );
+    assert_eq!(
+        call(&mut stream, &["SCRIPT", "EXISTS", sha]),
+        Reply::Array(vec![Reply::Integer(0)])
+    );
+    let reply = call(&mut stream, &["SCRIPT", "KILL"]);
+    assert!(matches!(reply, Reply::Error(e) if e.starts_with("NOTBUSY")));
+
+    // 无限递归在达到调用深度限制时报错，而不是耗尽连接线程的栈
+    let script = "local function f() return f() + 1 end return f()";
+    let reply = call(&mut stream, &["EVAL", script, "0"]);
+    assert!(matches!(reply, Reply::Error(e) if e.contains("stack overflow")));
+
+    // 脚本中的SELECT不影响调用者
+    call(
+        &mut stream,
+        &[
+            "EVAL",
+            "redis.call('SELECT', 1) redis.call('SET', 'db1', 'x')",
+            "0",
+        ],
+    );
+    assert_eq!(call(&mut stream, &["EXISTS", "db1"]), Reply::Integer(0));
+    call(&mut stream, &["SELECT", "1"]);
+    assert_eq!(call(&mut stream, &["GET", "db1"]), Reply::bulk("x"));
+}
+
+#[test]
+fn test_script_kill() {
+    use crate::server::{
+        config::Config,
+        server::{call, read_reply, start_test_server_with},
+    };
+    use std::{io::Write, net::TcpStream, thread, time::Duration};
+
+    let addr = start_test_server_with(Config {
+        busy_reply_threshold: Duration::from_millis(100),
+        ..Config::default()
+    });
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut other = TcpStream::connect(addr).unwrap();
+
+    // 执行过写命令的脚本不能被中止，结束后写入的结果可见
+    let script =
+        "redis.call('SET', 'k', 'v') local i = 0 while i < 300000 do i = i + 1 end return i";
+    let mut buf = vec![];
+    Reply::Array(["EVAL", script, "0"].iter().map(Reply::bulk).collect()).encode(RESP2, &mut buf);
+    stream.write_all(&buf).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    let reply = call(&mut other, &["SCRIPT", "KILL"]);
+    match reply {
+        Reply::Error(e) if e.starts_with("UNKILLABLE") => {
+            assert_eq!(read_reply(&mut stream), Reply::Integer(300000));
+        }
+        // 脚本已经执行完
+        Reply::Error(e) if e.starts_with("NOTBUSY") => {
+            assert_eq!(read_reply(&mut stream), Reply::Integer(300000));
+        }
+        reply => panic!("unexpected reply {:?}", reply),
+    }
+
+    // 只读的死循环超过阈值后其它客户端收到BUSY，SCRIPT KILL之后脚本返回错误
+    let mut buf = vec![];
+    Reply::Array(
+        ["EVAL", "while true do end", "0"]
+            .iter()
+            .map(Reply::bulk)
+            .collect(),
+    )
+    .encode(RESP2, &mut buf);
+    stream.write_all(&buf).unwrap();
+    thread::sleep(Duration::from_millis(300));
+    let reply = call(&mut other, &["GET", "k"]);
+    assert!(matches!(reply, Reply::Error(e) if e.starts_with("BUSY")));
+    assert_eq!(call(&mut other, &["SCRIPT", "KILL"]), Reply::ok());
+    let reply = read_reply(&mut stream);
+    assert!(matches!(reply, Reply::Error(e) if e.contains("Script killed by user")));
+    assert_eq!(call(&mut other, &["GET", "k"]), Reply::bulk("v"));
+}
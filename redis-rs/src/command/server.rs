@@ -0,0 +1,319 @@
+use std::{process, thread};
+
+use crate::{
+    common::{error::Result, utils},
+    db::{aof, db::Db, notify, rdb},
+    server::{resp::Reply, server::REDIS_VERSION},
+};
+
+use super::table::Context;
+
+/*
+ * BGREWRITEAOF，在后台根据当前数据生成新的AOF。
+ * 没有开启appendonly时只生成文件，不会开始追加写入
+ */
+pub fn bgrewriteaof(ctx: &mut Context) -> Result<Reply> {
+    let dbs: Vec<Db> = ctx.dbs().into_iter().map(|db| db.clone()).collect();
+    match ctx.server.aof() {
+        Some(aof) => {
+            if !aof::rewrite_background(aof.clone(), dbs) {
+                return Ok(Reply::error(
+                    "Background append only file rewriting already in progress",
+                ));
+            }
+        }
+        None => {
+            let path = ctx.server.config().aof_path();
+            thread::spawn(move || {
+                if let Err(e) = aof::rewrite(&dbs, &path) {
+                    println!("[aof] background rewrite failed: {}", e);
+                }
+            });
+        }
+    }
+    return Ok(Reply::Status(
+        "Background append only file rewriting started".to_string(),
+    ));
+}
+
+/*
+ * BGSAVE，在后台把所有db保存到db.rdb
+ */
+pub fn bgsave(ctx: &mut Context) -> Result<Reply> {
+    let dbs: Vec<Db> = ctx.dbs().into_iter().map(|db| db.clone()).collect();
+    if !rdb::save_background(dbs, ctx.server.rdb_saving().clone()) {
+        return Ok(Reply::error("Background save already in progress"));
+    }
+    return Ok(Reply::Status("Background saving started".to_string()));
+}
+
+/*
+ * FLUSHDB [ASYNC|SYNC]，清空当前db
+ */
+pub fn flushdb(ctx: &mut Context) -> Result<Reply> {
+    if !flush_mode_valid(ctx) {
+        return Ok(Reply::error("syntax error"));
+    }
+    ctx.server.touch_watched_db(ctx.client.db, ctx.db, None);
+    ctx.db.clear();
+    return Ok(Reply::ok());
+}
+
+/*
+ * FLUSHALL [ASYNC|SYNC]，清空所有db
+ */
+pub fn flushall(ctx: &mut Context) -> Result<Reply> {
+    if !flush_mode_valid(ctx) {
+        return Ok(Reply::error("syntax error"));
+    }
+    let server = ctx.server;
+    for (index, db) in ctx.dbs().into_iter().enumerate() {
+        server.touch_watched_db(index, db, None);
+        db.clear();
+    }
+    return Ok(Reply::ok());
+}
+
+// 清空总是同步进行，ASYNC只做语法上的兼容
+fn flush_mode_valid(ctx: &Context) -> bool {
+    return match ctx.args.len() {
+        1 => true,
+        2 => matches!(ctx.arg(1).to_lowercase().as_str(), "async" | "sync"),
+        _ => false,
+    };
+}
+
+pub fn dbsize(ctx: &mut Context) -> Result<Reply> {
+    return Ok(Reply::Integer(ctx.db.dict.dict_size() as i64));
+}
+
+/*
+ * INFO [section ...]，默认返回全部section：server、clients、memory、replication、keyspace
+ */
+pub fn info(ctx: &mut Context) -> Result<Reply> {
+    let sections: Vec<String> = ctx.args[1..]
+        .iter()
+        .map(|arg| String::from_utf8_lossy(arg).to_lowercase())
+        .collect();
+    let wanted = |name: &str| {
+        return sections.is_empty()
+            || sections
+                .iter()
+                .any(|s| s == name || matches!(s.as_str(), "all" | "default" | "everything"));
+    };
+    let config = ctx.server.config();
+    let mut info = String::new();
+    let mut section = |title: &str, fields: Vec<(String, String)>| {
+        if !info.is_empty() {
+            info.push_str("\r\n");
+        }
+        info.push_str(&format!("# {}\r\n", title));
+        for (name, value) in fields {
+            info.push_str(&format!("{}:{}\r\n", name, value));
+        }
+    };
+    if wanted("server") {
+        section(
+            "Server",
+            vec![
+                ("redis_version".to_string(), REDIS_VERSION.to_string()),
+                ("redis_mode".to_string(), "standalone".to_string()),
+                ("process_id".to_string(), process::id().to_string()),
+                ("tcp_port".to_string(), config.port.to_string()),
+                ("hz".to_string(), config.hz.to_string()),
+            ],
+        );
+    }
+    if wanted("clients") {
+        let blocked = ctx.server.blocking().lock().unwrap().blocked_clients();
+        section(
+            "Clients",
+            vec![("blocked_clients".to_string(), blocked.to_string())],
+        );
+    }
+    if wanted("memory") {
+        let used: usize = ctx.dbs().iter().map(|db| db.used_memory()).sum();
+        section(
+            "Memory",
+            vec![
+                ("used_memory".to_string(), used.to_string()),
+                ("maxmemory".to_string(), config.maxmemory.to_string()),
+                (
+                    "maxmemory_policy".to_string(),
+                    config.maxmemory_policy.name().to_string(),
+                ),
+            ],
+        );
+    }
+    if wanted("replication") {
+        section(
+            "Replication",
+            ctx.server.replication().lock().unwrap().info(),
+        );
+    }
+    if wanted("keyspace") {
+        let mut fields = vec![];
+        for (index, db) in ctx.dbs().into_iter().enumerate() {
+            let keys = db.dict.dict_size();
+            if keys > 0 {
+                fields.push((
+                    format!("db{}", index),
+                    format!("keys={},expires={},avg_ttl=0", keys, db.expires.dict_size()),
+                ));
+            }
+        }
+        section("Keyspace", fields);
+    }
+    return Ok(Reply::Bulk(info.into_bytes()));
+}
+
+/*
+ * CONFIG GET pattern | CONFIG SET parameter value，
+ * 其余参数只能在启动时指定，运行时只读；目前只有notify-keyspace-events可以修改
+ */
+pub fn config(ctx: &mut Context) -> Result<Reply> {
+    let sub = ctx.arg(1).to_lowercase();
+    return Ok(match (sub.as_str(), ctx.args.len()) {
+        ("get", 3) => {
+            let pattern = ctx.args[2].to_ascii_lowercase();
+            let mut items = vec![];
+            for (name, value) in config_parameters(ctx) {
+                if utils::glob_match(&pattern, name.as_bytes()) {
+                    items.push(Reply::bulk(name));
+                    items.push(Reply::bulk(value));
+                }
+            }
+            Reply::Array(items)
+        }
+        ("set", 4) => match ctx.arg(2).to_lowercase().as_str() {
+            "notify-keyspace-events" => match notify::parse_flags(&ctx.arg(3)) {
+                Some(flags) => {
+                    ctx.server.set_keyspace_events(flags);
+                    Reply::ok()
+                }
+                None => Reply::error(format!(
+                    "Invalid argument '{}' for CONFIG SET 'notify-keyspace-events'",
+                    ctx.arg(3)
+                )),
+            },
+            _ => Reply::error(format!("Unsupported CONFIG parameter: {}", ctx.arg(2))),
+        },
+        _ => Reply::error(format!(
+            "unknown subcommand or wrong number of arguments for '{}'. Try CONFIG HELP.",
+            ctx.arg(1)
+        )),
+    });
+}
+
+fn config_parameters(ctx: &Context) -> Vec<(&'static str, String)> {
+    let config = ctx.server.config();
+    let yes_no = |value: bool| if value { "yes" } else { "no" }.to_string();
+    return vec![
+        ("port", config.port.to_string()),
+        ("dir", config.dir.clone()),
+        ("databases", config.databases.to_string()),
+        ("hz", config.hz.to_string()),
+        ("maxmemory", config.maxmemory.to_string()),
+        (
+            "maxmemory-policy",
+            config.maxmemory_policy.name().to_string(),
+        ),
+        ("appendonly", yes_no(config.appendonly)),
+        (
+            "notify-keyspace-events",
+            notify::format_flags(ctx.server.keyspace_events()),
+        ),
+    ];
+}
+
+/*
+ * COMMAND | COMMAND COUNT | COMMAND INFO [name ...] | COMMAND GETKEYS cmd [arg ...]
+ */
+pub fn command(ctx: &mut Context) -> Result<Reply> {
+    let table = ctx.server.commands();
+    if ctx.args.len() == 1 {
+        return Ok(Reply::Array(
+            table.commands().iter().map(|cmd| cmd.info()).collect(),
+        ));
+    }
+    let sub = ctx.arg(1).to_lowercase();
+    return Ok(match (sub.as_str(), ctx.args.len()) {
+        ("count", 2) => Reply::Integer(table.len() as i64),
+        ("info", 2) => Reply::Array(table.commands().iter().map(|cmd| cmd.info()).collect()),
+        ("info", _) => Reply::Array(
+            ctx.args[2..]
+                .iter()
+                .map(|name| match table.lookup(name) {
+                    Some(cmd) => cmd.info(),
+                    None => Reply::NullArray,
+                })
+                .collect(),
+        ),
+        ("getkeys", n) if n >= 3 => {
+            let args = &ctx.args[2..];
+            match table.lookup(&args[0]) {
+                None => Reply::error("Invalid command specified"),
+                Some(cmd) if !cmd.check_arity(args.len()) => {
+                    Reply::error("Invalid number of arguments specified for command")
+                }
+                Some(cmd) => {
+                    let indexes = cmd.key_indexes(args.len());
+                    if indexes.is_empty() {
+                        Reply::error("The command has no key arguments")
+                    } else {
+                        Reply::Array(indexes.into_iter().map(|i| Reply::bulk(&args[i])).collect())
+                    }
+                }
+            }
+        }
+        _ => Reply::error(format!(
+            "unknown subcommand or wrong number of arguments for '{}'. Try COMMAND HELP.",
+            ctx.arg(1)
+        )),
+    });
+}
+
+#[test]
+fn test_command() {
+    use crate::server::server::{call, start_test_server};
+    use std::net::TcpStream;
+
+    let mut stream = TcpStream::connect(start_test_server()).unwrap();
+    let count = match call(&mut stream, &["COMMAND", "COUNT"]) {
+        Reply::Integer(count) => count,
+        reply => panic!("unexpected reply: {:?}", reply),
+    };
+    match call(&mut stream, &["COMMAND"]) {
+        Reply::Array(infos) => assert_eq!(infos.len() as i64, count),
+        reply => panic!("unexpected reply: {:?}", reply),
+    }
+    assert_eq!(
+        call(&mut stream, &["COMMAND", "INFO", "get", "foo"]),
+        Reply::Array(vec![
+            Reply::Array(vec![
+                Reply::bulk("get"),
+                Reply::Integer(2),
+                Reply::Array(vec![
+                    Reply::Status("readonly".to_string()),
+                    Reply::Status("fast".to_string())
+                ]),
+                Reply::Integer(1),
+                Reply::Integer(1),
+                Reply::Integer(1),
+            ]),
+            Reply::NullArray,
+        ])
+    );
+    assert_eq!(
+        call(&mut stream, &["COMMAND", "GETKEYS", "del", "a", "b"]),
+        Reply::Array(vec![Reply::bulk("a"), Reply::bulk("b")])
+    );
+    assert_eq!(
+        call(&mut stream, &["COMMAND", "GETKEYS", "ping"]),
+        Reply::error("The command has no key arguments")
+    );
+    assert_eq!(
+        call(&mut stream, &["SET", "k"]),
+        Reply::error("wrong number of arguments for 'set' command")
+    );
+}
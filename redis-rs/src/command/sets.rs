@@ -0,0 +1,376 @@
+use std::{collections::HashSet, sync::Arc};
+
+use crate::{
+    common::error::Result,
+    db::{
+        db::Db,
+        notify::{NOTIFY_GENERIC, NOTIFY_SET},
+    },
+    server::resp::Reply,
+    types::{
+        object::{Object, ObjectValue},
+        set::SetObject,
+    },
+};
+
+use super::{keys::ScanOptions, table::Context};
+
+enum SetOperation {
+    Inter,
+    Union,
+    Diff,
+}
+
+/*
+ * SADD key member [member ...]，返回新增的元素数
+ */
+pub fn sadd(ctx: &mut Context) -> Result<Reply> {
+    let key = ctx.key(1);
+    let set = set_or_create(ctx.db, &key)?;
+    let added = ctx.args[2..].iter().filter(|m| set.add(m)).count();
+    if added > 0 {
+        ctx.db.notify(NOTIFY_SET, "sadd", &key);
+    }
+    return Ok(Reply::Integer(added as i64));
+}
+
+/*
+ * SREM key member [member ...]，返回删除的元素数
+ */
+pub fn srem(ctx: &mut Context) -> Result<Reply> {
+    let key = ctx.key(1);
+    let removed = match lookup_set_mut(ctx.db, &key)? {
+        Some(set) => ctx.args[2..].iter().filter(|m| set.remove(m)).count(),
+        None => 0,
+    };
+    if removed > 0 {
+        ctx.db.notify(NOTIFY_SET, "srem", &key);
+    }
+    delete_if_empty(ctx.db, &key)?;
+    return Ok(Reply::Integer(removed as i64));
+}
+
+pub fn sismember(ctx: &mut Context) -> Result<Reply> {
+    let exists = match lookup_set(ctx.db, &ctx.key(1))? {
+        Some(set) => set.contains(&ctx.args[2]),
+        None => false,
+    };
+    return Ok(Reply::Integer(exists as i64));
+}
+
+pub fn smismember(ctx: &mut Context) -> Result<Reply> {
+    let members = &ctx.args[2..];
+    let exists = match lookup_set(ctx.db, &ctx.key(1))? {
+        Some(set) => members.iter().map(|m| set.contains(m)).collect(),
+        None => vec![false; members.len()],
+    };
+    return Ok(Reply::Array(
+        exists
+            .into_iter()
+            .map(|e| Reply::Integer(e as i64))
+            .collect(),
+    ));
+}
+
+pub fn smembers(ctx: &mut Context) -> Result<Reply> {
+    let members = match lookup_set(ctx.db, &ctx.key(1))? {
+        Some(set) => set.members(),
+        None => vec![],
+    };
+    return Ok(set_reply(members));
+}
+
+pub fn scard(ctx: &mut Context) -> Result<Reply> {
+    let len = match lookup_set(ctx.db, &ctx.key(1))? {
+        Some(set) => set.len(),
+        None => 0,
+    };
+    return Ok(Reply::Integer(len as i64));
+}
+
+/*
+ * SPOP key [count]，指定count时返回数组
+ */
+pub fn spop(ctx: &mut Context) -> Result<Reply> {
+    let count = match ctx.args.len() {
+        2 => None,
+        3 => match ctx.integer_arg(2)? {
+            count if count < 0 => {
+                return Ok(Reply::error("value is out of range, must be positive"))
+            }
+            count => Some(count as usize),
+        },
+        _ => return Ok(Reply::error("syntax error")),
+    };
+    let key = ctx.key(1);
+    let set = match lookup_set_mut(ctx.db, &key)? {
+        Some(set) => set,
+        None if count.is_some() => return Ok(Reply::Array(vec![])),
+        None => return Ok(Reply::Null),
+    };
+    let members: Vec<Vec<u8>> = (0..count.unwrap_or(1)).map_while(|_| set.pop()).collect();
+    if !members.is_empty() {
+        ctx.db.notify(NOTIFY_SET, "spop", &key);
+    }
+    delete_if_empty(ctx.db, &key)?;
+    // 随机弹出的元素以SREM写入AOF
+    if !members.is_empty() {
+        let mut args = vec![b"SREM".to_vec(), key];
+        args.extend(members.iter().cloned());
+        ctx.rewrite_args(&args);
+    }
+    return Ok(match count {
+        Some(_) => Reply::Array(members.into_iter().map(Reply::Bulk).collect()),
+        None => members
+            .into_iter()
+            .next()
+            .map(Reply::Bulk)
+            .unwrap_or(Reply::Null),
+    });
+}
+
+/*
+ * SRANDMEMBER key [count]，count为负数时元素可能重复
+ */
+pub fn srandmember(ctx: &mut Context) -> Result<Reply> {
+    let count = match ctx.args.len() {
+        2 => None,
+        3 => Some(ctx.integer_arg(2)?),
+        _ => return Ok(Reply::error("syntax error")),
+    };
+    let set = match lookup_set(ctx.db, &ctx.key(1))? {
+        Some(set) => set,
+        None if count.is_some() => return Ok(Reply::Array(vec![])),
+        None => return Ok(Reply::Null),
+    };
+    return Ok(match count {
+        Some(count) => Reply::Array(
+            set.random_members(count)
+                .into_iter()
+                .map(Reply::Bulk)
+                .collect(),
+        ),
+        None => set.random_member().map(Reply::Bulk).unwrap_or(Reply::Null),
+    });
+}
+
+pub fn sinter(ctx: &mut Context) -> Result<Reply> {
+    let set = compute(ctx.db, &ctx.args[1..], SetOperation::Inter)?;
+    return Ok(set_reply(set.members()));
+}
+
+pub fn sunion(ctx: &mut Context) -> Result<Reply> {
+    let set = compute(ctx.db, &ctx.args[1..], SetOperation::Union)?;
+    return Ok(set_reply(set.members()));
+}
+
+pub fn sdiff(ctx: &mut Context) -> Result<Reply> {
+    let set = compute(ctx.db, &ctx.args[1..], SetOperation::Diff)?;
+    return Ok(set_reply(set.members()));
+}
+
+pub fn sinterstore(ctx: &mut Context) -> Result<Reply> {
+    return store(ctx, SetOperation::Inter, "sinterstore");
+}
+
+pub fn sunionstore(ctx: &mut Context) -> Result<Reply> {
+    return store(ctx, SetOperation::Union, "sunionstore");
+}
+
+pub fn sdiffstore(ctx: &mut Context) -> Result<Reply> {
+    return store(ctx, SetOperation::Diff, "sdiffstore");
+}
+
+/*
+ * SSCAN key cursor [MATCH pattern] [COUNT count]
+ */
+pub fn sscan(ctx: &mut Context) -> Result<Reply> {
+    let options = ScanOptions::parse(ctx, 2)?;
+    let (cursor, members) = match lookup_set(ctx.db, &ctx.key(1))? {
+        Some(set) => set.scan(options.cursor, options.count),
+        None => (0, vec![]),
+    };
+    let items = members
+        .into_iter()
+        .filter(|m| options.matches(m))
+        .map(Reply::Bulk)
+        .collect();
+    return Ok(ScanOptions::reply(cursor, items));
+}
+
+/*
+ * S*STORE destination key [key ...]：覆盖destination（不论原来的类型），
+ * 结果为空时删除destination，返回结果集合的元素数
+ */
+fn store(ctx: &mut Context, operation: SetOperation, event: &'static str) -> Result<Reply> {
+    let set = compute(ctx.db, &ctx.args[2..], operation)?;
+    let destination = ctx.key(1);
+    let len = set.len();
+    let deleted = ctx.db.delete(&destination)?;
+    if len > 0 {
+        let obj = Object::new(ObjectValue::Set(Arc::new(set)))?;
+        ctx.db.set_object(&destination, obj, false)?;
+        ctx.db.notify(NOTIFY_SET, event, &destination);
+    } else if deleted {
+        ctx.db.notify(NOTIFY_GENERIC, "del", &destination);
+    }
+    return Ok(Reply::Integer(len as i64));
+}
+
+/*
+ * 不存在的key视为空集合，任意一个key类型错误时返回WRONGTYPE
+ */
+fn compute(db: &mut Db, keys: &[Vec<u8>], operation: SetOperation) -> Result<SetObject> {
+    let mut sets = Vec::with_capacity(keys.len());
+    for key in keys.iter() {
+        // 只读取，避免触发共享对象的复制
+        let members = match db.lookup_key(key)? {
+            Some(obj) => obj.as_set()?.members(),
+            None => vec![],
+        };
+        sets.push(members);
+    }
+    let (first, others) = sets.split_first().unwrap();
+    let others: Vec<HashSet<&Vec<u8>>> = others.iter().map(|s| s.iter().collect()).collect();
+    let members: Vec<&Vec<u8>> = match operation {
+        SetOperation::Inter => first
+            .iter()
+            .filter(|m| others.iter().all(|s| s.contains(m)))
+            .collect(),
+        SetOperation::Union => first
+            .iter()
+            .chain(others.iter().flat_map(|s| s.iter().copied()))
+            .collect(),
+        SetOperation::Diff => first
+            .iter()
+            .filter(|m| !others.iter().any(|s| s.contains(m)))
+            .collect(),
+    };
+    let mut set = SetObject::new();
+    for member in members {
+        set.add(member);
+    }
+    return Ok(set);
+}
+
+/*
+ * RESP3下回复set，RESP2下为数组
+ */
+fn set_reply(members: Vec<Vec<u8>>) -> Reply {
+    return Reply::Set(members.into_iter().map(Reply::Bulk).collect());
+}
+
+/*
+ * key不存在时返回None，不是集合时返回WRONGTYPE
+ */
+fn lookup_set<'a>(db: &'a mut Db, key: &[u8]) -> Result<Option<&'a SetObject>> {
+    return match db.lookup_key(key)? {
+        Some(obj) => Ok(Some(obj.as_set()?)),
+        None => Ok(None),
+    };
+}
+
+fn lookup_set_mut<'a>(db: &'a mut Db, key: &[u8]) -> Result<Option<&'a mut SetObject>> {
+    return match db.lookup_key_mut(key)? {
+        Some(obj) => Ok(Some(obj.as_set_mut()?)),
+        None => Ok(None),
+    };
+}
+
+fn set_or_create<'a>(db: &'a mut Db, key: &[u8]) -> Result<&'a mut SetObject> {
+    if db.lookup_key(key)?.is_none() {
+        let obj = Object::new(ObjectValue::Set(Arc::new(SetObject::new())))?;
+        db.set_object(key, obj, false)?;
+    }
+    return db.lookup_key_mut(key)?.unwrap().as_set_mut();
+}
+
+/*
+ * 集合为空时删除key，redis中不存在空集合
+ */
+fn delete_if_empty(db: &mut Db, key: &[u8]) -> Result<()> {
+    let empty = match db.lookup_key(key)? {
+        Some(obj) => obj.as_set().map(|set| set.is_empty()).unwrap_or(false),
+        None => false,
+    };
+    if empty {
+        db.delete(key)?;
+        db.notify(NOTIFY_GENERIC, "del", key);
+    }
+    return Ok(());
+}
+
+#[test]
+fn test_set_commands() {
+    use crate::server::server::{call, start_test_server};
+    use std::net::TcpStream;
+
+    let mut stream = TcpStream::connect(start_test_server()).unwrap();
+    let mut call = |args: &[&str]| call(&mut stream, args);
+    let bulks = |values: &[&str]| Reply::Array(values.iter().map(Reply::bulk).collect());
+    // dict编码下元素无序，排序后再比较
+    let sorted = |reply: Reply| match reply {
+        Reply::Array(mut items) => {
+            items.sort_by_key(|item| format!("{:?}", item));
+            Reply::Array(items)
+        }
+        reply => reply,
+    };
+
+    assert_eq!(call(&["SADD", "s", "3", "1", "2", "1"]), Reply::Integer(3));
+    assert_eq!(call(&["SMEMBERS", "s"]), bulks(&["1", "2", "3"]));
+    assert_eq!(call(&["SCARD", "s"]), Reply::Integer(3));
+    assert_eq!(call(&["SISMEMBER", "s", "2"]), Reply::Integer(1));
+    assert_eq!(
+        call(&["SMISMEMBER", "s", "2", "a"]),
+        Reply::Array(vec![Reply::Integer(1), Reply::Integer(0)])
+    );
+    assert_eq!(call(&["SREM", "s", "2", "a"]), Reply::Integer(1));
+    assert_eq!(call(&["SADD", "t", "a", "1", "b"]), Reply::Integer(3));
+    assert_eq!(call(&["SINTER", "s", "t"]), bulks(&["1"]));
+    assert_eq!(call(&["SINTER", "s", "t", "none"]), bulks(&[]));
+    assert_eq!(
+        sorted(call(&["SUNION", "s", "t"])),
+        bulks(&["1", "3", "a", "b"])
+    );
+    assert_eq!(sorted(call(&["SDIFF", "t", "s"])), bulks(&["a", "b"]));
+    assert_eq!(call(&["SDIFFSTORE", "d", "s", "t"]), Reply::Integer(1));
+    assert_eq!(call(&["SMEMBERS", "d"]), bulks(&["3"]));
+    assert_eq!(call(&["SUNIONSTORE", "d", "s", "t"]), Reply::Integer(4));
+    assert_eq!(call(&["SINTERSTORE", "d", "s", "none"]), Reply::Integer(0));
+    assert_eq!(call(&["EXISTS", "d"]), Reply::Integer(0));
+    assert_eq!(
+        call(&["SSCAN", "s", "0", "MATCH", "3"]),
+        ScanOptions::reply(0, vec![Reply::bulk("3")])
+    );
+    assert_eq!(
+        call(&["SSCAN", "t", "0", "MATCH", "a"]),
+        ScanOptions::reply(0, vec![Reply::bulk("a")])
+    );
+
+    // 随机操作
+    assert_eq!(call(&["SRANDMEMBER", "none"]), Reply::Null);
+    assert_eq!(call(&["SRANDMEMBER", "none", "2"]), bulks(&[]));
+    assert_eq!(sorted(call(&["SRANDMEMBER", "s", "5"])), bulks(&["1", "3"]));
+    match call(&["SRANDMEMBER", "s", "-3"]) {
+        Reply::Array(items) => assert_eq!(items.len(), 3),
+        reply => panic!("unexpected reply: {:?}", reply),
+    }
+    assert_eq!(sorted(call(&["SPOP", "s", "5"])), bulks(&["1", "3"]));
+    assert_eq!(call(&["EXISTS", "s"]), Reply::Integer(0));
+    assert_eq!(call(&["SPOP", "s"]), Reply::Null);
+    assert_eq!(
+        call(&["SPOP", "t", "-1"]),
+        Reply::error("value is out of range, must be positive")
+    );
+
+    // 类型错误
+    assert_eq!(call(&["SET", "str", "v"]), Reply::ok());
+    let wrong_type = Reply::Error(
+        "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+    );
+    assert_eq!(call(&["SADD", "str", "v"]), wrong_type);
+    assert_eq!(call(&["SUNION", "t", "str"]), wrong_type);
+    assert_eq!(call(&["SUNIONSTORE", "str", "t"]), Reply::Integer(3));
+    assert_eq!(call(&["SCARD", "str"]), Reply::Integer(3));
+}
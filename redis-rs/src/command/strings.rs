@@ -0,0 +1,406 @@
+use chrono::Local;
+
+use crate::{
+    common::error::{Error, ErrorKind, Result},
+    db::{
+        db::Db,
+        notify::{NOTIFY_GENERIC, NOTIFY_STRING},
+    },
+    server::resp::Reply,
+    types::{
+        object::{Object, ObjectValue},
+        strings::{self, StringObject},
+    },
+};
+
+use super::table::Context;
+
+// 与redis的proto-max-bulk-len一致
+const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+/*
+ * SET key value [NX | XX] [GET] [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+ * PXAT unix-time-milliseconds | KEEPTTL]
+ */
+pub fn set(ctx: &mut Context) -> Result<Reply> {
+    let argc = ctx.args.len();
+    let (mut nx, mut xx, mut get, mut keep_ttl) = (false, false, false, false);
+    let mut expire_time: Option<i64> = None;
+    let mut i = 3;
+    while i < argc {
+        let opt = ctx.arg(i).to_uppercase();
+        match opt.as_str() {
+            "NX" if !xx => nx = true,
+            "XX" if !nx => xx = true,
+            "GET" => get = true,
+            "KEEPTTL" if expire_time.is_none() => keep_ttl = true,
+            "EX" | "PX" | "EXAT" | "PXAT" if expire_time.is_none() && !keep_ttl && i + 1 < argc => {
+                i += 1;
+                let time = ctx.integer_arg(i)?;
+                let millis = match opt.as_str() {
+                    "EX" | "EXAT" => time.checked_mul(1000),
+                    _ => Some(time),
+                };
+                let millis = match (opt.as_str(), millis) {
+                    ("EX", Some(m)) | ("PX", Some(m)) => {
+                        m.checked_add(Local::now().timestamp_millis())
+                    }
+                    (_, m) => m,
+                };
+                match millis {
+                    Some(m) if time > 0 => expire_time = Some(m),
+                    _ => return Ok(Reply::error("invalid expire time in 'set' command")),
+                }
+            }
+            _ => return Ok(Reply::error("syntax error")),
+        }
+        i += 1;
+    }
+
+    let key = ctx.key(1);
+    let old = if get { ctx.db.get(&key)? } else { None };
+    let exists = ctx.db.exist(&key)?;
+    if (nx && exists) || (xx && !exists) {
+        return Ok(old.map(Reply::bulk).unwrap_or(Reply::Null));
+    }
+    set_value(ctx.db, &key, &ctx.args[2], keep_ttl)?;
+    ctx.db.notify(NOTIFY_STRING, "set", &key);
+    if let Some(time) = expire_time {
+        ctx.db.set_expire(&key, time)?;
+        ctx.db.notify(NOTIFY_GENERIC, "expire", &key);
+        // 相对的过期时间改写为绝对时间
+        let args = ctx.args;
+        ctx.rewrite_args(&[
+            b"SET".as_ref(),
+            &args[1],
+            &args[2],
+            b"PXAT",
+            time.to_string().as_bytes(),
+        ]);
+    }
+    if get {
+        return Ok(old.map(Reply::bulk).unwrap_or(Reply::Null));
+    }
+    return Ok(Reply::ok());
+}
+
+pub fn setnx(ctx: &mut Context) -> Result<Reply> {
+    let key = ctx.key(1);
+    if ctx.db.exist(&key)? {
+        return Ok(Reply::Integer(0));
+    }
+    set_value(ctx.db, &key, &ctx.args[2], false)?;
+    ctx.db.notify(NOTIFY_STRING, "set", &key);
+    return Ok(Reply::Integer(1));
+}
+
+pub fn get(ctx: &mut Context) -> Result<Reply> {
+    return Ok(bulk_or_null(ctx.db.get(&ctx.key(1))?));
+}
+
+/*
+ * GETSET key value，设置新值并清除过期时间
+ */
+pub fn getset(ctx: &mut Context) -> Result<Reply> {
+    let key = ctx.key(1);
+    let old = ctx.db.get(&key)?;
+    set_value(ctx.db, &key, &ctx.args[2], false)?;
+    ctx.db.notify(NOTIFY_STRING, "set", &key);
+    return Ok(bulk_or_null(old));
+}
+
+pub fn getdel(ctx: &mut Context) -> Result<Reply> {
+    let key = ctx.key(1);
+    let old = ctx.db.get(&key)?;
+    if old.is_some() {
+        ctx.db.delete(&key)?;
+        ctx.db.notify(NOTIFY_GENERIC, "del", &key);
+    }
+    return Ok(bulk_or_null(old));
+}
+
+/*
+ * MGET key [key ...]，不存在或者不是字符串的key返回nil
+ */
+pub fn mget(ctx: &mut Context) -> Result<Reply> {
+    let mut values = vec![];
+    for i in 1..ctx.args.len() {
+        let value = match ctx.db.lookup_key(&ctx.key(i))? {
+            Some(obj) => obj.as_string().ok().map(|s| s.get()),
+            None => None,
+        };
+        values.push(bulk_or_null(value));
+    }
+    return Ok(Reply::Array(values));
+}
+
+pub fn mset(ctx: &mut Context) -> Result<Reply> {
+    if ctx.args.len().is_multiple_of(2) {
+        return Ok(Reply::error("wrong number of arguments for 'mset' command"));
+    }
+    for i in (1..ctx.args.len()).step_by(2) {
+        set_value(ctx.db, &ctx.key(i), &ctx.args[i + 1], false)?;
+        ctx.db.notify(NOTIFY_STRING, "set", &ctx.key(i));
+    }
+    return Ok(Reply::ok());
+}
+
+/*
+ * 只要有一个key已存在就不做任何修改
+ */
+pub fn msetnx(ctx: &mut Context) -> Result<Reply> {
+    if ctx.args.len().is_multiple_of(2) {
+        return Ok(Reply::error(
+            "wrong number of arguments for 'msetnx' command",
+        ));
+    }
+    for i in (1..ctx.args.len()).step_by(2) {
+        if ctx.db.exist(&ctx.key(i))? {
+            return Ok(Reply::Integer(0));
+        }
+    }
+    for i in (1..ctx.args.len()).step_by(2) {
+        set_value(ctx.db, &ctx.key(i), &ctx.args[i + 1], false)?;
+        ctx.db.notify(NOTIFY_STRING, "set", &ctx.key(i));
+    }
+    return Ok(Reply::Integer(1));
+}
+
+pub fn append(ctx: &mut Context) -> Result<Reply> {
+    let key = ctx.key(1);
+    let len = match ctx.db.lookup_key_mut(&key)? {
+        Some(obj) => {
+            let s = obj.as_string_mut()?;
+            check_string_len(s.len() + ctx.args[2].len())?;
+            s.append(&ctx.args[2])
+        }
+        None => {
+            set_value(ctx.db, &key, &ctx.args[2], false)?;
+            ctx.args[2].len()
+        }
+    };
+    ctx.db.notify(NOTIFY_STRING, "append", &key);
+    return Ok(Reply::Integer(len as i64));
+}
+
+pub fn strlen(ctx: &mut Context) -> Result<Reply> {
+    let len = match ctx.db.lookup_key(&ctx.key(1))? {
+        Some(obj) => obj.as_string()?.len(),
+        None => 0,
+    };
+    return Ok(Reply::Integer(len as i64));
+}
+
+/*
+ * GETRANGE key start end
+ */
+pub fn getrange(ctx: &mut Context) -> Result<Reply> {
+    let start = ctx.integer_arg(2)?;
+    let end = ctx.integer_arg(3)?;
+    let value = match ctx.db.lookup_key(&ctx.key(1))? {
+        Some(obj) => obj.as_string()?.get_range(start, end),
+        None => vec![],
+    };
+    return Ok(Reply::Bulk(value));
+}
+
+/*
+ * SETRANGE key offset value，返回修改后的长度
+ */
+pub fn setrange(ctx: &mut Context) -> Result<Reply> {
+    let key = ctx.key(1);
+    let offset = ctx.integer_arg(2)?;
+    if offset < 0 {
+        return Ok(Reply::error("offset is out of range"));
+    }
+    let offset = offset as usize;
+    let value = &ctx.args[3];
+    let len = match ctx.db.lookup_key_mut(&key)? {
+        Some(obj) => {
+            let s = obj.as_string_mut()?;
+            if value.is_empty() {
+                return Ok(Reply::Integer(s.len() as i64));
+            }
+            check_string_len(offset + value.len())?;
+            s.set_range(offset, value)
+        }
+        // 空字符串不创建key
+        None if value.is_empty() => return Ok(Reply::Integer(0)),
+        None => {
+            check_string_len(offset + value.len())?;
+            let mut s = StringObject::from_bytes(b"");
+            let len = s.set_range(offset, value);
+            ctx.db
+                .set_object(&key, Object::new(ObjectValue::Strings(s.into()))?, false)?;
+            len
+        }
+    };
+    ctx.db.notify(NOTIFY_STRING, "setrange", &key);
+    return Ok(Reply::Integer(len as i64));
+}
+
+pub fn incr(ctx: &mut Context) -> Result<Reply> {
+    return incr_decr(ctx, 1);
+}
+
+pub fn decr(ctx: &mut Context) -> Result<Reply> {
+    return incr_decr(ctx, -1);
+}
+
+pub fn incrby(ctx: &mut Context) -> Result<Reply> {
+    let delta = ctx.integer_arg(2)?;
+    return incr_decr(ctx, delta);
+}
+
+pub fn decrby(ctx: &mut Context) -> Result<Reply> {
+    let delta = ctx.integer_arg(2)?;
+    return match delta.checked_neg() {
+        Some(delta) => incr_decr(ctx, delta),
+        None => Ok(Reply::error("decrement would overflow")),
+    };
+}
+
+pub fn incrbyfloat(ctx: &mut Context) -> Result<Reply> {
+    let delta = strings::parse_float(&ctx.args[2]).ok_or_else(|| {
+        return Error::new(ErrorKind::Invalid, "value is not a valid float".to_string());
+    })?;
+    let key = ctx.key(1);
+    let value = match ctx.db.lookup_key_mut(&key)? {
+        Some(obj) => obj.as_string_mut()?.incr_by_float(delta)?,
+        None => {
+            let mut s = StringObject::default();
+            let value = s.incr_by_float(delta)?;
+            ctx.db
+                .set_object(&key, Object::new(ObjectValue::Strings(s.into()))?, false)?;
+            value
+        }
+    };
+    ctx.db.notify(NOTIFY_STRING, "incrbyfloat", &key);
+    // 与redis一致，以SET写入AOF，避免重放时浮点运算的误差
+    ctx.rewrite_args(&[
+        b"SET".as_slice(),
+        &key,
+        value.to_string().as_bytes(),
+        b"KEEPTTL",
+    ]);
+    return Ok(Reply::bulk(value.to_string()));
+}
+
+/*
+ * 已存在的key原地修改（保留过期时间），不存在的key从0开始
+ */
+fn incr_decr(ctx: &mut Context, delta: i64) -> Result<Reply> {
+    let key = ctx.key(1);
+    let value = match ctx.db.lookup_key_mut(&key)? {
+        Some(obj) => obj.as_string_mut()?.incr_by(delta)?,
+        None => {
+            let value = StringObject::default().incr_by(delta)?;
+            let obj = Object::new(ObjectValue::Strings(ctx.db.create_integer(value)))?;
+            ctx.db.set_object(&key, obj, false)?;
+            value
+        }
+    };
+    ctx.db.notify(NOTIFY_STRING, "incrby", &key);
+    return Ok(Reply::Integer(value));
+}
+
+fn set_value(db: &mut Db, key: &[u8], value: &[u8], keep_ttl: bool) -> Result<bool> {
+    if !keep_ttl {
+        return db.set(key, value);
+    }
+    let obj = Object::new(ObjectValue::Strings(db.create_string(value)))?;
+    return db.set_object(key, obj, true);
+}
+
+fn bulk_or_null(value: Option<Box<[u8]>>) -> Reply {
+    return match value {
+        Some(v) => Reply::bulk(v),
+        None => Reply::Null,
+    };
+}
+
+fn check_string_len(len: usize) -> Result<()> {
+    if len > MAX_STRING_LEN {
+        return Err(Error::new(
+            ErrorKind::Invalid,
+            "string exceeds maximum allowed size (proto-max-bulk-len)".to_string(),
+        ));
+    }
+    return Ok(());
+}
+
+#[test]
+fn test_string_commands() {
+    use crate::server::server::{call, start_test_server};
+    use std::net::TcpStream;
+
+    let mut stream = TcpStream::connect(start_test_server()).unwrap();
+    let mut call = |args: &[&str]| call(&mut stream, args);
+
+    // SET选项
+    assert_eq!(call(&["SET", "k", "v", "NX"]), Reply::ok());
+    assert_eq!(call(&["SET", "k", "v2", "NX"]), Reply::Null);
+    assert_eq!(call(&["SET", "x", "v", "XX"]), Reply::Null);
+    assert_eq!(call(&["SET", "k", "v3", "XX", "GET"]), Reply::bulk("v"));
+    assert_eq!(
+        call(&["SET", "k", "v", "NX", "XX"]),
+        Reply::error("syntax error")
+    );
+    assert_eq!(
+        call(&["SET", "k", "v", "EX", "0"]),
+        Reply::error("invalid expire time in 'set' command")
+    );
+    assert_eq!(call(&["SET", "t", "v", "PX", "50"]), Reply::ok());
+    assert_eq!(call(&["SET", "t", "v2", "KEEPTTL"]), Reply::ok());
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert_eq!(call(&["GET", "t"]), Reply::Null);
+    assert_eq!(call(&["SETNX", "k", "v"]), Reply::Integer(0));
+    assert_eq!(call(&["GETSET", "k", "new"]), Reply::bulk("v3"));
+    assert_eq!(call(&["GETDEL", "k"]), Reply::bulk("new"));
+    assert_eq!(call(&["GET", "k"]), Reply::Null);
+
+    // APPEND / STRLEN / GETRANGE / SETRANGE
+    assert_eq!(call(&["APPEND", "s", "Hello"]), Reply::Integer(5));
+    assert_eq!(call(&["APPEND", "s", " World"]), Reply::Integer(11));
+    assert_eq!(call(&["STRLEN", "s"]), Reply::Integer(11));
+    assert_eq!(call(&["GETRANGE", "s", "-5", "-1"]), Reply::bulk("World"));
+    assert_eq!(call(&["SETRANGE", "s", "6", "Redis"]), Reply::Integer(11));
+    assert_eq!(call(&["GET", "s"]), Reply::bulk("Hello Redis"));
+    assert_eq!(call(&["SETRANGE", "p", "2", "x"]), Reply::Integer(3));
+    assert_eq!(call(&["GET", "p"]), Reply::bulk("\0\0x"));
+    assert_eq!(call(&["SETRANGE", "e", "2", ""]), Reply::Integer(0));
+    assert_eq!(call(&["EXISTS", "e"]), Reply::Integer(0));
+
+    // INCR家族
+    assert_eq!(call(&["INCR", "n"]), Reply::Integer(1));
+    assert_eq!(call(&["INCRBY", "n", "10"]), Reply::Integer(11));
+    assert_eq!(call(&["DECR", "n"]), Reply::Integer(10));
+    assert_eq!(call(&["DECRBY", "n", "20"]), Reply::Integer(-10));
+    assert_eq!(call(&["INCRBYFLOAT", "n", "0.5"]), Reply::bulk("-9.5"));
+    assert_eq!(
+        call(&["INCR", "s"]),
+        Reply::error("value is not an integer or out of range")
+    );
+    assert_eq!(
+        call(&["SET", "m", i64::MAX.to_string().as_str()]),
+        Reply::ok()
+    );
+    assert_eq!(
+        call(&["INCR", "m"]),
+        Reply::error("increment or decrement would overflow")
+    );
+
+    // MGET / MSET / MSETNX
+    assert_eq!(call(&["MSET", "a", "1", "b", "2"]), Reply::ok());
+    assert_eq!(
+        call(&["MSET", "a", "1", "b"]),
+        Reply::error("wrong number of arguments for 'mset' command")
+    );
+    assert_eq!(
+        call(&["MGET", "a", "b", "c"]),
+        Reply::Array(vec![Reply::bulk("1"), Reply::bulk("2"), Reply::Null])
+    );
+    assert_eq!(call(&["MSETNX", "c", "3", "a", "x"]), Reply::Integer(0));
+    assert_eq!(call(&["EXISTS", "c"]), Reply::Integer(0));
+    assert_eq!(call(&["MSETNX", "c", "3", "d", "4"]), Reply::Integer(1));
+}
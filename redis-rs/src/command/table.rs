@@ -0,0 +1,1044 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::{
+    common::error::{Error, ErrorKind, Result},
+    db::db::Db,
+    server::{
+        blocking::{BlockRequest, BlockedOp},
+        client::Client,
+        resp::Reply,
+        server::Server,
+    },
+    types::strings,
+};
+
+use super::{
+    connection, hashes, keys, lists, pubsub, replication, scripting, server, sets,
+    strings as string_commands, transaction, zsets,
+};
+
+/*
+ * 命令标记，与redis的命令表一致
+ */
+pub const CMD_WRITE: u32 = 1 << 0;
+pub const CMD_READONLY: u32 = 1 << 1;
+pub const CMD_DENYOOM: u32 = 1 << 2;
+pub const CMD_ADMIN: u32 = 1 << 3;
+pub const CMD_PUBSUB: u32 = 1 << 4;
+pub const CMD_NOSCRIPT: u32 = 1 << 5;
+pub const CMD_BLOCKING: u32 = 1 << 6;
+pub const CMD_LOADING: u32 = 1 << 7;
+pub const CMD_STALE: u32 = 1 << 8;
+pub const CMD_FAST: u32 = 1 << 9;
+
+const FLAG_NAMES: [(u32, &str); 10] = [
+    (CMD_WRITE, "write"),
+    (CMD_READONLY, "readonly"),
+    (CMD_DENYOOM, "denyoom"),
+    (CMD_ADMIN, "admin"),
+    (CMD_PUBSUB, "pubsub"),
+    (CMD_NOSCRIPT, "noscript"),
+    (CMD_BLOCKING, "blocking"),
+    (CMD_LOADING, "loading"),
+    (CMD_STALE, "stale"),
+    (CMD_FAST, "fast"),
+];
+
+/*
+ * 命令执行的上下文：执行期间持有db锁，保证单条命令的原子性
+ */
+pub struct Context<'a> {
+    pub server: &'a Server,
+    pub client: &'a mut Client,
+    // 客户端当前选择的db
+    pub db: &'a mut Db,
+    // 当前db前后的其它db，只有跨db的命令（MOVE、SWAPDB等）使用
+    dbs_before: Vec<&'a mut Db>,
+    dbs_after: Vec<&'a mut Db>,
+    // 包含命令名本身
+    pub args: &'a [Vec<u8>],
+    // 写入AOF的命令，为None时使用args；
+    // 依赖当前时间或者随机结果的命令需要改写成重放时结果确定的形式
+    pub propagate: Option<Vec<Vec<u8>>>,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(
+        server: &'a Server,
+        client: &'a mut Client,
+        dbs: &'a mut [&mut Db],
+        args: &'a [Vec<u8>],
+    ) -> Self {
+        let mut dbs_before: Vec<&'a mut Db> = dbs.iter_mut().map(|db| &mut **db).collect();
+        let dbs_after = dbs_before.split_off(client.db + 1);
+        let db = dbs_before.pop().unwrap();
+        return Self {
+            server,
+            client,
+            db,
+            dbs_before,
+            dbs_after,
+            args,
+            propagate: None,
+        };
+    }
+
+    /*
+     * 按编号排列的全部db，包括当前db
+     */
+    pub fn dbs(&mut self) -> Vec<&mut Db> {
+        return self
+            .dbs_before
+            .iter_mut()
+            .chain(std::iter::once(&mut self.db))
+            .chain(self.dbs_after.iter_mut())
+            .map(|db| &mut **db)
+            .collect();
+    }
+
+    /*
+     * 同时取得两个不同编号的db
+     */
+    pub fn db_pair(&mut self, first: usize, second: usize) -> (&mut Db, &mut Db) {
+        assert_ne!(first, second);
+        let mut dbs = self.dbs();
+        let (low, high) = (first.min(second), first.max(second));
+        let high_db = dbs.swap_remove(high);
+        let low_db = dbs.swap_remove(low);
+        return if first < second {
+            (low_db, high_db)
+        } else {
+            (high_db, low_db)
+        };
+    }
+
+    /*
+     * 解析db编号，超出配置的databases时返回错误
+     */
+    pub fn db_index_arg(&self, index: usize) -> Result<usize> {
+        let db = self.integer_arg(index)?;
+        if db < 0 || db as usize >= self.server.config().databases {
+            return Err(Error::new(
+                ErrorKind::Invalid,
+                "DB index is out of range".to_string(),
+            ));
+        }
+        return Ok(db as usize);
+    }
+
+    pub fn arg(&self, index: usize) -> String {
+        return String::from_utf8_lossy(&self.args[index]).to_string();
+    }
+
+    /*
+     * key按原始字节使用，不能像arg一样经过UTF-8转换，否则不同的非UTF-8 key会变成同一个key
+     */
+    pub fn key(&self, index: usize) -> Vec<u8> {
+        return self.args[index].clone();
+    }
+
+    pub fn rewrite_args<T: AsRef<[u8]>>(&mut self, args: &[T]) {
+        self.propagate = Some(args.iter().map(|arg| arg.as_ref().to_vec()).collect());
+    }
+
+    pub fn integer_arg(&self, index: usize) -> Result<i64> {
+        return strings::parse_integer(&self.args[index]).ok_or_else(|| {
+            return Error::new(
+                ErrorKind::Invalid,
+                "value is not an integer or out of range".to_string(),
+            );
+        });
+    }
+
+    /*
+     * 阻塞命令的超时时间，单位为秒，可以是小数，0表示一直等待
+     */
+    pub fn timeout_arg(&self, index: usize) -> Result<Option<Duration>> {
+        let timeout = strings::parse_float(&self.args[index]).ok_or_else(|| {
+            return Error::new(
+                ErrorKind::Invalid,
+                "timeout is not a float or out of range".to_string(),
+            );
+        })?;
+        if timeout < 0.0 {
+            return Err(Error::new(
+                ErrorKind::Invalid,
+                "timeout is negative".to_string(),
+            ));
+        }
+        if timeout == 0.0 {
+            return Ok(None);
+        }
+        return Duration::try_from_secs_f64(timeout)
+            .map(Some)
+            .map_err(|_| Error::new(ErrorKind::Invalid, "timeout is out of range".to_string()));
+    }
+
+    /*
+     * 阻塞命令没有取到数据：记录等待的key，命令返回后由Server阻塞连接；
+     * 事务和脚本中不阻塞，直接返回超时的回复
+     */
+    pub fn block(&mut self, keys: Vec<Vec<u8>>, timeout: Option<Duration>, op: BlockedOp) -> Reply {
+        let reply = op.timeout_reply();
+        if !self.client.deny_blocking {
+            self.client.blocked = Some(BlockRequest { keys, timeout, op });
+        }
+        return reply;
+    }
+}
+
+pub type CommandProc = fn(&mut Context) -> Result<Reply>;
+
+pub struct Command {
+    pub name: &'static str,
+    pub proc: CommandProc,
+    // 大于0表示参数个数固定，小于0表示至少-arity个参数（都包含命令名）
+    pub arity: i64,
+    pub flags: u32,
+    // key的位置：第一个、最后一个（负数表示从末尾倒数）以及步长，没有key时都为0
+    pub first_key: i64,
+    pub last_key: i64,
+    pub key_step: i64,
+}
+
+impl Command {
+    const fn new(
+        name: &'static str,
+        proc: CommandProc,
+        arity: i64,
+        flags: u32,
+        first_key: i64,
+        last_key: i64,
+        key_step: i64,
+    ) -> Self {
+        return Self {
+            name,
+            proc,
+            arity,
+            flags,
+            first_key,
+            last_key,
+            key_step,
+        };
+    }
+
+    pub fn check_arity(&self, argc: usize) -> bool {
+        let argc = argc as i64;
+        return (self.arity > 0 && argc == self.arity) || (self.arity < 0 && argc >= -self.arity);
+    }
+
+    pub fn has_flag(&self, flag: u32) -> bool {
+        return self.flags & flag != 0;
+    }
+
+    pub fn flag_names(&self) -> Vec<&'static str> {
+        return FLAG_NAMES
+            .iter()
+            .filter(|(flag, _)| self.has_flag(*flag))
+            .map(|(_, name)| *name)
+            .collect();
+    }
+
+    /*
+     * 根据key的位置从参数中取出所有key的下标
+     */
+    pub fn key_indexes(&self, argc: usize) -> Vec<usize> {
+        if self.first_key == 0 {
+            return vec![];
+        }
+        let argc = argc as i64;
+        let last = if self.last_key < 0 {
+            argc + self.last_key
+        } else {
+            self.last_key
+        };
+        let mut indexes = vec![];
+        let mut i = self.first_key;
+        while i <= last && i < argc {
+            indexes.push(i as usize);
+            i += self.key_step;
+        }
+        return indexes;
+    }
+
+    /*
+     * COMMAND / COMMAND INFO中的一条：
+     * name arity flags first_key last_key step
+     */
+    pub fn info(&self) -> Reply {
+        return Reply::Array(vec![
+            Reply::bulk(self.name),
+            Reply::Integer(self.arity),
+            Reply::Set(
+                self.flag_names()
+                    .into_iter()
+                    .map(|f| Reply::Status(f.to_string()))
+                    .collect(),
+            ),
+            Reply::Integer(self.first_key),
+            Reply::Integer(self.last_key),
+            Reply::Integer(self.key_step),
+        ]);
+    }
+}
+
+static COMMANDS: &[Command] = &[
+    // connection
+    Command::new("ping", connection::ping, -1, CMD_STALE | CMD_FAST, 0, 0, 0),
+    Command::new("echo", connection::echo, 2, CMD_FAST, 0, 0, 0),
+    Command::new(
+        "select",
+        connection::select,
+        2,
+        CMD_LOADING | CMD_STALE | CMD_FAST,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "hello",
+        connection::hello,
+        -1,
+        CMD_NOSCRIPT | CMD_LOADING | CMD_STALE | CMD_FAST,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "quit",
+        connection::quit,
+        -1,
+        CMD_NOSCRIPT | CMD_LOADING | CMD_STALE | CMD_FAST,
+        0,
+        0,
+        0,
+    ),
+    // strings
+    Command::new(
+        "set",
+        string_commands::set,
+        -3,
+        CMD_WRITE | CMD_DENYOOM,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "setnx",
+        string_commands::setnx,
+        3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "get",
+        string_commands::get,
+        2,
+        CMD_READONLY | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "getset",
+        string_commands::getset,
+        3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "getdel",
+        string_commands::getdel,
+        2,
+        CMD_WRITE | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "mget",
+        string_commands::mget,
+        -2,
+        CMD_READONLY | CMD_FAST,
+        1,
+        -1,
+        1,
+    ),
+    Command::new(
+        "mset",
+        string_commands::mset,
+        -3,
+        CMD_WRITE | CMD_DENYOOM,
+        1,
+        -1,
+        2,
+    ),
+    Command::new(
+        "msetnx",
+        string_commands::msetnx,
+        -3,
+        CMD_WRITE | CMD_DENYOOM,
+        1,
+        -1,
+        2,
+    ),
+    Command::new(
+        "append",
+        string_commands::append,
+        3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "strlen",
+        string_commands::strlen,
+        2,
+        CMD_READONLY | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "getrange",
+        string_commands::getrange,
+        4,
+        CMD_READONLY,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "setrange",
+        string_commands::setrange,
+        4,
+        CMD_WRITE | CMD_DENYOOM,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "incr",
+        string_commands::incr,
+        2,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "decr",
+        string_commands::decr,
+        2,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "incrby",
+        string_commands::incrby,
+        3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "decrby",
+        string_commands::decrby,
+        3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "incrbyfloat",
+        string_commands::incrbyfloat,
+        3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    // lists
+    Command::new(
+        "lpush",
+        lists::lpush,
+        -3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "rpush",
+        lists::rpush,
+        -3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new("lpop", lists::lpop, -2, CMD_WRITE | CMD_FAST, 1, 1, 1),
+    Command::new("rpop", lists::rpop, -2, CMD_WRITE | CMD_FAST, 1, 1, 1),
+    Command::new("lrange", lists::lrange, 4, CMD_READONLY, 1, 1, 1),
+    Command::new("lindex", lists::lindex, 3, CMD_READONLY, 1, 1, 1),
+    Command::new("lset", lists::lset, 4, CMD_WRITE | CMD_DENYOOM, 1, 1, 1),
+    Command::new(
+        "linsert",
+        lists::linsert,
+        5,
+        CMD_WRITE | CMD_DENYOOM,
+        1,
+        1,
+        1,
+    ),
+    Command::new("lrem", lists::lrem, 4, CMD_WRITE, 1, 1, 1),
+    Command::new("ltrim", lists::ltrim, 4, CMD_WRITE, 1, 1, 1),
+    Command::new("llen", lists::llen, 2, CMD_READONLY | CMD_FAST, 1, 1, 1),
+    Command::new("lmove", lists::lmove, 5, CMD_WRITE | CMD_DENYOOM, 1, 2, 1),
+    Command::new(
+        "blpop",
+        lists::blpop,
+        -3,
+        CMD_WRITE | CMD_BLOCKING,
+        1,
+        -2,
+        1,
+    ),
+    Command::new(
+        "brpop",
+        lists::brpop,
+        -3,
+        CMD_WRITE | CMD_BLOCKING,
+        1,
+        -2,
+        1,
+    ),
+    Command::new(
+        "blmove",
+        lists::blmove,
+        6,
+        CMD_WRITE | CMD_DENYOOM | CMD_BLOCKING,
+        1,
+        2,
+        1,
+    ),
+    // hashes
+    Command::new(
+        "hset",
+        hashes::hset,
+        -4,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "hsetnx",
+        hashes::hsetnx,
+        4,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new("hget", hashes::hget, 3, CMD_READONLY | CMD_FAST, 1, 1, 1),
+    Command::new("hmget", hashes::hmget, -3, CMD_READONLY | CMD_FAST, 1, 1, 1),
+    Command::new("hdel", hashes::hdel, -3, CMD_WRITE | CMD_FAST, 1, 1, 1),
+    Command::new(
+        "hexists",
+        hashes::hexists,
+        3,
+        CMD_READONLY | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new("hlen", hashes::hlen, 2, CMD_READONLY | CMD_FAST, 1, 1, 1),
+    Command::new("hgetall", hashes::hgetall, 2, CMD_READONLY, 1, 1, 1),
+    Command::new("hkeys", hashes::hkeys, 2, CMD_READONLY, 1, 1, 1),
+    Command::new("hvals", hashes::hvals, 2, CMD_READONLY, 1, 1, 1),
+    Command::new(
+        "hincrby",
+        hashes::hincrby,
+        4,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "hincrbyfloat",
+        hashes::hincrbyfloat,
+        4,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new("hscan", hashes::hscan, -3, CMD_READONLY, 1, 1, 1),
+    // sets
+    Command::new(
+        "sadd",
+        sets::sadd,
+        -3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new("srem", sets::srem, -3, CMD_WRITE | CMD_FAST, 1, 1, 1),
+    Command::new(
+        "sismember",
+        sets::sismember,
+        3,
+        CMD_READONLY | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "smismember",
+        sets::smismember,
+        -3,
+        CMD_READONLY | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new("smembers", sets::smembers, 2, CMD_READONLY, 1, 1, 1),
+    Command::new("scard", sets::scard, 2, CMD_READONLY | CMD_FAST, 1, 1, 1),
+    Command::new("spop", sets::spop, -2, CMD_WRITE | CMD_FAST, 1, 1, 1),
+    Command::new("srandmember", sets::srandmember, -2, CMD_READONLY, 1, 1, 1),
+    Command::new("sinter", sets::sinter, -2, CMD_READONLY, 1, -1, 1),
+    Command::new("sunion", sets::sunion, -2, CMD_READONLY, 1, -1, 1),
+    Command::new("sdiff", sets::sdiff, -2, CMD_READONLY, 1, -1, 1),
+    Command::new(
+        "sinterstore",
+        sets::sinterstore,
+        -3,
+        CMD_WRITE | CMD_DENYOOM,
+        1,
+        -1,
+        1,
+    ),
+    Command::new(
+        "sunionstore",
+        sets::sunionstore,
+        -3,
+        CMD_WRITE | CMD_DENYOOM,
+        1,
+        -1,
+        1,
+    ),
+    Command::new(
+        "sdiffstore",
+        sets::sdiffstore,
+        -3,
+        CMD_WRITE | CMD_DENYOOM,
+        1,
+        -1,
+        1,
+    ),
+    Command::new("sscan", sets::sscan, -3, CMD_READONLY, 1, 1, 1),
+    // sorted sets
+    Command::new(
+        "zadd",
+        zsets::zadd,
+        -4,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "zincrby",
+        zsets::zincrby,
+        4,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new("zrange", zsets::zrange, -4, CMD_READONLY, 1, 1, 1),
+    Command::new("zrank", zsets::zrank, 3, CMD_READONLY | CMD_FAST, 1, 1, 1),
+    Command::new(
+        "zrevrank",
+        zsets::zrevrank,
+        3,
+        CMD_READONLY | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new("zscore", zsets::zscore, 3, CMD_READONLY | CMD_FAST, 1, 1, 1),
+    Command::new("zcard", zsets::zcard, 2, CMD_READONLY | CMD_FAST, 1, 1, 1),
+    Command::new("zrem", zsets::zrem, -3, CMD_WRITE | CMD_FAST, 1, 1, 1),
+    Command::new("zcount", zsets::zcount, 4, CMD_READONLY | CMD_FAST, 1, 1, 1),
+    Command::new("zpopmin", zsets::zpopmin, -2, CMD_WRITE | CMD_FAST, 1, 1, 1),
+    Command::new("zpopmax", zsets::zpopmax, -2, CMD_WRITE | CMD_FAST, 1, 1, 1),
+    Command::new(
+        "bzpopmin",
+        zsets::bzpopmin,
+        -3,
+        CMD_WRITE | CMD_BLOCKING | CMD_FAST,
+        1,
+        -2,
+        1,
+    ),
+    Command::new(
+        "bzpopmax",
+        zsets::bzpopmax,
+        -3,
+        CMD_WRITE | CMD_BLOCKING | CMD_FAST,
+        1,
+        -2,
+        1,
+    ),
+    // 源key的个数由numkeys决定，这里只描述destination
+    Command::new(
+        "zunionstore",
+        zsets::zunionstore,
+        -4,
+        CMD_WRITE | CMD_DENYOOM,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "zinterstore",
+        zsets::zinterstore,
+        -4,
+        CMD_WRITE | CMD_DENYOOM,
+        1,
+        1,
+        1,
+    ),
+    // keys
+    Command::new("del", keys::del, -2, CMD_WRITE, 1, -1, 1),
+    Command::new(
+        "exists",
+        keys::exists,
+        -2,
+        CMD_READONLY | CMD_FAST,
+        1,
+        -1,
+        1,
+    ),
+    Command::new("unlink", keys::unlink, -2, CMD_WRITE | CMD_FAST, 1, -1, 1),
+    Command::new("expire", keys::expire, -3, CMD_WRITE | CMD_FAST, 1, 1, 1),
+    Command::new("pexpire", keys::pexpire, -3, CMD_WRITE | CMD_FAST, 1, 1, 1),
+    Command::new(
+        "expireat",
+        keys::expireat,
+        -3,
+        CMD_WRITE | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new(
+        "pexpireat",
+        keys::pexpireat,
+        -3,
+        CMD_WRITE | CMD_FAST,
+        1,
+        1,
+        1,
+    ),
+    Command::new("ttl", keys::ttl, 2, CMD_READONLY | CMD_FAST, 1, 1, 1),
+    Command::new("pttl", keys::pttl, 2, CMD_READONLY | CMD_FAST, 1, 1, 1),
+    Command::new("persist", keys::persist, 2, CMD_WRITE | CMD_FAST, 1, 1, 1),
+    Command::new("type", keys::r#type, 2, CMD_READONLY | CMD_FAST, 1, 1, 1),
+    Command::new("keys", keys::keys, 2, CMD_READONLY, 0, 0, 0),
+    Command::new("scan", keys::scan, -2, CMD_READONLY, 0, 0, 0),
+    Command::new("randomkey", keys::randomkey, 1, CMD_READONLY, 0, 0, 0),
+    Command::new("rename", keys::rename, 3, CMD_WRITE, 1, 2, 1),
+    Command::new("renamenx", keys::renamenx, 3, CMD_WRITE | CMD_FAST, 1, 2, 1),
+    Command::new("copy", keys::copy, -3, CMD_WRITE | CMD_DENYOOM, 1, 2, 1),
+    Command::new("object", keys::object, -2, CMD_READONLY, 2, 2, 1),
+    Command::new("move", keys::r#move, 3, CMD_WRITE | CMD_FAST, 1, 1, 1),
+    Command::new("swapdb", keys::swapdb, 3, CMD_WRITE | CMD_FAST, 0, 0, 0),
+    // pubsub
+    Command::new(
+        "subscribe",
+        pubsub::subscribe,
+        -2,
+        CMD_PUBSUB | CMD_NOSCRIPT | CMD_LOADING | CMD_STALE,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "unsubscribe",
+        pubsub::unsubscribe,
+        -1,
+        CMD_PUBSUB | CMD_NOSCRIPT | CMD_LOADING | CMD_STALE,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "psubscribe",
+        pubsub::psubscribe,
+        -2,
+        CMD_PUBSUB | CMD_NOSCRIPT | CMD_LOADING | CMD_STALE,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "punsubscribe",
+        pubsub::punsubscribe,
+        -1,
+        CMD_PUBSUB | CMD_NOSCRIPT | CMD_LOADING | CMD_STALE,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "publish",
+        pubsub::publish,
+        3,
+        CMD_PUBSUB | CMD_LOADING | CMD_STALE | CMD_FAST,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "pubsub",
+        pubsub::pubsub,
+        -2,
+        CMD_PUBSUB | CMD_LOADING | CMD_STALE,
+        0,
+        0,
+        0,
+    ),
+    // transactions
+    Command::new(
+        "multi",
+        transaction::multi,
+        1,
+        CMD_NOSCRIPT | CMD_LOADING | CMD_STALE | CMD_FAST,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "exec",
+        transaction::exec,
+        1,
+        CMD_NOSCRIPT | CMD_LOADING | CMD_STALE,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "discard",
+        transaction::discard,
+        1,
+        CMD_NOSCRIPT | CMD_LOADING | CMD_STALE | CMD_FAST,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "watch",
+        transaction::watch,
+        -2,
+        CMD_NOSCRIPT | CMD_LOADING | CMD_STALE | CMD_FAST,
+        1,
+        -1,
+        1,
+    ),
+    Command::new(
+        "unwatch",
+        transaction::unwatch,
+        1,
+        CMD_NOSCRIPT | CMD_LOADING | CMD_STALE | CMD_FAST,
+        0,
+        0,
+        0,
+    ),
+    // scripting
+    Command::new(
+        "eval",
+        scripting::eval,
+        -3,
+        CMD_NOSCRIPT | CMD_STALE,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "evalsha",
+        scripting::evalsha,
+        -3,
+        CMD_NOSCRIPT | CMD_STALE,
+        0,
+        0,
+        0,
+    ),
+    Command::new("script", scripting::script, -2, CMD_NOSCRIPT, 0, 0, 0),
+    // server
+    Command::new(
+        "command",
+        server::command,
+        -1,
+        CMD_LOADING | CMD_STALE,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "bgrewriteaof",
+        server::bgrewriteaof,
+        1,
+        CMD_ADMIN | CMD_NOSCRIPT,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "bgsave",
+        server::bgsave,
+        1,
+        CMD_ADMIN | CMD_NOSCRIPT,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "config",
+        server::config,
+        -2,
+        CMD_ADMIN | CMD_NOSCRIPT | CMD_LOADING | CMD_STALE,
+        0,
+        0,
+        0,
+    ),
+    Command::new("flushdb", server::flushdb, -1, CMD_WRITE, 0, 0, 0),
+    Command::new("flushall", server::flushall, -1, CMD_WRITE, 0, 0, 0),
+    Command::new(
+        "dbsize",
+        server::dbsize,
+        1,
+        CMD_READONLY | CMD_FAST,
+        0,
+        0,
+        0,
+    ),
+    Command::new("info", server::info, -1, CMD_LOADING | CMD_STALE, 0, 0, 0),
+    // replication
+    Command::new(
+        "replicaof",
+        replication::replicaof,
+        3,
+        CMD_ADMIN | CMD_NOSCRIPT | CMD_STALE,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "slaveof",
+        replication::replicaof,
+        3,
+        CMD_ADMIN | CMD_NOSCRIPT | CMD_STALE,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "replconf",
+        replication::replconf,
+        -1,
+        CMD_ADMIN | CMD_NOSCRIPT | CMD_LOADING | CMD_STALE,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "psync",
+        replication::psync,
+        -3,
+        CMD_ADMIN | CMD_NOSCRIPT,
+        0,
+        0,
+        0,
+    ),
+    Command::new(
+        "sync",
+        replication::sync,
+        1,
+        CMD_ADMIN | CMD_NOSCRIPT,
+        0,
+        0,
+        0,
+    ),
+];
+
+/*
+ * 命令表：命令名（小写）到命令的映射
+ */
+pub struct CommandTable {
+    commands: HashMap<&'static str, &'static Command>,
+}
+
+impl Default for CommandTable {
+    fn default() -> Self {
+        return Self {
+            commands: COMMANDS.iter().map(|cmd| (cmd.name, cmd)).collect(),
+        };
+    }
+}
+
+impl CommandTable {
+    pub fn lookup(&self, name: &[u8]) -> Option<&'static Command> {
+        let name = String::from_utf8_lossy(name).to_lowercase();
+        return self.commands.get(name.as_str()).copied();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.commands.len();
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        return self.commands.is_empty();
+    }
+
+    /*
+     * 按命令名排序，保证COMMAND输出稳定
+     */
+    pub fn commands(&self) -> Vec<&'static Command> {
+        let mut commands: Vec<&'static Command> = self.commands.values().copied().collect();
+        commands.sort_by_key(|cmd| cmd.name);
+        return commands;
+    }
+}
+
+#[test]
+fn test_command_table() {
+    let table = CommandTable::default();
+    assert_eq!(table.len(), COMMANDS.len());
+
+    let get = table.lookup(b"GeT").unwrap();
+    assert!(get.check_arity(2));
+    assert!(!get.check_arity(3));
+    assert_eq!(get.flag_names(), vec!["readonly", "fast"]);
+
+    let del = table.lookup(b"del").unwrap();
+    assert!(!del.check_arity(1));
+    assert!(del.check_arity(5));
+    assert_eq!(del.key_indexes(4), vec![1, 2, 3]);
+    assert!(table.lookup(b"ping").unwrap().key_indexes(2).is_empty());
+    assert!(table.lookup(b"foo").is_none());
+}
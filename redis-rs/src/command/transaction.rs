@@ -0,0 +1,161 @@
+use crate::{common::error::Result, server::resp::Reply};
+
+use super::table::Context;
+
+/*
+ * MULTI，之后的命令入队，直到EXEC或者DISCARD
+ */
+pub fn multi(ctx: &mut Context) -> Result<Reply> {
+    if ctx.client.multi.is_some() {
+        return Ok(Reply::error("MULTI calls can not be nested"));
+    }
+    ctx.client.multi = Some(vec![]);
+    return Ok(Reply::ok());
+}
+
+/*
+ * 事务中的EXEC由Server直接执行队列，这里只处理不在事务中的情况
+ */
+pub fn exec(_ctx: &mut Context) -> Result<Reply> {
+    return Ok(Reply::error("EXEC without MULTI"));
+}
+
+pub fn discard(ctx: &mut Context) -> Result<Reply> {
+    if ctx.client.multi.is_none() {
+        return Ok(Reply::error("DISCARD without MULTI"));
+    }
+    ctx.server.discard_transaction(ctx.client);
+    return Ok(Reply::ok());
+}
+
+/*
+ * WATCH key [key ...]，记录key当前是否存在，以便EXEC时发现key已经过期
+ */
+pub fn watch(ctx: &mut Context) -> Result<Reply> {
+    if ctx.client.multi.is_some() {
+        return Ok(Reply::error("WATCH inside MULTI is not allowed"));
+    }
+    for i in 1..ctx.args.len() {
+        let exists = ctx.db.exist(&ctx.key(i))?;
+        let db = ctx.client.db;
+        ctx.server.watch_key(ctx.client, db, &ctx.args[i], exists);
+    }
+    return Ok(Reply::ok());
+}
+
+pub fn unwatch(ctx: &mut Context) -> Result<Reply> {
+    ctx.server.unwatch_all(ctx.client);
+    return Ok(Reply::ok());
+}
+
+#[test]
+fn test_transaction() {
+    use crate::server::server::{call, start_test_server};
+    use std::net::TcpStream;
+
+    let addr = start_test_server();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut other = TcpStream::connect(addr).unwrap();
+
+    assert!(matches!(call(&mut stream, &["EXEC"]), Reply::Error(e) if e.contains("without MULTI")));
+    assert!(
+        matches!(call(&mut stream, &["DISCARD"]), Reply::Error(e) if e.contains("without MULTI"))
+    );
+
+    // 命令入队，EXEC时依次执行，执行时的错误单独返回
+    assert_eq!(call(&mut stream, &["MULTI"]), Reply::ok());
+    assert!(matches!(call(&mut stream, &["MULTI"]), Reply::Error(e) if e.contains("nested")));
+    let queued = Reply::Status("QUEUED".to_string());
+    assert_eq!(call(&mut stream, &["SET", "a", "1"]), queued);
+    assert_eq!(call(&mut stream, &["LPUSH", "a", "x"]), queued);
+    assert_eq!(call(&mut stream, &["INCR", "a"]), queued);
+    assert_eq!(call(&mut other, &["GET", "a"]), Reply::Null);
+    match call(&mut stream, &["EXEC"]) {
+        Reply::Array(replies) => {
+            assert_eq!(replies.len(), 3);
+            assert_eq!(replies[0], Reply::ok());
+            assert!(matches!(&replies[1], Reply::Error(e) if e.starts_with("WRONGTYPE")));
+            assert_eq!(replies[2], Reply::Integer(2));
+        }
+        reply => panic!("unexpected reply {:?}", reply),
+    }
+
+    // 入队时出错，整个事务被放弃
+    call(&mut stream, &["MULTI"]);
+    call(&mut stream, &["SET", "a", "3"]);
+    assert!(matches!(
+        call(&mut stream, &["NOSUCHCOMMAND"]),
+        Reply::Error(_)
+    ));
+    assert!(matches!(call(&mut stream, &["GET"]), Reply::Error(_)));
+    assert!(matches!(call(&mut stream, &["EXEC"]), Reply::Error(e) if e.starts_with("EXECABORT")));
+    assert_eq!(call(&mut stream, &["GET", "a"]), Reply::bulk("2"));
+
+    call(&mut stream, &["MULTI"]);
+    call(&mut stream, &["SET", "a", "3"]);
+    assert_eq!(call(&mut stream, &["DISCARD"]), Reply::ok());
+    assert_eq!(call(&mut stream, &["GET", "a"]), Reply::bulk("2"));
+
+    // WATCH的key被其他连接修改后EXEC返回空
+    assert_eq!(call(&mut stream, &["WATCH", "a"]), Reply::ok());
+    call(&mut other, &["SET", "a", "10"]);
+    call(&mut stream, &["MULTI"]);
+    assert!(
+        matches!(call(&mut stream, &["WATCH", "a"]), Reply::Error(e) if e.contains("inside MULTI"))
+    );
+    call(&mut stream, &["SET", "a", "3"]);
+    assert_eq!(call(&mut stream, &["EXEC"]), Reply::NullArray);
+    assert_eq!(call(&mut stream, &["GET", "a"]), Reply::bulk("10"));
+
+    // EXEC之后自动取消WATCH
+    call(&mut other, &["SET", "a", "11"]);
+    call(&mut stream, &["MULTI"]);
+    call(&mut stream, &["SET", "a", "3"]);
+    assert_eq!(
+        call(&mut stream, &["EXEC"]),
+        Reply::Array(vec![Reply::ok()])
+    );
+
+    // 没有被修改的key、UNWATCH之后的修改都不影响
+    call(&mut stream, &["WATCH", "a", "b"]);
+    call(&mut other, &["SET", "c", "1"]);
+    call(&mut stream, &["MULTI"]);
+    call(&mut stream, &["INCR", "a"]);
+    assert_eq!(
+        call(&mut stream, &["EXEC"]),
+        Reply::Array(vec![Reply::Integer(4)])
+    );
+    call(&mut stream, &["WATCH", "a"]);
+    call(&mut stream, &["UNWATCH"]);
+    call(&mut other, &["SET", "a", "1"]);
+    call(&mut stream, &["MULTI"]);
+    call(&mut stream, &["GET", "a"]);
+    assert_eq!(
+        call(&mut stream, &["EXEC"]),
+        Reply::Array(vec![Reply::bulk("1")])
+    );
+
+    // 监视的key过期、被FLUSHDB或者在其他db中修改
+    call(&mut stream, &["SET", "t", "1", "PX", "50"]);
+    call(&mut stream, &["WATCH", "t"]);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    call(&mut stream, &["MULTI"]);
+    call(&mut stream, &["SET", "x", "1"]);
+    assert_eq!(call(&mut stream, &["EXEC"]), Reply::NullArray);
+
+    call(&mut stream, &["WATCH", "a"]);
+    call(&mut other, &["FLUSHDB"]);
+    call(&mut stream, &["MULTI"]);
+    call(&mut stream, &["SET", "x", "1"]);
+    assert_eq!(call(&mut stream, &["EXEC"]), Reply::NullArray);
+
+    call(&mut stream, &["WATCH", "a"]);
+    call(&mut other, &["SELECT", "1"]);
+    call(&mut other, &["SET", "a", "1"]);
+    call(&mut stream, &["MULTI"]);
+    call(&mut stream, &["SET", "x", "1"]);
+    assert_eq!(
+        call(&mut stream, &["EXEC"]),
+        Reply::Array(vec![Reply::ok()])
+    );
+}
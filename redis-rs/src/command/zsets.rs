@@ -0,0 +1,707 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    common::error::{Error, ErrorKind, Result},
+    db::{
+        db::Db,
+        notify::{NOTIFY_GENERIC, NOTIFY_ZSET},
+    },
+    encoding::skiplist::SkiplistRange,
+    server::{
+        blocking::BlockedOp,
+        resp::{Reply, RESP3},
+    },
+    types::{
+        object::{Object, ObjectValue},
+        zset::{parse_score, LexRange, ScoreRange, ZSetEntry, ZSetObject},
+    },
+};
+
+use super::table::Context;
+
+#[derive(PartialEq)]
+enum RangeBy {
+    Rank,
+    Score,
+    Lex,
+}
+
+enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+/*
+ * ZADD key [NX|XX] [GT|LT] [CH] [INCR] score member [score member ...]
+ */
+pub fn zadd(ctx: &mut Context) -> Result<Reply> {
+    let (mut nx, mut xx, mut gt, mut lt, mut ch, mut incr) =
+        (false, false, false, false, false, false);
+    let mut i = 2;
+    while i < ctx.args.len() {
+        match ctx.arg(i).to_uppercase().as_str() {
+            "NX" => nx = true,
+            "XX" => xx = true,
+            "GT" => gt = true,
+            "LT" => lt = true,
+            "CH" => ch = true,
+            "INCR" => incr = true,
+            _ => break,
+        }
+        i += 1;
+    }
+    let pairs = &ctx.args[i..];
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return Ok(Reply::error("syntax error"));
+    }
+    if nx && xx {
+        return Ok(Reply::error(
+            "XX and NX options at the same time are not compatible",
+        ));
+    }
+    if (gt && lt) || (nx && (gt || lt)) {
+        return Ok(Reply::error(
+            "GT, LT, and/or NX options at the same time are not compatible",
+        ));
+    }
+    if incr && pairs.len() > 2 {
+        return Ok(Reply::error(
+            "INCR option supports a single increment-element pair",
+        ));
+    }
+    // 先校验全部分数，避免修改到一半失败
+    let mut entries = Vec::with_capacity(pairs.len() / 2);
+    for pair in pairs.chunks(2) {
+        entries.push((float_arg(&pair[0])?, &pair[1]));
+    }
+
+    let key = ctx.key(1);
+    // XX时不创建key
+    if xx && lookup_zset(ctx.db, &key)?.is_none() {
+        return Ok(if incr { Reply::Null } else { Reply::Integer(0) });
+    }
+    let zset = zset_or_create(ctx.db, &key)?;
+    let (mut added, mut updated) = (0, 0);
+    let mut incr_result = None;
+    for (score, member) in entries {
+        let current = zset.score(member);
+        if (nx && current.is_some()) || (xx && current.is_none()) {
+            continue;
+        }
+        let score = match (incr, current) {
+            (true, Some(current)) => current + score,
+            _ => score,
+        };
+        if score.is_nan() {
+            return Ok(Reply::error("resulting score is not a number (NaN)"));
+        }
+        match current {
+            Some(current) if (gt && score <= current) || (lt && score >= current) => continue,
+            Some(current) if current != score => updated += 1,
+            Some(_) => {}
+            None => added += 1,
+        }
+        zset.insert(member, score);
+        incr_result = Some(score);
+    }
+    if incr_result.is_some() {
+        ctx.db
+            .notify(NOTIFY_ZSET, if incr { "zincr" } else { "zadd" }, &key);
+    }
+    if incr {
+        return Ok(incr_result.map(Reply::Double).unwrap_or(Reply::Null));
+    }
+    return Ok(Reply::Integer(if ch { added + updated } else { added }));
+}
+
+/*
+ * ZINCRBY key increment member
+ */
+pub fn zincrby(ctx: &mut Context) -> Result<Reply> {
+    let increment = float_arg(&ctx.args[2])?;
+    let key = ctx.key(1);
+    let zset = zset_or_create(ctx.db, &key)?;
+    let member = &ctx.args[3];
+    let score = zset.score(member).unwrap_or(0.0) + increment;
+    if score.is_nan() {
+        return Ok(Reply::error("resulting score is not a number (NaN)"));
+    }
+    zset.insert(member, score);
+    ctx.db.notify(NOTIFY_ZSET, "zincr", &key);
+    return Ok(Reply::Double(score));
+}
+
+/*
+ * ZRANGE key start stop [BYSCORE|BYLEX] [REV] [LIMIT offset count] [WITHSCORES]
+ * BYSCORE和BYLEX时start和stop为区间，REV时先写max再写min
+ */
+pub fn zrange(ctx: &mut Context) -> Result<Reply> {
+    let mut by = RangeBy::Rank;
+    let (mut reverse, mut with_scores) = (false, false);
+    let mut limit = None;
+    let mut i = 4;
+    while i < ctx.args.len() {
+        match ctx.arg(i).to_uppercase().as_str() {
+            "BYSCORE" => by = RangeBy::Score,
+            "BYLEX" => by = RangeBy::Lex,
+            "REV" => reverse = true,
+            "WITHSCORES" => with_scores = true,
+            "LIMIT" if i + 2 < ctx.args.len() => {
+                limit = Some((ctx.integer_arg(i + 1)?, ctx.integer_arg(i + 2)?));
+                i += 2;
+            }
+            _ => return Ok(Reply::error("syntax error")),
+        }
+        i += 1;
+    }
+    if limit.is_some() && by == RangeBy::Rank {
+        return Ok(Reply::error(
+            "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX",
+        ));
+    }
+    if with_scores && by == RangeBy::Lex {
+        return Ok(Reply::error(
+            "syntax error, WITHSCORES not supported in combination with BYLEX",
+        ));
+    }
+
+    let (min, max) = match reverse {
+        true => (&ctx.args[3], &ctx.args[2]),
+        false => (&ctx.args[2], &ctx.args[3]),
+    };
+    let range: Option<Box<dyn SkiplistRange>> = match by {
+        RangeBy::Rank => None,
+        RangeBy::Score => Some(Box::new(ScoreRange::parse(min, max)?)),
+        RangeBy::Lex => Some(Box::new(LexRange::parse(min, max)?)),
+    };
+    let (start, stop) = match by {
+        RangeBy::Rank => (ctx.integer_arg(2)?, ctx.integer_arg(3)?),
+        _ => (0, 0),
+    };
+    let (offset, count) = limit.unwrap_or((0, -1));
+    let entries = match (lookup_zset(ctx.db, &ctx.key(1))?, range) {
+        // 负数的offset返回空
+        (_, _) if offset < 0 => vec![],
+        (Some(zset), Some(range)) => {
+            let count = if count < 0 {
+                None
+            } else {
+                Some(count as usize)
+            };
+            zset.range(range.as_ref(), reverse, offset as usize, count)
+        }
+        (Some(zset), None) => zset.range_by_rank(start, stop, reverse),
+        (None, _) => vec![],
+    };
+    return Ok(entries_reply(ctx, entries, with_scores));
+}
+
+pub fn zrank(ctx: &mut Context) -> Result<Reply> {
+    return rank(ctx, false);
+}
+
+pub fn zrevrank(ctx: &mut Context) -> Result<Reply> {
+    return rank(ctx, true);
+}
+
+pub fn zscore(ctx: &mut Context) -> Result<Reply> {
+    let score = match lookup_zset(ctx.db, &ctx.key(1))? {
+        Some(zset) => zset.score(&ctx.args[2]),
+        None => None,
+    };
+    return Ok(score.map(Reply::Double).unwrap_or(Reply::Null));
+}
+
+pub fn zcard(ctx: &mut Context) -> Result<Reply> {
+    let len = match lookup_zset(ctx.db, &ctx.key(1))? {
+        Some(zset) => zset.len(),
+        None => 0,
+    };
+    return Ok(Reply::Integer(len as i64));
+}
+
+/*
+ * ZREM key member [member ...]，返回删除的元素数
+ */
+pub fn zrem(ctx: &mut Context) -> Result<Reply> {
+    let key = ctx.key(1);
+    let removed = match lookup_zset_mut(ctx.db, &key)? {
+        Some(zset) => ctx.args[2..].iter().filter(|m| zset.remove(m)).count(),
+        None => 0,
+    };
+    if removed > 0 {
+        ctx.db.notify(NOTIFY_ZSET, "zrem", &key);
+    }
+    delete_if_empty(ctx.db, &key)?;
+    return Ok(Reply::Integer(removed as i64));
+}
+
+/*
+ * ZCOUNT key min max
+ */
+pub fn zcount(ctx: &mut Context) -> Result<Reply> {
+    let range = ScoreRange::parse(&ctx.args[2], &ctx.args[3])?;
+    let count = match lookup_zset(ctx.db, &ctx.key(1))? {
+        Some(zset) => zset.count(&range),
+        None => 0,
+    };
+    return Ok(Reply::Integer(count as i64));
+}
+
+pub fn zpopmin(ctx: &mut Context) -> Result<Reply> {
+    return pop(ctx, false);
+}
+
+pub fn zpopmax(ctx: &mut Context) -> Result<Reply> {
+    return pop(ctx, true);
+}
+
+/*
+ * BZPOPMIN/BZPOPMAX key [key ...] timeout，从第一个非空的有序集合弹出，都为空时阻塞
+ */
+pub fn bzpopmin(ctx: &mut Context) -> Result<Reply> {
+    return blocking_pop(ctx, false);
+}
+
+pub fn bzpopmax(ctx: &mut Context) -> Result<Reply> {
+    return blocking_pop(ctx, true);
+}
+
+pub fn zunionstore(ctx: &mut Context) -> Result<Reply> {
+    return store(ctx, true);
+}
+
+pub fn zinterstore(ctx: &mut Context) -> Result<Reply> {
+    return store(ctx, false);
+}
+
+/*
+ * 从有序集合中弹出分数最小（最大）的元素，供ZPOPMIN/ZPOPMAX和阻塞版本使用
+ */
+pub fn pop_entries(db: &mut Db, key: &[u8], max: bool, count: usize) -> Result<Vec<ZSetEntry>> {
+    let entries = match lookup_zset_mut(db, key)? {
+        Some(zset) => (0..count).map_while(|_| zset.pop(max)).collect(),
+        None => vec![],
+    };
+    if !entries.is_empty() {
+        db.notify(NOTIFY_ZSET, if max { "zpopmax" } else { "zpopmin" }, key);
+    }
+    delete_if_empty(db, key)?;
+    return Ok(entries);
+}
+
+fn rank(ctx: &mut Context, reverse: bool) -> Result<Reply> {
+    let rank = match lookup_zset(ctx.db, &ctx.key(1))? {
+        Some(zset) => zset.rank(&ctx.args[2], reverse),
+        None => None,
+    };
+    return Ok(rank
+        .map(|r| Reply::Integer(r as i64))
+        .unwrap_or(Reply::Null));
+}
+
+/*
+ * ZPOPMIN/ZPOPMAX key [count]
+ */
+fn pop(ctx: &mut Context, max: bool) -> Result<Reply> {
+    let count = match ctx.args.len() {
+        2 => None,
+        3 => match ctx.integer_arg(2)? {
+            count if count < 0 => {
+                return Ok(Reply::error("value is out of range, must be positive"))
+            }
+            count => Some(count as usize),
+        },
+        _ => return Ok(Reply::error("syntax error")),
+    };
+    let entries = pop_entries(ctx.db, &ctx.key(1), max, count.unwrap_or(1))?;
+    // 不指定count时总是回复扁平的member score
+    return Ok(match count {
+        Some(_) => entries_reply(ctx, entries, true),
+        None => Reply::Array(
+            entries
+                .into_iter()
+                .flat_map(|(m, s)| vec![Reply::Bulk(m), Reply::Double(s)])
+                .collect(),
+        ),
+    });
+}
+
+/*
+ * 回复[key, member, score]，传播为对应的ZPOPMIN/ZPOPMAX
+ */
+fn blocking_pop(ctx: &mut Context, max: bool) -> Result<Reply> {
+    let last = ctx.args.len() - 1;
+    let timeout = ctx.timeout_arg(last)?;
+    let op = BlockedOp::ZPop { max };
+    for i in 1..last {
+        if let Some((member, score)) = pop_entries(ctx.db, &ctx.key(i), max, 1)?.pop() {
+            ctx.rewrite_args(&op.command(&ctx.args[i]));
+            return Ok(Reply::Array(vec![
+                Reply::bulk(&ctx.args[i]),
+                Reply::Bulk(member),
+                Reply::Double(score),
+            ]));
+        }
+    }
+    return Ok(ctx.block(ctx.args[1..last].to_vec(), timeout, op));
+}
+
+/*
+ * ZUNIONSTORE/ZINTERSTORE destination numkeys key [key ...]
+ *     [WEIGHTS weight [weight ...]] [AGGREGATE SUM|MIN|MAX]
+ * 源key可以是集合，元素的分数视为1
+ */
+fn store(ctx: &mut Context, union: bool) -> Result<Reply> {
+    let numkeys = ctx.integer_arg(2)?;
+    if numkeys < 1 {
+        return Ok(Reply::error(format!(
+            "at least 1 input key is needed for '{}' command",
+            ctx.arg(0).to_lowercase()
+        )));
+    }
+    let numkeys = numkeys as usize;
+    if 3 + numkeys > ctx.args.len() {
+        return Ok(Reply::error("syntax error"));
+    }
+    let mut weights = vec![1.0; numkeys];
+    let mut aggregate = Aggregate::Sum;
+    let mut i = 3 + numkeys;
+    while i < ctx.args.len() {
+        match ctx.arg(i).to_uppercase().as_str() {
+            "WEIGHTS" if i + numkeys < ctx.args.len() => {
+                for (j, weight) in weights.iter_mut().enumerate() {
+                    *weight = parse_score(&ctx.args[i + 1 + j]).ok_or_else(|| {
+                        return Error::new(
+                            ErrorKind::Invalid,
+                            "weight value is not a float".to_string(),
+                        );
+                    })?;
+                }
+                i += numkeys;
+            }
+            "AGGREGATE" if i + 1 < ctx.args.len() => {
+                aggregate = match ctx.arg(i + 1).to_uppercase().as_str() {
+                    "SUM" => Aggregate::Sum,
+                    "MIN" => Aggregate::Min,
+                    "MAX" => Aggregate::Max,
+                    _ => return Ok(Reply::error("syntax error")),
+                };
+                i += 1;
+            }
+            _ => return Ok(Reply::error("syntax error")),
+        }
+        i += 1;
+    }
+
+    let mut sources = Vec::with_capacity(numkeys);
+    for (key, weight) in ctx.args[3..3 + numkeys].iter().zip(weights) {
+        let entries = source_entries(ctx.db, key)?;
+        sources.push(
+            entries
+                .into_iter()
+                .map(|(m, s)| (m, weighted(s, weight)))
+                .collect::<HashMap<_, _>>(),
+        );
+    }
+    let (first, others) = sources.split_first().unwrap();
+    let mut result = ZSetObject::new();
+    if union {
+        let mut scores: HashMap<&Vec<u8>, f64> = HashMap::new();
+        for (member, score) in sources.iter().flatten() {
+            scores
+                .entry(member)
+                .and_modify(|s| *s = aggregate.apply(*s, *score))
+                .or_insert(*score);
+        }
+        for (member, score) in scores {
+            result.insert(member, score);
+        }
+    } else {
+        for (member, score) in first.iter() {
+            let mut score = *score;
+            let mut all = true;
+            for other in others.iter() {
+                match other.get(member) {
+                    Some(s) => score = aggregate.apply(score, *s),
+                    None => {
+                        all = false;
+                        break;
+                    }
+                }
+            }
+            if all {
+                result.insert(member, score);
+            }
+        }
+    }
+
+    let destination = ctx.key(1);
+    let len = result.len();
+    let deleted = ctx.db.delete(&destination)?;
+    if len > 0 {
+        let obj = Object::new(ObjectValue::ZSet(Arc::new(result)))?;
+        ctx.db.set_object(&destination, obj, false)?;
+        let event = if union { "zunionstore" } else { "zinterstore" };
+        ctx.db.notify(NOTIFY_ZSET, event, &destination);
+    } else if deleted {
+        ctx.db.notify(NOTIFY_GENERIC, "del", &destination);
+    }
+    return Ok(Reply::Integer(len as i64));
+}
+
+impl Aggregate {
+    fn apply(&self, a: f64, b: f64) -> f64 {
+        return match self {
+            // inf + -inf视为0，与redis一致
+            Aggregate::Sum => {
+                let sum = a + b;
+                if sum.is_nan() {
+                    0.0
+                } else {
+                    sum
+                }
+            }
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        };
+    }
+}
+
+fn weighted(score: f64, weight: f64) -> f64 {
+    let score = score * weight;
+    return if score.is_nan() { 0.0 } else { score };
+}
+
+/*
+ * 读取ZUNIONSTORE/ZINTERSTORE的源key，只读取，避免触发共享对象的复制
+ */
+fn source_entries(db: &mut Db, key: &[u8]) -> Result<Vec<ZSetEntry>> {
+    let obj = match db.lookup_key(key)? {
+        Some(obj) => obj,
+        None => return Ok(vec![]),
+    };
+    if let Ok(set) = obj.as_set() {
+        return Ok(set.members().into_iter().map(|m| (m, 1.0)).collect());
+    }
+    return Ok(obj.as_zset()?.entries());
+}
+
+/*
+ * WITHSCORES时RESP3回复[member, score]对的数组，RESP2下为扁平数组
+ */
+fn entries_reply(ctx: &Context, entries: Vec<ZSetEntry>, with_scores: bool) -> Reply {
+    if !with_scores {
+        return Reply::Array(entries.into_iter().map(|(m, _)| Reply::Bulk(m)).collect());
+    }
+    if ctx.client.protocol >= RESP3 {
+        return Reply::Array(
+            entries
+                .into_iter()
+                .map(|(m, s)| Reply::Array(vec![Reply::Bulk(m), Reply::Double(s)]))
+                .collect(),
+        );
+    }
+    return Reply::Array(
+        entries
+            .into_iter()
+            .flat_map(|(m, s)| vec![Reply::Bulk(m), Reply::Double(s)])
+            .collect(),
+    );
+}
+
+fn float_arg(value: &[u8]) -> Result<f64> {
+    return parse_score(value).ok_or_else(|| {
+        return Error::new(ErrorKind::Invalid, "value is not a valid float".to_string());
+    });
+}
+
+/*
+ * key不存在时返回None，不是有序集合时返回WRONGTYPE
+ */
+fn lookup_zset<'a>(db: &'a mut Db, key: &[u8]) -> Result<Option<&'a ZSetObject>> {
+    return match db.lookup_key(key)? {
+        Some(obj) => Ok(Some(obj.as_zset()?)),
+        None => Ok(None),
+    };
+}
+
+fn lookup_zset_mut<'a>(db: &'a mut Db, key: &[u8]) -> Result<Option<&'a mut ZSetObject>> {
+    return match db.lookup_key_mut(key)? {
+        Some(obj) => Ok(Some(obj.as_zset_mut()?)),
+        None => Ok(None),
+    };
+}
+
+fn zset_or_create<'a>(db: &'a mut Db, key: &[u8]) -> Result<&'a mut ZSetObject> {
+    if db.lookup_key(key)?.is_none() {
+        let obj = Object::new(ObjectValue::ZSet(Arc::new(ZSetObject::new())))?;
+        db.set_object(key, obj, false)?;
+    }
+    return db.lookup_key_mut(key)?.unwrap().as_zset_mut();
+}
+
+/*
+ * 有序集合为空时删除key
+ */
+fn delete_if_empty(db: &mut Db, key: &[u8]) -> Result<()> {
+    let empty = match db.lookup_key(key)? {
+        Some(obj) => obj.as_zset().map(|zset| zset.is_empty()).unwrap_or(false),
+        None => false,
+    };
+    if empty {
+        db.delete(key)?;
+        db.notify(NOTIFY_GENERIC, "del", key);
+    }
+    return Ok(());
+}
+
+#[test]
+fn test_zset_commands() {
+    use crate::server::server::{call, start_test_server};
+    use std::net::TcpStream;
+
+    let mut stream = TcpStream::connect(start_test_server()).unwrap();
+    let mut call = |args: &[&str]| call(&mut stream, args);
+    let bulks = |values: &[&str]| Reply::Array(values.iter().map(Reply::bulk).collect());
+
+    assert_eq!(
+        call(&["ZADD", "z", "1", "a", "2", "b", "3", "c"]),
+        Reply::Integer(3)
+    );
+    assert_eq!(
+        call(&["ZADD", "z", "NX", "5", "a", "4", "d"]),
+        Reply::Integer(1)
+    );
+    assert_eq!(
+        call(&["ZADD", "z", "XX", "CH", "5", "a", "1", "e"]),
+        Reply::Integer(1)
+    );
+    assert_eq!(
+        call(&["ZADD", "z", "GT", "CH", "0", "a"]),
+        Reply::Integer(0)
+    );
+    assert_eq!(call(&["ZADD", "z", "INCR", "1", "a"]), Reply::bulk("6"));
+    assert_eq!(call(&["ZADD", "z", "LT", "INCR", "1", "a"]), Reply::Null);
+    assert_eq!(
+        call(&["ZADD", "z", "NX", "XX", "1", "a"]),
+        Reply::error("XX and NX options at the same time are not compatible")
+    );
+    assert_eq!(
+        call(&["ZADD", "z", "GT", "LT", "1", "a"]),
+        Reply::error("GT, LT, and/or NX options at the same time are not compatible")
+    );
+    assert_eq!(
+        call(&["ZADD", "z", "1", "a", "2"]),
+        Reply::error("syntax error")
+    );
+    assert_eq!(
+        call(&["ZADD", "z", "x", "a"]),
+        Reply::error("value is not a valid float")
+    );
+    assert_eq!(call(&["ZADD", "none", "XX", "1", "a"]), Reply::Integer(0));
+    assert_eq!(call(&["EXISTS", "none"]), Reply::Integer(0));
+
+    // z: b=2 c=3 d=4 a=6
+    assert_eq!(
+        call(&["ZRANGE", "z", "0", "-1"]),
+        bulks(&["b", "c", "d", "a"])
+    );
+    assert_eq!(
+        call(&["ZRANGE", "z", "0", "1", "REV", "WITHSCORES"]),
+        bulks(&["a", "6", "d", "4"])
+    );
+    assert_eq!(
+        call(&["ZRANGE", "z", "(2", "+inf", "BYSCORE", "LIMIT", "1", "2"]),
+        bulks(&["d", "a"])
+    );
+    assert_eq!(
+        call(&["ZRANGE", "z", "4", "-inf", "BYSCORE", "REV"]),
+        bulks(&["d", "c", "b"])
+    );
+    assert_eq!(
+        call(&["ZRANGE", "z", "0", "1", "LIMIT", "0", "1"]),
+        Reply::error(
+            "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"
+        )
+    );
+    assert_eq!(
+        call(&["ZRANGE", "z", "x", "1", "BYSCORE"]),
+        Reply::error("min or max is not a float")
+    );
+    assert_eq!(call(&["ZRANK", "z", "d"]), Reply::Integer(2));
+    assert_eq!(call(&["ZREVRANK", "z", "d"]), Reply::Integer(1));
+    assert_eq!(call(&["ZRANK", "z", "x"]), Reply::Null);
+    assert_eq!(call(&["ZSCORE", "z", "c"]), Reply::bulk("3"));
+    assert_eq!(call(&["ZCOUNT", "z", "3", "(6"]), Reply::Integer(2));
+    assert_eq!(call(&["ZINCRBY", "z", "-1.5", "c"]), Reply::bulk("1.5"));
+    assert_eq!(call(&["ZCARD", "z"]), Reply::Integer(4));
+
+    // 字典序
+    assert_eq!(
+        call(&["ZADD", "lex", "0", "a", "0", "b", "0", "c"]),
+        Reply::Integer(3)
+    );
+    assert_eq!(
+        call(&["ZRANGE", "lex", "[b", "+", "BYLEX"]),
+        bulks(&["b", "c"])
+    );
+    assert_eq!(
+        call(&["ZRANGE", "lex", "(c", "-", "BYLEX", "REV", "LIMIT", "0", "1"]),
+        bulks(&["b"])
+    );
+    assert_eq!(
+        call(&["ZRANGE", "lex", "b", "+", "BYLEX"]),
+        Reply::error("min or max not valid string range item")
+    );
+
+    // 弹出
+    assert_eq!(call(&["ZPOPMIN", "z"]), bulks(&["c", "1.5"]));
+    assert_eq!(call(&["ZPOPMAX", "z", "2"]), bulks(&["a", "6", "d", "4"]));
+    assert_eq!(call(&["ZPOPMAX", "none"]), bulks(&[]));
+    assert_eq!(call(&["ZREM", "z", "b", "x"]), Reply::Integer(1));
+    assert_eq!(call(&["EXISTS", "z"]), Reply::Integer(0));
+
+    // 并集和交集
+    call(&["ZADD", "z1", "1", "a", "2", "b"]);
+    call(&["ZADD", "z2", "3", "b", "4", "c"]);
+    call(&["SADD", "s", "a"]);
+    assert_eq!(
+        call(&["ZUNIONSTORE", "out", "2", "z1", "z2", "WEIGHTS", "2", "1"]),
+        Reply::Integer(3)
+    );
+    assert_eq!(
+        call(&["ZRANGE", "out", "0", "-1", "WITHSCORES"]),
+        bulks(&["a", "2", "c", "4", "b", "7"])
+    );
+    assert_eq!(
+        call(&["ZINTERSTORE", "out", "2", "z1", "z2", "AGGREGATE", "MAX"]),
+        Reply::Integer(1)
+    );
+    assert_eq!(
+        call(&["ZRANGE", "out", "0", "-1", "WITHSCORES"]),
+        bulks(&["b", "3"])
+    );
+    assert_eq!(
+        call(&["ZINTERSTORE", "out", "2", "z1", "s"]),
+        Reply::Integer(1)
+    );
+    assert_eq!(
+        call(&["ZRANGE", "out", "0", "-1", "WITHSCORES"]),
+        bulks(&["a", "2"])
+    );
+    assert_eq!(
+        call(&["ZINTERSTORE", "out", "2", "z1", "none"]),
+        Reply::Integer(0)
+    );
+    assert_eq!(call(&["EXISTS", "out"]), Reply::Integer(0));
+    assert_eq!(
+        call(&["ZUNIONSTORE", "out", "0", "z1"]),
+        Reply::error("at least 1 input key is needed for 'zunionstore' command")
+    );
+    assert_eq!(
+        call(&["ZUNIONSTORE", "out", "3", "z1", "z2"]),
+        Reply::error("syntax error")
+    );
+}
@@ -0,0 +1,45 @@
+/*
+ * CRC-64/Jones，与redis的crc64一致（反射输入输出，初始值和结果异或值都为0），
+ * 用于rdb文件末尾的校验和
+ */
+const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+const TABLE: [u64; 256] = make_table();
+
+const fn make_table() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    return table;
+}
+
+/*
+ * 在crc的基础上继续计算，可以分段调用
+ */
+pub fn crc64(mut crc: u64, data: &[u8]) -> u64 {
+    for b in data {
+        crc = TABLE[((crc ^ *b as u64) & 0xff) as usize] ^ (crc >> 8);
+    }
+    return crc;
+}
+
+#[test]
+fn test_crc64() {
+    // redis源码中crc64的测试用例
+    assert_eq!(crc64(0, b"123456789"), 0xe9c6_d914_c4b8_d9ca);
+    let data = b"This is a test of the emergency broadcast system.";
+    assert_eq!(crc64(crc64(0, &data[..10]), &data[10..]), crc64(0, data));
+}
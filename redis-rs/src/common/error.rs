@@ -2,10 +2,12 @@ use std::fmt::Display;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ErrorKind {
     Parser,
     Invalid,
+    // 对错误类型的对象执行命令，回复WRONGTYPE
+    WrongType,
     IO,
 }
 
@@ -19,11 +21,19 @@ impl Error {
     pub fn new(kind: ErrorKind, details: String) -> Self {
         return Self { kind, details };
     }
+
+    pub fn kind(&self) -> ErrorKind {
+        return self.kind;
+    }
+
+    pub fn details(&self) -> &str {
+        return &self.details;
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        return write!(f, "{}", self);
+        return write!(f, "{}", self.details);
     }
 }
 
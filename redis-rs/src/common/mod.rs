@@ -1,2 +1,4 @@
-pub mod utils;
+pub mod crc64;
 pub mod error;
+pub mod sha1;
+pub mod utils;
@@ -12,6 +12,7 @@ where
     return value.as_ref().parse().ok();
 }
 
+#[allow(dead_code)]
 pub fn parse_millis<I>(value: I) -> Result<DateTime<Local>, super::error::Error>
 where
     I: AsRef<str>,
@@ -25,6 +26,133 @@ where
     return Ok(time);
 }
 
+/*
+ * 带单位的内存大小，与redis的memtoll一致：k/m/g为1000的倍数，kb/mb/gb为1024的倍数
+ */
+pub fn parse_memory(value: &str) -> Option<usize> {
+    let value = value.to_lowercase();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let multiple: usize = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    return number.parse::<usize>().ok()?.checked_mul(multiple);
+}
+
 pub fn elasped(start: i64) -> i64 {
     return Local::now().timestamp_millis() - start;
 }
+
+/*
+ * glob风格的模式匹配，与redis的stringmatchlen一致：支持 * ? [abc] [^a] [a-z] 以及 \ 转义
+ */
+pub fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    while p < pattern.len() {
+        match pattern[p] {
+            b'*' => {
+                while p + 1 < pattern.len() && pattern[p + 1] == b'*' {
+                    p += 1;
+                }
+                if p + 1 == pattern.len() {
+                    return true;
+                }
+                return (s..=string.len()).any(|i| glob_match(&pattern[p + 1..], &string[i..]));
+            }
+            b'?' => {
+                if s >= string.len() {
+                    return false;
+                }
+                s += 1;
+            }
+            b'[' => {
+                if s >= string.len() {
+                    return false;
+                }
+                p += 1;
+                let not = p < pattern.len() && pattern[p] == b'^';
+                if not {
+                    p += 1;
+                }
+                let mut matched = false;
+                loop {
+                    if p >= pattern.len() {
+                        // 没有闭合的]，当作在模式末尾结束
+                        p -= 1;
+                        break;
+                    }
+                    let c = pattern[p];
+                    if c == b'\\' && p + 2 < pattern.len() {
+                        p += 1;
+                        matched |= pattern[p] == string[s];
+                    } else if c == b']' {
+                        break;
+                    } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' {
+                        let (start, end) = (c.min(pattern[p + 2]), c.max(pattern[p + 2]));
+                        matched |= start <= string[s] && string[s] <= end;
+                        p += 2;
+                    } else {
+                        matched |= c == string[s];
+                    }
+                    p += 1;
+                }
+                if matched == not {
+                    return false;
+                }
+                s += 1;
+            }
+            b'\\' if p + 1 < pattern.len() => {
+                p += 1;
+                if s >= string.len() || pattern[p] != string[s] {
+                    return false;
+                }
+                s += 1;
+            }
+            c => {
+                if s >= string.len() || c != string[s] {
+                    return false;
+                }
+                s += 1;
+            }
+        }
+        p += 1;
+    }
+    return s == string.len();
+}
+
+#[test]
+fn test_glob_match() {
+    assert!(glob_match(b"*", b""));
+    assert!(glob_match(b"h?llo", b"hello"));
+    assert!(glob_match(b"h*llo", b"heeeello"));
+    assert!(glob_match(b"h[ae]llo", b"hallo"));
+    assert!(!glob_match(b"h[ae]llo", b"hillo"));
+    assert!(glob_match(b"h[^e]llo", b"hallo"));
+    assert!(!glob_match(b"h[^e]llo", b"hello"));
+    assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+    assert!(glob_match(b"user:*:name", b"user:1000:name"));
+    assert!(!glob_match(b"user:*:name", b"user:1000:age"));
+    assert!(glob_match(b"a\\*b", b"a*b"));
+    assert!(!glob_match(b"a\\*b", b"axb"));
+    assert!(!glob_match(b"?", b""));
+}
+
+#[test]
+fn test_parse_memory() {
+    assert_eq!(parse_memory("100"), Some(100));
+    assert_eq!(parse_memory("1k"), Some(1000));
+    assert_eq!(parse_memory("1KB"), Some(1024));
+    assert_eq!(parse_memory("2gb"), Some(2 * 1024 * 1024 * 1024));
+    assert_eq!(parse_memory("1tb"), None);
+    assert_eq!(parse_memory("mb"), None);
+    assert_eq!(parse_memory("-1"), None);
+}
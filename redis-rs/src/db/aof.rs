@@ -1 +1,401 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Read, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
 
+use crate::{
+    common::error::{Error, ErrorKind, Result},
+    server::resp::{self, Reply, RESP2},
+    types::object::Object,
+};
+
+use super::db::Db;
+
+// 重写时每条命令最多包含的元素个数，与redis的AOF_REWRITE_ITEMS_PER_CMD一致
+const AOF_REWRITE_ITEMS_PER_CMD: usize = 64;
+
+/*
+ * AOF刷盘策略，与redis的appendfsync一致
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppendFsync {
+    // 每条命令都fsync
+    Always,
+    // 每秒fsync一次，由后台任务执行
+    EverySec,
+    // 交给操作系统决定
+    No,
+}
+
+impl AppendFsync {
+    pub fn parse(name: &str) -> Option<Self> {
+        return match name.to_lowercase().as_str() {
+            "always" => Some(AppendFsync::Always),
+            "everysec" => Some(AppendFsync::EverySec),
+            "no" => Some(AppendFsync::No),
+            _ => None,
+        };
+    }
+}
+
+/*
+ * 追加写入的命令日志：写命令以RESP格式追加到文件末尾，启动时重放恢复数据。
+ * BGREWRITEAOF期间新的命令同时写入旧文件和重写缓冲区，
+ * 重写完成后把缓冲区追加到新文件再替换旧文件
+ */
+pub struct Aof {
+    path: String,
+    file: File,
+    fsync: AppendFsync,
+    last_fsync: Instant,
+    // 上次fsync之后是否有新的写入
+    dirty: bool,
+    rewrite_buf: Option<Vec<u8>>,
+    // 上一条命令所在的db，切换db时先写入SELECT
+    selected_db: Option<usize>,
+}
+
+impl Aof {
+    pub fn open(path: &str, fsync: AppendFsync) -> Result<Self> {
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        return Ok(Self {
+            path: path.to_string(),
+            file,
+            fsync,
+            last_fsync: Instant::now(),
+            dirty: false,
+            rewrite_buf: None,
+            selected_db: None,
+        });
+    }
+
+    /*
+     * 追加一条在db中执行的命令，always策略下立即fsync
+     */
+    pub fn feed(&mut self, db: usize, args: &[Vec<u8>]) -> Result<()> {
+        let mut buf = vec![];
+        if self.selected_db != Some(db) {
+            encode_command(&[b"SELECT".to_vec(), db.to_string().into_bytes()], &mut buf);
+            self.selected_db = Some(db);
+        }
+        encode_command(args, &mut buf);
+        self.file.write_all(&buf)?;
+        if let Some(rewrite_buf) = &mut self.rewrite_buf {
+            rewrite_buf.extend_from_slice(&buf);
+        }
+        self.dirty = true;
+        if self.fsync == AppendFsync::Always {
+            self.fsync()?;
+        }
+        return Ok(());
+    }
+
+    /*
+     * everysec策略下距离上次fsync超过1秒时执行，由后台任务调用
+     */
+    pub fn fsync_if_needed(&mut self) -> Result<()> {
+        if self.fsync == AppendFsync::EverySec
+            && self.dirty
+            && self.last_fsync.elapsed() >= Duration::from_secs(1)
+        {
+            self.fsync()?;
+        }
+        return Ok(());
+    }
+
+    fn fsync(&mut self) -> Result<()> {
+        self.file.sync_data()?;
+        self.last_fsync = Instant::now();
+        self.dirty = false;
+        return Ok(());
+    }
+
+    pub fn is_rewriting(&self) -> bool {
+        return self.rewrite_buf.is_some();
+    }
+
+    /*
+     * 重写完成：追加重写期间的命令，原子替换旧文件并切换到新文件
+     */
+    fn finish_rewrite(&mut self, temp_path: &str) -> Result<()> {
+        let buf = self.rewrite_buf.take().unwrap_or_default();
+        let mut temp = OpenOptions::new().append(true).open(temp_path)?;
+        temp.write_all(&buf)?;
+        temp.sync_all()?;
+        fs::rename(temp_path, &self.path)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.dirty = false;
+        return Ok(());
+    }
+}
+
+/*
+ * BGREWRITEAOF：dbs为开始重写时的快照，在后台线程生成新文件，
+ * 已经在重写时返回false
+ */
+pub fn rewrite_background(aof: Arc<Mutex<Aof>>, dbs: Vec<Db>) -> bool {
+    let path = {
+        let mut aof = aof.lock().unwrap();
+        if aof.is_rewriting() {
+            return false;
+        }
+        aof.rewrite_buf = Some(vec![]);
+        // 新文件结束时所在的db不确定，重写期间的第一条命令需要重新SELECT
+        aof.selected_db = None;
+        aof.path.clone()
+    };
+    thread::spawn(move || {
+        let temp_path = format!("{}.rewrite", path);
+        let res = rewrite(&dbs, &temp_path).and_then(|_| {
+            return aof.lock().unwrap().finish_rewrite(&temp_path);
+        });
+        match res {
+            Ok(()) => println!("[aof] background rewrite finished"),
+            Err(e) => {
+                println!("[aof] background rewrite failed: {}", e);
+                aof.lock().unwrap().rewrite_buf = None;
+                fs::remove_file(&temp_path).unwrap_or(());
+            }
+        }
+    });
+    return true;
+}
+
+/*
+ * 根据每个db的当前状态生成最少的命令，空的db和已过期的key直接跳过
+ */
+pub fn rewrite(dbs: &[Db], path: &str) -> Result<()> {
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut writer = BufWriter::new(
+        OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(path)?,
+    );
+    let now = chrono::Local::now().timestamp_millis();
+    let mut buf = vec![];
+    for (index, db) in dbs.iter().enumerate() {
+        if db.dict.dict_size() == 0 {
+            continue;
+        }
+        encode_command(
+            &[b"SELECT".to_vec(), index.to_string().into_bytes()],
+            &mut buf,
+        );
+        let expires: HashMap<&[u8], i64> = db
+            .expires
+            .dict_iter()
+            .map(|(k, time)| (k.as_bytes(), *time))
+            .collect();
+        for (key, obj) in db.dict.dict_iter() {
+            let expire_time = expires.get(key.as_bytes()).copied();
+            if matches!(expire_time, Some(time) if time <= now) {
+                continue;
+            }
+            for args in object_commands(key.as_bytes(), obj)? {
+                encode_command(&args, &mut buf);
+            }
+            if let Some(time) = expire_time {
+                let args = [
+                    b"PEXPIREAT".to_vec(),
+                    key.as_bytes().to_vec(),
+                    time.to_string().into_bytes(),
+                ];
+                encode_command(&args, &mut buf);
+            }
+            writer.write_all(&buf)?;
+            buf.clear();
+        }
+    }
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    return Ok(());
+}
+
+/*
+ * 重建一个对象的命令，元素较多时拆分成多条
+ */
+fn object_commands(key: &[u8], obj: &Object) -> Result<Vec<Vec<Vec<u8>>>> {
+    let command = |name: &str, items: &[Vec<u8>]| {
+        let mut args = vec![name.as_bytes().to_vec(), key.to_vec()];
+        args.extend_from_slice(items);
+        return args;
+    };
+    let batch = |name: &str, items: Vec<Vec<u8>>, item_len: usize| {
+        return items
+            .chunks(AOF_REWRITE_ITEMS_PER_CMD * item_len)
+            .map(|chunk| command(name, chunk))
+            .collect::<Vec<_>>();
+    };
+    return Ok(match obj.get_type() {
+        "String" => vec![command("SET", &[obj.as_string()?.get().to_vec()])],
+        "List" => batch("RPUSH", obj.as_list()?.iter().collect(), 1),
+        "Hash" => {
+            let items = obj
+                .as_hash()?
+                .entries()
+                .into_iter()
+                .flat_map(|(field, value)| [field, value])
+                .collect();
+            batch("HSET", items, 2)
+        }
+        "Set" => batch("SADD", obj.as_set()?.members(), 1),
+        "ZSet" => {
+            let items = obj
+                .as_zset()?
+                .entries()
+                .into_iter()
+                .flat_map(|(member, score)| [resp::format_double(score).into_bytes(), member])
+                .collect();
+            batch("ZADD", items, 2)
+        }
+        _ => vec![],
+    });
+}
+
+pub fn encode_command(args: &[Vec<u8>], buf: &mut Vec<u8>) {
+    Reply::Array(args.iter().map(Reply::bulk).collect()).encode(RESP2, buf);
+}
+
+/*
+ * 读取AOF中的全部命令，文件不存在时返回None。
+ * 文件末尾的命令不完整（写入时宕机）时，load_truncated为true则截断到最后一条完整的命令，
+ * 否则返回错误；格式错误总是返回错误
+ */
+pub fn load(path: &str, load_truncated: bool) -> Result<Option<Vec<Vec<Vec<u8>>>>> {
+    println!("[aof] load from {} ...", path);
+    if !Path::new(path).exists() {
+        println!("[aof] no aof file ...");
+        return Ok(None);
+    }
+    let mut buf = vec![];
+    File::open(path)?.read_to_end(&mut buf)?;
+
+    let mut commands = vec![];
+    let mut offset = 0;
+    while let Some(args) = resp::parse_request(&buf, &mut offset)? {
+        if !args.is_empty() {
+            commands.push(args);
+        }
+    }
+    if offset < buf.len() {
+        if !load_truncated {
+            return Err(Error::new(
+                ErrorKind::Parser,
+                format!(
+                    "unexpected end of file at offset {}, set aof-load-truncated to yes to repair",
+                    offset
+                ),
+            ));
+        }
+        println!(
+            "[aof] truncated tail found, discard the last {} bytes",
+            buf.len() - offset
+        );
+        OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .set_len(offset as u64)?;
+    }
+    return Ok(Some(commands));
+}
+
+#[test]
+fn test_aof() {
+    use chrono::Local;
+
+    let dir = std::env::temp_dir().join(format!("redis-rs-aof-{}", std::process::id()));
+    let path = dir.join("appendonly.aof");
+    let path = path.to_str().unwrap();
+    fs::remove_file(path).unwrap_or(());
+    let args = |values: &[&str]| {
+        return values
+            .iter()
+            .map(|v| v.as_bytes().to_vec())
+            .collect::<Vec<_>>();
+    };
+
+    assert_eq!(AppendFsync::parse("EVERYSEC"), Some(AppendFsync::EverySec));
+    assert_eq!(AppendFsync::parse("sometimes"), None);
+    assert!(load(path, true).unwrap().is_none());
+
+    let mut aof = Aof::open(path, AppendFsync::Always).unwrap();
+    aof.feed(0, &args(&["SET", "a", "1"])).unwrap();
+    aof.feed(0, &args(&["RPUSH", "l", "x", "y"])).unwrap();
+    aof.feed(2, &args(&["SET", "a", "2"])).unwrap();
+    assert_eq!(
+        load(path, false).unwrap().unwrap(),
+        vec![
+            args(&["SELECT", "0"]),
+            args(&["SET", "a", "1"]),
+            args(&["RPUSH", "l", "x", "y"]),
+            args(&["SELECT", "2"]),
+            args(&["SET", "a", "2"])
+        ]
+    );
+
+    // 不完整的末尾：不允许截断时报错，允许时截断后可以继续追加
+    let len = fs::metadata(path).unwrap().len();
+    aof.file.write_all(b"*3\r\n$3\r\nSET\r\n$1\r\nb").unwrap();
+    assert!(load(path, false).is_err());
+    assert_eq!(load(path, true).unwrap().unwrap().len(), 5);
+    assert_eq!(fs::metadata(path).unwrap().len(), len);
+    // 重新打开后第一条命令总是先SELECT
+    let mut aof = Aof::open(path, AppendFsync::No).unwrap();
+    aof.feed(2, &args(&["DEL", "a"])).unwrap();
+    let commands = load(path, false).unwrap().unwrap();
+    assert_eq!(commands.len(), 7);
+    assert_eq!(commands[5], args(&["SELECT", "2"]));
+
+    // 重写只保留当前状态，已过期的key被跳过
+    let mut db = Db::new(dir.to_str().unwrap().to_string(), None, None);
+    db.set(b"s", b"v").unwrap();
+    db.set(b"gone", b"v").unwrap();
+    let now = Local::now().timestamp_millis();
+    db.set_expire(b"s", now + 100_000).unwrap();
+    db.set_expire(b"gone", now - 1).unwrap();
+    let rewrite_path = format!("{}.test", path);
+    let mut dbs = vec![Db::new(dir.to_str().unwrap().to_string(), None, None), db];
+    rewrite(&dbs, &rewrite_path).unwrap();
+    assert_eq!(
+        load(&rewrite_path, false).unwrap().unwrap(),
+        vec![
+            args(&["SELECT", "1"]),
+            args(&["SET", "s", "v"]),
+            args(&["PEXPIREAT", "s", &(now + 100_000).to_string()])
+        ]
+    );
+
+    // 后台重写期间的命令追加到新文件
+    let aof = Arc::new(Mutex::new(aof));
+    dbs[0].set(b"r", b"v").unwrap();
+    assert!(rewrite_background(aof.clone(), dbs.clone()));
+    aof.lock()
+        .unwrap()
+        .feed(2, &args(&["SET", "t", "1"]))
+        .unwrap();
+    while aof.lock().unwrap().is_rewriting() {
+        thread::sleep(Duration::from_millis(10));
+    }
+    aof.lock()
+        .unwrap()
+        .feed(2, &args(&["SET", "u", "1"]))
+        .unwrap();
+    let commands = load(path, false).unwrap().unwrap();
+    assert_eq!(commands.len(), 8);
+    assert_eq!(commands[5], args(&["SELECT", "2"]));
+    assert_eq!(commands[6], args(&["SET", "t", "1"]));
+    assert_eq!(commands[7], args(&["SET", "u", "1"]));
+    fs::remove_dir_all(dir).unwrap_or(());
+}
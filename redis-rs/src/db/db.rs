@@ -1,16 +1,16 @@
 use std::{
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    },
-    thread,
+    sync::{atomic::AtomicBool, Arc},
+    time::{Duration, Instant},
 };
 
 use chrono::Local;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    common::error::{Error, ErrorKind, Result},
+    common::{
+        error::{Error, ErrorKind, Result},
+        utils,
+    },
     encoding::sds::Sds,
     types::{
         dict::Dict,
@@ -19,9 +19,16 @@ use crate::{
     },
 };
 
-use super::{rdb, shared::SharedObject};
+use super::{
+    notify::{self, Event},
+    rdb,
+    shared::SharedObject,
+};
 
 const THRESH_HOLD: f32 = 0.9;
+// 与redis的ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP和ACTIVE_EXPIRE_CYCLE_ACCEPTABLE_STALE一致
+const ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP: usize = 20;
+const ACTIVE_EXPIRE_CYCLE_ACCEPTABLE_STALE: usize = 10;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Db {
@@ -32,6 +39,18 @@ pub struct Db {
     shared_object: SharedObject,
     #[serde(skip)]
     is_saving: Arc<AtomicBool>,
+    // 所有key和value估算的内存之和
+    #[serde(skip)]
+    used_memory: usize,
+    // 还没有发布的键空间事件
+    #[serde(skip)]
+    events: Vec<Event>,
+    // 从节点不主动删除过期的key，等待主节点同步DEL
+    #[serde(skip)]
+    replica: bool,
+    // 从节点执行主节点的复制流时，已过期的key仍然视为存在，与主节点上的执行结果一致
+    #[serde(skip)]
+    master_stream: bool,
 }
 
 impl Db {
@@ -42,26 +61,51 @@ impl Db {
             expires: expires.unwrap_or(Dict::new(THRESH_HOLD)),
             shared_object: SharedObject::default(),
             is_saving: Arc::new(AtomicBool::default()),
+            used_memory: 0,
+            events: vec![],
+            replica: false,
+            master_stream: false,
         };
     }
 
-    pub fn exist(&mut self, key: &str) -> Result<bool> {
-        return match self.check_exist(Arc::new(key.into())) {
+    /*
+     * 清空所有key，保留存储目录和主从复制相关的状态
+     */
+    pub fn clear(&mut self) {
+        let (replica, master_stream) = (self.replica, self.master_stream);
+        *self = Db::new(self.store_dir.clone(), None, None);
+        self.replica = replica;
+        self.master_stream = master_stream;
+    }
+
+    pub fn set_replica(&mut self, replica: bool) {
+        self.replica = replica;
+    }
+
+    pub fn set_master_stream(&mut self, master_stream: bool) {
+        self.master_stream = master_stream;
+    }
+
+    pub fn exist(&mut self, key: &[u8]) -> Result<bool> {
+        return match self.check_exist(Arc::new(Sds::new(key))) {
             Ok(true) => Ok(true),
             _ => Ok(false),
         };
     }
 
-    pub fn delete(&mut self, key: &str) -> Result<bool> {
-        let k: Arc<Sds> = Arc::new(key.into());
+    pub fn delete(&mut self, key: &[u8]) -> Result<bool> {
+        let k: Arc<Sds> = Arc::new(Sds::new(key));
 
-        self.dict.dict_delete(k.clone())?;
+        let deleted = self.dict.dict_delete(k.clone())?;
         self.expires.dict_delete(k)?;
-        return Ok(true);
+        if let Some((_, obj)) = &deleted {
+            self.used_memory -= obj.accounted_memory;
+        }
+        return Ok(deleted.is_some());
     }
 
-    pub fn set_expire_time(&mut self, key: &str, time: &str) -> Result<()> {
-        let k: Arc<Sds> = Arc::new(key.into());
+    pub fn set_expire_time(&mut self, key: &[u8], time: &str) -> Result<()> {
+        let k: Arc<Sds> = Arc::new(Sds::new(key));
         self.check_exist(k.clone())?;
 
         let expire_time: i64 = time
@@ -73,68 +117,310 @@ impl Db {
                 "expire time must greater than current time.".to_string(),
             ));
         }
-        self.expires.dict_add(k, expire_time)?;
+        self.expires.dict_replace(k, expire_time)?;
         return Ok(());
     }
 
-    pub fn is_expired(&mut self, key: &str) -> Result<bool> {
-        return self.check_exist(Arc::new(key.into())).map(|e| !e);
+    #[allow(dead_code)]
+    pub fn is_expired(&mut self, key: &[u8]) -> Result<bool> {
+        return self.check_exist(Arc::new(Sds::new(key))).map(|e| !e);
     }
 
-    pub fn delete_expire(&mut self, key: &str) -> Result<bool> {
-        let k: Arc<Sds> = Arc::new(key.into());
+    pub fn delete_expire(&mut self, key: &[u8]) -> Result<bool> {
+        let k: Arc<Sds> = Arc::new(Sds::new(key));
         self.check_exist(k.clone())?;
 
         self.expires.dict_delete(k)?;
         return Ok(true);
     }
 
-    pub fn set(&mut self, key: &str, val: &str) -> Result<bool> {
-        //TODO 条件
-        let value = match self.shared_object.get(&val) {
-            Some(o) => o,
-            None => Arc::new(StringObject::new(val)),
+    /*
+     * 覆盖已存在的值并清除过期时间，返回是否为新增的key
+     */
+    pub fn set(&mut self, key: &[u8], val: &[u8]) -> Result<bool> {
+        let value = self.create_string(val);
+        return self.set_object(key, Object::new(ObjectValue::Strings(value))?, false);
+    }
+
+    /*
+     * 覆盖已存在的值，keep_ttl为false时清除过期时间，返回是否为新增的key
+     */
+    pub fn set_object(&mut self, key: &[u8], mut obj: Object, keep_ttl: bool) -> Result<bool> {
+        let k: Arc<Sds> = Arc::new(Sds::new(key));
+        if !keep_ttl {
+            self.expires.dict_delete(k.clone())?;
+        }
+        if let Some(old) = self.dict.dict_find(&k) {
+            self.used_memory -= old.accounted_memory;
+        }
+        obj.accounted_memory = k.alloc_size() + obj.memory_usage();
+        self.used_memory += obj.accounted_memory;
+        let added = self.dict.dict_replace(k, obj)?;
+        if added {
+            self.notify(notify::NOTIFY_NEW, "new", key);
+        }
+        return Ok(added);
+    }
+
+    /*
+     * 记录键空间事件，命令执行完后由server取出并发布
+     */
+    pub fn notify(&mut self, class: u32, event: &'static str, key: &[u8]) {
+        self.events.push(Event {
+            class,
+            event,
+            key: key.to_vec(),
+        });
+    }
+
+    pub fn take_events(&mut self) -> Vec<Event> {
+        return std::mem::take(&mut self.events);
+    }
+
+    /*
+     * 查找未过期的key（过期的key会被删除），并刷新访问时间。
+     * key所在的分段被BGSAVE的快照共享时不刷新，避免只读的命令复制分段
+     */
+    pub fn lookup_key(&mut self, key: &[u8]) -> Result<Option<&Object>> {
+        let k: Arc<Sds> = Arc::new(Sds::new(key));
+
+        return match self.check_exist(k.clone()) {
+            Ok(true) => {
+                if let Some(obj) = self.dict.dict_get_unshared_mut(&k) {
+                    obj.touch();
+                }
+                Ok(self.dict.dict_find(&k))
+            }
+            _ => Ok(None),
+        };
+    }
+
+    /*
+     * 与lookup_key相同，但是返回可变引用，用于原地修改value的命令
+     */
+    pub fn lookup_key_mut(&mut self, key: &[u8]) -> Result<Option<&mut Object>> {
+        let k: Arc<Sds> = Arc::new(Sds::new(key));
+
+        return match self.check_exist(k.clone()) {
+            Ok(true) => Ok(self.dict.dict_get_mut(k)?.map(|obj| {
+                obj.touch();
+                return obj;
+            })),
+            _ => Ok(None),
         };
-        return self.dict.dict_add(
-            Arc::new(Sds::new(key.as_ref())),
-            Object::new(ObjectValue::Strings(value))?,
-        );
     }
 
-    pub fn get(&mut self, key: &str) -> Result<Option<Box<[u8]>>> {
-        let k: Arc<Sds> = Arc::new(key.into());
+    /*
+     * 与lookup_key相同，但是不更新访问时间和访问计数（OBJECT等命令使用）
+     */
+    pub fn peek_key(&mut self, key: &[u8]) -> Result<Option<&Object>> {
+        let k: Arc<Sds> = Arc::new(Sds::new(key));
 
         return match self.check_exist(k.clone()) {
-            Ok(true) => Ok(self.dict.dict_get(k)?.and_then(|mut o| o.get())),
+            Ok(true) => Ok(self.dict.dict_find(&k)),
             _ => Ok(None),
         };
     }
 
+    pub fn used_memory(&self) -> usize {
+        return self.used_memory;
+    }
+
+    /*
+     * 命令原地修改value后重新计算key占用的内存，没有变化时不修改（避免复制被快照共享的分段）
+     */
+    pub fn update_memory(&mut self, key: &[u8]) -> Result<()> {
+        let k: Arc<Sds> = Arc::new(Sds::new(key));
+        let (old, new) = match self.dict.dict_find(&k) {
+            Some(obj) => (obj.accounted_memory, k.alloc_size() + obj.memory_usage()),
+            None => return Ok(()),
+        };
+        if old != new {
+            if let Some(obj) = self.dict.dict_get_mut(k)? {
+                obj.accounted_memory = new;
+            }
+            self.used_memory = self.used_memory - old + new;
+        }
+        return Ok(());
+    }
+
+    /*
+     * 重新计算全部key占用的内存，用于校验增量统计的结果
+     */
+    #[cfg(test)]
+    pub fn recompute_memory(&mut self) -> Result<()> {
+        let keys: Vec<Arc<Sds>> = self.dict.dict_iter().map(|(k, _)| k.clone()).collect();
+        self.used_memory = 0;
+        for key in keys {
+            let key_size = key.alloc_size();
+            if let Some(obj) = self.dict.dict_get_mut(key)? {
+                obj.accounted_memory = key_size + obj.memory_usage();
+                self.used_memory += obj.accounted_memory;
+            }
+        }
+        return Ok(());
+    }
+
+    /*
+     * 整数值优先使用共享对象
+     */
+    pub fn create_string(&self, value: &[u8]) -> Arc<StringObject> {
+        return match self.shared_object.get(value) {
+            Some(o) => o,
+            None => Arc::new(StringObject::from_bytes(value)),
+        };
+    }
+
+    pub fn create_integer(&self, value: i64) -> Arc<StringObject> {
+        return match self.shared_object.get_integer(value) {
+            Some(o) => o,
+            None => Arc::new(StringObject::from_integer(value)),
+        };
+    }
+
+    /*
+     * 过期时间（毫秒时间戳），没有设置时返回None
+     */
+    pub fn get_expire(&mut self, key: &[u8]) -> Result<Option<i64>> {
+        return self.expires.dict_get(Arc::new(Sds::new(key)));
+    }
+
+    /*
+     * 不校验时间，已经过去的时间会在下次访问时删除key
+     */
+    pub fn set_expire(&mut self, key: &[u8], expire_time: i64) -> Result<()> {
+        self.expires
+            .dict_replace(Arc::new(Sds::new(key)), expire_time)?;
+        return Ok(());
+    }
+
+    /*
+     * 字符串的值，key不存在时返回None，不是字符串时返回WRONGTYPE
+     */
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Box<[u8]>>> {
+        return match self.lookup_key(key)? {
+            Some(obj) => Ok(Some(obj.as_string()?.get())),
+            None => Ok(None),
+        };
+    }
+
+    /*
+     * 匹配pattern的所有未过期的key，遍历时顺便删除已过期的key
+     */
+    pub fn keys(&mut self, pattern: &[u8]) -> Result<Vec<Arc<Sds>>> {
+        let candidates: Vec<Arc<Sds>> = self
+            .dict
+            .dict_iter()
+            .filter(|(k, _)| utils::glob_match(pattern, k.as_bytes()))
+            .map(|(k, _)| k.clone())
+            .collect();
+        let mut keys = vec![];
+        for key in candidates {
+            if self.exist(key.as_bytes())? {
+                keys.push(key);
+            }
+        }
+        return Ok(keys);
+    }
+
+    /*
+     * 游标迭代，rehash期间也不会遗漏一直存在的key；返回的key可能已经过期，由调用方过滤
+     */
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(Arc<Sds>, Object)>) {
+        return self.dict.dict_scan(cursor, count);
+    }
+
+    /*
+     * 随机返回一个未过期的key，选中的key已经过期时删除后重新选择。
+     * 从节点不删除过期的key，与redis一致，尝试dict大小的次数后直接返回选中的key
+     */
+    pub fn random_key(&mut self) -> Result<Option<Arc<Sds>>> {
+        let mut tries = 0;
+        while let Some((key, _)) = self.dict.dict_get_random_key()? {
+            if self.exist(key.as_bytes())? {
+                return Ok(Some(key));
+            }
+            tries += 1;
+            if self.replica && tries >= self.dict.dict_size() {
+                return Ok(Some(key));
+            }
+        }
+        return Ok(None);
+    }
+
+    /*
+     * 把src连同过期时间改名为dst，覆盖dst原有的值，src不存在时返回false
+     */
+    pub fn rename(&mut self, src: &[u8], dst: &[u8]) -> Result<bool> {
+        let obj = match self.peek_key(src)? {
+            Some(obj) => obj.clone(),
+            None => return Ok(false),
+        };
+        let expire_time = self.get_expire(src)?;
+        self.delete(src)?;
+        self.set_object(dst, obj, false)?;
+        if let Some(time) = expire_time {
+            self.set_expire(dst, time)?;
+        }
+        return Ok(true);
+    }
+
+    /*
+     * 后台保存当前db，与BGSAVE使用同样的写时复制快照
+     */
+    #[allow(dead_code)]
     pub fn bgsave(&self) {
-        if self
-            .is_saving
-            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
-            .is_ok()
-        {
-            let db_clone = self.clone();
-            let is_saving_clone = self.is_saving.clone();
-            thread::spawn(move || {
-                rdb::save(db_clone, is_saving_clone);
-            });
+        rdb::save_background(vec![self.clone()], self.is_saving.clone());
+    }
+
+    /*
+     * 主动过期：从设置了过期时间的key中采样并删除已过期的（从节点不执行），
+     * 过期比例超过ACTIVE_EXPIRE_CYCLE_ACCEPTABLE_STALE%时继续下一轮，总耗时不超过time_limit，
+     * 返回删除的key数
+     */
+    pub fn active_expire_cycle(&mut self, time_limit: Duration) -> Result<usize> {
+        if self.replica {
+            return Ok(0);
+        }
+        let start = Instant::now();
+        let mut expired_total = 0;
+        loop {
+            let samples = self
+                .expires
+                .dict_get_some_keys(ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP);
+            if samples.is_empty() {
+                break;
+            }
+            let now = Local::now().timestamp_millis();
+            let mut expired = 0;
+            for (key, expire_time) in samples.iter() {
+                if now >= *expire_time {
+                    self.delete(key.as_bytes())?;
+                    self.notify(notify::NOTIFY_EXPIRED, "expired", key.as_bytes());
+                    expired += 1;
+                }
+            }
+            expired_total += expired;
+            if expired * 100 <= samples.len() * ACTIVE_EXPIRE_CYCLE_ACCEPTABLE_STALE
+                || start.elapsed() >= time_limit
+            {
+                break;
+            }
         }
+        return Ok(expired_total);
     }
 
     fn check_exist(&mut self, key: Arc<Sds>) -> Result<bool> {
         if !self.dict.dict_contanins_key(key.clone())? {
             return Err(Error::new(
                 ErrorKind::Invalid,
-                format!("key is not exist: {}", key.to_string()),
+                format!("key is not exist: {}", key),
             ));
         }
 
         let expired;
         if let Some(expire_time) = self.expires.dict_get(key.clone())? {
-            expired = Local::now().timestamp_millis() > expire_time;
+            expired = Local::now().timestamp_millis() >= expire_time;
         } else {
             expired = false;
         }
@@ -142,9 +428,13 @@ impl Db {
         if !expired {
             return Ok(true);
         }
+        // 从节点上已过期的key视为不存在，但是由主节点删除
+        if self.replica {
+            return Ok(self.master_stream);
+        }
 
-        // FIXME master才做过期删除操作
-        self.delete(&key.to_string())?;
+        self.delete(key.as_bytes())?;
+        self.notify(notify::NOTIFY_EXPIRED, "expired", key.as_bytes());
 
         return Ok(false);
     }
@@ -153,42 +443,179 @@ impl Db {
 #[test]
 fn db() {
     let mut db = Db::new("store".to_string(), None, None);
-    let kv = "default";
+    let kv: &[u8] = b"default";
     assert!(db.set(kv, kv).is_ok());
     // base
     assert!(db.exist(kv).unwrap());
-    assert_eq!(db.get(kv).unwrap().unwrap().as_ref(), kv.as_bytes());
+    assert_eq!(db.get(kv).unwrap().unwrap().as_ref(), kv);
     assert!(db.delete(kv).unwrap());
     // expire
     let expire_time = Local::now().timestamp_millis() + 1000;
     assert!(db.set_expire_time(kv, &expire_time.to_string()).is_err());
     assert!(db.set(kv, kv).is_ok());
     assert!(db.set_expire_time(kv, &expire_time.to_string()).is_ok());
-    assert_eq!(db.get(kv).unwrap().unwrap().as_ref(), kv.as_bytes());
-    thread::sleep(std::time::Duration::from_secs(1));
+    assert_eq!(db.get(kv).unwrap().unwrap().as_ref(), kv);
+    std::thread::sleep(std::time::Duration::from_secs(1));
     assert!(db.is_expired(kv).unwrap());
     assert!(!db.exist(kv).unwrap());
     assert_eq!(db.get(kv).unwrap(), None);
 }
 
+#[test]
+fn used_memory() {
+    let mut db = Db::new("store".to_string(), None, None);
+    assert_eq!(db.used_memory(), 0);
+    db.set(b"a", b"1").unwrap();
+    let small = db.used_memory();
+    assert!(small > 0);
+    db.set(b"a", "v".repeat(1000).as_bytes()).unwrap();
+    assert!(db.used_memory() > small + 1000);
+
+    // 原地修改后需要调用update_memory
+    db.lookup_key_mut(b"a")
+        .unwrap()
+        .unwrap()
+        .as_string_mut()
+        .unwrap()
+        .append(&[b'v'; 1000]);
+    let before = db.used_memory();
+    db.update_memory(b"a").unwrap();
+    assert!(db.used_memory() > before);
+    let used = db.used_memory();
+    db.recompute_memory().unwrap();
+    assert_eq!(db.used_memory(), used);
+    db.delete(b"a").unwrap();
+    assert_eq!(db.used_memory(), 0);
+}
+
+#[test]
+fn read_during_snapshot() {
+    let mut db = Db::new("store".to_string(), None, None);
+    for i in 0..1000 {
+        db.set(i.to_string().as_bytes(), b"v").unwrap();
+    }
+    // BGSAVE期间只读的命令不复制分段，也不更新访问信息
+    let snapshot = db.clone();
+    let counter = db.peek_key(b"1").unwrap().unwrap().lfu_counter();
+    for i in 0..1000 {
+        let key = i.to_string();
+        assert!(db.lookup_key(key.as_bytes()).unwrap().is_some());
+        assert!(db.peek_key(key.as_bytes()).unwrap().is_some());
+        db.update_memory(key.as_bytes()).unwrap();
+    }
+    assert_eq!(db.dict.copied_segments(&snapshot.dict), 0);
+    assert_eq!(db.peek_key(b"1").unwrap().unwrap().lfu_counter(), counter);
+
+    // 修改只复制key所在的分段
+    db.lookup_key_mut(b"1").unwrap().unwrap();
+    assert_eq!(db.dict.copied_segments(&snapshot.dict), 1);
+    assert!(db.peek_key(b"1").unwrap().unwrap().lfu_counter() > counter);
+
+    // 快照结束后读操作更新访问信息
+    drop(snapshot);
+    let counter = db.peek_key(b"2").unwrap().unwrap().lfu_counter();
+    db.lookup_key(b"2").unwrap().unwrap();
+    assert!(db.peek_key(b"2").unwrap().unwrap().lfu_counter() > counter);
+}
+
+#[test]
+fn active_expire() {
+    let mut db = Db::new("store".to_string(), None, None);
+    let now = Local::now().timestamp_millis();
+    for i in 0..100 {
+        let key = format!("expired-{}", i);
+        db.set(key.as_bytes(), b"v").unwrap();
+        db.set_expire(key.as_bytes(), now - 1).unwrap();
+    }
+    for i in 0..10 {
+        let key = format!("alive-{}", i);
+        db.set(key.as_bytes(), b"v").unwrap();
+        db.set_expire(key.as_bytes(), now + 100_000).unwrap();
+    }
+    db.set(b"persistent", b"v").unwrap();
+
+    // 没有访问过的过期key也会被删除，未过期的key不受影响
+    let expired = db.active_expire_cycle(Duration::from_secs(10)).unwrap();
+    assert!(expired > 0);
+    assert!(db.dict.dict_size() < 111);
+    assert!(db.exist(b"alive-0").unwrap());
+    assert!(db.exist(b"persistent").unwrap());
+    while db.active_expire_cycle(Duration::from_secs(10)).unwrap() > 0 {}
+    assert_eq!(db.expires.dict_size(), 10);
+    assert_eq!(db.dict.dict_size(), 11);
+}
+
+#[test]
+fn replica_expire() {
+    let mut db = Db::new("store".to_string(), None, None);
+    db.set_replica(true);
+    let now = Local::now().timestamp_millis();
+    db.set(b"expired", b"v").unwrap();
+    db.set_expire(b"expired", now - 1).unwrap();
+    db.take_events();
+
+    // 从节点上过期的key读不到，但只有主节点传播DEL之后才会删除
+    assert!(!db.exist(b"expired").unwrap());
+    assert_eq!(db.active_expire_cycle(Duration::from_secs(10)).unwrap(), 0);
+    assert_eq!(db.dict.dict_size(), 1);
+    assert!(db.take_events().is_empty());
+    db.clear();
+    assert_eq!(db.dict.dict_size(), 0);
+    db.set(b"expired", b"v").unwrap();
+    db.set_expire(b"expired", now - 1).unwrap();
+    assert!(!db.exist(b"expired").unwrap());
+    assert_eq!(db.dict.dict_size(), 1);
+
+    db.set_master_stream(true);
+    assert!(db.exist(b"expired").unwrap());
+    db.set_master_stream(false);
+
+    db.set_replica(false);
+    assert!(!db.exist(b"expired").unwrap());
+    assert_eq!(db.dict.dict_size(), 0);
+}
+
 #[test]
 fn bgsave() {
-    let kv = "default";
-    let base_path = "store";
-    let rdb_path = std::path::Path::new("store/db.rdb");
+    let kv: &[u8] = b"default";
+    let base_path = std::env::temp_dir().join("redis-rs-bgsave");
+    let base_path = base_path.to_str().unwrap();
+    let rdb_path_str = format!("{}/db.rdb", base_path);
+    let rdb_path = std::path::Path::new(&rdb_path_str);
     if rdb_path.exists() {
-        let db = rdb::load(base_path).unwrap();
-        assert!(db.is_some());
-        assert_eq!(
-            db.unwrap().get(kv).unwrap().unwrap().as_ref(),
-            kv.as_bytes()
-        );
+        let dbs = rdb::load(base_path, 16).unwrap();
+        assert!(dbs.is_some());
+        assert_eq!(dbs.unwrap()[0].get(kv).unwrap().unwrap().as_ref(), kv);
     } else {
         let mut db = Db::new(base_path.to_string(), None, None);
         assert!(db.set(kv, kv).is_ok());
         db.set(kv, kv).unwrap();
         db.bgsave();
-        thread::sleep(std::time::Duration::from_secs(1));
-        assert_eq!(db.is_saving.load(Ordering::SeqCst), false);
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        assert!(!db.is_saving.load(std::sync::atomic::Ordering::SeqCst));
+    }
+}
+
+#[test]
+fn bgsave_snapshot() {
+    let base_path = std::env::temp_dir().join(format!("redis-rs-snapshot-{}", std::process::id()));
+    let base_path = base_path.to_str().unwrap();
+    let mut db = Db::new(base_path.to_string(), None, None);
+    for i in 0..1000 {
+        db.set(format!("key-{}", i).as_bytes(), b"before").unwrap();
+    }
+    db.bgsave();
+    // 开始保存之后的修改不影响快照
+    db.set(b"key-0", b"after").unwrap();
+    db.delete(b"key-1").unwrap();
+    db.set(b"new", b"after").unwrap();
+    while db.is_saving.load(std::sync::atomic::Ordering::SeqCst) {
+        std::thread::sleep(Duration::from_millis(10));
     }
+    let mut loaded = rdb::load(base_path, 16).unwrap().unwrap().remove(0);
+    assert_eq!(loaded.dict.dict_size(), 1000);
+    assert_eq!(loaded.get(b"key-0").unwrap().unwrap().as_ref(), b"before");
+    assert!(loaded.exist(b"key-1").unwrap());
+    assert!(!loaded.exist(b"new").unwrap());
+    std::fs::remove_dir_all(base_path).unwrap_or(());
 }
@@ -0,0 +1,265 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::{common::error::Result, encoding::sds::Sds};
+
+use super::{db::Db, notify::NOTIFY_EVICTED};
+
+/*
+ * 内存淘汰策略，与redis的maxmemory-policy一致
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    NoEviction,
+    AllKeysLru,
+    VolatileLru,
+    AllKeysLfu,
+    VolatileLfu,
+    AllKeysRandom,
+    VolatileRandom,
+    VolatileTtl,
+}
+
+const POLICIES: [(EvictionPolicy, &str); 8] = [
+    (EvictionPolicy::NoEviction, "noeviction"),
+    (EvictionPolicy::AllKeysLru, "allkeys-lru"),
+    (EvictionPolicy::VolatileLru, "volatile-lru"),
+    (EvictionPolicy::AllKeysLfu, "allkeys-lfu"),
+    (EvictionPolicy::VolatileLfu, "volatile-lfu"),
+    (EvictionPolicy::AllKeysRandom, "allkeys-random"),
+    (EvictionPolicy::VolatileRandom, "volatile-random"),
+    (EvictionPolicy::VolatileTtl, "volatile-ttl"),
+];
+
+impl EvictionPolicy {
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        return POLICIES
+            .iter()
+            .find(|(_, n)| *n == name)
+            .map(|(policy, _)| *policy);
+    }
+
+    pub fn name(&self) -> &'static str {
+        return POLICIES.iter().find(|(p, _)| p == self).unwrap().1;
+    }
+
+    pub fn is_lfu(&self) -> bool {
+        return matches!(
+            self,
+            EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu
+        );
+    }
+
+    /*
+     * 是否只淘汰设置了过期时间的key
+     */
+    fn is_volatile(&self) -> bool {
+        return matches!(
+            self,
+            EvictionPolicy::VolatileLru
+                | EvictionPolicy::VolatileLfu
+                | EvictionPolicy::VolatileRandom
+                | EvictionPolicy::VolatileTtl
+        );
+    }
+}
+
+/*
+ * 所有db使用的内存之和超过maxmemory时按策略淘汰key，直到降到maxmemory以下，
+ * 没有可以淘汰的key时返回false
+ */
+pub fn evict(
+    dbs: &mut [Db],
+    maxmemory: usize,
+    policy: EvictionPolicy,
+    samples: usize,
+) -> Result<bool> {
+    while dbs.iter().map(|db| db.used_memory()).sum::<usize>() > maxmemory {
+        if policy == EvictionPolicy::NoEviction {
+            return Ok(false);
+        }
+        // 每个db分别采样，选出所有db中最适合淘汰的key
+        let mut best: Option<(usize, Arc<Sds>, i64)> = None;
+        for (index, db) in dbs.iter_mut().enumerate() {
+            if let Some((key, score)) = db.select_victim(policy, samples)? {
+                if best.as_ref().is_none_or(|(_, _, s)| score > *s) {
+                    best = Some((index, key, score));
+                }
+            }
+        }
+        match best {
+            Some((index, key, _)) => {
+                dbs[index].delete(key.as_bytes())?;
+                dbs[index].notify(NOTIFY_EVICTED, "evicted", key.as_bytes());
+            }
+            None => return Ok(false),
+        }
+    }
+    return Ok(true);
+}
+
+impl Db {
+    #[cfg(test)]
+    pub fn evict(
+        &mut self,
+        maxmemory: usize,
+        policy: EvictionPolicy,
+        samples: usize,
+    ) -> Result<bool> {
+        return evict(std::slice::from_mut(self), maxmemory, policy, samples);
+    }
+
+    /*
+     * 近似算法：随机采样samples个key，选出其中最适合淘汰的一个以及它的分数，分数越大越应该被淘汰。
+     * 随机策略的分数也是随机的，以便在多个db之间随机选择
+     */
+    fn select_victim(
+        &mut self,
+        policy: EvictionPolicy,
+        samples: usize,
+    ) -> Result<Option<(Arc<Sds>, i64)>> {
+        let random_score = || rand::thread_rng().gen::<i64>();
+        match policy {
+            EvictionPolicy::AllKeysRandom => {
+                return Ok(self
+                    .dict
+                    .dict_get_random_key()?
+                    .map(|(k, _)| (k, random_score())));
+            }
+            EvictionPolicy::VolatileRandom => {
+                return Ok(self
+                    .expires
+                    .dict_get_random_key()?
+                    .map(|(k, _)| (k, random_score())));
+            }
+            // 最早过期的key
+            EvictionPolicy::VolatileTtl => {
+                return Ok(self
+                    .expires
+                    .dict_get_some_keys(samples)
+                    .into_iter()
+                    .min_by_key(|(_, expire_time)| *expire_time)
+                    .map(|(k, expire_time)| (k, -expire_time)));
+            }
+            _ => {}
+        }
+
+        let candidates = match policy.is_volatile() {
+            true => {
+                let mut candidates = vec![];
+                for (key, _) in self.expires.dict_get_some_keys(samples) {
+                    if let Some(obj) = self.dict.dict_get(key.clone())? {
+                        candidates.push((key, obj));
+                    }
+                }
+                candidates
+            }
+            false => self.dict.dict_get_some_keys(samples),
+        };
+        // LRU为空闲时间，LFU为访问计数的反向值
+        return Ok(candidates
+            .into_iter()
+            .map(|(k, obj)| match policy.is_lfu() {
+                true => (k, (u8::MAX - obj.lfu_counter()) as i64),
+                false => (k, obj.idle_time()),
+            })
+            .max_by_key(|(_, score)| *score));
+    }
+}
+
+#[test]
+fn test_evict() {
+    use chrono::Local;
+
+    assert_eq!(
+        EvictionPolicy::parse("ALLKEYS-LRU"),
+        Some(EvictionPolicy::AllKeysLru)
+    );
+    assert_eq!(EvictionPolicy::VolatileTtl.name(), "volatile-ttl");
+    assert_eq!(EvictionPolicy::parse("lru"), None);
+
+    let fill = |db: &mut Db| {
+        for i in 0..100 {
+            db.set(format!("key-{}", i).as_bytes(), "v".repeat(100).as_bytes())
+                .unwrap();
+        }
+    };
+
+    // noeviction不淘汰
+    let mut db = Db::new("store".to_string(), None, None);
+    fill(&mut db);
+    let used = db.used_memory();
+    assert!(!db.evict(used / 2, EvictionPolicy::NoEviction, 5).unwrap());
+    assert_eq!(db.dict.dict_size(), 100);
+
+    // allkeys淘汰到maxmemory以下
+    for policy in [
+        EvictionPolicy::AllKeysLru,
+        EvictionPolicy::AllKeysLfu,
+        EvictionPolicy::AllKeysRandom,
+    ] {
+        let mut db = Db::new("store".to_string(), None, None);
+        fill(&mut db);
+        assert!(db.evict(used / 2, policy, 5).unwrap());
+        assert!(db.used_memory() <= used / 2);
+        assert!(db.dict.dict_size() >= 40);
+    }
+
+    // volatile只淘汰设置了过期时间的key，没有时返回false
+    let mut db = Db::new("store".to_string(), None, None);
+    fill(&mut db);
+    let now = Local::now().timestamp_millis();
+    for i in 0..10 {
+        db.set_expire(format!("key-{}", i).as_bytes(), now + 1000 * (i + 1))
+            .unwrap();
+    }
+    assert!(!db.evict(used / 2, EvictionPolicy::VolatileTtl, 20).unwrap());
+    assert_eq!(db.dict.dict_size(), 90);
+    assert_eq!(db.expires.dict_size(), 0);
+    assert!(db.exist(b"key-10").unwrap());
+
+    // volatile-ttl优先淘汰最早过期的key
+    let mut db = Db::new("store".to_string(), None, None);
+    fill(&mut db);
+    for i in 0..10 {
+        db.set_expire(format!("key-{}", i).as_bytes(), now + 1000 * (i + 1))
+            .unwrap();
+    }
+    let target = db.used_memory() - 1;
+    assert!(db.evict(target, EvictionPolicy::VolatileTtl, 20).unwrap());
+    assert!(!db.exist(b"key-0").unwrap());
+    assert!(db.exist(b"key-1").unwrap());
+
+    // allkeys-lru优先淘汰空闲时间最长的key
+    let mut db = Db::new("store".to_string(), None, None);
+    db.set(b"old", b"v").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    db.set(b"new", b"v").unwrap();
+    let target = db.used_memory() - 1;
+    assert!(db.evict(target, EvictionPolicy::AllKeysLru, 5).unwrap());
+    assert!(!db.exist(b"old").unwrap());
+    assert!(db.exist(b"new").unwrap());
+}
+
+#[test]
+fn test_evict_dbs() {
+    let mut dbs: Vec<Db> = (0..3)
+        .map(|_| Db::new("store".to_string(), None, None))
+        .collect();
+    for i in 0..30 {
+        dbs[i % 3]
+            .set(format!("key-{}", i).as_bytes(), b"v")
+            .unwrap();
+    }
+    // 只有一个db中的key设置了过期时间
+    dbs[1]
+        .set_expire(b"key-1", chrono::Local::now().timestamp_millis() + 1000)
+        .unwrap();
+    let used: usize = dbs.iter().map(|db| db.used_memory()).sum();
+    assert!(evict(&mut dbs, used - 1, EvictionPolicy::VolatileLru, 5).unwrap());
+    assert!(!dbs[1].exist(b"key-1").unwrap());
+    assert!(evict(&mut dbs, used / 2, EvictionPolicy::AllKeysRandom, 5).unwrap());
+    assert!(dbs.iter().map(|db| db.used_memory()).sum::<usize>() <= used / 2);
+}
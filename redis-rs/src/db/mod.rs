@@ -1,4 +1,6 @@
+pub mod aof;
 pub mod db;
+pub mod evict;
+pub mod notify;
 pub mod rdb;
 pub mod shared;
-pub mod aof;
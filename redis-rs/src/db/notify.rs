@@ -0,0 +1,130 @@
+/*
+ * 键空间通知的类别，与redis的notify-keyspace-events一致
+ */
+pub const NOTIFY_KEYSPACE: u32 = 1 << 0; // K
+pub const NOTIFY_KEYEVENT: u32 = 1 << 1; // E
+pub const NOTIFY_GENERIC: u32 = 1 << 2; // g
+pub const NOTIFY_STRING: u32 = 1 << 3; // $
+pub const NOTIFY_LIST: u32 = 1 << 4; // l
+pub const NOTIFY_SET: u32 = 1 << 5; // s
+pub const NOTIFY_HASH: u32 = 1 << 6; // h
+pub const NOTIFY_ZSET: u32 = 1 << 7; // z
+pub const NOTIFY_EXPIRED: u32 = 1 << 8; // x
+pub const NOTIFY_EVICTED: u32 = 1 << 9; // e
+pub const NOTIFY_STREAM: u32 = 1 << 10; // t
+pub const NOTIFY_KEY_MISS: u32 = 1 << 11; // m
+pub const NOTIFY_NEW: u32 = 1 << 12; // n
+                                     // A：g$lshzxet的别名，不包括m和n
+pub const NOTIFY_ALL: u32 = NOTIFY_GENERIC
+    | NOTIFY_STRING
+    | NOTIFY_LIST
+    | NOTIFY_SET
+    | NOTIFY_HASH
+    | NOTIFY_ZSET
+    | NOTIFY_EXPIRED
+    | NOTIFY_EVICTED
+    | NOTIFY_STREAM;
+
+const FLAGS: [(char, u32); 13] = [
+    ('g', NOTIFY_GENERIC),
+    ('$', NOTIFY_STRING),
+    ('l', NOTIFY_LIST),
+    ('s', NOTIFY_SET),
+    ('h', NOTIFY_HASH),
+    ('z', NOTIFY_ZSET),
+    ('x', NOTIFY_EXPIRED),
+    ('e', NOTIFY_EVICTED),
+    ('t', NOTIFY_STREAM),
+    ('m', NOTIFY_KEY_MISS),
+    ('n', NOTIFY_NEW),
+    ('K', NOTIFY_KEYSPACE),
+    ('E', NOTIFY_KEYEVENT),
+];
+
+/*
+ * Db中产生的事件，由server在命令执行后发布
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub class: u32,
+    pub event: &'static str,
+    pub key: Vec<u8>,
+}
+
+/*
+ * 解析标志字符串，包含未知字符时返回None
+ */
+pub fn parse_flags(value: &str) -> Option<u32> {
+    let mut flags = 0;
+    for c in value.chars() {
+        flags |= match c {
+            'A' => NOTIFY_ALL,
+            _ => FLAGS.iter().find(|(f, _)| *f == c)?.1,
+        };
+    }
+    return Some(flags);
+}
+
+/*
+ * 与redis的格式一致：类别全开时写作A，然后是K、E、m、n
+ */
+pub fn format_flags(flags: u32) -> String {
+    let mut value = String::new();
+    if flags & NOTIFY_ALL == NOTIFY_ALL {
+        value.push('A');
+    }
+    for c in "g$lshzxetKEmn".chars() {
+        let flag = FLAGS.iter().find(|(f, _)| *f == c).unwrap().1;
+        if flags & flag == 0 || (flag & NOTIFY_ALL != 0 && value.starts_with('A')) {
+            continue;
+        }
+        value.push(c);
+    }
+    return value;
+}
+
+/*
+ * 事件按配置需要发布到的频道：__keyspace@<db>__:<key>的消息是事件名，
+ * __keyevent@<db>__:<event>的消息是key；既没有K也没有E时不发布
+ */
+pub fn channels(flags: u32, db: usize, event: &Event) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut messages = vec![];
+    if flags & event.class == 0 {
+        return messages;
+    }
+    if flags & NOTIFY_KEYSPACE != 0 {
+        let mut channel = format!("__keyspace@{}__:", db).into_bytes();
+        channel.extend_from_slice(&event.key);
+        messages.push((channel, event.event.as_bytes().to_vec()));
+    }
+    if flags & NOTIFY_KEYEVENT != 0 {
+        let channel = format!("__keyevent@{}__:{}", db, event.event).into_bytes();
+        messages.push((channel, event.key.clone()));
+    }
+    return messages;
+}
+
+#[test]
+fn test_parse_flags() {
+    assert_eq!(parse_flags(""), Some(0));
+    assert_eq!(parse_flags("Ex"), Some(NOTIFY_KEYEVENT | NOTIFY_EXPIRED));
+    assert_eq!(parse_flags("KA"), Some(NOTIFY_KEYSPACE | NOTIFY_ALL));
+    assert_eq!(parse_flags("Kq"), None);
+    assert_eq!(format_flags(parse_flags("nEAK").unwrap()), "AKEn");
+    assert_eq!(format_flags(parse_flags("x$E").unwrap()), "$xE");
+    assert_eq!(format_flags(0), "");
+
+    let event = Event {
+        class: NOTIFY_GENERIC,
+        event: "del",
+        key: b"k".to_vec(),
+    };
+    assert!(channels(NOTIFY_KEYSPACE | NOTIFY_STRING, 0, &event).is_empty());
+    assert_eq!(
+        channels(NOTIFY_KEYSPACE | NOTIFY_KEYEVENT | NOTIFY_ALL, 3, &event),
+        vec![
+            (b"__keyspace@3__:k".to_vec(), b"del".to_vec()),
+            (b"__keyevent@3__:del".to_vec(), b"k".to_vec()),
+        ]
+    );
+}
@@ -1,48 +1,461 @@
 use std::{
-    fs::{self, OpenOptions},
-    io::{BufReader, Read, Write},
-    mem,
+    collections::HashMap,
+    convert::{TryFrom, TryInto},
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Read, Write},
     path::Path,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
+    thread,
 };
 
-use crate::{common::error::Result, db::db::Db};
+use chrono::Local;
+
+use crate::{
+    common::{
+        crc64::crc64,
+        error::{Error, ErrorKind, Result},
+    },
+    db::db::Db,
+    server::server::REDIS_VERSION,
+    types::{
+        hash::HashObject,
+        list::ListObject,
+        object::{Object, ObjectValue},
+        set::SetObject,
+        strings,
+        zset::ZSetObject,
+    },
+};
+
+/*
+ * 文件格式与redis的rdb一致（只使用不依赖内部编码的类型）：
+ * "REDIS" + 4位版本号 | AUX字段 | SELECTDB | RESIZEDB | [EXPIRETIME_MS] 类型 key value ... | EOF | CRC64
+ */
+const RDB_MAGIC: &[u8] = b"REDIS";
+const RDB_VERSION: u32 = 9;
+
+const RDB_TYPE_STRING: u8 = 0;
+const RDB_TYPE_LIST: u8 = 1;
+const RDB_TYPE_SET: u8 = 2;
+const RDB_TYPE_HASH: u8 = 4;
+const RDB_TYPE_ZSET_2: u8 = 5;
+
+const RDB_OPCODE_AUX: u8 = 0xfa;
+const RDB_OPCODE_RESIZEDB: u8 = 0xfb;
+const RDB_OPCODE_EXPIRETIME_MS: u8 = 0xfc;
+const RDB_OPCODE_SELECTDB: u8 = 0xfe;
+const RDB_OPCODE_EOF: u8 = 0xff;
+
+// 长度编码的前两位
+const RDB_6BITLEN: u8 = 0;
+const RDB_14BITLEN: u8 = 1;
+const RDB_32BITLEN: u8 = 0x80;
+const RDB_64BITLEN: u8 = 0x81;
+const RDB_ENCVAL: u8 = 3;
+
+// 以整数保存的字符串
+const RDB_ENC_INT8: u8 = 0;
+const RDB_ENC_INT16: u8 = 1;
+const RDB_ENC_INT32: u8 = 2;
 
 /*
- * TODO
- * 1.使用fork进程。copy on write，利用虚拟进程分页来充分利用资源
- * 2.过滤已过期的key
- * 3.优化持久化文件格式
- *
- * 线程复制（会增大内存开销）
+ * 写入的同时计算校验和
  */
-pub fn save(db: Db, is_saving: Arc<AtomicBool>) {
+struct RdbWriter<W: Write> {
+    writer: W,
+    crc: u64,
+}
+
+impl<W: Write> RdbWriter<W> {
+    fn new(writer: W) -> Self {
+        return Self { writer, crc: 0 };
+    }
+
+    fn write_raw(&mut self, buf: &[u8]) -> Result<()> {
+        self.crc = crc64(self.crc, buf);
+        self.writer.write_all(buf)?;
+        return Ok(());
+    }
+
+    fn write_len(&mut self, len: u64) -> Result<()> {
+        if len < 1 << 6 {
+            return self.write_raw(&[(RDB_6BITLEN << 6) | len as u8]);
+        } else if len < 1 << 14 {
+            return self.write_raw(&[(RDB_14BITLEN << 6) | (len >> 8) as u8, len as u8]);
+        } else if len <= u32::MAX as u64 {
+            self.write_raw(&[RDB_32BITLEN])?;
+            return self.write_raw(&(len as u32).to_be_bytes());
+        }
+        self.write_raw(&[RDB_64BITLEN])?;
+        return self.write_raw(&len.to_be_bytes());
+    }
+
+    /*
+     * 规范形式的整数在32位范围内时以整数编码保存
+     */
+    fn write_string(&mut self, value: &[u8]) -> Result<()> {
+        if value.len() <= 11 {
+            if let Some(int) = strings::parse_integer(value) {
+                let prefix = RDB_ENCVAL << 6;
+                if let Ok(v) = i8::try_from(int) {
+                    return self.write_raw(&[prefix | RDB_ENC_INT8, v as u8]);
+                } else if let Ok(v) = i16::try_from(int) {
+                    self.write_raw(&[prefix | RDB_ENC_INT16])?;
+                    return self.write_raw(&v.to_le_bytes());
+                } else if let Ok(v) = i32::try_from(int) {
+                    self.write_raw(&[prefix | RDB_ENC_INT32])?;
+                    return self.write_raw(&v.to_le_bytes());
+                }
+            }
+        }
+        self.write_len(value.len() as u64)?;
+        return self.write_raw(value);
+    }
+
+    fn write_aux(&mut self, key: &str, value: &str) -> Result<()> {
+        self.write_raw(&[RDB_OPCODE_AUX])?;
+        self.write_string(key.as_bytes())?;
+        return self.write_string(value.as_bytes());
+    }
+
+    /*
+     * 类型 key value
+     */
+    fn write_object(&mut self, key: &[u8], obj: &Object) -> Result<()> {
+        let rdb_type = match obj.get_type() {
+            "String" => RDB_TYPE_STRING,
+            "List" => RDB_TYPE_LIST,
+            "Set" => RDB_TYPE_SET,
+            "Hash" => RDB_TYPE_HASH,
+            "ZSet" => RDB_TYPE_ZSET_2,
+            t => {
+                return Err(Error::new(
+                    ErrorKind::Invalid,
+                    format!("can not save object of type {}", t),
+                ))
+            }
+        };
+        self.write_raw(&[rdb_type])?;
+        self.write_string(key)?;
+        match rdb_type {
+            RDB_TYPE_STRING => {
+                self.write_string(&obj.as_string()?.get())?;
+            }
+            RDB_TYPE_LIST => {
+                let list = obj.as_list()?;
+                self.write_len(list.len() as u64)?;
+                for value in list.iter() {
+                    self.write_string(&value)?;
+                }
+            }
+            RDB_TYPE_SET => {
+                let members = obj.as_set()?.members();
+                self.write_len(members.len() as u64)?;
+                for member in members {
+                    self.write_string(&member)?;
+                }
+            }
+            RDB_TYPE_HASH => {
+                let entries = obj.as_hash()?.entries();
+                self.write_len(entries.len() as u64)?;
+                for (field, value) in entries {
+                    self.write_string(&field)?;
+                    self.write_string(&value)?;
+                }
+            }
+            RDB_TYPE_ZSET_2 => {
+                let entries = obj.as_zset()?.entries();
+                self.write_len(entries.len() as u64)?;
+                for (member, score) in entries {
+                    self.write_string(&member)?;
+                    self.write_raw(&score.to_le_bytes())?;
+                }
+            }
+            _ => {}
+        }
+        return Ok(());
+    }
+}
+
+/*
+ * 把所有db写入writer，空的db和已过期的key不会写入
+ */
+pub fn dump<W: Write>(dbs: &[Db], writer: W) -> Result<W> {
+    let mut rdb = RdbWriter::new(writer);
+    rdb.write_raw(RDB_MAGIC)?;
+    rdb.write_raw(format!("{:04}", RDB_VERSION).as_bytes())?;
+    rdb.write_aux("redis-ver", REDIS_VERSION)?;
+    rdb.write_aux("redis-bits", &(usize::BITS).to_string())?;
+    rdb.write_aux("ctime", &Local::now().timestamp().to_string())?;
+    let used_memory: usize = dbs.iter().map(|db| db.used_memory()).sum();
+    rdb.write_aux("used-mem", &used_memory.to_string())?;
+
+    let now = Local::now().timestamp_millis();
+    for (index, db) in dbs.iter().enumerate() {
+        if db.dict.dict_size() == 0 {
+            continue;
+        }
+        rdb.write_raw(&[RDB_OPCODE_SELECTDB])?;
+        rdb.write_len(index as u64)?;
+        rdb.write_raw(&[RDB_OPCODE_RESIZEDB])?;
+        rdb.write_len(db.dict.dict_size() as u64)?;
+        rdb.write_len(db.expires.dict_size() as u64)?;
+
+        let expires: HashMap<&[u8], i64> = db
+            .expires
+            .dict_iter()
+            .map(|(k, time)| (k.as_bytes(), *time))
+            .collect();
+        for (key, obj) in db.dict.dict_iter() {
+            if let Some(time) = expires.get(key.as_bytes()) {
+                if *time <= now {
+                    continue;
+                }
+                rdb.write_raw(&[RDB_OPCODE_EXPIRETIME_MS])?;
+                rdb.write_raw(&time.to_le_bytes())?;
+            }
+            rdb.write_object(key.as_bytes(), obj)?;
+        }
+    }
+    rdb.write_raw(&[RDB_OPCODE_EOF])?;
+    let crc = rdb.crc;
+    rdb.writer.write_all(&crc.to_le_bytes())?;
+    return Ok(rdb.writer);
+}
+
+/*
+ * 后台保存：dict分段和value都是写时复制的，clone只复制分段指针，
+ * 保存期间的额外内存只与被修改的key有关。已经在保存时返回false
+ */
+pub fn save_background(dbs: Vec<Db>, is_saving: Arc<AtomicBool>) -> bool {
+    if is_saving
+        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        return false;
+    }
+    thread::spawn(move || save(dbs, is_saving));
+    return true;
+}
+
+/*
+ * 写入临时文件后再原子替换，保证db.rdb总是完整的
+ */
+pub fn save(dbs: Vec<Db>, is_saving: Arc<AtomicBool>) {
     let res_func = || -> Result<()> {
-        let store_dir = &db.store_dir;
+        let store_dir = &dbs[0].store_dir;
+        fs::create_dir_all(store_dir)?;
         let temp_path = format!("{}/db.tmp", store_dir);
-        let mut file = OpenOptions::new()
-            .create_new(true)
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
             .write(true)
             .open(&temp_path)?;
-        let db_bytes = bincode::serialize(&db)?;
-        file.write_all(&db_bytes.len().to_le_bytes())?;
-        file.write_all(&db_bytes)?;
+        let mut writer = dump(&dbs, BufWriter::new(file))?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
         fs::rename(temp_path, format!("{}/db.rdb", store_dir))?;
         return Ok(());
     };
-    res_func().unwrap_or(());
+    if let Err(e) = res_func() {
+        println!("[rdb] save failed: {}", e);
+    }
     is_saving
         .compare_exchange(true, false, Ordering::SeqCst, Ordering::SeqCst)
         .unwrap();
 }
 
 /*
- * 1.过滤已过期的key
+ * 从缓冲区顺序读取，EOF之后校验整个文件的CRC64
  */
-pub fn load(path: &str) -> Result<Option<Db>> {
+struct RdbReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> RdbReader<'a> {
+    fn read_raw(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.pos + len > self.buf.len() {
+            return Err(rdb_error("unexpected end of file"));
+        }
+        let data = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        return Ok(data);
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        return Ok(self.read_raw(1)?[0]);
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        return Ok(self.read_raw(N)?.try_into().unwrap());
+    }
+
+    /*
+     * 返回(长度或整数编码类型, 是否为整数编码)
+     */
+    fn read_len_or_encoding(&mut self) -> Result<(u64, bool)> {
+        let first = self.read_u8()?;
+        return match first >> 6 {
+            RDB_6BITLEN => Ok(((first & 0x3f) as u64, false)),
+            RDB_14BITLEN => Ok((
+                (((first & 0x3f) as u64) << 8) | self.read_u8()? as u64,
+                false,
+            )),
+            RDB_ENCVAL => Ok(((first & 0x3f) as u64, true)),
+            _ => match first {
+                RDB_32BITLEN => Ok((u32::from_be_bytes(self.read_array()?) as u64, false)),
+                RDB_64BITLEN => Ok((u64::from_be_bytes(self.read_array()?), false)),
+                _ => Err(rdb_error(&format!("unknown length encoding {}", first))),
+            },
+        };
+    }
+
+    fn read_len(&mut self) -> Result<usize> {
+        return match self.read_len_or_encoding()? {
+            (len, false) => Ok(len as usize),
+            (_, true) => Err(rdb_error("unexpected string encoding")),
+        };
+    }
+
+    fn read_string(&mut self) -> Result<Vec<u8>> {
+        let value = match self.read_len_or_encoding()? {
+            (len, false) => self.read_raw(len as usize)?.to_vec(),
+            (enc, true) => {
+                let int = match enc as u8 {
+                    RDB_ENC_INT8 => i8::from_le_bytes(self.read_array()?) as i64,
+                    RDB_ENC_INT16 => i16::from_le_bytes(self.read_array()?) as i64,
+                    RDB_ENC_INT32 => i32::from_le_bytes(self.read_array()?) as i64,
+                    _ => return Err(rdb_error(&format!("unknown string encoding {}", enc))),
+                };
+                int.to_string().into_bytes()
+            }
+        };
+        return Ok(value);
+    }
+
+    fn read_object(&mut self, db: &Db, rdb_type: u8) -> Result<ObjectValue> {
+        let value = match rdb_type {
+            RDB_TYPE_STRING => ObjectValue::Strings(db.create_string(&self.read_string()?)),
+            RDB_TYPE_LIST => {
+                let mut list = ListObject::new();
+                for _ in 0..self.read_len()? {
+                    list.push(&self.read_string()?, false);
+                }
+                ObjectValue::List(Arc::new(list))
+            }
+            RDB_TYPE_SET => {
+                let mut set = SetObject::new();
+                for _ in 0..self.read_len()? {
+                    set.add(&self.read_string()?);
+                }
+                ObjectValue::Set(Arc::new(set))
+            }
+            RDB_TYPE_HASH => {
+                let mut hash = HashObject::new();
+                for _ in 0..self.read_len()? {
+                    let field = self.read_string()?;
+                    hash.set(&field, &self.read_string()?);
+                }
+                ObjectValue::Hash(Arc::new(hash))
+            }
+            RDB_TYPE_ZSET_2 => {
+                let mut zset = ZSetObject::new();
+                for _ in 0..self.read_len()? {
+                    let member = self.read_string()?;
+                    let score = f64::from_le_bytes(self.read_array()?);
+                    if score.is_nan() {
+                        return Err(rdb_error("zset score is nan"));
+                    }
+                    zset.insert(&member, score);
+                }
+                ObjectValue::ZSet(Arc::new(zset))
+            }
+            _ => return Err(rdb_error(&format!("unknown object type {}", rdb_type))),
+        };
+        return Ok(value);
+    }
+}
+
+fn rdb_error(msg: &str) -> Error {
+    return Error::new(ErrorKind::Parser, format!("invalid rdb: {}", msg));
+}
+
+/*
+ * 解析完整的rdb内容，返回databases个db，跳过已过期的key
+ */
+pub fn restore(buf: &[u8], store_dir: &str, databases: usize) -> Result<Vec<Db>> {
+    let mut reader = RdbReader { buf, pos: 0 };
+    if reader.read_raw(RDB_MAGIC.len())? != RDB_MAGIC {
+        return Err(rdb_error("wrong signature"));
+    }
+    let version = std::str::from_utf8(reader.read_raw(4)?)
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .ok_or_else(|| {
+            return rdb_error("invalid version");
+        })?;
+    if version == 0 || version > RDB_VERSION {
+        return Err(rdb_error(&format!("can't handle version {}", version)));
+    }
+
+    let mut dbs: Vec<Db> = (0..databases)
+        .map(|_| Db::new(store_dir.to_string(), None, None))
+        .collect();
+    let mut index = 0;
+    let now = Local::now().timestamp_millis();
+    let mut expire_time: Option<i64> = None;
+    loop {
+        let opcode = reader.read_u8()?;
+        match opcode {
+            RDB_OPCODE_AUX => {
+                reader.read_string()?;
+                reader.read_string()?;
+            }
+            RDB_OPCODE_SELECTDB => {
+                index = reader.read_len()?;
+                if index >= databases {
+                    return Err(rdb_error(&format!("DB index {} is out of range", index)));
+                }
+            }
+            RDB_OPCODE_RESIZEDB => {
+                reader.read_len()?;
+                reader.read_len()?;
+            }
+            RDB_OPCODE_EXPIRETIME_MS => {
+                expire_time = Some(i64::from_le_bytes(reader.read_array()?));
+            }
+            RDB_OPCODE_EOF => {
+                let crc = crc64(0, &buf[..reader.pos]);
+                let expected = u64::from_le_bytes(reader.read_array()?);
+                // 与redis一致，校验和为0表示保存时没有计算
+                if expected != 0 && expected != crc {
+                    return Err(rdb_error("checksum mismatch"));
+                }
+                return Ok(dbs);
+            }
+            rdb_type => {
+                let db = &mut dbs[index];
+                let key = reader.read_string()?;
+                let value = reader.read_object(db, rdb_type)?;
+                match expire_time.take() {
+                    Some(time) if time <= now => {}
+                    time => {
+                        db.set_object(&key, Object::new(value)?, false)?;
+                        if let Some(time) = time {
+                            db.set_expire(&key, time)?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn load(path: &str, databases: usize) -> Result<Option<Vec<Db>>> {
     let rdb_path_str = format!("{}/db.rdb", path);
     println!("[rdb] load db from {} ...", &rdb_path_str);
     let rdb_path: &Path = rdb_path_str.as_ref();
@@ -50,31 +463,23 @@ pub fn load(path: &str) -> Result<Option<Db>> {
         println!("[rdb] no rdb file ...");
         return Ok(None);
     }
-    let mut reader = BufReader::new(OpenOptions::new().read(true).open(rdb_path)?);
-
-    let mut buf = vec![0; mem::size_of::<usize>()];
-    let db: Option<Db>;
-    if let Ok(_) = reader.read_exact(&mut buf) {
-        let dict_size: usize = bincode::deserialize(&buf)?;
-        buf = vec![0; dict_size];
-        reader.read_exact(&mut buf)?;
-        db = Some(bincode::deserialize(&buf)?);
-    } else {
-        db = None;
+    let mut buf = vec![];
+    File::open(rdb_path)?.read_to_end(&mut buf)?;
+    if buf.is_empty() {
+        return Ok(None);
     }
-
-    return Ok(db);
+    return Ok(Some(restore(&buf, path, databases)?));
 }
 
 #[test]
 fn rdb() {
     use crate::db::db::Db;
-    use chrono::Local;
 
-    let store_dir = "store";
+    let store_dir = std::env::temp_dir().join("redis-rs-rdb");
+    let store_dir = store_dir.to_str().unwrap();
     let mut db = Db::new(store_dir.to_string(), None, None);
-    let kv = "demo";
-    db.set(&kv, &kv).unwrap();
+    let kv: &[u8] = b"demo";
+    db.set(kv, kv).unwrap();
     // expire after 10s.
     let expire_time = Local::now().timestamp_millis() + 10000;
     db.set_expire_time(kv, &expire_time.to_string()).unwrap();
@@ -82,15 +487,137 @@ fn rdb() {
     let is_saving = Arc::new(AtomicBool::new(true));
     let is_saving_clone = is_saving.clone();
     std::thread::spawn(move || {
-        save(db_cloned, is_saving_clone);
+        save(vec![db_cloned], is_saving_clone);
     });
     while is_saving.load(Ordering::SeqCst) {}
     assert!(std::path::Path::exists(
         format!("{}/db.rdb", store_dir).as_ref()
     ));
 
-    let db_loaded: Db = load(store_dir).unwrap().unwrap();
+    let db_loaded: Db = load(store_dir, 16).unwrap().unwrap().remove(0);
     assert_eq!(db.dict.dict_size(), db_loaded.dict.dict_size());
     assert_eq!(db.expires.dict_size(), db_loaded.expires.dict_size());
     fs::remove_file(format!("{}/db.rdb", store_dir)).unwrap();
 }
+
+#[test]
+fn rdb_format() {
+    let mut db = Db::new("store".to_string(), None, None);
+    // 覆盖各种长度编码和整数编码
+    db.set(b"small", b"1").unwrap();
+    db.set(b"int16", b"-1000").unwrap();
+    db.set(b"int32", b"100000").unwrap();
+    db.set(b"not-int", b"007").unwrap();
+    db.set(b"long", "x".repeat(20000).as_bytes()).unwrap();
+    let now = Local::now().timestamp_millis();
+    db.set(b"ttl", b"v").unwrap();
+    db.set_expire(b"ttl", now + 100_000).unwrap();
+    db.set(b"expired", b"v").unwrap();
+    db.set_expire(b"expired", now - 1).unwrap();
+    // 非UTF-8的key按原始字节保存
+    db.set(b"\xff", b"a").unwrap();
+    db.set(b"\xfe", b"b").unwrap();
+
+    let mut list = ListObject::new();
+    for i in 0..100 {
+        list.push(i.to_string().as_bytes(), false);
+    }
+    let mut hash = HashObject::new();
+    hash.set(b"field", b"value");
+    let mut set = SetObject::new();
+    set.add(b"1");
+    set.add(b"a");
+    let mut zset = ZSetObject::new();
+    zset.insert(b"a", 1.5);
+    zset.insert(b"b", f64::NEG_INFINITY);
+    for (key, value) in [
+        ("list", ObjectValue::List(Arc::new(list))),
+        ("hash", ObjectValue::Hash(Arc::new(hash))),
+        ("set", ObjectValue::Set(Arc::new(set))),
+        ("zset", ObjectValue::ZSet(Arc::new(zset))),
+    ] {
+        db.set_object(key.as_bytes(), Object::new(value).unwrap(), false)
+            .unwrap();
+    }
+
+    let buf = dump(std::slice::from_ref(&db), vec![]).unwrap();
+    assert_eq!(&buf[..9], b"REDIS0009");
+    let mut loaded = restore(&buf, "store", 16).unwrap().remove(0);
+    assert_eq!(loaded.dict.dict_size(), db.dict.dict_size() - 1);
+    assert!(!loaded.exist(b"expired").unwrap());
+    assert_eq!(loaded.get_expire(b"ttl").unwrap(), Some(now + 100_000));
+    assert_eq!(
+        loaded.get(b"\xff").unwrap().as_deref(),
+        Some(b"a".as_slice())
+    );
+    assert_eq!(
+        loaded.get(b"\xfe").unwrap().as_deref(),
+        Some(b"b".as_slice())
+    );
+    for key in ["small", "int16", "int32", "not-int", "long"] {
+        assert_eq!(
+            loaded.get(key.as_bytes()).unwrap(),
+            db.get(key.as_bytes()).unwrap()
+        );
+    }
+    let list = loaded
+        .lookup_key(b"list")
+        .unwrap()
+        .unwrap()
+        .as_list()
+        .unwrap();
+    assert_eq!(list.len(), 100);
+    assert_eq!(list.get(99), Some(b"99".to_vec()));
+    let hash = loaded
+        .lookup_key(b"hash")
+        .unwrap()
+        .unwrap()
+        .as_hash()
+        .unwrap();
+    assert_eq!(hash.get(b"field"), Some(b"value".to_vec()));
+    let set = loaded
+        .lookup_key(b"set")
+        .unwrap()
+        .unwrap()
+        .as_set()
+        .unwrap();
+    assert!(set.contains(b"1") && set.contains(b"a"));
+    let zset = loaded
+        .lookup_key(b"zset")
+        .unwrap()
+        .unwrap()
+        .as_zset()
+        .unwrap();
+    assert_eq!(zset.score(b"b"), Some(f64::NEG_INFINITY));
+    assert_eq!(zset.rank(b"a", false), Some(1));
+
+    // 任意字节被修改时校验失败，截断时报错
+    let mut corrupted = buf.clone();
+    corrupted[20] ^= 0xff;
+    assert!(restore(&corrupted, "store", 16).is_err());
+    assert!(restore(&buf[..buf.len() - 4], "store", 16).is_err());
+    assert!(restore(b"REDIX0009", "store", 16).is_err());
+    // 校验和为0时不校验
+    let mut unchecked = buf[..buf.len() - 8].to_vec();
+    unchecked.extend_from_slice(&[0; 8]);
+    assert!(restore(&unchecked, "store", 16).is_ok());
+}
+
+#[test]
+fn rdb_databases() {
+    let mut dbs: Vec<Db> = (0..4)
+        .map(|_| Db::new("store".to_string(), None, None))
+        .collect();
+    dbs[0].set(b"a", b"0").unwrap();
+    dbs[3].set(b"a", b"3").unwrap();
+    dbs[3].set(b"b", b"3").unwrap();
+    let buf = dump(&dbs, vec![]).unwrap();
+    let mut loaded = restore(&buf, "store", 4).unwrap();
+    assert_eq!(loaded.len(), 4);
+    assert_eq!(loaded[0].get(b"a").unwrap().unwrap().as_ref(), b"0");
+    assert_eq!(loaded[1].dict.dict_size(), 0);
+    assert_eq!(loaded[3].get(b"a").unwrap().unwrap().as_ref(), b"3");
+    assert_eq!(loaded[3].dict.dict_size(), 2);
+    // db数量不足时报错
+    assert!(restore(&buf, "store", 2).is_err());
+}
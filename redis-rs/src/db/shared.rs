@@ -3,7 +3,7 @@ use std::{
     sync::Arc,
 };
 
-use crate::{common::utils, types::strings::StringObject};
+use crate::types::strings::{self, StringObject};
 
 pub const SHARED_NUMBER: i64 = 1000;
 
@@ -22,9 +22,9 @@ impl Default for SharedObject {
 impl SharedObject {
     pub fn get<T>(&self, val: T) -> Option<Arc<StringObject>>
     where
-        T: AsRef<str>,
+        T: AsRef<[u8]>,
     {
-        if let Some(num) = utils::parse_str::<T, i64>(&val) {
+        if let Some(num) = strings::parse_integer(val.as_ref()) {
             return self.get_integer(num);
         } else {
             return None;
@@ -32,7 +32,7 @@ impl SharedObject {
     }
 
     pub fn get_integer(&self, num: i64) -> Option<Arc<StringObject>> {
-        if num < SHARED_NUMBER {
+        if (0..SHARED_NUMBER).contains(&num) {
             return Some(self.integers[num as usize].clone());
         } else {
             return None;
@@ -42,11 +42,14 @@ impl SharedObject {
     fn init_integers() -> [Arc<StringObject>; SHARED_NUMBER as usize] {
         let mut uninit_integers: [MaybeUninit<Arc<StringObject>>; SHARED_NUMBER as usize] =
             unsafe { MaybeUninit::uninit().assume_init() };
-        for i in 0..SHARED_NUMBER as usize {
-            uninit_integers[i].write(Arc::new(StringObject::new(i.to_string())));
+        for (i, integer) in uninit_integers.iter_mut().enumerate() {
+            integer.write(Arc::new(StringObject::new(i.to_string())));
         }
         return unsafe {
-            mem::transmute::<_, [Arc<StringObject>; SHARED_NUMBER as usize]>(uninit_integers)
+            mem::transmute::<
+                [MaybeUninit<Arc<StringObject>>; SHARED_NUMBER as usize],
+                [Arc<StringObject>; SHARED_NUMBER as usize],
+            >(uninit_integers)
         };
     }
 }
@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+
+const INTSET_ENC_INT16: usize = 2;
+const INTSET_ENC_INT32: usize = 4;
+const INTSET_ENC_INT64: usize = 8;
+
+/*
+ * 有序的整数集合：所有元素使用相同的宽度（2、4、8字节）紧凑存放，
+ * 加入超出当前宽度的元素时整体升级，查找使用二分法
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Intset {
+    encoding: usize,
+    contents: Vec<u8>,
+}
+
+impl Default for Intset {
+    fn default() -> Self {
+        return Self {
+            encoding: INTSET_ENC_INT16,
+            contents: Vec::new(),
+        };
+    }
+}
+
+impl Intset {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.contents.len() / self.encoding;
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        return self.contents.is_empty();
+    }
+
+    #[allow(dead_code)]
+    pub fn bytes(&self) -> usize {
+        return self.contents.len();
+    }
+
+    pub fn alloc_size(&self) -> usize {
+        return std::mem::size_of::<Self>() + self.contents.capacity();
+    }
+
+    pub fn get(&self, index: usize) -> Option<i64> {
+        if index >= self.len() {
+            return None;
+        }
+        return Some(self.get_encoded(index, self.encoding));
+    }
+
+    pub fn contains(&self, value: i64) -> bool {
+        return Self::value_encoding(value) <= self.encoding && self.search(value).is_ok();
+    }
+
+    /*
+     * 返回是否为新增的元素
+     */
+    pub fn add(&mut self, value: i64) -> bool {
+        if Self::value_encoding(value) > self.encoding {
+            self.upgrade_and_add(value);
+            return true;
+        }
+        return match self.search(value) {
+            Ok(_) => false,
+            Err(index) => {
+                let offset = index * self.encoding;
+                let bytes = Self::encode(value, self.encoding);
+                self.contents.splice(offset..offset, bytes);
+                true
+            }
+        };
+    }
+
+    pub fn remove(&mut self, value: i64) -> bool {
+        if Self::value_encoding(value) > self.encoding {
+            return false;
+        }
+        return match self.search(value) {
+            Ok(index) => {
+                let offset = index * self.encoding;
+                self.contents.drain(offset..offset + self.encoding);
+                true
+            }
+            Err(_) => false,
+        };
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = i64> + '_ {
+        return (0..self.len()).map(move |i| self.get_encoded(i, self.encoding));
+    }
+
+    fn search(&self, value: i64) -> Result<usize, usize> {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            let current = self.get_encoded(mid, self.encoding);
+            if current == value {
+                return Ok(mid);
+            } else if current < value {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        return Err(low);
+    }
+
+    /*
+     * 升级编码后，新元素一定比所有元素都小（负数）或者都大（正数）
+     */
+    fn upgrade_and_add(&mut self, value: i64) {
+        let old_encoding = self.encoding;
+        let len = self.len();
+        let new_encoding = Self::value_encoding(value);
+        let mut contents = Vec::with_capacity((len + 1) * new_encoding);
+        if value < 0 {
+            contents.extend(Self::encode(value, new_encoding));
+        }
+        for i in 0..len {
+            contents.extend(Self::encode(
+                self.get_encoded(i, old_encoding),
+                new_encoding,
+            ));
+        }
+        if value >= 0 {
+            contents.extend(Self::encode(value, new_encoding));
+        }
+        self.encoding = new_encoding;
+        self.contents = contents;
+    }
+
+    fn get_encoded(&self, index: usize, encoding: usize) -> i64 {
+        let bytes = &self.contents[index * encoding..(index + 1) * encoding];
+        return match encoding {
+            INTSET_ENC_INT16 => i16::from_le_bytes([bytes[0], bytes[1]]) as i64,
+            INTSET_ENC_INT32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64,
+            _ => {
+                let mut buf = [0; 8];
+                buf.copy_from_slice(bytes);
+                i64::from_le_bytes(buf)
+            }
+        };
+    }
+
+    fn encode(value: i64, encoding: usize) -> Vec<u8> {
+        return value.to_le_bytes()[..encoding].to_vec();
+    }
+
+    fn value_encoding(value: i64) -> usize {
+        if value >= i16::MIN as i64 && value <= i16::MAX as i64 {
+            return INTSET_ENC_INT16;
+        } else if value >= i32::MIN as i64 && value <= i32::MAX as i64 {
+            return INTSET_ENC_INT32;
+        }
+        return INTSET_ENC_INT64;
+    }
+}
+
+#[test]
+fn test_intset() {
+    let mut set = Intset::new();
+    assert!(set.add(5));
+    assert!(set.add(1));
+    assert!(set.add(3));
+    assert!(!set.add(3));
+    assert_eq!(set.bytes(), 6);
+    assert_eq!(set.iter().collect::<Vec<_>>(), vec![1, 3, 5]);
+
+    // 升级编码
+    assert!(set.add(1 << 20));
+    assert_eq!(set.bytes(), 16);
+    assert!(set.add(-(1 << 40)));
+    assert_eq!(set.bytes(), 40);
+    assert_eq!(
+        set.iter().collect::<Vec<_>>(),
+        vec![-(1 << 40), 1, 3, 5, 1 << 20]
+    );
+    assert!(set.contains(1 << 20));
+    assert!(!set.contains(4));
+    assert!(set.remove(3));
+    assert!(!set.remove(3));
+    assert!(!set.remove(i64::MAX));
+    assert_eq!(set.len(), 4);
+    assert_eq!(set.get(0), Some(-(1 << 40)));
+    assert_eq!(set.get(4), None);
+}
@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::strings;
+
+/*
+ * 条目编码（第一个字节）：
+ * 0xxxxxxx             7位无符号整数
+ * 10xxxxxx <data>      长度小于64的字符串
+ * 11110000 <u32> <data> 长度不小于64的字符串
+ * 11110100 <i64>       其他整数
+ */
+const LP_ENCODING_7BIT_UINT: u8 = 0x00;
+const LP_ENCODING_6BIT_STR: u8 = 0x80;
+const LP_ENCODING_32BIT_STR: u8 = 0xF0;
+const LP_ENCODING_64BIT_INT: u8 = 0xF4;
+
+/*
+ * 紧凑的列表：所有条目连续编码在一块内存中，节省指针和分配的开销
+ * 只适合保存少量元素，访问是O(n)的
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Listpack {
+    buf: Vec<u8>,
+    len: usize,
+}
+
+impl Listpack {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    /*
+     * 编码后占用的字节数
+     */
+    pub fn bytes(&self) -> usize {
+        return self.buf.len();
+    }
+
+    pub fn alloc_size(&self) -> usize {
+        return std::mem::size_of::<Self>() + self.buf.capacity();
+    }
+
+    /*
+     * value编码成条目后占用的字节数
+     */
+    pub fn entry_bytes(value: &[u8]) -> usize {
+        return Self::encode(value).len();
+    }
+
+    pub fn get(&self, index: usize) -> Option<Vec<u8>> {
+        let offset = self.offset(index)?;
+        return Some(self.decode(offset).0);
+    }
+
+    pub fn push_back(&mut self, value: &[u8]) {
+        self.insert(self.len, value);
+    }
+
+    pub fn push_front(&mut self, value: &[u8]) {
+        self.insert(0, value);
+    }
+
+    /*
+     * 在index之前插入，index等于长度时追加到末尾
+     */
+    pub fn insert(&mut self, index: usize, value: &[u8]) {
+        let offset = if index >= self.len {
+            self.buf.len()
+        } else {
+            self.offset(index).unwrap()
+        };
+        let entry = Self::encode(value);
+        self.buf.splice(offset..offset, entry);
+        self.len += 1;
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Vec<u8>> {
+        let offset = self.offset(index)?;
+        let (value, size) = self.decode(offset);
+        self.buf.drain(offset..offset + size);
+        self.len -= 1;
+        return Some(value);
+    }
+
+    pub fn replace(&mut self, index: usize, value: &[u8]) -> bool {
+        let offset = match self.offset(index) {
+            Some(offset) => offset,
+            None => return false,
+        };
+        let size = self.decode(offset).1;
+        self.buf.splice(offset..offset + size, Self::encode(value));
+        return true;
+    }
+
+    /*
+     * 从at开始拆分出一个新的listpack
+     */
+    pub fn split_off(&mut self, at: usize) -> Listpack {
+        let offset = match self.offset(at) {
+            Some(offset) => offset,
+            None => return Listpack::new(),
+        };
+        let other = Listpack {
+            buf: self.buf.split_off(offset),
+            len: self.len - at,
+        };
+        self.len = at;
+        return other;
+    }
+
+    pub fn iter(&self) -> ListpackIter<'_> {
+        return ListpackIter {
+            listpack: self,
+            offset: 0,
+        };
+    }
+
+    fn offset(&self, index: usize) -> Option<usize> {
+        if index >= self.len {
+            return None;
+        }
+        let mut offset = 0;
+        for _ in 0..index {
+            offset += self.entry_size(offset);
+        }
+        return Some(offset);
+    }
+
+    fn entry_size(&self, offset: usize) -> usize {
+        let encoding = self.buf[offset];
+        if encoding & 0x80 == LP_ENCODING_7BIT_UINT {
+            return 1;
+        } else if encoding & 0xC0 == LP_ENCODING_6BIT_STR {
+            return 1 + (encoding & 0x3F) as usize;
+        } else if encoding == LP_ENCODING_32BIT_STR {
+            return 5 + self.read_u32(offset + 1) as usize;
+        }
+        return 9;
+    }
+
+    /*
+     * 返回条目的值以及条目占用的字节数
+     */
+    fn decode(&self, offset: usize) -> (Vec<u8>, usize) {
+        let encoding = self.buf[offset];
+        let size = self.entry_size(offset);
+        let value = if encoding & 0x80 == LP_ENCODING_7BIT_UINT {
+            encoding.to_string().into_bytes()
+        } else if encoding & 0xC0 == LP_ENCODING_6BIT_STR {
+            self.buf[offset + 1..offset + size].to_vec()
+        } else if encoding == LP_ENCODING_32BIT_STR {
+            self.buf[offset + 5..offset + size].to_vec()
+        } else {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&self.buf[offset + 1..offset + 9]);
+            i64::from_le_bytes(bytes).to_string().into_bytes()
+        };
+        return (value, size);
+    }
+
+    fn encode(value: &[u8]) -> Vec<u8> {
+        let mut entry;
+        match strings::parse_integer(value) {
+            Some(i) if (0..128).contains(&i) => {
+                entry = vec![LP_ENCODING_7BIT_UINT | i as u8];
+            }
+            Some(i) => {
+                entry = vec![LP_ENCODING_64BIT_INT];
+                entry.extend_from_slice(&i.to_le_bytes());
+            }
+            None if value.len() < 64 => {
+                entry = vec![LP_ENCODING_6BIT_STR | value.len() as u8];
+                entry.extend_from_slice(value);
+            }
+            None => {
+                entry = vec![LP_ENCODING_32BIT_STR];
+                entry.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                entry.extend_from_slice(value);
+            }
+        }
+        return entry;
+    }
+
+    fn read_u32(&self, offset: usize) -> u32 {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&self.buf[offset..offset + 4]);
+        return u32::from_le_bytes(bytes);
+    }
+}
+
+pub struct ListpackIter<'a> {
+    listpack: &'a Listpack,
+    offset: usize,
+}
+
+impl<'a> Iterator for ListpackIter<'a> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.listpack.buf.len() {
+            return None;
+        }
+        let (value, size) = self.listpack.decode(self.offset);
+        self.offset += size;
+        return Some(value);
+    }
+}
+
+#[test]
+fn test_listpack() {
+    let mut lp = Listpack::new();
+    let long = "x".repeat(100);
+    lp.push_back(b"1");
+    lp.push_back(b"-1024");
+    lp.push_back(b"hello");
+    lp.push_back(long.as_bytes());
+    lp.push_front(b"007");
+    assert_eq!(lp.len(), 5);
+    // 1 + 9 + 6 + 105 + 4
+    assert_eq!(lp.bytes(), 125);
+    let values: Vec<Vec<u8>> = lp.iter().collect();
+    assert_eq!(
+        values,
+        vec![
+            b"007".to_vec(),
+            b"1".to_vec(),
+            b"-1024".to_vec(),
+            b"hello".to_vec(),
+            long.as_bytes().to_vec()
+        ]
+    );
+
+    assert_eq!(lp.get(2).unwrap(), b"-1024");
+    assert!(lp.get(5).is_none());
+    assert!(lp.replace(2, b"world"));
+    assert_eq!(lp.remove(0).unwrap(), b"007");
+    lp.insert(1, b"127");
+    assert_eq!(lp.get(1).unwrap(), b"127");
+
+    let tail = lp.split_off(2);
+    assert_eq!(
+        lp.iter().collect::<Vec<_>>(),
+        vec![b"1".to_vec(), b"127".to_vec()]
+    );
+    assert_eq!(tail.len(), 3);
+    assert_eq!(tail.get(0).unwrap(), b"world");
+}
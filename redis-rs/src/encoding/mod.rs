@@ -1,3 +1,6 @@
-pub mod sds;
-pub mod list_node;
 pub mod encoding;
+pub mod intset;
+pub mod listpack;
+pub mod quicklist;
+pub mod sds;
+pub mod skiplist;
@@ -0,0 +1,248 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use super::listpack::Listpack;
+
+/*
+ * 由listpack节点组成的双端列表，每个节点的大小不超过fill字节
+ * 两端的push/pop只涉及头尾节点，中间的插入只需要移动单个节点内的数据
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quicklist {
+    nodes: VecDeque<Listpack>,
+    len: usize,
+    fill: usize,
+}
+
+impl Quicklist {
+    pub fn new(fill: usize) -> Self {
+        return Self {
+            nodes: VecDeque::new(),
+            len: 0,
+            fill,
+        };
+    }
+
+    /*
+     * 将一个listpack按fill拆分成多个节点
+     */
+    pub fn from_listpack(listpack: Listpack, fill: usize) -> Self {
+        let mut quicklist = Self::new(fill);
+        quicklist.len = listpack.len();
+        if !listpack.is_empty() {
+            quicklist.nodes.push_back(listpack);
+        }
+        if !quicklist.nodes.is_empty() {
+            quicklist.split_node(0);
+        }
+        return quicklist;
+    }
+
+    /*
+     * 只剩一个节点时取出该节点
+     */
+    pub fn into_listpack(mut self) -> Option<Listpack> {
+        return match self.nodes.len() {
+            0 => Some(Listpack::new()),
+            1 => self.nodes.pop_front(),
+            _ => None,
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    pub fn node_count(&self) -> usize {
+        return self.nodes.len();
+    }
+
+    pub fn bytes(&self) -> usize {
+        return self.nodes.iter().map(|node| node.bytes()).sum();
+    }
+
+    pub fn alloc_size(&self) -> usize {
+        return std::mem::size_of::<Self>()
+            + self
+                .nodes
+                .iter()
+                .map(|node| node.alloc_size())
+                .sum::<usize>();
+    }
+
+    pub fn get(&self, index: usize) -> Option<Vec<u8>> {
+        let (node, offset) = self.locate(index)?;
+        return self.nodes[node].get(offset);
+    }
+
+    pub fn push_front(&mut self, value: &[u8]) {
+        match self.nodes.front_mut() {
+            Some(head) if head.bytes() + Listpack::entry_bytes(value) <= self.fill => {
+                head.push_front(value)
+            }
+            _ => {
+                let mut node = Listpack::new();
+                node.push_front(value);
+                self.nodes.push_front(node);
+            }
+        }
+        self.len += 1;
+    }
+
+    pub fn push_back(&mut self, value: &[u8]) {
+        match self.nodes.back_mut() {
+            Some(tail) if tail.bytes() + Listpack::entry_bytes(value) <= self.fill => {
+                tail.push_back(value)
+            }
+            _ => {
+                let mut node = Listpack::new();
+                node.push_back(value);
+                self.nodes.push_back(node);
+            }
+        }
+        self.len += 1;
+    }
+
+    /*
+     * 在index之前插入，节点超过fill时从中间拆分
+     */
+    pub fn insert(&mut self, index: usize, value: &[u8]) {
+        if index == 0 {
+            return self.push_front(value);
+        } else if index >= self.len {
+            return self.push_back(value);
+        }
+        let (node, offset) = self.locate(index).unwrap();
+        self.nodes[node].insert(offset, value);
+        self.len += 1;
+        self.split_node(node);
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Vec<u8>> {
+        let (node, offset) = self.locate(index)?;
+        let value = self.nodes[node].remove(offset);
+        self.len -= 1;
+        if self.nodes[node].is_empty() {
+            self.nodes.remove(node);
+        } else {
+            self.merge_node(node);
+        }
+        return value;
+    }
+
+    pub fn replace(&mut self, index: usize, value: &[u8]) -> bool {
+        let (node, offset) = match self.locate(index) {
+            Some(location) => location,
+            None => return false,
+        };
+        self.nodes[node].replace(offset, value);
+        self.split_node(node);
+        return true;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        return self.nodes.iter().flat_map(|node| node.iter());
+    }
+
+    /*
+     * 下标对应的节点以及在节点内的偏移，从离下标较近的一端开始查找
+     */
+    fn locate(&self, index: usize) -> Option<(usize, usize)> {
+        if index >= self.len {
+            return None;
+        }
+        if index < self.len / 2 {
+            let mut offset = index;
+            for (i, node) in self.nodes.iter().enumerate() {
+                if offset < node.len() {
+                    return Some((i, offset));
+                }
+                offset -= node.len();
+            }
+        } else {
+            let mut remain = self.len - index;
+            for (i, node) in self.nodes.iter().enumerate().rev() {
+                if remain <= node.len() {
+                    return Some((i, node.len() - remain));
+                }
+                remain -= node.len();
+            }
+        }
+        return None;
+    }
+
+    /*
+     * 节点超过fill时从中间拆分，直到每个节点都不超过fill（单个元素的节点除外）
+     */
+    fn split_node(&mut self, node: usize) {
+        if self.nodes[node].len() > 1 && self.nodes[node].bytes() > self.fill {
+            let at = self.nodes[node].len() / 2;
+            let tail = self.nodes[node].split_off(at);
+            self.nodes.insert(node + 1, tail);
+            self.split_node(node + 1);
+            self.split_node(node);
+        }
+    }
+
+    /*
+     * 相邻节点合并后不超过fill的一半时合并，避免删除后留下大量碎片节点
+     */
+    fn merge_node(&mut self, node: usize) {
+        if node + 1 < self.nodes.len()
+            && self.nodes[node].bytes() + self.nodes[node + 1].bytes() <= self.fill / 2
+        {
+            let next = self.nodes.remove(node + 1).unwrap();
+            for value in next.iter() {
+                self.nodes[node].push_back(&value);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_quicklist() {
+    let mut ql = Quicklist::new(64);
+    for i in 0..100 {
+        ql.push_back(format!("value-{}", i).as_bytes());
+    }
+    ql.push_front(b"head");
+    assert_eq!(ql.len(), 101);
+    assert!(ql.node_count() > 1);
+    assert!(ql.nodes.iter().all(|node| node.bytes() <= 64));
+    assert_eq!(ql.get(0).unwrap(), b"head");
+    assert_eq!(ql.get(100).unwrap(), b"value-99");
+    assert_eq!(ql.get(51).unwrap(), b"value-50");
+
+    // 中间插入后节点被拆分
+    ql.insert(50, "x".repeat(40).as_bytes());
+    assert_eq!(ql.get(50).unwrap(), "x".repeat(40).as_bytes());
+    assert!(ql
+        .nodes
+        .iter()
+        .all(|node| node.len() == 1 || node.bytes() <= 64));
+    assert!(ql.replace(50, b"y"));
+    assert_eq!(ql.remove(50).unwrap(), b"y");
+
+    let values: Vec<Vec<u8>> = ql.iter().collect();
+    assert_eq!(values.len(), 101);
+    assert_eq!(values[1], b"value-0");
+
+    while ql.len() > 1 {
+        ql.remove(0);
+    }
+    assert_eq!(ql.into_listpack().unwrap().get(0).unwrap(), b"value-99");
+
+    let mut lp = Listpack::new();
+    for i in 0..20 {
+        lp.push_back(format!("value-{}", i).as_bytes());
+    }
+    let ql = Quicklist::from_listpack(lp, 64);
+    assert!(ql.node_count() > 1);
+    assert_eq!(ql.iter().nth(19).unwrap(), b"value-19");
+}
@@ -1,5 +1,6 @@
 use serde::Deserialize;
 use serde::Serialize;
+use std::fmt::Display;
 use std::hash::Hash;
 
 const MB_SIZE: usize = 1024;
@@ -22,21 +23,19 @@ impl PartialEq for Sds {
 
 impl Hash for Sds {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.buf.hash(state);
+        self.as_bytes().hash(state);
     }
 }
 
-impl Into<Sds> for &str {
-    fn into(self) -> Sds {
-        return Sds::get_key(self);
+impl From<&str> for Sds {
+    fn from(value: &str) -> Sds {
+        return Sds::get_key(value);
     }
 }
 
-impl ToString for Sds {
-    fn to_string(&self) -> String {
-        return std::str::from_utf8(&self.buf[..self.used])
-            .map(|s| s.to_string())
-            .unwrap();
+impl Display for Sds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "{}", String::from_utf8_lossy(&self.buf[..self.used]));
     }
 }
 
@@ -68,31 +67,59 @@ impl Sds {
         return self.used;
     }
 
+    /*
+     * 实际占用的内存，包括预留的空间
+     */
+    pub fn alloc_size(&self) -> usize {
+        return std::mem::size_of::<Self>() + self.buf.len();
+    }
+
     /*
      * 动态扩容
      */
     pub fn push(&mut self, value: &[u8]) {
-        if self.free < value.len() {
-            let need_length = self.used + value.len();
-            let mut buf;
-            if need_length > MB_SIZE {
-                buf = Self::malloc(need_length + MB_SIZE);
-            } else {
-                buf = Self::malloc(need_length << 1);
+        self.make_room(value.len());
+        Self::copy(value, &mut self.buf, self.used);
+        self.set_size(self.used + value.len());
+    }
+
+    /*
+     * 从offset开始覆盖写入，超出当前长度时自动扩容，中间的空洞用0填充
+     */
+    pub fn set_range(&mut self, offset: usize, value: &[u8]) {
+        let end = offset + value.len();
+        if end > self.used {
+            self.make_room(end - self.used);
+            if offset > self.used {
+                self.buf[self.used..offset].fill(0);
             }
-            Self::copy(&self.buf, &mut buf, 0);
-            self.buf = buf;
+            self.set_size(end);
         }
-        Self::copy(&value, &mut self.buf, self.used);
-        self.set_size(self.used + value.len());
+        Self::copy(value, &mut self.buf, offset);
+    }
+
+    fn make_room(&mut self, add_len: usize) {
+        if self.free >= add_len {
+            return;
+        }
+        let need_length = self.used + add_len;
+        let mut buf;
+        if need_length > MB_SIZE {
+            buf = Self::malloc(need_length + MB_SIZE);
+        } else {
+            buf = Self::malloc(need_length << 1);
+        }
+        Self::copy(self.as_bytes(), &mut buf, 0);
+        self.buf = buf;
+        self.free = self.buf.len() - self.used;
     }
 
     fn copy(from: &[u8], to: &mut [u8], to_index: usize) {
-        to[to_index..].copy_from_slice(from);
+        to[to_index..to_index + from.len()].copy_from_slice(from);
     }
 
     fn malloc(size: usize) -> Box<[u8]> {
-        return Vec::with_capacity(size).into_boxed_slice();
+        return vec![0; size].into_boxed_slice();
     }
 
     fn set_size(&mut self, used: usize) {
@@ -100,3 +127,23 @@ impl Sds {
         self.free = self.buf.len() - used;
     }
 }
+
+#[test]
+fn test_sds() {
+    let mut sds = Sds::new(b"hello");
+    sds.push(b" world");
+    assert_eq!(sds.as_bytes(), b"hello world");
+    assert_eq!(sds.len(), 11);
+    assert_eq!(sds, Sds::new(b"hello world"));
+
+    sds.set_range(6, b"redis");
+    assert_eq!(sds.as_bytes(), b"hello redis");
+    sds.set_range(13, b"!");
+    assert_eq!(sds.as_bytes(), b"hello redis\0\0!");
+
+    // 相等的sds哈希值相同，与预分配的空间无关
+    use std::collections::HashSet;
+    let mut set = HashSet::new();
+    set.insert(sds.clone());
+    assert!(set.contains(&Sds::new(b"hello redis\0\0!")));
+}
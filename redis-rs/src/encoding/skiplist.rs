@@ -0,0 +1,418 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// 与redis的ZSKIPLIST_MAXLEVEL和ZSKIPLIST_P一致
+const SKIPLIST_MAXLEVEL: usize = 32;
+const SKIPLIST_P: f64 = 0.25;
+// 头节点固定在下标0，不保存元素
+const HEAD: usize = 0;
+
+/*
+ * 跳表的区间：below_min表示元素在区间左侧，above_max表示元素在区间右侧
+ */
+pub trait SkiplistRange {
+    fn below_min(&self, score: f64, member: &[u8]) -> bool;
+    fn above_max(&self, score: f64, member: &[u8]) -> bool;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Level {
+    forward: Option<usize>,
+    // 到forward节点跨过的元素个数，用于计算排名
+    span: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Node {
+    member: Vec<u8>,
+    score: f64,
+    backward: Option<usize>,
+    levels: Vec<Level>,
+}
+
+/*
+ * 按(score, member)排序的跳表，节点存放在数组中并通过下标互相引用，
+ * 删除的节点放入空闲列表复用；排名从0开始
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Skiplist {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    tail: Option<usize>,
+    length: usize,
+    level: usize,
+}
+
+impl Default for Skiplist {
+    fn default() -> Self {
+        let head = Node {
+            member: vec![],
+            score: 0.0,
+            backward: None,
+            levels: vec![
+                Level {
+                    forward: None,
+                    span: 0,
+                };
+                SKIPLIST_MAXLEVEL
+            ],
+        };
+        return Self {
+            nodes: vec![head],
+            free: vec![],
+            tail: None,
+            length: 0,
+            level: 1,
+        };
+    }
+}
+
+impl Skiplist {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.length;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.length == 0;
+    }
+
+    /*
+     * 估算占用的内存：只采样前几个节点，按节点数推算
+     */
+    pub fn alloc_size(&self, samples: usize) -> usize {
+        let base =
+            std::mem::size_of::<Self>() + self.nodes.capacity() * std::mem::size_of::<Node>();
+        let sampled: Vec<usize> = self
+            .iter(0, false)
+            .take(samples)
+            .map(|(member, _)| member.len())
+            .collect();
+        if sampled.is_empty() {
+            return base;
+        }
+        let levels: usize = self.nodes.iter().map(|node| node.levels.capacity()).sum();
+        return base
+            + levels * std::mem::size_of::<Level>()
+            + sampled.iter().sum::<usize>() * self.length / sampled.len();
+    }
+
+    /*
+     * 插入元素，调用方保证member不在跳表中
+     */
+    pub fn insert(&mut self, score: f64, member: &[u8]) {
+        let mut update = [HEAD; SKIPLIST_MAXLEVEL];
+        let mut rank = [0; SKIPLIST_MAXLEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i == self.level - 1 { 0 } else { rank[i + 1] };
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if !self.less(next, score, member) {
+                    break;
+                }
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let level = Self::random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.length;
+            }
+            self.level = level;
+        }
+
+        let node = self.alloc(Node {
+            member: member.to_vec(),
+            score,
+            backward: None,
+            levels: vec![
+                Level {
+                    forward: None,
+                    span: 0,
+                };
+                level
+            ],
+        });
+        for i in 0..level {
+            let prev = &self.nodes[update[i]].levels[i];
+            let (forward, span) = (prev.forward, prev.span);
+            self.nodes[node].levels[i] = Level {
+                forward,
+                span: span - (rank[0] - rank[i]),
+            };
+            self.nodes[update[i]].levels[i] = Level {
+                forward: Some(node),
+                span: rank[0] - rank[i] + 1,
+            };
+        }
+        for (i, prev) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[*prev].levels[i].span += 1;
+        }
+
+        self.nodes[node].backward = if update[0] == HEAD {
+            None
+        } else {
+            Some(update[0])
+        };
+        match self.nodes[node].levels[0].forward {
+            Some(next) => self.nodes[next].backward = Some(node),
+            None => self.tail = Some(node),
+        }
+        self.length += 1;
+    }
+
+    /*
+     * 删除元素，返回是否找到
+     */
+    pub fn delete(&mut self, score: f64, member: &[u8]) -> bool {
+        let mut update = [HEAD; SKIPLIST_MAXLEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if !self.less(next, score, member) {
+                    break;
+                }
+                x = next;
+            }
+            update[i] = x;
+        }
+        return match self.nodes[x].levels[0].forward {
+            Some(node) if self.nodes[node].score == score && self.nodes[node].member == member => {
+                self.delete_node(node, &update);
+                true
+            }
+            _ => false,
+        };
+    }
+
+    /*
+     * 元素的排名，不存在时返回None
+     */
+    pub fn rank(&self, score: f64, member: &[u8]) -> Option<usize> {
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                let node = &self.nodes[next];
+                if node.score > score || (node.score == score && node.member.as_slice() > member) {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if x != HEAD && self.nodes[x].member == member {
+                return Some(traversed - 1);
+            }
+        }
+        return None;
+    }
+
+    /*
+     * 区间内第一个元素的排名
+     */
+    pub fn first_in_range(&self, range: &dyn SkiplistRange) -> Option<usize> {
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                let node = &self.nodes[next];
+                if !range.below_min(node.score, &node.member) {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+        }
+        let node = &self.nodes[self.nodes[x].levels[0].forward?];
+        if range.above_max(node.score, &node.member) {
+            return None;
+        }
+        return Some(traversed);
+    }
+
+    /*
+     * 区间内最后一个元素的排名
+     */
+    pub fn last_in_range(&self, range: &dyn SkiplistRange) -> Option<usize> {
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                let node = &self.nodes[next];
+                if range.above_max(node.score, &node.member) {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+        }
+        let node = &self.nodes[x];
+        if x == HEAD || range.below_min(node.score, &node.member) {
+            return None;
+        }
+        return Some(traversed - 1);
+    }
+
+    /*
+     * 从指定排名开始正向（或反向）迭代
+     */
+    pub fn iter(&self, rank: usize, reverse: bool) -> SkiplistIter<'_> {
+        return SkiplistIter {
+            list: self,
+            next: self.node_by_rank(rank),
+            reverse,
+        };
+    }
+
+    fn node_by_rank(&self, rank: usize) -> Option<usize> {
+        // 排名从1开始计算跨度
+        let rank = rank + 1;
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if traversed + self.nodes[x].levels[i].span > rank {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if traversed == rank {
+                return Some(x);
+            }
+        }
+        return None;
+    }
+
+    fn delete_node(&mut self, node: usize, update: &[usize]) {
+        for (i, prev) in update.iter().enumerate().take(self.level) {
+            if self.nodes[*prev].levels[i].forward == Some(node) {
+                let Level { forward, span } = self.nodes[node].levels[i].clone();
+                let level = &mut self.nodes[*prev].levels[i];
+                level.span += span;
+                level.span -= 1;
+                level.forward = forward;
+            } else {
+                self.nodes[*prev].levels[i].span -= 1;
+            }
+        }
+        let backward = self.nodes[node].backward;
+        match self.nodes[node].levels[0].forward {
+            Some(next) => self.nodes[next].backward = backward,
+            None => self.tail = backward,
+        }
+        while self.level > 1 && self.nodes[HEAD].levels[self.level - 1].forward.is_none() {
+            self.level -= 1;
+        }
+        self.length -= 1;
+        self.nodes[node].member = vec![];
+        self.nodes[node].levels = vec![];
+        self.free.push(node);
+    }
+
+    fn less(&self, node: usize, score: f64, member: &[u8]) -> bool {
+        let node = &self.nodes[node];
+        return node.score < score || (node.score == score && node.member.as_slice() < member);
+    }
+
+    fn alloc(&mut self, node: Node) -> usize {
+        return match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+    }
+
+    fn random_level() -> usize {
+        let mut rng = rand::thread_rng();
+        let mut level = 1;
+        while level < SKIPLIST_MAXLEVEL && rng.gen::<f64>() < SKIPLIST_P {
+            level += 1;
+        }
+        return level;
+    }
+}
+
+pub struct SkiplistIter<'a> {
+    list: &'a Skiplist,
+    next: Option<usize>,
+    reverse: bool,
+}
+
+impl<'a> Iterator for SkiplistIter<'a> {
+    type Item = (&'a [u8], f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = &self.list.nodes[self.next?];
+        self.next = if self.reverse {
+            node.backward
+        } else {
+            node.levels[0].forward
+        };
+        return Some((&node.member, node.score));
+    }
+}
+
+#[test]
+fn test_skiplist() {
+    struct Range(f64, f64);
+    impl SkiplistRange for Range {
+        fn below_min(&self, score: f64, _: &[u8]) -> bool {
+            return score < self.0;
+        }
+        fn above_max(&self, score: f64, _: &[u8]) -> bool {
+            return score > self.1;
+        }
+    }
+
+    let mut list = Skiplist::new();
+    for i in (0..100).rev() {
+        list.insert(i as f64, format!("m{}", i).as_bytes());
+    }
+    list.insert(10.0, b"m10b");
+    assert_eq!(list.len(), 101);
+    assert_eq!(list.rank(0.0, b"m0"), Some(0));
+    assert_eq!(list.rank(10.0, b"m10b"), Some(11));
+    assert_eq!(list.rank(11.0, b"m11"), Some(12));
+    assert_eq!(list.rank(11.0, b"m12"), None);
+
+    let values: Vec<f64> = list.iter(98, false).map(|(_, s)| s).collect();
+    assert_eq!(values, vec![97.0, 98.0, 99.0]);
+    let values: Vec<&[u8]> = list.iter(12, true).map(|(m, _)| m).take(3).collect();
+    assert_eq!(values, vec![&b"m11"[..], b"m10b", b"m10"]);
+    assert!(list.iter(101, false).next().is_none());
+
+    assert_eq!(list.first_in_range(&Range(10.0, 20.0)), Some(10));
+    assert_eq!(list.last_in_range(&Range(10.0, 20.0)), Some(21));
+    assert_eq!(list.first_in_range(&Range(10.5, 10.6)), None);
+    assert_eq!(list.last_in_range(&Range(10.5, 10.6)), None);
+    assert_eq!(list.first_in_range(&Range(200.0, 300.0)), None);
+
+    // 删除后排名和跨度保持正确
+    assert!(list.delete(10.0, b"m10"));
+    assert!(!list.delete(10.0, b"m10"));
+    assert!(!list.delete(11.0, b"m10b"));
+    assert_eq!(list.rank(10.0, b"m10b"), Some(10));
+    for i in (0..50).filter(|i| *i != 10) {
+        assert!(list.delete(i as f64, format!("m{}", i).as_bytes()));
+    }
+    assert_eq!(list.len(), 51);
+    assert_eq!(list.rank(50.0, b"m50"), Some(1));
+    assert_eq!(list.iter(0, false).next(), Some((&b"m10b"[..], 10.0)));
+    list.insert(0.0, b"new");
+    assert_eq!(list.iter(51, true).next(), Some((&b"m99"[..], 99.0)));
+    assert_eq!(list.nodes.len(), 102);
+}
@@ -1,13 +1,22 @@
-use std::collections::{BTreeMap, HashMap};
+// 统一使用显式return，模块与所在目录同名
+#![allow(clippy::needless_return, clippy::module_inception)]
 
+mod command;
 mod common;
 mod db;
 mod encoding;
+mod script;
 mod server;
 mod types;
 
+use server::{config::Config, server::Server};
+
 fn main() {
-    println!("Hello, world!");
-    let mut map: BTreeMap<f32, i32> = BTreeMap::new();
-    map.insert(1.1, 1);
+    let res = Config::from_args(std::env::args().skip(1))
+        .and_then(Server::new)
+        .and_then(Server::run);
+    if let Err(e) = res {
+        println!("[server] {}", e);
+        std::process::exit(1);
+    }
 }
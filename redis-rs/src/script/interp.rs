@@ -0,0 +1,742 @@
+use std::{cell::RefCell, rc::Rc, sync::Arc};
+
+use super::{
+    parser::{BinOp, Block, Expr, FuncBody, Stat, TableField, UnOp},
+    script::Host,
+    value::{format_number, CallResult, Function, Scope, ScriptError, Table, Value},
+};
+
+// 函数调用的最大深度，避免无限递归导致栈溢出
+const MAX_CALL_DEPTH: usize = 100;
+// 每执行这么多步检查一次是否被SCRIPT KILL中止
+const CHECK_INTERVAL: u64 = 1000;
+
+enum Flow {
+    Normal,
+    Break,
+    Return(Vec<Value>),
+}
+
+type ExecResult<T> = std::result::Result<T, ScriptError>;
+
+/*
+ * 解释执行语法树：全局变量只读，脚本只能使用局部变量
+ */
+pub struct Interp<'h> {
+    globals: Rc<RefCell<Table>>,
+    host: &'h mut dyn Host,
+    steps: u64,
+    depth: usize,
+    // 正在执行的行，用于错误信息
+    line: usize,
+}
+
+impl<'h> Interp<'h> {
+    pub fn new(globals: Rc<RefCell<Table>>, host: &'h mut dyn Host) -> Self {
+        return Self {
+            globals,
+            host,
+            steps: 0,
+            depth: 0,
+            line: 0,
+        };
+    }
+
+    pub fn host(&mut self) -> &mut dyn Host {
+        return &mut *self.host;
+    }
+
+    pub fn line(&self) -> usize {
+        return self.line;
+    }
+
+    /*
+     * 带有当前位置的错误，与Lua的格式一致：user_script:<line>: <message>
+     */
+    pub fn error(&self, message: &str) -> ScriptError {
+        return ScriptError::message(format!("user_script:{}: {}", self.line, message));
+    }
+
+    /*
+     * 执行整个脚本
+     */
+    pub fn run(&mut self, chunk: &Arc<FuncBody>) -> CallResult {
+        let function = Value::Function(Rc::new(Function::Lua(chunk.clone(), Scope::new(None))));
+        return self.call(&function, vec![]);
+    }
+
+    fn step(&mut self) -> ExecResult<()> {
+        self.steps += 1;
+        if self.steps.is_multiple_of(CHECK_INTERVAL) && self.host.interrupted() {
+            return Err(ScriptError {
+                value: Value::from("Script killed by user with SCRIPT KILL..."),
+                killed: true,
+            });
+        }
+        return Ok(());
+    }
+
+    pub fn call(&mut self, function: &Value, args: Vec<Value>) -> CallResult {
+        let function = match function {
+            Value::Function(function) => function.clone(),
+            value => {
+                return Err(self.error(&format!("attempt to call a {} value", value.type_name())))
+            }
+        };
+        match function.as_ref() {
+            Function::Builtin(_, builtin) => return builtin(self, args),
+            Function::Lua(body, env) => {
+                if self.depth >= MAX_CALL_DEPTH {
+                    return Err(self.error("stack overflow"));
+                }
+                let mut args = args.into_iter();
+                let params: Vec<Value> = body
+                    .params
+                    .iter()
+                    .map(|_| args.next().unwrap_or(Value::Nil))
+                    .collect();
+                let varargs = match body.vararg {
+                    true => args.collect(),
+                    false => vec![],
+                };
+                let scope = Scope::function(Some(env.clone()), varargs);
+                for (name, value) in body.params.iter().zip(params) {
+                    scope.declare(name, value);
+                }
+                let line = self.line;
+                self.depth += 1;
+                let flow = self.exec_block(&body.body, &scope);
+                self.depth -= 1;
+                let values = match flow? {
+                    Flow::Return(values) => values,
+                    _ => vec![],
+                };
+                self.line = line;
+                return Ok(values);
+            }
+        }
+    }
+
+    fn exec_block(&mut self, block: &Block, scope: &Rc<Scope>) -> ExecResult<Flow> {
+        for (stat, line) in block {
+            self.line = *line;
+            self.step()?;
+            match self.exec(stat, scope)? {
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
+        }
+        return Ok(Flow::Normal);
+    }
+
+    fn exec(&mut self, stat: &Stat, scope: &Rc<Scope>) -> ExecResult<Flow> {
+        match stat {
+            Stat::Expr(expr) => {
+                self.eval_multi(expr, scope)?;
+            }
+            Stat::Local(names, exprs) => {
+                let values = self.eval_list(exprs, scope)?;
+                let mut values = values.into_iter();
+                for name in names {
+                    scope.declare(name, values.next().unwrap_or(Value::Nil));
+                }
+            }
+            Stat::LocalFunction(name, body) => {
+                scope.declare(name, Value::Nil);
+                let function = Function::Lua(body.clone(), scope.clone());
+                scope.declare(name, Value::Function(Rc::new(function)));
+            }
+            Stat::Assign(targets, exprs) => {
+                // 先求出所有左侧的table和key以及右侧的值，再依次赋值
+                let mut places = vec![];
+                for target in targets {
+                    places.push(match target {
+                        Expr::Index(object, key) => {
+                            Some((self.eval(object, scope)?, self.eval(key, scope)?))
+                        }
+                        _ => None,
+                    });
+                }
+                let mut values = self.eval_list(exprs, scope)?.into_iter();
+                for (target, place) in targets.iter().zip(places) {
+                    let value = values.next().unwrap_or(Value::Nil);
+                    match (target, place) {
+                        (Expr::Name(name), _) => self.assign_name(name, value, scope)?,
+                        (target, Some((object, key))) => {
+                            self.set_index(target, &object, key, value)?
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            Stat::If(branches, otherwise) => {
+                for (cond, body) in branches {
+                    if self.eval(cond, scope)?.truthy() {
+                        return self.exec_block(body, &Scope::new(Some(scope.clone())));
+                    }
+                }
+                if let Some(body) = otherwise {
+                    return self.exec_block(body, &Scope::new(Some(scope.clone())));
+                }
+            }
+            Stat::While(cond, body) => {
+                while self.eval(cond, scope)?.truthy() {
+                    self.step()?;
+                    match self.exec_block(body, &Scope::new(Some(scope.clone())))? {
+                        Flow::Break => break,
+                        Flow::Return(values) => return Ok(Flow::Return(values)),
+                        Flow::Normal => {}
+                    }
+                }
+            }
+            Stat::Repeat(body, cond) => loop {
+                self.step()?;
+                // until的条件可以使用循环体中的局部变量
+                let inner = Scope::new(Some(scope.clone()));
+                match self.exec_block(body, &inner)? {
+                    Flow::Break => break,
+                    Flow::Return(values) => return Ok(Flow::Return(values)),
+                    Flow::Normal => {}
+                }
+                if self.eval(cond, &inner)?.truthy() {
+                    break;
+                }
+            },
+            Stat::NumericFor(name, start, limit, step, body) => {
+                let number = |interp: &mut Self, expr: &Expr, what: &str| {
+                    return interp.eval(expr, scope)?.to_number().ok_or_else(|| {
+                        return interp.error(&format!("'for' {} must be a number", what));
+                    });
+                };
+                let mut i = number(self, start, "initial value")?;
+                let limit = number(self, limit, "limit")?;
+                let step = match step {
+                    Some(step) => number(self, step, "step")?,
+                    None => 1.0,
+                };
+                while (step > 0.0 && i <= limit) || (step <= 0.0 && i >= limit) {
+                    self.step()?;
+                    let inner = Scope::new(Some(scope.clone()));
+                    inner.declare(name, Value::Number(i));
+                    match self.exec_block(body, &inner)? {
+                        Flow::Break => break,
+                        Flow::Return(values) => return Ok(Flow::Return(values)),
+                        Flow::Normal => {}
+                    }
+                    i += step;
+                }
+            }
+            Stat::GenericFor(names, exprs, body) => {
+                let mut values = self.eval_list(exprs, scope)?.into_iter();
+                let function = values.next().unwrap_or(Value::Nil);
+                let state = values.next().unwrap_or(Value::Nil);
+                let mut control = values.next().unwrap_or(Value::Nil);
+                loop {
+                    self.step()?;
+                    let results = self.call(&function, vec![state.clone(), control.clone()])?;
+                    let mut results = results.into_iter();
+                    let first = results.next().unwrap_or(Value::Nil);
+                    if first.is_nil() {
+                        break;
+                    }
+                    control = first.clone();
+                    let inner = Scope::new(Some(scope.clone()));
+                    inner.declare(&names[0], first);
+                    for name in &names[1..] {
+                        inner.declare(name, results.next().unwrap_or(Value::Nil));
+                    }
+                    match self.exec_block(body, &inner)? {
+                        Flow::Break => break,
+                        Flow::Return(values) => return Ok(Flow::Return(values)),
+                        Flow::Normal => {}
+                    }
+                }
+            }
+            Stat::Do(body) => return self.exec_block(body, &Scope::new(Some(scope.clone()))),
+            Stat::Return(exprs) => return Ok(Flow::Return(self.eval_list(exprs, scope)?)),
+            Stat::Break => return Ok(Flow::Break),
+        }
+        return Ok(Flow::Normal);
+    }
+
+    /*
+     * 只能给局部变量赋值，不允许创建或者修改全局变量
+     */
+    fn assign_name(&mut self, name: &str, value: Value, scope: &Rc<Scope>) -> ExecResult<()> {
+        if scope.set(name, value) {
+            return Ok(());
+        }
+        if self.globals.borrow().get_str(name).is_nil() {
+            return Err(self.error(&format!(
+                "Script attempted to create global variable '{}'",
+                name
+            )));
+        }
+        return Err(self.error("Attempt to modify a readonly table"));
+    }
+
+    fn set_index(
+        &mut self,
+        target: &Expr,
+        object: &Value,
+        key: Value,
+        value: Value,
+    ) -> ExecResult<()> {
+        let table = match object {
+            Value::Table(table) => table,
+            value => {
+                return Err(self.error(&format!(
+                    "attempt to index {}",
+                    self.describe(target, value)
+                )))
+            }
+        };
+        if table.borrow().readonly {
+            return Err(self.error("Attempt to modify a readonly table"));
+        }
+        let res = table.borrow_mut().set(key, value);
+        return res.map_err(|e| self.error(e));
+    }
+
+    /*
+     * 错误信息中对变量的描述，比如global 'x' (a nil value)
+     */
+    fn describe(&self, expr: &Expr, value: &Value) -> String {
+        let name = match expr {
+            Expr::Name(name) => format!("global '{}' ", name),
+            Expr::Index(_, key) => match key.as_ref() {
+                Expr::Str(key) => format!("field '{}' ", String::from_utf8_lossy(key)),
+                _ => String::new(),
+            },
+            Expr::Method(_, name, _, _) => format!("method '{}' ", name),
+            _ => String::new(),
+        };
+        return format!("{}(a {} value)", name, value.type_name())
+            .trim_start()
+            .to_string();
+    }
+
+    /*
+     * 表达式的所有值：函数调用和...可能有多个值，其它表达式只有一个
+     */
+    fn eval_multi(&mut self, expr: &Expr, scope: &Rc<Scope>) -> ExecResult<Vec<Value>> {
+        match expr {
+            Expr::Vararg => return Ok(scope.varargs()),
+            Expr::Call(function, args, line) => {
+                let value = self.eval(function, scope)?;
+                let args = self.eval_list(args, scope)?;
+                self.line = *line;
+                if !matches!(value, Value::Function(_)) {
+                    return Err(self.error(&format!(
+                        "attempt to call {}",
+                        self.describe(function, &value)
+                    )));
+                }
+                return self.call(&value, args);
+            }
+            Expr::Method(object, name, args, line) => {
+                let object = self.eval(object, scope)?;
+                let function = self.index(&object, &Value::from(name.as_str()), expr)?;
+                let mut values = vec![object];
+                values.extend(self.eval_list(args, scope)?);
+                self.line = *line;
+                if !matches!(function, Value::Function(_)) {
+                    return Err(self.error(&format!(
+                        "attempt to call {}",
+                        self.describe(expr, &function)
+                    )));
+                }
+                return self.call(&function, values);
+            }
+            expr => return Ok(vec![self.eval(expr, scope)?]),
+        }
+    }
+
+    /*
+     * 表达式列表的值：最后一个表达式展开所有的值，其它的只取第一个
+     */
+    fn eval_list(&mut self, exprs: &[Expr], scope: &Rc<Scope>) -> ExecResult<Vec<Value>> {
+        let mut values = vec![];
+        for (i, expr) in exprs.iter().enumerate() {
+            if i == exprs.len() - 1 {
+                values.extend(self.eval_multi(expr, scope)?);
+            } else {
+                values.push(self.eval(expr, scope)?);
+            }
+        }
+        return Ok(values);
+    }
+
+    fn eval(&mut self, expr: &Expr, scope: &Rc<Scope>) -> ExecResult<Value> {
+        return Ok(match expr {
+            Expr::Nil => Value::Nil,
+            Expr::True => Value::Boolean(true),
+            Expr::False => Value::Boolean(false),
+            Expr::Number(n) => Value::Number(*n),
+            Expr::Str(s) => Value::Str(s.as_ref().into()),
+            Expr::Function(body) => {
+                Value::Function(Rc::new(Function::Lua(body.clone(), scope.clone())))
+            }
+            Expr::Name(name) => match scope.get(name) {
+                Some(value) => value,
+                None => {
+                    let value = self.globals.borrow().get_str(name);
+                    if value.is_nil() {
+                        return Err(self.error(&format!(
+                            "Script attempted to access nonexistent global variable '{}'",
+                            name
+                        )));
+                    }
+                    value
+                }
+            },
+            Expr::Index(object, key) => {
+                let value = self.eval(object, scope)?;
+                let key = self.eval(key, scope)?;
+                self.index(&value, &key, object)?
+            }
+            Expr::Vararg | Expr::Call(..) | Expr::Method(..) => self
+                .eval_multi(expr, scope)?
+                .into_iter()
+                .next()
+                .unwrap_or(Value::Nil),
+            Expr::Paren(expr) => self.eval(expr, scope)?,
+            Expr::Table(fields) => {
+                let mut table = Table::default();
+                for (i, field) in fields.iter().enumerate() {
+                    match field {
+                        TableField::Positional(expr) if i == fields.len() - 1 => {
+                            for value in self.eval_multi(expr, scope)? {
+                                table.push(value);
+                            }
+                        }
+                        TableField::Positional(expr) => {
+                            let value = self.eval(expr, scope)?;
+                            table.push(value);
+                        }
+                        TableField::Named(key, value) => {
+                            let key = self.eval(key, scope)?;
+                            let value = self.eval(value, scope)?;
+                            table.set(key, value).map_err(|e| self.error(e))?;
+                        }
+                    }
+                }
+                Value::table(table)
+            }
+            Expr::Unary(op, operand) => {
+                let value = self.eval(operand, scope)?;
+                self.unary(*op, value)?
+            }
+            Expr::Binary(BinOp::And, left, right) => {
+                let left = self.eval(left, scope)?;
+                match left.truthy() {
+                    true => self.eval(right, scope)?,
+                    false => left,
+                }
+            }
+            Expr::Binary(BinOp::Or, left, right) => {
+                let left = self.eval(left, scope)?;
+                match left.truthy() {
+                    true => left,
+                    false => self.eval(right, scope)?,
+                }
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left, scope)?;
+                let right = self.eval(right, scope)?;
+                self.binary(*op, left, right)?
+            }
+        });
+    }
+
+    /*
+     * t[k]：字符串的字段来自string库，支持s:upper()这样的写法
+     */
+    fn index(&mut self, object: &Value, key: &Value, expr: &Expr) -> ExecResult<Value> {
+        return match object {
+            Value::Table(table) => Ok(table.borrow().get(key)),
+            Value::Str(_) => match self.globals.borrow().get_str("string") {
+                Value::Table(string) => Ok(string.borrow().get(key)),
+                _ => Ok(Value::Nil),
+            },
+            value => Err(self.error(&format!("attempt to index {}", self.describe(expr, value)))),
+        };
+    }
+
+    fn unary(&mut self, op: UnOp, value: Value) -> ExecResult<Value> {
+        return match op {
+            UnOp::Not => Ok(Value::Boolean(!value.truthy())),
+            UnOp::Neg => match value.to_number() {
+                Some(n) => Ok(Value::Number(-n)),
+                None => Err(self.error(&format!(
+                    "attempt to perform arithmetic on a {} value",
+                    value.type_name()
+                ))),
+            },
+            UnOp::Len => match &value {
+                Value::Str(s) => Ok(Value::Number(s.len() as f64)),
+                Value::Table(t) => Ok(Value::Number(t.borrow().len() as f64)),
+                _ => Err(self.error(&format!(
+                    "attempt to get length of a {} value",
+                    value.type_name()
+                ))),
+            },
+        };
+    }
+
+    fn binary(&mut self, op: BinOp, left: Value, right: Value) -> ExecResult<Value> {
+        match op {
+            BinOp::Eq => return Ok(Value::Boolean(left.raw_equals(&right))),
+            BinOp::Ne => return Ok(Value::Boolean(!left.raw_equals(&right))),
+            BinOp::Lt => return self.less_than(&left, &right).map(Value::Boolean),
+            BinOp::Gt => return self.less_than(&right, &left).map(Value::Boolean),
+            BinOp::Le => return self.less_than(&right, &left).map(|r| Value::Boolean(!r)),
+            BinOp::Ge => return self.less_than(&left, &right).map(|r| Value::Boolean(!r)),
+            BinOp::Concat => {
+                return match (left.to_bytes(), right.to_bytes()) {
+                    (Some(mut a), Some(b)) => {
+                        a.extend_from_slice(&b);
+                        Ok(Value::from(a.as_slice()))
+                    }
+                    (None, _) => Err(self.error(&format!(
+                        "attempt to concatenate a {} value",
+                        left.type_name()
+                    ))),
+                    (_, None) => Err(self.error(&format!(
+                        "attempt to concatenate a {} value",
+                        right.type_name()
+                    ))),
+                }
+            }
+            _ => {}
+        }
+        let (a, b) = match (left.to_number(), right.to_number()) {
+            (Some(a), Some(b)) => (a, b),
+            (None, _) => {
+                return Err(self.error(&format!(
+                    "attempt to perform arithmetic on a {} value",
+                    left.type_name()
+                )))
+            }
+            (_, None) => {
+                return Err(self.error(&format!(
+                    "attempt to perform arithmetic on a {} value",
+                    right.type_name()
+                )))
+            }
+        };
+        return Ok(Value::Number(match op {
+            BinOp::Add => a + b,
+            BinOp::Sub => a - b,
+            BinOp::Mul => a * b,
+            BinOp::Div => a / b,
+            BinOp::Mod => a - (a / b).floor() * b,
+            BinOp::Pow => a.powf(b),
+            _ => unreachable!(),
+        }));
+    }
+
+    /*
+     * 数字按大小比较，字符串按字节比较，其它类型不能比较
+     */
+    pub fn less_than(&self, left: &Value, right: &Value) -> ExecResult<bool> {
+        return match (left, right) {
+            (Value::Number(a), Value::Number(b)) => Ok(a < b),
+            (Value::Str(a), Value::Str(b)) => Ok(a < b),
+            _ if left.type_name() == right.type_name() => Err(self.error(&format!(
+                "attempt to compare two {} values",
+                left.type_name()
+            ))),
+            _ => Err(self.error(&format!(
+                "attempt to compare {} with {}",
+                left.type_name(),
+                right.type_name()
+            ))),
+        };
+    }
+
+    /*
+     * tostring的结果
+     */
+    pub fn to_string(&self, value: &Value) -> Vec<u8> {
+        return match value {
+            Value::Number(n) => format_number(*n).into_bytes(),
+            Value::Str(s) => s.to_vec(),
+            value => value.to_string().into_bytes(),
+        };
+    }
+}
+
+#[test]
+fn test_interp() {
+    use super::script::run;
+    use crate::server::resp::Reply;
+
+    // redis.call返回参数个数，不访问服务器
+    struct TestHost;
+    impl Host for TestHost {
+        fn call(&mut self, args: Vec<Vec<u8>>) -> Reply {
+            return match args[0].as_slice() {
+                b"fail" => Reply::error("failed"),
+                _ => Reply::Integer(args.len() as i64),
+            };
+        }
+
+        fn interrupted(&mut self) -> bool {
+            return false;
+        }
+    }
+    let eval = |source: &str| {
+        let chunk = super::parser::parse(source.as_bytes()).unwrap();
+        return run(
+            "sha",
+            &chunk,
+            &[b"k".to_vec()],
+            &[b"5".to_vec()],
+            &mut TestHost,
+        );
+    };
+    let bulk = |s: &str| Reply::Bulk(s.as_bytes().to_vec());
+
+    assert_eq!(eval("return 2 ^ 10 + 7 % 3 - -1"), Reply::Integer(1026));
+    assert_eq!(eval("return ARGV[1] * 2 .. ''"), bulk("10"));
+    assert_eq!(eval("return #KEYS + #'abc'"), Reply::Integer(4));
+    assert_eq!(
+        eval("return 1 < 2 and 'a' < 'b' and not (nil or false)"),
+        Reply::Integer(1)
+    );
+
+    // 闭包和递归
+    let source = "local function counter() local n = 0 return function() n = n + 1 return n end end \
+                  local c = counter() c() c() \
+                  local function fib(n) if n < 2 then return n end return fib(n - 1) + fib(n - 2) end \
+                  return {c(), fib(15)}";
+    assert_eq!(
+        eval(source),
+        Reply::Array(vec![Reply::Integer(3), Reply::Integer(610)])
+    );
+
+    // 循环
+    let source = "local s = 0 for i = 10, 1, -2 do s = s + i end \
+                  local t = {} for k, v in pairs({a = 1, b = 2}) do t[#t + 1] = k .. v end \
+                  local n = 0 repeat local m = n n = n + 1 until m >= 3 \
+                  while true do if n > 10 then break end n = n * 2 end \
+                  return {s, table.concat(t, ','), n}";
+    assert_eq!(
+        eval(source),
+        Reply::Array(vec![Reply::Integer(30), bulk("a1,b2"), Reply::Integer(16)])
+    );
+
+    // 可变参数
+    assert_eq!(
+        eval("local function f(...) return select('#', ...), ... end return {f(4, 5, 6)}"),
+        Reply::Array(vec![
+            Reply::Integer(3),
+            Reply::Integer(4),
+            Reply::Integer(5),
+            Reply::Integer(6)
+        ])
+    );
+
+    // error和pcall
+    assert_eq!(
+        eval("local ok, e = pcall(error, 'boom', 0) return {tostring(ok), e}"),
+        Reply::Array(vec![bulk("false"), bulk("boom")])
+    );
+    assert_eq!(
+        eval("local ok, e = pcall(function() local x = nil + 1 end) return e"),
+        bulk("user_script:1: attempt to perform arithmetic on a nil value")
+    );
+    assert_eq!(
+        eval("local ok, e = pcall(function() error({code = 7}) end) return e.code"),
+        Reply::Integer(7)
+    );
+    assert_eq!(
+        eval("return redis.pcall('fail')"),
+        Reply::Error("ERR failed".to_string())
+    );
+    assert_eq!(
+        eval("local r = redis.pcall('fail') return r.err"),
+        bulk("ERR failed")
+    );
+    assert_eq!(
+        eval("redis.call('fail') return 1"),
+        Reply::Error("ERR failed".to_string())
+    );
+    assert_eq!(eval("return redis.call('x', 1, 'y')"), Reply::Integer(3));
+    assert_eq!(
+        eval("error('oops')"),
+        Reply::Error("ERR user_script:1: oops script: sha, on @user_script:1.".to_string())
+    );
+
+    // string和table库
+    assert_eq!(
+        eval("return {string.format('%05.1f|%-3d|%s|%x', 3.14159, 7, 'hi', 255), ('abc'):upper(), string.sub('hello', 2, -2)}"),
+        Reply::Array(vec![bulk("003.1|7  |hi|ff"), bulk("ABC"), bulk("ell")])
+    );
+    assert_eq!(
+        eval("return {string.rep('ab', 3), string.byte('A'), string.find('hello world', 'o w', 1, true)}"),
+        Reply::Array(vec![bulk("ababab"), Reply::Integer(65), Reply::Integer(5), Reply::Integer(7)])
+    );
+    assert_eq!(
+        eval(
+            "local t = {5, 2, 8, 1} table.sort(t) table.insert(t, 1, 0) table.remove(t) \
+              table.sort(t, function(a, b) return a > b end) return t"
+        ),
+        Reply::Array(vec![
+            Reply::Integer(5),
+            Reply::Integer(2),
+            Reply::Integer(1),
+            Reply::Integer(0)
+        ])
+    );
+    assert_eq!(
+        eval("return {tonumber('0x10'), tonumber('z', 36), tonumber('x') == nil, math.floor(-1.5), math.max(1, 9, 3)}"),
+        Reply::Array(vec![
+            Reply::Integer(16),
+            Reply::Integer(35),
+            Reply::Integer(1),
+            Reply::Integer(-2),
+            Reply::Integer(9),
+        ])
+    );
+    assert_eq!(eval("return tostring(1/3)"), bulk("0.33333333333333"));
+
+    // 沙箱：不能访问或者创建全局变量，也不能修改库
+    assert!(
+        matches!(eval("return os.time()"), Reply::Error(e) if e.contains("nonexistent global variable 'os'"))
+    );
+    assert!(
+        matches!(eval("local x = 1 y = 2"), Reply::Error(e) if e.contains("create global variable 'y'"))
+    );
+    assert!(matches!(eval("redis = nil"), Reply::Error(e) if e.contains("readonly table")));
+    assert!(matches!(eval("string.len = nil"), Reply::Error(e) if e.contains("readonly table")));
+    assert!(
+        matches!(eval("table.insert(math, 1)"), Reply::Error(e) if e.contains("readonly table"))
+    );
+}
+
+#[test]
+fn test_interp_killed() {
+    use super::script::run;
+    use crate::server::resp::Reply;
+
+    // 第一次检查时就被中止，pcall也不能捕获
+    struct KilledHost;
+    impl Host for KilledHost {
+        fn call(&mut self, _: Vec<Vec<u8>>) -> Reply {
+            return Reply::ok();
+        }
+
+        fn interrupted(&mut self) -> bool {
+            return true;
+        }
+    }
+    let chunk =
+        super::parser::parse(b"while true do pcall(function() while true do end end) end").unwrap();
+    let reply = run("sha", &chunk, &[], &[], &mut KilledHost);
+    assert_eq!(
+        reply,
+        Reply::Error("ERR Script killed by user with SCRIPT KILL...".to_string())
+    );
+}
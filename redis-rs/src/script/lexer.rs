@@ -0,0 +1,422 @@
+/*
+ * 脚本的词法分析，语法是Lua 5.1的一个子集
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Name(String),
+    Number(f64),
+    Str(Vec<u8>),
+    // 关键字
+    And,
+    Break,
+    Do,
+    Else,
+    Elseif,
+    End,
+    False,
+    For,
+    Function,
+    If,
+    In,
+    Local,
+    Nil,
+    Not,
+    Or,
+    Repeat,
+    Return,
+    Then,
+    True,
+    Until,
+    While,
+    // 运算符和分隔符
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Caret,
+    Hash,
+    Eq,
+    Ne,
+    Le,
+    Ge,
+    Lt,
+    Gt,
+    Assign,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Semicolon,
+    Colon,
+    Comma,
+    Dot,
+    Concat,
+    Ellipsis,
+    Eof,
+}
+
+const KEYWORDS: [(&str, Token); 21] = [
+    ("and", Token::And),
+    ("break", Token::Break),
+    ("do", Token::Do),
+    ("else", Token::Else),
+    ("elseif", Token::Elseif),
+    ("end", Token::End),
+    ("false", Token::False),
+    ("for", Token::For),
+    ("function", Token::Function),
+    ("if", Token::If),
+    ("in", Token::In),
+    ("local", Token::Local),
+    ("nil", Token::Nil),
+    ("not", Token::Not),
+    ("or", Token::Or),
+    ("repeat", Token::Repeat),
+    ("return", Token::Return),
+    ("then", Token::Then),
+    ("true", Token::True),
+    ("until", Token::Until),
+    ("while", Token::While),
+];
+
+/*
+ * 编译错误：行号以及错误信息
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub message: String,
+}
+
+pub type SyntaxResult<T> = std::result::Result<T, SyntaxError>;
+
+pub fn syntax_error<T>(line: usize, message: String) -> SyntaxResult<T> {
+    return Err(SyntaxError { line, message });
+}
+
+/*
+ * 把源码切分成(token, 行号)，末尾是Eof
+ */
+pub fn tokenize(source: &[u8]) -> SyntaxResult<Vec<(Token, usize)>> {
+    let mut lexer = Lexer {
+        src: source,
+        pos: 0,
+        line: 1,
+    };
+    let mut tokens = vec![];
+    loop {
+        lexer.skip_whitespace()?;
+        let line = lexer.line;
+        let token = lexer.next_token()?;
+        let eof = token == Token::Eof;
+        tokens.push((token, line));
+        if eof {
+            return Ok(tokens);
+        }
+    }
+}
+
+struct Lexer<'a> {
+    src: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn peek(&self, offset: usize) -> u8 {
+        return self.src.get(self.pos + offset).copied().unwrap_or(0);
+    }
+
+    fn bump(&mut self) -> u8 {
+        let c = self.peek(0);
+        self.pos += 1;
+        if c == b'\n' {
+            self.line += 1;
+        }
+        return c;
+    }
+
+    /*
+     * 跳过空白和注释：--到行尾，或者--[[ ]]长注释
+     */
+    fn skip_whitespace(&mut self) -> SyntaxResult<()> {
+        loop {
+            match self.peek(0) {
+                b' ' | b'\t' | b'\r' | b'\n' => {
+                    self.bump();
+                }
+                b'-' if self.peek(1) == b'-' => {
+                    self.pos += 2;
+                    if self.peek(0) == b'[' {
+                        if let Some(level) = self.long_bracket_level() {
+                            self.read_long_string(level)?;
+                            continue;
+                        }
+                    }
+                    while self.pos < self.src.len() && self.peek(0) != b'\n' {
+                        self.bump();
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /*
+     * 当前位置是[==[形式的长括号时返回等号的个数
+     */
+    fn long_bracket_level(&self) -> Option<usize> {
+        let mut level = 0;
+        while self.peek(1 + level) == b'=' {
+            level += 1;
+        }
+        return match self.peek(1 + level) {
+            b'[' => Some(level),
+            _ => None,
+        };
+    }
+
+    fn read_long_string(&mut self, level: usize) -> SyntaxResult<Vec<u8>> {
+        let line = self.line;
+        self.pos += level + 2;
+        // 紧跟在开括号后的换行不计入内容
+        if self.peek(0) == b'\r' {
+            self.bump();
+        }
+        if self.peek(0) == b'\n' {
+            self.bump();
+        }
+        let mut buf = vec![];
+        loop {
+            if self.pos >= self.src.len() {
+                return syntax_error(line, "unfinished long string".to_string());
+            }
+            if self.peek(0) == b']'
+                && (1..=level).all(|i| self.peek(i) == b'=')
+                && self.peek(level + 1) == b']'
+            {
+                self.pos += level + 2;
+                return Ok(buf);
+            }
+            buf.push(self.bump());
+        }
+    }
+
+    fn next_token(&mut self) -> SyntaxResult<Token> {
+        if self.pos >= self.src.len() {
+            return Ok(Token::Eof);
+        }
+        let c = self.peek(0);
+        if c.is_ascii_alphabetic() || c == b'_' {
+            let start = self.pos;
+            while self.peek(0).is_ascii_alphanumeric() || self.peek(0) == b'_' {
+                self.pos += 1;
+            }
+            let name = String::from_utf8_lossy(&self.src[start..self.pos]).to_string();
+            return Ok(match KEYWORDS.iter().find(|(k, _)| *k == name) {
+                Some((_, token)) => token.clone(),
+                None => Token::Name(name),
+            });
+        }
+        if c.is_ascii_digit() || (c == b'.' && self.peek(1).is_ascii_digit()) {
+            return self.read_number();
+        }
+        if c == b'"' || c == b'\'' {
+            return self.read_string(c);
+        }
+        if c == b'[' {
+            if let Some(level) = self.long_bracket_level() {
+                return Ok(Token::Str(self.read_long_string(level)?));
+            }
+        }
+        let two = [c, self.peek(1)];
+        let token = match &two {
+            b"==" => Some(Token::Eq),
+            b"~=" => Some(Token::Ne),
+            b"<=" => Some(Token::Le),
+            b">=" => Some(Token::Ge),
+            b".." if self.peek(2) == b'.' => {
+                self.pos += 3;
+                return Ok(Token::Ellipsis);
+            }
+            b".." => Some(Token::Concat),
+            _ => None,
+        };
+        if let Some(token) = token {
+            self.pos += 2;
+            return Ok(token);
+        }
+        self.pos += 1;
+        return Ok(match c {
+            b'+' => Token::Plus,
+            b'-' => Token::Minus,
+            b'*' => Token::Star,
+            b'/' => Token::Slash,
+            b'%' => Token::Percent,
+            b'^' => Token::Caret,
+            b'#' => Token::Hash,
+            b'<' => Token::Lt,
+            b'>' => Token::Gt,
+            b'=' => Token::Assign,
+            b'(' => Token::LParen,
+            b')' => Token::RParen,
+            b'{' => Token::LBrace,
+            b'}' => Token::RBrace,
+            b'[' => Token::LBracket,
+            b']' => Token::RBracket,
+            b';' => Token::Semicolon,
+            b':' => Token::Colon,
+            b',' => Token::Comma,
+            b'.' => Token::Dot,
+            _ => return syntax_error(self.line, format!("unexpected symbol near '{}'", c as char)),
+        });
+    }
+
+    fn read_number(&mut self) -> SyntaxResult<Token> {
+        let start = self.pos;
+        if self.peek(0) == b'0' && matches!(self.peek(1), b'x' | b'X') {
+            self.pos += 2;
+            while self.peek(0).is_ascii_hexdigit() {
+                self.pos += 1;
+            }
+        } else {
+            while self.peek(0).is_ascii_digit() || self.peek(0) == b'.' {
+                self.pos += 1;
+            }
+            if matches!(self.peek(0), b'e' | b'E') {
+                self.pos += 1;
+                if matches!(self.peek(0), b'+' | b'-') {
+                    self.pos += 1;
+                }
+                while self.peek(0).is_ascii_digit() {
+                    self.pos += 1;
+                }
+            }
+        }
+        // 数字后面紧跟字母或下划线是错误的写法，比如3x
+        while self.peek(0).is_ascii_alphanumeric() || self.peek(0) == b'_' {
+            self.pos += 1;
+        }
+        let text = String::from_utf8_lossy(&self.src[start..self.pos]).to_string();
+        return match parse_number(&text) {
+            Some(n) => Ok(Token::Number(n)),
+            None => syntax_error(self.line, format!("malformed number near '{}'", text)),
+        };
+    }
+
+    fn read_string(&mut self, quote: u8) -> SyntaxResult<Token> {
+        let line = self.line;
+        self.pos += 1;
+        let mut buf = vec![];
+        loop {
+            let c = self.peek(0);
+            if self.pos >= self.src.len() || c == b'\n' {
+                return syntax_error(line, "unfinished string".to_string());
+            }
+            self.bump();
+            if c == quote {
+                return Ok(Token::Str(buf));
+            }
+            if c != b'\\' {
+                buf.push(c);
+                continue;
+            }
+            let escaped = self.bump();
+            buf.push(match escaped {
+                b'n' => b'\n',
+                b't' => b'\t',
+                b'r' => b'\r',
+                b'a' => 0x07,
+                b'b' => 0x08,
+                b'f' => 0x0c,
+                b'v' => 0x0b,
+                b'\\' | b'"' | b'\'' | b'\n' => escaped,
+                b'0'..=b'9' => {
+                    // \ddd，最多三位十进制数
+                    let mut value = (escaped - b'0') as u32;
+                    for _ in 0..2 {
+                        if !self.peek(0).is_ascii_digit() {
+                            break;
+                        }
+                        value = value * 10 + (self.bump() - b'0') as u32;
+                    }
+                    if value > 255 {
+                        return syntax_error(self.line, "escape sequence too large".to_string());
+                    }
+                    value as u8
+                }
+                _ => {
+                    return syntax_error(
+                        self.line,
+                        format!("invalid escape sequence '\\{}'", escaped as char),
+                    )
+                }
+            });
+        }
+    }
+}
+
+/*
+ * 数字字面量以及tonumber：十进制（可以带小数和指数）或者0x开头的十六进制
+ */
+pub fn parse_number(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        let n = u64::from_str_radix(hex, 16).ok()? as f64;
+        return Some(if negative { -n } else { n });
+    }
+    // 排除Rust能解析但Lua不接受的写法
+    if text.is_empty()
+        || !text
+            .bytes()
+            .all(|c| c.is_ascii_digit() || matches!(c, b'.' | b'e' | b'E' | b'+' | b'-'))
+    {
+        return None;
+    }
+    return text.parse::<f64>().ok();
+}
+
+#[test]
+fn test_tokenize() {
+    let tokens: Vec<Token> = tokenize(b"local x = 1.5e2 -- comment\nreturn x..'a\\n' ~= [[b]]")
+        .unwrap()
+        .into_iter()
+        .map(|(token, _)| token)
+        .collect();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Local,
+            Token::Name("x".to_string()),
+            Token::Assign,
+            Token::Number(150.0),
+            Token::Return,
+            Token::Name("x".to_string()),
+            Token::Concat,
+            Token::Str(b"a\n".to_vec()),
+            Token::Ne,
+            Token::Str(b"b".to_vec()),
+            Token::Eof,
+        ]
+    );
+    assert_eq!(
+        tokenize(b"--[==[ long\n comment ]==] 0x1F").unwrap()[0].0,
+        Token::Number(31.0)
+    );
+    assert_eq!(tokenize(b"a\n'unfinished").unwrap_err().line, 2);
+    assert!(tokenize(b"3x").is_err());
+    assert_eq!(parse_number(" 10 "), Some(10.0));
+    assert_eq!(parse_number("-0x10"), Some(-16.0));
+    assert_eq!(parse_number("inf"), None);
+    assert_eq!(parse_number("1e"), None);
+}
@@ -0,0 +1,6 @@
+pub mod interp;
+pub mod lexer;
+pub mod parser;
+pub mod script;
+pub mod stdlib;
+pub mod value;
@@ -0,0 +1,630 @@
+use std::sync::Arc;
+
+use super::lexer::{syntax_error, tokenize, SyntaxResult, Token};
+
+// 表达式和代码块的最大嵌套深度，避免递归下降时栈溢出
+const MAX_DEPTH: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Concat,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnOp {
+    Neg,
+    Not,
+    Len,
+}
+
+#[derive(Debug)]
+pub enum Expr {
+    Nil,
+    True,
+    False,
+    Number(f64),
+    Str(Arc<[u8]>),
+    Vararg,
+    Function(Arc<FuncBody>),
+    Name(String),
+    Index(Box<Expr>, Box<Expr>),
+    // 函数调用，记录所在的行用于错误信息
+    Call(Box<Expr>, Vec<Expr>, usize),
+    // obj:name(args)
+    Method(Box<Expr>, String, Vec<Expr>, usize),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Unary(UnOp, Box<Expr>),
+    Table(Vec<TableField>),
+    // 括号中的表达式只取第一个值
+    Paren(Box<Expr>),
+}
+
+#[derive(Debug)]
+pub enum TableField {
+    Positional(Expr),
+    Named(Expr, Expr),
+}
+
+#[derive(Debug)]
+pub enum Stat {
+    Expr(Expr),
+    Local(Vec<String>, Vec<Expr>),
+    Assign(Vec<Expr>, Vec<Expr>),
+    LocalFunction(String, Arc<FuncBody>),
+    If(Vec<(Expr, Block)>, Option<Block>),
+    While(Expr, Block),
+    Repeat(Block, Expr),
+    NumericFor(String, Expr, Expr, Option<Expr>, Block),
+    GenericFor(Vec<String>, Vec<Expr>, Block),
+    Do(Block),
+    Return(Vec<Expr>),
+    Break,
+}
+
+// 语句以及所在的行
+pub type Block = Vec<(Stat, usize)>;
+
+#[derive(Debug)]
+pub struct FuncBody {
+    pub params: Vec<String>,
+    pub vararg: bool,
+    pub body: Block,
+}
+
+/*
+ * 解析整个脚本，脚本本身是一个可变参数的函数
+ */
+pub fn parse(source: &[u8]) -> SyntaxResult<Arc<FuncBody>> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        pos: 0,
+        depth: 0,
+    };
+    let body = parser.block()?;
+    if parser.peek() != &Token::Eof {
+        return parser.unexpected();
+    }
+    return Ok(Arc::new(FuncBody {
+        params: vec![],
+        vararg: true,
+        body,
+    }));
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        return &self.tokens[self.pos].0;
+    }
+
+    fn peek_at(&self, offset: usize) -> &Token {
+        let index = (self.pos + offset).min(self.tokens.len() - 1);
+        return &self.tokens[index].0;
+    }
+
+    fn line(&self) -> usize {
+        return self.tokens[self.pos].1;
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].0.clone();
+        if self.pos < self.tokens.len() - 1 {
+            self.pos += 1;
+        }
+        return token;
+    }
+
+    fn check(&mut self, token: &Token) -> bool {
+        if self.peek() == token {
+            self.advance();
+            return true;
+        }
+        return false;
+    }
+
+    fn expect(&mut self, token: Token, what: &str) -> SyntaxResult<()> {
+        if self.check(&token) {
+            return Ok(());
+        }
+        return syntax_error(
+            self.line(),
+            format!("'{}' expected near {}", what, describe(self.peek())),
+        );
+    }
+
+    fn unexpected<T>(&self) -> SyntaxResult<T> {
+        return syntax_error(
+            self.line(),
+            format!("unexpected symbol near {}", describe(self.peek())),
+        );
+    }
+
+    fn name(&mut self) -> SyntaxResult<String> {
+        return match self.advance() {
+            Token::Name(name) => Ok(name),
+            token => syntax_error(
+                self.line(),
+                format!("<name> expected near {}", describe(&token)),
+            ),
+        };
+    }
+
+    fn enter(&mut self) -> SyntaxResult<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return syntax_error(self.line(), "chunk has too many syntax levels".to_string());
+        }
+        return Ok(());
+    }
+
+    fn block(&mut self) -> SyntaxResult<Block> {
+        self.enter()?;
+        let mut block = vec![];
+        loop {
+            let line = self.line();
+            match self.peek() {
+                Token::Eof | Token::End | Token::Else | Token::Elseif | Token::Until => break,
+                Token::Return => {
+                    self.advance();
+                    let values = match self.peek() {
+                        Token::Eof
+                        | Token::End
+                        | Token::Else
+                        | Token::Elseif
+                        | Token::Until
+                        | Token::Semicolon => vec![],
+                        _ => self.expr_list()?,
+                    };
+                    self.check(&Token::Semicolon);
+                    block.push((Stat::Return(values), line));
+                    // return必须是代码块的最后一条语句
+                    break;
+                }
+                _ => {
+                    let stat = self.statement()?;
+                    self.check(&Token::Semicolon);
+                    block.push((stat, line));
+                }
+            }
+        }
+        self.depth -= 1;
+        return Ok(block);
+    }
+
+    fn statement(&mut self) -> SyntaxResult<Stat> {
+        match self.peek() {
+            Token::If => return self.if_statement(),
+            Token::While => {
+                self.advance();
+                let cond = self.expr()?;
+                self.expect(Token::Do, "do")?;
+                let body = self.block()?;
+                self.expect(Token::End, "end")?;
+                return Ok(Stat::While(cond, body));
+            }
+            Token::Do => {
+                self.advance();
+                let body = self.block()?;
+                self.expect(Token::End, "end")?;
+                return Ok(Stat::Do(body));
+            }
+            Token::For => return self.for_statement(),
+            Token::Repeat => {
+                self.advance();
+                let body = self.block()?;
+                self.expect(Token::Until, "until")?;
+                let cond = self.expr()?;
+                return Ok(Stat::Repeat(body, cond));
+            }
+            Token::Function => {
+                // function a.b.c(...)等价于a.b.c = function(...)
+                self.advance();
+                let mut target = Expr::Name(self.name()?);
+                let mut method = false;
+                loop {
+                    match self.peek() {
+                        Token::Dot => {
+                            self.advance();
+                            let key = self.name()?;
+                            target = Expr::Index(Box::new(target), Box::new(str_expr(&key)));
+                        }
+                        Token::Colon => {
+                            self.advance();
+                            let key = self.name()?;
+                            target = Expr::Index(Box::new(target), Box::new(str_expr(&key)));
+                            method = true;
+                            break;
+                        }
+                        _ => break,
+                    }
+                }
+                let body = self.function_body(method)?;
+                return Ok(Stat::Assign(vec![target], vec![Expr::Function(body)]));
+            }
+            Token::Local => {
+                self.advance();
+                if self.check(&Token::Function) {
+                    let name = self.name()?;
+                    let body = self.function_body(false)?;
+                    return Ok(Stat::LocalFunction(name, body));
+                }
+                let mut names = vec![self.name()?];
+                while self.check(&Token::Comma) {
+                    names.push(self.name()?);
+                }
+                let values = match self.check(&Token::Assign) {
+                    true => self.expr_list()?,
+                    false => vec![],
+                };
+                return Ok(Stat::Local(names, values));
+            }
+            Token::Break => {
+                self.advance();
+                return Ok(Stat::Break);
+            }
+            _ => {}
+        }
+
+        let expr = self.suffixed_expr()?;
+        if matches!(self.peek(), Token::Assign | Token::Comma) {
+            let mut targets = vec![expr];
+            while self.check(&Token::Comma) {
+                targets.push(self.suffixed_expr()?);
+            }
+            self.expect(Token::Assign, "=")?;
+            for target in &targets {
+                if !matches!(target, Expr::Name(_) | Expr::Index(..)) {
+                    return syntax_error(self.line(), "syntax error near '='".to_string());
+                }
+            }
+            let values = self.expr_list()?;
+            return Ok(Stat::Assign(targets, values));
+        }
+        if !matches!(expr, Expr::Call(..) | Expr::Method(..)) {
+            return syntax_error(
+                self.line(),
+                format!("syntax error near {}", describe(self.peek())),
+            );
+        }
+        return Ok(Stat::Expr(expr));
+    }
+
+    fn if_statement(&mut self) -> SyntaxResult<Stat> {
+        self.advance();
+        let mut branches = vec![];
+        let cond = self.expr()?;
+        self.expect(Token::Then, "then")?;
+        branches.push((cond, self.block()?));
+        let mut otherwise = None;
+        loop {
+            match self.advance() {
+                Token::Elseif => {
+                    let cond = self.expr()?;
+                    self.expect(Token::Then, "then")?;
+                    branches.push((cond, self.block()?));
+                }
+                Token::Else => {
+                    otherwise = Some(self.block()?);
+                    self.expect(Token::End, "end")?;
+                    break;
+                }
+                Token::End => break,
+                token => {
+                    return syntax_error(
+                        self.line(),
+                        format!("'end' expected near {}", describe(&token)),
+                    )
+                }
+            }
+        }
+        return Ok(Stat::If(branches, otherwise));
+    }
+
+    fn for_statement(&mut self) -> SyntaxResult<Stat> {
+        self.advance();
+        let first = self.name()?;
+        if self.check(&Token::Assign) {
+            let start = self.expr()?;
+            self.expect(Token::Comma, ",")?;
+            let limit = self.expr()?;
+            let step = match self.check(&Token::Comma) {
+                true => Some(self.expr()?),
+                false => None,
+            };
+            self.expect(Token::Do, "do")?;
+            let body = self.block()?;
+            self.expect(Token::End, "end")?;
+            return Ok(Stat::NumericFor(first, start, limit, step, body));
+        }
+        let mut names = vec![first];
+        while self.check(&Token::Comma) {
+            names.push(self.name()?);
+        }
+        self.expect(Token::In, "in")?;
+        let exprs = self.expr_list()?;
+        self.expect(Token::Do, "do")?;
+        let body = self.block()?;
+        self.expect(Token::End, "end")?;
+        return Ok(Stat::GenericFor(names, exprs, body));
+    }
+
+    /*
+     * 参数列表和函数体，方法定义时第一个参数是self
+     */
+    fn function_body(&mut self, method: bool) -> SyntaxResult<Arc<FuncBody>> {
+        self.expect(Token::LParen, "(")?;
+        let mut params = vec![];
+        if method {
+            params.push("self".to_string());
+        }
+        let mut vararg = false;
+        if !self.check(&Token::RParen) {
+            loop {
+                if self.check(&Token::Ellipsis) {
+                    vararg = true;
+                    break;
+                }
+                params.push(self.name()?);
+                if !self.check(&Token::Comma) {
+                    break;
+                }
+            }
+            self.expect(Token::RParen, ")")?;
+        }
+        let body = self.block()?;
+        self.expect(Token::End, "end")?;
+        return Ok(Arc::new(FuncBody {
+            params,
+            vararg,
+            body,
+        }));
+    }
+
+    fn expr_list(&mut self) -> SyntaxResult<Vec<Expr>> {
+        let mut exprs = vec![self.expr()?];
+        while self.check(&Token::Comma) {
+            exprs.push(self.expr()?);
+        }
+        return Ok(exprs);
+    }
+
+    fn expr(&mut self) -> SyntaxResult<Expr> {
+        return self.binary_expr(0);
+    }
+
+    /*
+     * 按优先级解析二元运算，优先级与Lua 5.1一致：
+     * or < and < 比较 < ..（右结合） < + - < * / % < 一元运算 < ^（右结合）
+     */
+    fn binary_expr(&mut self, limit: u8) -> SyntaxResult<Expr> {
+        self.enter()?;
+        let mut left = match self.peek() {
+            Token::Not | Token::Minus | Token::Hash => {
+                let op = match self.advance() {
+                    Token::Not => UnOp::Not,
+                    Token::Minus => UnOp::Neg,
+                    _ => UnOp::Len,
+                };
+                Expr::Unary(op, Box::new(self.binary_expr(UNARY_PRIORITY)?))
+            }
+            _ => self.simple_expr()?,
+        };
+        while let Some((op, left_priority, right_priority)) = binary_op(self.peek()) {
+            if left_priority <= limit {
+                break;
+            }
+            self.advance();
+            let right = self.binary_expr(right_priority)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        self.depth -= 1;
+        return Ok(left);
+    }
+
+    fn simple_expr(&mut self) -> SyntaxResult<Expr> {
+        let expr = match self.peek() {
+            Token::Nil => Expr::Nil,
+            Token::True => Expr::True,
+            Token::False => Expr::False,
+            Token::Number(n) => Expr::Number(*n),
+            Token::Str(s) => Expr::Str(s.as_slice().into()),
+            Token::Ellipsis => Expr::Vararg,
+            Token::Function => {
+                self.advance();
+                return Ok(Expr::Function(self.function_body(false)?));
+            }
+            Token::LBrace => return self.table(),
+            _ => return self.suffixed_expr(),
+        };
+        self.advance();
+        return Ok(expr);
+    }
+
+    fn primary_expr(&mut self) -> SyntaxResult<Expr> {
+        return match self.peek() {
+            Token::Name(_) => Ok(Expr::Name(self.name()?)),
+            Token::LParen => {
+                self.advance();
+                let expr = self.expr()?;
+                self.expect(Token::RParen, ")")?;
+                Ok(Expr::Paren(Box::new(expr)))
+            }
+            _ => self.unexpected(),
+        };
+    }
+
+    /*
+     * 变量、下标、字段以及函数调用：a.b[c]:d(e)
+     */
+    fn suffixed_expr(&mut self) -> SyntaxResult<Expr> {
+        let mut expr = self.primary_expr()?;
+        loop {
+            let line = self.line();
+            match self.peek() {
+                Token::Dot => {
+                    self.advance();
+                    let key = self.name()?;
+                    expr = Expr::Index(Box::new(expr), Box::new(str_expr(&key)));
+                }
+                Token::LBracket => {
+                    self.advance();
+                    let key = self.expr()?;
+                    self.expect(Token::RBracket, "]")?;
+                    expr = Expr::Index(Box::new(expr), Box::new(key));
+                }
+                Token::Colon => {
+                    self.advance();
+                    let name = self.name()?;
+                    let args = self.call_args()?;
+                    expr = Expr::Method(Box::new(expr), name, args, line);
+                }
+                Token::LParen | Token::Str(_) | Token::LBrace => {
+                    let args = self.call_args()?;
+                    expr = Expr::Call(Box::new(expr), args, line);
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    /*
+     * f(a, b)、f"str"以及f{...}
+     */
+    fn call_args(&mut self) -> SyntaxResult<Vec<Expr>> {
+        match self.peek() {
+            Token::Str(s) => {
+                let arg = Expr::Str(s.as_slice().into());
+                self.advance();
+                return Ok(vec![arg]);
+            }
+            Token::LBrace => return Ok(vec![self.table()?]),
+            _ => {}
+        }
+        self.expect(Token::LParen, "(")?;
+        if self.check(&Token::RParen) {
+            return Ok(vec![]);
+        }
+        let args = self.expr_list()?;
+        self.expect(Token::RParen, ")")?;
+        return Ok(args);
+    }
+
+    fn table(&mut self) -> SyntaxResult<Expr> {
+        self.expect(Token::LBrace, "{")?;
+        let mut fields = vec![];
+        while !self.check(&Token::RBrace) {
+            let field = match (self.peek(), self.peek_at(1)) {
+                (Token::LBracket, _) => {
+                    self.advance();
+                    let key = self.expr()?;
+                    self.expect(Token::RBracket, "]")?;
+                    self.expect(Token::Assign, "=")?;
+                    TableField::Named(key, self.expr()?)
+                }
+                (Token::Name(_), Token::Assign) => {
+                    let key = self.name()?;
+                    self.advance();
+                    TableField::Named(str_expr(&key), self.expr()?)
+                }
+                _ => TableField::Positional(self.expr()?),
+            };
+            fields.push(field);
+            if !self.check(&Token::Comma) && !self.check(&Token::Semicolon) {
+                self.expect(Token::RBrace, "}")?;
+                break;
+            }
+        }
+        return Ok(Expr::Table(fields));
+    }
+}
+
+const UNARY_PRIORITY: u8 = 8;
+
+/*
+ * 二元运算符以及左右两侧的优先级，右结合的运算符右侧优先级更低
+ */
+fn binary_op(token: &Token) -> Option<(BinOp, u8, u8)> {
+    return Some(match token {
+        Token::Or => (BinOp::Or, 1, 1),
+        Token::And => (BinOp::And, 2, 2),
+        Token::Eq => (BinOp::Eq, 3, 3),
+        Token::Ne => (BinOp::Ne, 3, 3),
+        Token::Lt => (BinOp::Lt, 3, 3),
+        Token::Le => (BinOp::Le, 3, 3),
+        Token::Gt => (BinOp::Gt, 3, 3),
+        Token::Ge => (BinOp::Ge, 3, 3),
+        Token::Concat => (BinOp::Concat, 5, 4),
+        Token::Plus => (BinOp::Add, 6, 6),
+        Token::Minus => (BinOp::Sub, 6, 6),
+        Token::Star => (BinOp::Mul, 7, 7),
+        Token::Slash => (BinOp::Div, 7, 7),
+        Token::Percent => (BinOp::Mod, 7, 7),
+        Token::Caret => (BinOp::Pow, 10, 9),
+        _ => return None,
+    });
+}
+
+fn str_expr(s: &str) -> Expr {
+    return Expr::Str(s.as_bytes().into());
+}
+
+fn describe(token: &Token) -> String {
+    return match token {
+        Token::Eof => "<eof>".to_string(),
+        Token::Name(name) => format!("'{}'", name),
+        Token::Number(n) => format!("'{}'", n),
+        Token::Str(s) => format!("'{}'", String::from_utf8_lossy(s)),
+        token => format!("'{:?}'", token).to_lowercase(),
+    };
+}
+
+#[test]
+fn test_parse() {
+    let chunk = parse(b"local a, b = 1, 2 + 3 * 4 ^ 2 ^ 0.5\nreturn a .. b").unwrap();
+    assert_eq!(chunk.body.len(), 2);
+    match &chunk.body[0].0 {
+        Stat::Local(names, values) => {
+            assert_eq!(names, &["a", "b"]);
+            // 2 + (3 * (4 ^ (2 ^ 0.5)))
+            match &values[1] {
+                Expr::Binary(BinOp::Add, _, right) => match right.as_ref() {
+                    Expr::Binary(BinOp::Mul, _, right) => {
+                        assert!(matches!(right.as_ref(), Expr::Binary(BinOp::Pow, _, _)))
+                    }
+                    expr => panic!("unexpected expr: {:?}", expr),
+                },
+                expr => panic!("unexpected expr: {:?}", expr),
+            }
+        }
+        stat => panic!("unexpected statement: {:?}", stat),
+    }
+    assert_eq!(chunk.body[1].1, 2);
+
+    parse(b"function t.f(x, ...) for i = 1, #x do if x[i] then break end end end").unwrap();
+    parse(b"local t = {1, 2; x = 3, ['y'] = 4,} for k, v in pairs(t) do end").unwrap();
+    parse(b"redis.call('set', KEYS[1], ARGV[1]) s:upper() f{1} f'x'").unwrap();
+    assert_eq!(parse(b"x = ").unwrap_err().line, 1);
+    assert!(parse(b"return 1 return 2").is_err());
+    assert!(parse(b"a + 1").is_err());
+    assert!(parse(b"if x then").is_err());
+    assert!(parse(&[b'('; 1000]).is_err());
+}
@@ -0,0 +1,270 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    common::sha1::sha1_hex,
+    server::resp::{parse_reply, Reply, RESP2},
+};
+
+use super::{
+    interp::Interp,
+    parser::{parse, FuncBody},
+    stdlib::globals,
+    value::{Table, Value},
+};
+
+/*
+ * 脚本访问服务器的接口：执行命令，以及检查脚本是否被SCRIPT KILL中止
+ */
+pub trait Host {
+    fn call(&mut self, args: Vec<Vec<u8>>) -> Reply;
+    fn interrupted(&mut self) -> bool;
+}
+
+/*
+ * 脚本缓存（按源码的SHA1索引）以及正在执行的脚本的状态
+ */
+#[derive(Default)]
+pub struct Scripts {
+    cache: Mutex<HashMap<String, Arc<FuncBody>>>,
+    // 正在执行的脚本的开始时间
+    running: Mutex<Option<Instant>>,
+    killed: AtomicBool,
+    // 执行过写命令的脚本不能被SCRIPT KILL中止
+    wrote: AtomicBool,
+}
+
+impl Scripts {
+    /*
+     * 编译并缓存脚本，返回SHA1以及编译结果
+     */
+    pub fn load(&self, source: &[u8]) -> std::result::Result<(String, Arc<FuncBody>), Reply> {
+        let sha = sha1_hex(source);
+        if let Some(chunk) = self.get(&sha) {
+            return Ok((sha, chunk));
+        }
+        let chunk = parse(source).map_err(|e| {
+            return Reply::error(format!(
+                "Error compiling script (new function): user_script:{}: {}",
+                e.line, e.message
+            ));
+        })?;
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(sha.clone(), chunk.clone());
+        return Ok((sha, chunk));
+    }
+
+    pub fn get(&self, sha: &str) -> Option<Arc<FuncBody>> {
+        return self
+            .cache
+            .lock()
+            .unwrap()
+            .get(&sha.to_ascii_lowercase())
+            .cloned();
+    }
+
+    pub fn exists(&self, sha: &str) -> bool {
+        return self.get(sha).is_some();
+    }
+
+    pub fn flush(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    pub fn start(&self) {
+        self.killed.store(false, Ordering::SeqCst);
+        self.wrote.store(false, Ordering::SeqCst);
+        *self.running.lock().unwrap() = Some(Instant::now());
+    }
+
+    pub fn finish(&self) {
+        *self.running.lock().unwrap() = None;
+    }
+
+    pub fn is_running(&self) -> bool {
+        return self.running.lock().unwrap().is_some();
+    }
+
+    /*
+     * 脚本执行时间超过busy-reply-threshold时，其它客户端收到BUSY错误
+     */
+    pub fn is_busy(&self, threshold: Duration) -> bool {
+        return self
+            .running
+            .lock()
+            .unwrap()
+            .is_some_and(|start| start.elapsed() >= threshold);
+    }
+
+    pub fn set_wrote(&self) {
+        self.wrote.store(true, Ordering::SeqCst);
+    }
+
+    pub fn killed(&self) -> bool {
+        return self.killed.load(Ordering::SeqCst);
+    }
+
+    /*
+     * SCRIPT KILL：只能中止还没有写过数据的脚本
+     */
+    pub fn kill(&self) -> Reply {
+        if !self.is_running() {
+            return Reply::Error("NOTBUSY No scripts in execution right now.".to_string());
+        }
+        if self.wrote.load(Ordering::SeqCst) {
+            return Reply::Error(
+                "UNKILLABLE Sorry the script already executed write commands against the dataset. \
+                 You can either wait the script termination or kill the server in a hard way \
+                 using the SHUTDOWN NOSAVE command."
+                    .to_string(),
+            );
+        }
+        self.killed.store(true, Ordering::SeqCst);
+        return Reply::ok();
+    }
+}
+
+/*
+ * 执行脚本，KEYS和ARGV作为全局变量，返回值转换成回复
+ */
+pub fn run(
+    sha: &str,
+    chunk: &Arc<FuncBody>,
+    keys: &[Vec<u8>],
+    argv: &[Vec<u8>],
+    host: &mut dyn Host,
+) -> Reply {
+    let globals = globals();
+    {
+        let mut globals = globals.borrow_mut();
+        for (name, values) in [("KEYS", keys), ("ARGV", argv)] {
+            let values = values.iter().map(|v| Value::from(v.as_slice())).collect();
+            globals.readonly = false;
+            globals
+                .set(Value::from(name), Value::table(Table::from_array(values)))
+                .unwrap();
+            globals.readonly = true;
+        }
+    }
+    let mut interp = Interp::new(globals, host);
+    return match interp.run(chunk) {
+        Ok(values) => match values.first() {
+            Some(value) => value_to_reply(value),
+            None => Reply::Null,
+        },
+        Err(e) if e.killed => Reply::error(e.value.to_string()),
+        // redis.call的错误以及redis.error_reply的返回值原样作为错误回复
+        Err(e) => match error_reply(&e.value) {
+            Some(reply) => reply,
+            None => Reply::error(format!(
+                "{} script: {}, on @user_script:{}.",
+                String::from_utf8_lossy(&interp.to_string(&e.value)),
+                sha,
+                interp.line()
+            )),
+        },
+    };
+}
+
+/*
+ * {err=...}形式的table
+ */
+fn error_reply(value: &Value) -> Option<Reply> {
+    if let Value::Table(table) = value {
+        if let Some(err) = table.borrow().get_str("err").to_bytes() {
+            return Some(Reply::Error(String::from_utf8_lossy(&err).to_string()));
+        }
+    }
+    return None;
+}
+
+/*
+ * 脚本中redis.call的参数只能是字符串或者数字
+ */
+pub fn value_to_args(values: &[Value]) -> Option<Vec<Vec<u8>>> {
+    return values
+        .iter()
+        .map(|value| {
+            return match value {
+                // 整数不带小数部分，其它数字按%.17g转换
+                Value::Number(n) if n.fract() == 0.0 && n.abs() < 1e17 => {
+                    Some((*n as i64).to_string().into_bytes())
+                }
+                Value::Number(n) => Some(format!("{}", n).into_bytes()),
+                Value::Str(s) => Some(s.to_vec()),
+                _ => None,
+            };
+        })
+        .collect();
+}
+
+/*
+ * 命令的回复转换成脚本中的值，按RESP2的形式转换：
+ * 整数->number，字符串->string，null->false，状态->{ok=...}，错误->{err=...}，数组->table
+ */
+pub fn reply_to_value(reply: &Reply) -> Value {
+    let mut buf = vec![];
+    reply.encode(RESP2, &mut buf);
+    let reply = parse_reply(&buf, &mut 0)
+        .ok()
+        .flatten()
+        .unwrap_or(Reply::Null);
+    return convert_reply(reply);
+}
+
+fn convert_reply(reply: Reply) -> Value {
+    return match reply {
+        Reply::Integer(i) => Value::Number(i as f64),
+        Reply::Bulk(b) => Value::from(b.as_slice()),
+        Reply::Status(s) => single_field("ok", &s),
+        Reply::Error(e) => single_field("err", &e),
+        Reply::Array(items) => Value::table(Table::from_array(
+            items.into_iter().map(convert_reply).collect(),
+        )),
+        _ => Value::Boolean(false),
+    };
+}
+
+fn single_field(field: &str, value: &str) -> Value {
+    let mut table = Table::default();
+    table.set(Value::from(field), Value::from(value)).unwrap();
+    return Value::table(table);
+}
+
+/*
+ * 脚本的返回值转换成回复：number->整数（截断小数部分），string->bulk，true->1，
+ * false/nil->null，{ok=...}->状态，{err=...}->错误，其它table->数组（到第一个nil为止）
+ */
+pub fn value_to_reply(value: &Value) -> Reply {
+    return match value {
+        Value::Number(n) => Reply::Integer(*n as i64),
+        Value::Str(s) => Reply::Bulk(s.to_vec()),
+        Value::Boolean(true) => Reply::Integer(1),
+        Value::Boolean(false) | Value::Nil | Value::Function(_) => Reply::Null,
+        Value::Table(table) => {
+            if let Some(reply) = error_reply(value) {
+                return reply;
+            }
+            let table = table.borrow();
+            if let Some(ok) = table.get_str("ok").to_bytes() {
+                return Reply::Status(String::from_utf8_lossy(&ok).to_string());
+            }
+            Reply::Array(
+                table
+                    .array()
+                    .iter()
+                    .take_while(|v| !v.is_nil())
+                    .map(value_to_reply)
+                    .collect(),
+            )
+        }
+    };
+}
@@ -0,0 +1,889 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::common::sha1::sha1_hex;
+
+use super::{
+    interp::Interp,
+    script::{reply_to_value, value_to_args},
+    value::{format_number, Builtin, CallResult, ScriptError, Table, Value},
+};
+
+/*
+ * 脚本可以使用的全局变量：基础函数以及table、string、math、redis库，
+ * 不提供io、os、loadstring等可以访问外部环境的函数
+ */
+pub fn globals() -> Rc<RefCell<Table>> {
+    let mut globals = Table::default();
+    let base: [(&'static str, Builtin); 14] = [
+        ("tostring", tostring),
+        ("tonumber", tonumber),
+        ("type", type_),
+        ("ipairs", ipairs),
+        ("pairs", pairs),
+        ("next", next),
+        ("unpack", unpack),
+        ("select", select),
+        ("error", error),
+        ("assert", assert),
+        ("pcall", pcall),
+        ("rawget", rawget),
+        ("rawset", rawset),
+        ("rawequal", rawequal),
+    ];
+    for (name, f) in base {
+        set(&mut globals, name, Value::builtin(name, f));
+    }
+
+    let table = library(&[
+        ("insert", table_insert),
+        ("remove", table_remove),
+        ("concat", table_concat),
+        ("getn", table_getn),
+        ("sort", table_sort),
+    ]);
+    set(&mut globals, "table", Value::table(table));
+
+    let string = library(&[
+        ("len", string_len),
+        ("sub", string_sub),
+        ("upper", string_upper),
+        ("lower", string_lower),
+        ("rep", string_rep),
+        ("reverse", string_reverse),
+        ("byte", string_byte),
+        ("char", string_char),
+        ("format", string_format),
+        ("find", string_find),
+    ]);
+    set(&mut globals, "string", Value::table(string));
+
+    let mut math = library(&[
+        ("floor", math_floor),
+        ("ceil", math_ceil),
+        ("abs", math_abs),
+        ("max", math_max),
+        ("min", math_min),
+        ("sqrt", math_sqrt),
+        ("fmod", math_fmod),
+        ("pow", math_pow),
+    ]);
+    set(&mut math, "huge", Value::Number(f64::INFINITY));
+    set(&mut math, "pi", Value::Number(std::f64::consts::PI));
+    set(&mut globals, "math", Value::table(math));
+
+    let mut redis = library(&[
+        ("call", redis_call),
+        ("pcall", redis_pcall),
+        ("error_reply", redis_error_reply),
+        ("status_reply", redis_status_reply),
+        ("sha1hex", redis_sha1hex),
+        ("log", redis_log),
+    ]);
+    for (i, name) in ["LOG_DEBUG", "LOG_VERBOSE", "LOG_NOTICE", "LOG_WARNING"]
+        .iter()
+        .enumerate()
+    {
+        set(&mut redis, name, Value::Number(i as f64));
+    }
+    set(&mut globals, "redis", Value::table(redis));
+    return Rc::new(RefCell::new(globals));
+}
+
+fn set(table: &mut Table, name: &str, value: Value) {
+    table.readonly = false;
+    table.set(Value::from(name), value).unwrap();
+    table.readonly = true;
+}
+
+fn library(functions: &[(&'static str, Builtin)]) -> Table {
+    let mut table = Table::default();
+    for (name, f) in functions {
+        set(&mut table, name, Value::builtin(name, *f));
+    }
+    return table;
+}
+
+/*
+ * 参数检查，错误信息与Lua一致：bad argument #1 to 'insert' (table expected, got nil)
+ */
+fn bad_argument(interp: &Interp, n: usize, name: &str, message: &str) -> ScriptError {
+    return interp.error(&format!("bad argument #{} to '{}' ({})", n, name, message));
+}
+
+fn arg(args: &[Value], n: usize) -> Value {
+    return args.get(n - 1).cloned().unwrap_or(Value::Nil);
+}
+
+fn expected(interp: &Interp, args: &[Value], n: usize, name: &str, what: &str) -> ScriptError {
+    let got = match args.get(n - 1) {
+        Some(value) => value.type_name(),
+        None => "no value",
+    };
+    return bad_argument(interp, n, name, &format!("{} expected, got {}", what, got));
+}
+
+fn number_arg(interp: &Interp, args: &[Value], n: usize, name: &str) -> Result<f64, ScriptError> {
+    return arg(args, n)
+        .to_number()
+        .ok_or_else(|| expected(interp, args, n, name, "number"));
+}
+
+fn optional_number(
+    interp: &Interp,
+    args: &[Value],
+    n: usize,
+    name: &str,
+    default: f64,
+) -> Result<f64, ScriptError> {
+    return match arg(args, n) {
+        Value::Nil => Ok(default),
+        _ => number_arg(interp, args, n, name),
+    };
+}
+
+fn string_arg(
+    interp: &Interp,
+    args: &[Value],
+    n: usize,
+    name: &str,
+) -> Result<Vec<u8>, ScriptError> {
+    return arg(args, n)
+        .to_bytes()
+        .ok_or_else(|| expected(interp, args, n, name, "string"));
+}
+
+fn table_arg(
+    interp: &Interp,
+    args: &[Value],
+    n: usize,
+    name: &str,
+) -> Result<Rc<RefCell<Table>>, ScriptError> {
+    return match arg(args, n) {
+        Value::Table(table) => Ok(table),
+        _ => Err(expected(interp, args, n, name, "table")),
+    };
+}
+
+/*
+ * 会修改table的函数不能作用于只读的库
+ */
+fn writable_table_arg(
+    interp: &Interp,
+    args: &[Value],
+    n: usize,
+    name: &str,
+) -> Result<Rc<RefCell<Table>>, ScriptError> {
+    let table = table_arg(interp, args, n, name)?;
+    if table.borrow().readonly {
+        return Err(interp.error("Attempt to modify a readonly table"));
+    }
+    return Ok(table);
+}
+
+/*
+ * 字符串下标：从1开始，负数从末尾倒数
+ */
+fn string_index(i: f64, len: usize) -> i64 {
+    let i = i as i64;
+    return if i < 0 { len as i64 + i + 1 } else { i };
+}
+
+fn tostring(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    if args.is_empty() {
+        return Err(bad_argument(interp, 1, "tostring", "value expected"));
+    }
+    return Ok(vec![Value::from(interp.to_string(&args[0]).as_slice())]);
+}
+
+fn tonumber(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let base = optional_number(interp, &args, 2, "tonumber", 10.0)? as u32;
+    if !(2..=36).contains(&base) {
+        return Err(bad_argument(interp, 2, "tonumber", "base out of range"));
+    }
+    let value = arg(&args, 1);
+    if base == 10 {
+        return Ok(vec![match value.to_number() {
+            Some(n) => Value::Number(n),
+            None => Value::Nil,
+        }]);
+    }
+    let text = string_arg(interp, &args, 1, "tonumber")?;
+    let text = String::from_utf8_lossy(&text).trim().to_lowercase();
+    return Ok(vec![match i64::from_str_radix(&text, base) {
+        Ok(n) => Value::Number(n as f64),
+        Err(_) => Value::Nil,
+    }]);
+}
+
+fn type_(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    if args.is_empty() {
+        return Err(bad_argument(interp, 1, "type", "value expected"));
+    }
+    return Ok(vec![Value::from(args[0].type_name())]);
+}
+
+fn ipairs_next(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let table = table_arg(interp, &args, 1, "ipairs")?;
+    let i = number_arg(interp, &args, 2, "ipairs")? + 1.0;
+    let value = table.borrow().get(&Value::Number(i));
+    if value.is_nil() {
+        return Ok(vec![Value::Nil]);
+    }
+    return Ok(vec![Value::Number(i), value]);
+}
+
+fn ipairs(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let table = table_arg(interp, &args, 1, "ipairs")?;
+    return Ok(vec![
+        Value::builtin("ipairs_next", ipairs_next),
+        Value::Table(table),
+        Value::Number(0.0),
+    ]);
+}
+
+fn pairs(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let table = table_arg(interp, &args, 1, "pairs")?;
+    return Ok(vec![
+        Value::builtin("next", next),
+        Value::Table(table),
+        Value::Nil,
+    ]);
+}
+
+fn next(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let table = table_arg(interp, &args, 1, "next")?;
+    let res = table.borrow().next(&arg(&args, 2));
+    return match res {
+        Ok(Some((key, value))) => Ok(vec![key, value]),
+        Ok(None) => Ok(vec![Value::Nil]),
+        Err(()) => Err(interp.error("invalid key to 'next'")),
+    };
+}
+
+fn unpack(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let table = table_arg(interp, &args, 1, "unpack")?;
+    let table = table.borrow();
+    let start = optional_number(interp, &args, 2, "unpack", 1.0)? as i64;
+    let end = optional_number(interp, &args, 3, "unpack", table.len() as f64)? as i64;
+    if end - start >= 8000 {
+        return Err(interp.error("too many results to unpack"));
+    }
+    return Ok((start..=end)
+        .map(|i| table.get(&Value::Number(i as f64)))
+        .collect());
+}
+
+fn select(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let rest = args.len().saturating_sub(1);
+    if let Value::Str(s) = arg(&args, 1) {
+        if s.as_ref() == b"#" {
+            return Ok(vec![Value::Number(rest as f64)]);
+        }
+    }
+    let n = number_arg(interp, &args, 1, "select")? as i64;
+    let start = match n {
+        n if n < 0 && -n <= rest as i64 => rest as i64 + n,
+        n if n > 0 => (n - 1).min(rest as i64),
+        _ => return Err(bad_argument(interp, 1, "select", "index out of range")),
+    };
+    return Ok(args.into_iter().skip(1 + start as usize).collect());
+}
+
+/*
+ * error(message, level)：level为0时不添加位置信息
+ */
+fn error(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let level = optional_number(interp, &args, 2, "error", 1.0)?;
+    let value = arg(&args, 1);
+    if let (Value::Str(s), true) = (&value, level > 0.0) {
+        return Err(interp.error(&String::from_utf8_lossy(s)));
+    }
+    return Err(ScriptError::new(value));
+}
+
+fn assert(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    if arg(&args, 1).truthy() {
+        return Ok(args);
+    }
+    return match arg(&args, 2) {
+        Value::Nil => Err(interp.error("assertion failed!")),
+        message => Err(ScriptError::new(message)),
+    };
+}
+
+/*
+ * 捕获错误，但是不能阻止SCRIPT KILL中止脚本
+ */
+fn pcall(interp: &mut Interp, mut args: Vec<Value>) -> CallResult {
+    if args.is_empty() {
+        return Err(bad_argument(interp, 1, "pcall", "value expected"));
+    }
+    let function = args.remove(0);
+    return match interp.call(&function, args) {
+        Ok(mut values) => {
+            values.insert(0, Value::Boolean(true));
+            Ok(values)
+        }
+        Err(e) if e.killed => Err(e),
+        Err(e) => Ok(vec![Value::Boolean(false), e.value]),
+    };
+}
+
+fn rawget(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let table = table_arg(interp, &args, 1, "rawget")?;
+    let value = table.borrow().get(&arg(&args, 2));
+    return Ok(vec![value]);
+}
+
+fn rawset(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let table = writable_table_arg(interp, &args, 1, "rawset")?;
+    let res = table.borrow_mut().set(arg(&args, 2), arg(&args, 3));
+    res.map_err(|e| interp.error(e))?;
+    return Ok(vec![Value::Table(table)]);
+}
+
+fn rawequal(_: &mut Interp, args: Vec<Value>) -> CallResult {
+    return Ok(vec![Value::Boolean(
+        arg(&args, 1).raw_equals(&arg(&args, 2)),
+    )]);
+}
+
+fn table_insert(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let table = writable_table_arg(interp, &args, 1, "insert")?;
+    let len = table.borrow().len();
+    match args.len() {
+        2 => table.borrow_mut().push(args[1].clone()),
+        3 => {
+            let pos = number_arg(interp, &args, 2, "insert")? as i64;
+            if pos < 1 || pos as usize > len + 1 {
+                return Err(bad_argument(interp, 2, "insert", "position out of bounds"));
+            }
+            table.borrow_mut().insert(pos as usize, args[2].clone());
+        }
+        _ => return Err(interp.error("wrong number of arguments to 'insert'")),
+    }
+    return Ok(vec![]);
+}
+
+fn table_remove(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let table = writable_table_arg(interp, &args, 1, "remove")?;
+    let len = table.borrow().len();
+    if len == 0 {
+        return Ok(vec![Value::Nil]);
+    }
+    let pos = optional_number(interp, &args, 2, "remove", len as f64)? as i64;
+    if pos < 1 || pos as usize > len {
+        return Ok(vec![Value::Nil]);
+    }
+    let value = table.borrow_mut().remove(pos as usize);
+    return Ok(vec![value]);
+}
+
+fn table_concat(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let table = table_arg(interp, &args, 1, "concat")?;
+    let table = table.borrow();
+    let sep = match arg(&args, 2) {
+        Value::Nil => vec![],
+        _ => string_arg(interp, &args, 2, "concat")?,
+    };
+    let start = optional_number(interp, &args, 3, "concat", 1.0)? as i64;
+    let end = optional_number(interp, &args, 4, "concat", table.len() as f64)? as i64;
+    let mut buf = vec![];
+    for i in start..=end {
+        let value = table.get(&Value::Number(i as f64));
+        match value.to_bytes() {
+            Some(bytes) => buf.extend_from_slice(&bytes),
+            None => {
+                return Err(interp.error(&format!(
+                    "invalid value (at index {}) in table for 'concat'",
+                    i
+                )))
+            }
+        }
+        if i < end {
+            buf.extend_from_slice(&sep);
+        }
+    }
+    return Ok(vec![Value::from(buf.as_slice())]);
+}
+
+fn table_getn(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let table = table_arg(interp, &args, 1, "getn")?;
+    let len = table.borrow().len();
+    return Ok(vec![Value::Number(len as f64)]);
+}
+
+/*
+ * 归并排序：比较函数可能出错，也可能不满足全序，都不能导致panic
+ */
+fn table_sort(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let table = writable_table_arg(interp, &args, 1, "sort")?;
+    let comparator = arg(&args, 2);
+    let values = table.borrow().array().to_vec();
+    let sorted = merge_sort(interp, values, &comparator)?;
+    *table.borrow_mut().array_mut() = sorted;
+    return Ok(vec![]);
+}
+
+fn merge_sort(
+    interp: &mut Interp,
+    mut values: Vec<Value>,
+    comparator: &Value,
+) -> Result<Vec<Value>, ScriptError> {
+    if values.len() <= 1 {
+        return Ok(values);
+    }
+    let right = values.split_off(values.len() / 2);
+    let left = merge_sort(interp, values, comparator)?;
+    let right = merge_sort(interp, right, comparator)?;
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // 右边严格小于左边时才取右边，保持稳定
+        let less = match comparator {
+            Value::Nil => interp.less_than(b, a)?,
+            _ => {
+                let res = interp.call(comparator, vec![b.clone(), a.clone()])?;
+                res.first().is_some_and(|v| v.truthy())
+            }
+        };
+        if less {
+            merged.push(right.next().unwrap());
+        } else {
+            merged.push(left.next().unwrap());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    return Ok(merged);
+}
+
+fn string_len(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let s = string_arg(interp, &args, 1, "len")?;
+    return Ok(vec![Value::Number(s.len() as f64)]);
+}
+
+fn string_sub(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let s = string_arg(interp, &args, 1, "sub")?;
+    let start = string_index(number_arg(interp, &args, 2, "sub")?, s.len()).max(1);
+    let end =
+        string_index(optional_number(interp, &args, 3, "sub", -1.0)?, s.len()).min(s.len() as i64);
+    if start > end {
+        return Ok(vec![Value::from("")]);
+    }
+    return Ok(vec![Value::from(&s[start as usize - 1..end as usize])]);
+}
+
+fn string_upper(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let s = string_arg(interp, &args, 1, "upper")?;
+    return Ok(vec![Value::from(s.to_ascii_uppercase().as_slice())]);
+}
+
+fn string_lower(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let s = string_arg(interp, &args, 1, "lower")?;
+    return Ok(vec![Value::from(s.to_ascii_lowercase().as_slice())]);
+}
+
+fn string_rep(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let s = string_arg(interp, &args, 1, "rep")?;
+    let n = number_arg(interp, &args, 2, "rep")?.max(0.0) as usize;
+    // 与proto-max-bulk-len一致，避免脚本一次申请过多内存
+    if s.len().saturating_mul(n) > 512 * 1024 * 1024 {
+        return Err(interp.error("resulting string too large"));
+    }
+    return Ok(vec![Value::from(s.repeat(n).as_slice())]);
+}
+
+fn string_reverse(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let mut s = string_arg(interp, &args, 1, "reverse")?;
+    s.reverse();
+    return Ok(vec![Value::from(s.as_slice())]);
+}
+
+fn string_byte(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let s = string_arg(interp, &args, 1, "byte")?;
+    let start = optional_number(interp, &args, 2, "byte", 1.0)?;
+    let start = string_index(start, s.len()).max(1);
+    let end = optional_number(interp, &args, 3, "byte", start as f64)?;
+    let end = string_index(end, s.len()).min(s.len() as i64);
+    return Ok((start..=end)
+        .map(|i| Value::Number(s[i as usize - 1] as f64))
+        .collect());
+}
+
+fn string_char(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let mut buf = vec![];
+    for i in 1..=args.len() {
+        let c = number_arg(interp, &args, i, "char")?;
+        if !(0.0..=255.0).contains(&c) {
+            return Err(bad_argument(interp, i, "char", "invalid value"));
+        }
+        buf.push(c as u8);
+    }
+    return Ok(vec![Value::from(buf.as_slice())]);
+}
+
+/*
+ * 只支持普通的子串查找，不支持Lua的模式匹配
+ */
+fn string_find(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let s = string_arg(interp, &args, 1, "find")?;
+    let pattern = string_arg(interp, &args, 2, "find")?;
+    let init = optional_number(interp, &args, 3, "find", 1.0)?;
+    let init = string_index(init, s.len()).max(1) as usize;
+    let plain = arg(&args, 4).truthy();
+    if !plain && pattern.iter().any(|c| b"^$*+?.([%-".contains(c)) {
+        return Err(interp.error("patterns are not supported, use string.find(s, p, 1, true)"));
+    }
+    if init > s.len() + 1 {
+        return Ok(vec![Value::Nil]);
+    }
+    let found = match pattern.is_empty() {
+        true => Some(0),
+        false => s[init - 1..]
+            .windows(pattern.len())
+            .position(|w| w == pattern.as_slice()),
+    };
+    return Ok(match found {
+        Some(i) => vec![
+            Value::Number((init + i) as f64),
+            Value::Number((init + i + pattern.len() - 1) as f64),
+        ],
+        None => vec![Value::Nil],
+    });
+}
+
+/*
+ * string.format，支持%d %i %u %c %x %X %o %e %E %f %g %G %q %s %%以及标志、宽度和精度
+ */
+fn string_format(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let format = string_arg(interp, &args, 1, "format")?;
+    let mut buf = vec![];
+    let mut n = 1;
+    let mut i = 0;
+    while i < format.len() {
+        let c = format[i];
+        i += 1;
+        if c != b'%' {
+            buf.push(c);
+            continue;
+        }
+        if format.get(i) == Some(&b'%') {
+            buf.push(b'%');
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < format.len() && b"-+ #0".contains(&format[i]) {
+            i += 1;
+        }
+        let flags = &format[start..i];
+        let mut width = 0;
+        while i < format.len() && format[i].is_ascii_digit() {
+            width = width * 10 + (format[i] - b'0') as usize;
+            i += 1;
+        }
+        let mut precision = None;
+        if format.get(i) == Some(&b'.') {
+            i += 1;
+            let mut p = 0;
+            while i < format.len() && format[i].is_ascii_digit() {
+                p = p * 10 + (format[i] - b'0') as usize;
+                i += 1;
+            }
+            precision = Some(p);
+        }
+        let conversion = match format.get(i) {
+            Some(c) => *c,
+            None => return Err(interp.error("invalid option '%' to 'format'")),
+        };
+        i += 1;
+        n += 1;
+        let plus = flags.contains(&b'+');
+        let body = match conversion {
+            b'd' | b'i' | b'u' => {
+                let v = number_arg(interp, &args, n, "format")? as i64;
+                let digits = v.unsigned_abs().to_string();
+                let digits = match precision {
+                    Some(p) if digits.len() < p => format!("{:0>1$}", digits, p),
+                    _ => digits,
+                };
+                signed(v < 0, plus, digits)
+            }
+            b'x' | b'X' | b'o' => {
+                let v = number_arg(interp, &args, n, "format")? as i64;
+                match conversion {
+                    b'x' => format!("{:x}", v),
+                    b'X' => format!("{:X}", v),
+                    _ => format!("{:o}", v),
+                }
+            }
+            b'c' => {
+                let v = number_arg(interp, &args, n, "format")? as u8;
+                buf.push(v);
+                continue;
+            }
+            b'f' | b'F' => {
+                let v = number_arg(interp, &args, n, "format")?;
+                let p = precision.unwrap_or(6);
+                signed(v < 0.0, plus, format!("{:.*}", p, v.abs()))
+            }
+            b'e' | b'E' => {
+                let v = number_arg(interp, &args, n, "format")?;
+                let s = format_exponent(v.abs(), precision.unwrap_or(6));
+                let s = if conversion == b'E' {
+                    s.to_uppercase()
+                } else {
+                    s
+                };
+                signed(v < 0.0, plus, s)
+            }
+            b'g' | b'G' => {
+                let v = number_arg(interp, &args, n, "format")?;
+                let s = format_general(v.abs(), precision.unwrap_or(6).max(1));
+                let s = if conversion == b'G' {
+                    s.to_uppercase()
+                } else {
+                    s
+                };
+                signed(v < 0.0, plus, s)
+            }
+            b's' => {
+                let mut s = interp.to_string(&arg(&args, n));
+                if let Some(p) = precision {
+                    s.truncate(p);
+                }
+                pad(&mut buf, s, width, flags.contains(&b'-'), false);
+                continue;
+            }
+            b'q' => {
+                let s = string_arg(interp, &args, n, "format")?;
+                buf.push(b'"');
+                for c in s {
+                    match c {
+                        b'"' | b'\\' | b'\n' => buf.extend_from_slice(&[b'\\', c]),
+                        b'\r' => buf.extend_from_slice(b"\\r"),
+                        0 => buf.extend_from_slice(b"\\000"),
+                        c => buf.push(c),
+                    }
+                }
+                buf.push(b'"');
+                continue;
+            }
+            c => return Err(interp.error(&format!("invalid option '%{}' to 'format'", c as char))),
+        };
+        let zero = flags.contains(&b'0') && !flags.contains(&b'-');
+        pad(
+            &mut buf,
+            body.into_bytes(),
+            width,
+            flags.contains(&b'-'),
+            zero,
+        );
+    }
+    return Ok(vec![Value::from(buf.as_slice())]);
+}
+
+fn signed(negative: bool, plus: bool, digits: String) -> String {
+    return match (negative, plus) {
+        (true, _) => format!("-{}", digits),
+        (false, true) => format!("+{}", digits),
+        (false, false) => digits,
+    };
+}
+
+/*
+ * 按宽度补齐：左对齐时在右边补空格，补0时0放在符号之后
+ */
+fn pad(buf: &mut Vec<u8>, body: Vec<u8>, width: usize, left: bool, zero: bool) {
+    let fill = width.saturating_sub(body.len());
+    if left {
+        buf.extend_from_slice(&body);
+        buf.resize(buf.len() + fill, b' ');
+    } else if zero {
+        let sign = body.first().is_some_and(|c| *c == b'-' || *c == b'+') as usize;
+        buf.extend_from_slice(&body[..sign]);
+        buf.resize(buf.len() + fill, b'0');
+        buf.extend_from_slice(&body[sign..]);
+    } else {
+        buf.resize(buf.len() + fill, b' ');
+        buf.extend_from_slice(&body);
+    }
+}
+
+/*
+ * %e：指数至少两位并且带符号，比如1.5e+02
+ */
+fn format_exponent(v: f64, precision: usize) -> String {
+    if !v.is_finite() {
+        return format_number(v);
+    }
+    let s = format!("{:.*e}", precision, v);
+    let (mantissa, exp) = s.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    let sign = if exp < 0 { '-' } else { '+' };
+    return format!("{}e{}{:02}", mantissa, sign, exp.abs());
+}
+
+/*
+ * %g：precision位有效数字，去掉末尾的0
+ */
+fn format_general(v: f64, precision: usize) -> String {
+    if !v.is_finite() || v == 0.0 {
+        return format_number(v);
+    }
+    let exp = v.abs().log10().floor() as i32;
+    // 四舍五入可能进位，比如9.9999999变成10
+    let exp = match format_exponent(v, precision - 1).split_once('e') {
+        Some((_, e)) => e.parse().unwrap_or(exp),
+        None => exp,
+    };
+    let trim = |s: String| {
+        if !s.contains('.') {
+            return s;
+        }
+        return s.trim_end_matches('0').trim_end_matches('.').to_string();
+    };
+    if exp < -4 || exp >= precision as i32 {
+        let s = format_exponent(v, precision - 1);
+        let (mantissa, exp) = s.split_once('e').unwrap();
+        return format!("{}e{}", trim(mantissa.to_string()), exp);
+    }
+    let decimals = (precision as i32 - 1 - exp).max(0) as usize;
+    return trim(format!("{:.*}", decimals, v));
+}
+
+fn math_floor(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    return Ok(vec![Value::Number(
+        number_arg(interp, &args, 1, "floor")?.floor(),
+    )]);
+}
+
+fn math_ceil(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    return Ok(vec![Value::Number(
+        number_arg(interp, &args, 1, "ceil")?.ceil(),
+    )]);
+}
+
+fn math_abs(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    return Ok(vec![Value::Number(
+        number_arg(interp, &args, 1, "abs")?.abs(),
+    )]);
+}
+
+fn math_sqrt(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    return Ok(vec![Value::Number(
+        number_arg(interp, &args, 1, "sqrt")?.sqrt(),
+    )]);
+}
+
+fn math_fmod(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let a = number_arg(interp, &args, 1, "fmod")?;
+    let b = number_arg(interp, &args, 2, "fmod")?;
+    return Ok(vec![Value::Number(a % b)]);
+}
+
+fn math_pow(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let a = number_arg(interp, &args, 1, "pow")?;
+    let b = number_arg(interp, &args, 2, "pow")?;
+    return Ok(vec![Value::Number(a.powf(b))]);
+}
+
+fn math_max(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let mut max = number_arg(interp, &args, 1, "max")?;
+    for i in 2..=args.len() {
+        max = max.max(number_arg(interp, &args, i, "max")?);
+    }
+    return Ok(vec![Value::Number(max)]);
+}
+
+fn math_min(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let mut min = number_arg(interp, &args, 1, "min")?;
+    for i in 2..=args.len() {
+        min = min.min(number_arg(interp, &args, i, "min")?);
+    }
+    return Ok(vec![Value::Number(min)]);
+}
+
+/*
+ * redis.call：命令出错时抛出{err=...}，脚本没有捕获时作为错误回复返回给客户端
+ */
+fn redis_call(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let value = call_command(interp, args, "call")?;
+    if let Value::Table(table) = &value {
+        if !table.borrow().get_str("err").is_nil() {
+            return Err(ScriptError::new(value));
+        }
+    }
+    return Ok(vec![value]);
+}
+
+/*
+ * redis.pcall：命令出错时返回{err=...}
+ */
+fn redis_pcall(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    return Ok(vec![call_command(interp, args, "pcall")?]);
+}
+
+fn call_command(interp: &mut Interp, args: Vec<Value>, name: &str) -> Result<Value, ScriptError> {
+    if args.is_empty() {
+        return Err(interp.error(&format!(
+            "Please specify at least one argument for redis.{}()",
+            name
+        )));
+    }
+    let args = match value_to_args(&args) {
+        Some(args) => args,
+        None => {
+            return Err(interp.error("Lua redis lib command arguments must be strings or integers"))
+        }
+    };
+    let reply = interp.host().call(args);
+    return Ok(reply_to_value(&reply));
+}
+
+fn reply_table(interp: &Interp, args: &[Value], field: &str, name: &str) -> CallResult {
+    let message = match arg(args, 1) {
+        Value::Str(s) => s,
+        _ => return Err(expected(interp, args, 1, name, "string")),
+    };
+    let mut table = Table::default();
+    table.set(Value::from(field), Value::Str(message)).unwrap();
+    return Ok(vec![Value::table(table)]);
+}
+
+fn redis_error_reply(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    return reply_table(interp, &args, "err", "error_reply");
+}
+
+fn redis_status_reply(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    return reply_table(interp, &args, "ok", "status_reply");
+}
+
+fn redis_sha1hex(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    let s = string_arg(interp, &args, 1, "sha1hex")?;
+    return Ok(vec![Value::from(sha1_hex(&s).as_str())]);
+}
+
+fn redis_log(interp: &mut Interp, args: Vec<Value>) -> CallResult {
+    number_arg(interp, &args, 1, "log")?;
+    let mut message = vec![];
+    for (i, value) in args.iter().enumerate().skip(1) {
+        if i > 1 {
+            message.push(b' ');
+        }
+        message.extend_from_slice(&interp.to_string(value));
+    }
+    println!("[script] {}", String::from_utf8_lossy(&message));
+    return Ok(vec![]);
+}
+
+#[test]
+fn test_format() {
+    assert_eq!(format_general(100.0, 6), "100");
+    assert_eq!(format_general(0.1, 6), "0.1");
+    assert_eq!(format_general(1234567.0, 6), "1.23457e+06");
+    assert_eq!(format_general(9.9999999, 6), "10");
+    assert_eq!(format_exponent(150.0, 2), "1.50e+02");
+}
@@ -0,0 +1,462 @@
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc, sync::Arc};
+
+use super::{interp::Interp, parser::FuncBody};
+
+/*
+ * 脚本中的值，语义与Lua 5.1一致：table和函数是引用
+ */
+#[derive(Clone)]
+pub enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    Str(Rc<[u8]>),
+    Table(Rc<RefCell<Table>>),
+    Function(Rc<Function>),
+}
+
+pub type Builtin = fn(&mut Interp, Vec<Value>) -> CallResult;
+
+// 函数调用的结果：返回值列表或者错误
+pub type CallResult = std::result::Result<Vec<Value>, ScriptError>;
+
+pub enum Function {
+    Lua(Arc<FuncBody>, Rc<Scope>),
+    Builtin(&'static str, Builtin),
+}
+
+/*
+ * 作用域：局部变量以及外层作用域，闭包持有定义时的作用域
+ */
+#[derive(Default)]
+pub struct Scope {
+    vars: RefCell<HashMap<String, Value>>,
+    parent: Option<Rc<Scope>>,
+    // 函数最外层的作用域保存...对应的参数
+    varargs: Option<Vec<Value>>,
+}
+
+impl Scope {
+    pub fn new(parent: Option<Rc<Scope>>) -> Rc<Self> {
+        return Rc::new(Self {
+            vars: RefCell::new(HashMap::new()),
+            parent,
+            varargs: None,
+        });
+    }
+
+    pub fn function(parent: Option<Rc<Scope>>, varargs: Vec<Value>) -> Rc<Self> {
+        return Rc::new(Self {
+            vars: RefCell::new(HashMap::new()),
+            parent,
+            varargs: Some(varargs),
+        });
+    }
+
+    /*
+     * 所在函数的可变参数
+     */
+    pub fn varargs(&self) -> Vec<Value> {
+        return match (&self.varargs, &self.parent) {
+            (Some(varargs), _) => varargs.clone(),
+            (None, Some(parent)) => parent.varargs(),
+            (None, None) => vec![],
+        };
+    }
+
+    pub fn declare(&self, name: &str, value: Value) {
+        self.vars.borrow_mut().insert(name.to_string(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        if let Some(value) = self.vars.borrow().get(name) {
+            return Some(value.clone());
+        }
+        return self.parent.as_ref()?.get(name);
+    }
+
+    /*
+     * 给已经声明的局部变量赋值，没有找到时返回false
+     */
+    pub fn set(&self, name: &str, value: Value) -> bool {
+        if let Some(var) = self.vars.borrow_mut().get_mut(name) {
+            *var = value;
+            return true;
+        }
+        return match &self.parent {
+            Some(parent) => parent.set(name, value),
+            None => false,
+        };
+    }
+}
+
+/*
+ * 脚本运行时的错误：错误值（通常是字符串，redis.call出错时是{err=...}），
+ * 被SCRIPT KILL中止时pcall也不能捕获
+ */
+#[derive(Clone)]
+pub struct ScriptError {
+    pub value: Value,
+    pub killed: bool,
+}
+
+impl ScriptError {
+    pub fn new(value: Value) -> Self {
+        return Self {
+            value,
+            killed: false,
+        };
+    }
+
+    pub fn message(message: String) -> Self {
+        return Self::new(Value::from(message.as_str()));
+    }
+}
+
+/*
+ * table的key：数字按位比较（整数的数组部分另外存放），table和函数按地址比较
+ */
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum TableKey {
+    Boolean(bool),
+    Number(u64),
+    Str(Rc<[u8]>),
+    Ref(usize),
+}
+
+/*
+ * table分为数组部分（下标从1开始连续的值）和按插入顺序排列的哈希部分，
+ * 删除的字段保留空位，遍历过程中把字段赋值为nil不影响next
+ */
+#[derive(Default)]
+pub struct Table {
+    // 内置的库（redis、string等）不允许脚本修改
+    pub readonly: bool,
+    array: Vec<Value>,
+    entries: Vec<(Value, Value)>,
+    index: HashMap<TableKey, usize>,
+}
+
+impl Table {
+    pub fn from_array(values: Vec<Value>) -> Self {
+        let mut table = Table::default();
+        for value in values {
+            table.push(value);
+        }
+        return table;
+    }
+
+    /*
+     * 数组下标：1到array.len() + 1之间的整数
+     */
+    fn array_index(&self, key: &Value) -> Option<usize> {
+        if let Value::Number(n) = key {
+            if n.fract() == 0.0 && *n >= 1.0 && *n <= self.array.len() as f64 + 1.0 {
+                return Some(*n as usize - 1);
+            }
+        }
+        return None;
+    }
+
+    fn key(key: &Value) -> Option<TableKey> {
+        return match key {
+            Value::Nil => None,
+            Value::Boolean(b) => Some(TableKey::Boolean(*b)),
+            // 0.0和-0.0是同一个key
+            Value::Number(n) if *n == 0.0 => Some(TableKey::Number(0f64.to_bits())),
+            Value::Number(n) => Some(TableKey::Number(n.to_bits())),
+            Value::Str(s) => Some(TableKey::Str(s.clone())),
+            Value::Table(t) => Some(TableKey::Ref(Rc::as_ptr(t) as *const u8 as usize)),
+            Value::Function(f) => Some(TableKey::Ref(Rc::as_ptr(f) as *const u8 as usize)),
+        };
+    }
+
+    pub fn get(&self, key: &Value) -> Value {
+        if let Some(i) = self.array_index(key) {
+            return self.array.get(i).cloned().unwrap_or(Value::Nil);
+        }
+        return match Self::key(key).and_then(|k| self.index.get(&k)) {
+            Some(i) => self.entries[*i].1.clone(),
+            None => Value::Nil,
+        };
+    }
+
+    pub fn get_str(&self, key: &str) -> Value {
+        return self.get(&Value::from(key));
+    }
+
+    /*
+     * key为nil或者NaN时返回错误信息
+     */
+    pub fn set(&mut self, key: Value, value: Value) -> std::result::Result<(), &'static str> {
+        match &key {
+            Value::Nil => return Err("table index is nil"),
+            Value::Number(n) if n.is_nan() => return Err("table index is NaN"),
+            _ => {}
+        }
+        if let Some(i) = self.array_index(&key) {
+            if i == self.array.len() {
+                if !value.is_nil() {
+                    self.push(value);
+                }
+            } else {
+                self.array[i] = value;
+                while self.array.last().is_some_and(|v| v.is_nil()) {
+                    self.array.pop();
+                }
+            }
+            return Ok(());
+        }
+        let k = Self::key(&key).unwrap();
+        match self.index.get(&k) {
+            Some(i) => self.entries[*i].1 = value,
+            None if value.is_nil() => {}
+            None => {
+                self.index.insert(k, self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+        return Ok(());
+    }
+
+    /*
+     * 追加到数组末尾，哈希部分中紧接着的整数key一起移到数组部分
+     */
+    pub fn push(&mut self, value: Value) {
+        self.array.push(value);
+        loop {
+            let next = Value::Number(self.array.len() as f64 + 1.0);
+            let k = Self::key(&next).unwrap();
+            let i = match self.index.remove(&k) {
+                Some(i) => i,
+                None => return,
+            };
+            let value = std::mem::replace(&mut self.entries[i].1, Value::Nil);
+            if value.is_nil() {
+                return;
+            }
+            self.array.push(value);
+        }
+    }
+
+    /*
+     * #t：数组部分的长度
+     */
+    pub fn len(&self) -> usize {
+        return self.array.len();
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        return self.array.is_empty() && self.entries.iter().all(|(_, v)| v.is_nil());
+    }
+
+    pub fn insert(&mut self, pos: usize, value: Value) {
+        self.array.insert(pos - 1, value);
+    }
+
+    pub fn remove(&mut self, pos: usize) -> Value {
+        return self.array.remove(pos - 1);
+    }
+
+    pub fn array(&self) -> &[Value] {
+        return &self.array;
+    }
+
+    pub fn array_mut(&mut self) -> &mut Vec<Value> {
+        return &mut self.array;
+    }
+
+    /*
+     * 遍历顺序中key之后的一对，key为nil时从头开始；key不存在时返回错误
+     */
+    pub fn next(&self, key: &Value) -> std::result::Result<Option<(Value, Value)>, ()> {
+        let start = match key {
+            Value::Nil => 0,
+            // 遍历中被赋值为nil的数组元素之后继续
+            key => match self.array_index(key) {
+                Some(i) => (i + 1).min(self.array.len()),
+                None => {
+                    let k = Self::key(key).ok_or(())?;
+                    self.array.len() + self.index.get(&k).ok_or(())? + 1
+                }
+            },
+        };
+        for i in start..self.array.len() {
+            if !self.array[i].is_nil() {
+                return Ok(Some((Value::Number(i as f64 + 1.0), self.array[i].clone())));
+            }
+        }
+        let start = start.saturating_sub(self.array.len());
+        for (key, value) in self.entries.iter().skip(start) {
+            if !value.is_nil() {
+                return Ok(Some((key.clone(), value.clone())));
+            }
+        }
+        return Ok(None);
+    }
+}
+
+impl Value {
+    pub fn is_nil(&self) -> bool {
+        return matches!(self, Value::Nil);
+    }
+
+    /*
+     * 只有nil和false为假
+     */
+    pub fn truthy(&self) -> bool {
+        return !matches!(self, Value::Nil | Value::Boolean(false));
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        return match self {
+            Value::Nil => "nil",
+            Value::Boolean(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::Str(_) => "string",
+            Value::Table(_) => "table",
+            Value::Function(_) => "function",
+        };
+    }
+
+    pub fn table(table: Table) -> Self {
+        return Value::Table(Rc::new(RefCell::new(table)));
+    }
+
+    pub fn builtin(name: &'static str, f: Builtin) -> Self {
+        return Value::Function(Rc::new(Function::Builtin(name, f)));
+    }
+
+    /*
+     * 数字或者可以转换成数字的字符串
+     */
+    pub fn to_number(&self) -> Option<f64> {
+        return match self {
+            Value::Number(n) => Some(*n),
+            Value::Str(s) => super::lexer::parse_number(std::str::from_utf8(s).ok()?),
+            _ => None,
+        };
+    }
+
+    /*
+     * 字符串或者数字（转换成字符串）
+     */
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        return match self {
+            Value::Str(s) => Some(s.to_vec()),
+            Value::Number(n) => Some(format_number(*n).into_bytes()),
+            _ => None,
+        };
+    }
+
+    pub fn raw_equals(&self, other: &Value) -> bool {
+        return match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Table(a), Value::Table(b)) => Rc::ptr_eq(a, b),
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        };
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        return Value::Str(s.as_bytes().into());
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(s: &[u8]) -> Self {
+        return Value::Str(s.into());
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", format_number(*n)),
+            Value::Str(s) => write!(f, "{}", String::from_utf8_lossy(s)),
+            Value::Table(t) => write!(f, "table: {:p}", Rc::as_ptr(t)),
+            Value::Function(func) => match func.as_ref() {
+                Function::Builtin(name, _) => write!(f, "function: builtin: {}", name),
+                Function::Lua(..) => write!(f, "function: {:p}", Rc::as_ptr(func)),
+            },
+        };
+    }
+}
+
+/*
+ * 数字转换成字符串，与Lua的"%.14g"一致
+ */
+pub fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        return "nan".to_string();
+    }
+    if n.is_infinite() {
+        return if n > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        return format!("{}", n as i64);
+    }
+    // 先按14位有效数字取指数，再决定用定点还是科学计数法
+    let sci = format!("{:.13e}", n);
+    let (mantissa, exp) = sci.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    if !(-4..14).contains(&exp) {
+        let mantissa = trim_zeros(mantissa);
+        let sign = if exp < 0 { '-' } else { '+' };
+        return format!("{}e{}{:02}", mantissa, sign, exp.abs());
+    }
+    let decimals = (13 - exp).max(0) as usize;
+    return trim_zeros(&format!("{:.*}", decimals, n)).to_string();
+}
+
+fn trim_zeros(s: &str) -> &str {
+    if !s.contains('.') {
+        return s;
+    }
+    return s.trim_end_matches('0').trim_end_matches('.');
+}
+
+#[test]
+fn test_format_number() {
+    assert_eq!(format_number(3.0), "3");
+    assert_eq!(format_number(-0.5), "-0.5");
+    assert_eq!(format_number(0.1 + 0.2), "0.3");
+    assert_eq!(format_number(1.0 / 3.0), "0.33333333333333");
+    assert_eq!(format_number(1e15), "1e+15");
+    assert_eq!(format_number(1.5e-7), "1.5e-07");
+    assert_eq!(format_number(123456.789), "123456.789");
+}
+
+#[test]
+fn test_table() {
+    let mut table = Table::default();
+    table.set(Value::Number(2.0), Value::from("b")).unwrap();
+    table.set(Value::from("x"), Value::Number(1.0)).unwrap();
+    assert_eq!(table.len(), 0);
+    // 补上1之后2也移到数组部分
+    table.set(Value::Number(1.0), Value::from("a")).unwrap();
+    assert_eq!(table.len(), 2);
+    assert!(table.get(&Value::Number(2.0)).raw_equals(&Value::from("b")));
+    assert!(table.set(Value::Nil, Value::Nil).is_err());
+
+    let mut keys = vec![];
+    let mut key = Value::Nil;
+    while let Some((k, _)) = table.next(&key).unwrap() {
+        keys.push(k.to_string());
+        key = k;
+    }
+    assert_eq!(keys, ["1", "2", "x"]);
+    table.set(Value::from("x"), Value::Nil).unwrap();
+    assert!(table.next(&Value::Number(2.0)).unwrap().is_none());
+    table.set(Value::Number(2.0), Value::Nil).unwrap();
+    assert_eq!(table.len(), 1);
+    assert!(table.next(&Value::from("y")).is_err());
+}
@@ -0,0 +1,266 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::mpsc::{self, Receiver, Sender},
+    time::Duration,
+};
+
+use super::resp::Reply;
+
+/*
+ * 阻塞命令在key上等待的操作，key有数据时执行对应的非阻塞命令
+ */
+#[derive(Debug, Clone)]
+pub enum BlockedOp {
+    // BLPOP/BRPOP
+    Pop {
+        front: bool,
+    },
+    // BZPOPMIN/BZPOPMAX
+    ZPop {
+        max: bool,
+    },
+    // BLMOVE
+    Move {
+        destination: Vec<u8>,
+        from_left: bool,
+        to_left: bool,
+    },
+}
+
+impl BlockedOp {
+    /*
+     * 在key上执行的非阻塞命令，同时也是传播到AOF和从节点的命令
+     */
+    pub fn command(&self, key: &[u8]) -> Vec<Vec<u8>> {
+        let direction = |left: bool| {
+            return if left {
+                b"LEFT".to_vec()
+            } else {
+                b"RIGHT".to_vec()
+            };
+        };
+        return match self {
+            BlockedOp::Pop { front: true } => vec![b"LPOP".to_vec(), key.to_vec()],
+            BlockedOp::Pop { front: false } => vec![b"RPOP".to_vec(), key.to_vec()],
+            BlockedOp::ZPop { max: true } => vec![b"ZPOPMAX".to_vec(), key.to_vec()],
+            BlockedOp::ZPop { max: false } => vec![b"ZPOPMIN".to_vec(), key.to_vec()],
+            BlockedOp::Move {
+                destination,
+                from_left,
+                to_left,
+            } => vec![
+                b"LMOVE".to_vec(),
+                key.to_vec(),
+                destination.clone(),
+                direction(*from_left),
+                direction(*to_left),
+            ],
+        };
+    }
+
+    /*
+     * 非阻塞命令的回复转换成阻塞命令的回复，没有取到数据时返回None：
+     * BLPOP回复[key, element]，BZPOPMIN回复[key, member, score]，BLMOVE回复element
+     */
+    pub fn reply(&self, key: &[u8], reply: Reply) -> Option<Reply> {
+        return match (self, reply) {
+            (_, Reply::Error(e)) => Some(Reply::Error(e)),
+            (BlockedOp::Pop { .. }, Reply::Bulk(value)) => {
+                Some(Reply::Array(vec![Reply::bulk(key), Reply::Bulk(value)]))
+            }
+            (BlockedOp::ZPop { .. }, Reply::Array(items)) if !items.is_empty() => {
+                let mut reply = vec![Reply::bulk(key)];
+                reply.extend(items);
+                Some(Reply::Array(reply))
+            }
+            (BlockedOp::Move { .. }, Reply::Bulk(value)) => Some(Reply::Bulk(value)),
+            _ => None,
+        };
+    }
+
+    /*
+     * 超时的回复：BLMOVE是空字符串，其它是空数组
+     */
+    pub fn timeout_reply(&self) -> Reply {
+        return match self {
+            BlockedOp::Move { .. } => Reply::Null,
+            _ => Reply::NullArray,
+        };
+    }
+}
+
+/*
+ * 命令执行时没有数据，需要阻塞等待：key按命令中的顺序，timeout为None表示一直等待
+ */
+#[derive(Debug, Clone)]
+pub struct BlockRequest {
+    pub keys: Vec<Vec<u8>>,
+    pub timeout: Option<Duration>,
+    pub op: BlockedOp,
+}
+
+/*
+ * 被阻塞的客户端：等待的db和key，以及把结果交给客户端线程的通道
+ */
+struct Waiter {
+    db: usize,
+    keys: Vec<Vec<u8>>,
+    op: BlockedOp,
+    protocol: u8,
+    sender: Sender<Reply>,
+}
+
+/*
+ * 每个key上按阻塞的先后排队的客户端，以及有了新数据、需要尝试唤醒的key
+ */
+#[derive(Default)]
+pub struct Blocking {
+    waiters: HashMap<u64, Waiter>,
+    queues: HashMap<(usize, Vec<u8>), VecDeque<u64>>,
+    ready: Vec<(usize, Vec<u8>)>,
+    ready_set: HashSet<(usize, Vec<u8>)>,
+}
+
+impl Blocking {
+    /*
+     * 客户端加入所有key的等待队列末尾，返回接收结果的通道
+     */
+    pub fn block(
+        &mut self,
+        id: u64,
+        db: usize,
+        protocol: u8,
+        request: BlockRequest,
+    ) -> Receiver<Reply> {
+        let (sender, receiver) = mpsc::channel();
+        for key in &request.keys {
+            let queue = self.queues.entry((db, key.clone())).or_default();
+            // 同一个key出现多次时只排一次队
+            if !queue.contains(&id) {
+                queue.push_back(id);
+            }
+        }
+        self.waiters.insert(
+            id,
+            Waiter {
+                db,
+                keys: request.keys,
+                op: request.op,
+                protocol,
+                sender,
+            },
+        );
+        return receiver;
+    }
+
+    /*
+     * 从所有key的队列中移除，客户端已经不在阻塞时返回false
+     */
+    pub fn unblock(&mut self, id: u64) -> bool {
+        let waiter = match self.waiters.remove(&id) {
+            Some(waiter) => waiter,
+            None => return false,
+        };
+        for key in waiter.keys {
+            let entry = (waiter.db, key);
+            if let Some(queue) = self.queues.get_mut(&entry) {
+                queue.retain(|waiting| *waiting != id);
+                if queue.is_empty() {
+                    self.queues.remove(&entry);
+                }
+            }
+        }
+        return true;
+    }
+
+    /*
+     * key被修改，有客户端在等待时加入待处理的列表
+     */
+    pub fn signal(&mut self, db: usize, key: &[u8]) {
+        let entry = (db, key.to_vec());
+        if self.queues.contains_key(&entry) && self.ready_set.insert(entry.clone()) {
+            self.ready.push(entry);
+        }
+    }
+
+    pub fn take_ready(&mut self) -> Vec<(usize, Vec<u8>)> {
+        self.ready_set.clear();
+        return std::mem::take(&mut self.ready);
+    }
+
+    /*
+     * key上最早阻塞的客户端：id、等待的操作以及使用的协议
+     */
+    pub fn first_waiter(&self, db: usize, key: &[u8]) -> Option<(u64, BlockedOp, u8)> {
+        let id = *self.queues.get(&(db, key.to_vec()))?.front()?;
+        let waiter = &self.waiters[&id];
+        return Some((id, waiter.op.clone(), waiter.protocol));
+    }
+
+    /*
+     * 把结果交给被阻塞的客户端并解除阻塞
+     */
+    pub fn serve(&mut self, id: u64, reply: Reply) {
+        if let Some(waiter) = self.waiters.get(&id) {
+            waiter.sender.send(reply).unwrap_or(());
+        }
+        self.unblock(id);
+    }
+
+    pub fn blocked_clients(&self) -> usize {
+        return self.waiters.len();
+    }
+}
+
+#[test]
+fn test_blocking() {
+    let mut blocking = Blocking::default();
+    let request = |keys: &[&str]| BlockRequest {
+        keys: keys.iter().map(|k| k.as_bytes().to_vec()).collect(),
+        timeout: None,
+        op: BlockedOp::Pop { front: true },
+    };
+    let first = blocking.block(1, 0, 2, request(&["a", "b", "a"]));
+    let _second = blocking.block(2, 0, 2, request(&["a"]));
+    assert_eq!(blocking.blocked_clients(), 2);
+
+    // 没有等待者的key不需要处理，同一个key只处理一次
+    blocking.signal(0, b"c");
+    blocking.signal(1, b"a");
+    blocking.signal(0, b"a");
+    blocking.signal(0, b"a");
+    assert_eq!(blocking.take_ready(), vec![(0, b"a".to_vec())]);
+    assert!(blocking.take_ready().is_empty());
+
+    // 先阻塞的先被唤醒，唤醒后从所有key的队列中移除
+    assert_eq!(blocking.first_waiter(0, b"a").unwrap().0, 1);
+    blocking.serve(1, Reply::Integer(1));
+    assert_eq!(first.try_recv().unwrap(), Reply::Integer(1));
+    assert!(blocking.first_waiter(0, b"b").is_none());
+    assert_eq!(blocking.first_waiter(0, b"a").unwrap().0, 2);
+    assert!(blocking.unblock(2));
+    assert!(!blocking.unblock(2));
+    assert!(blocking.first_waiter(0, b"a").is_none());
+    assert_eq!(blocking.blocked_clients(), 0);
+
+    let op = BlockedOp::ZPop { max: false };
+    assert_eq!(op.reply(b"z", Reply::Array(vec![])), None);
+    assert_eq!(
+        op.reply(
+            b"z",
+            Reply::Array(vec![Reply::bulk("m"), Reply::Double(1.0)])
+        ),
+        Some(Reply::Array(vec![
+            Reply::bulk("z"),
+            Reply::bulk("m"),
+            Reply::Double(1.0)
+        ]))
+    );
+    let op = BlockedOp::Move {
+        destination: b"d".to_vec(),
+        from_left: false,
+        to_left: true,
+    };
+    assert_eq!(op.command(b"s")[3..], [b"RIGHT".to_vec(), b"LEFT".to_vec()]);
+    assert_eq!(op.timeout_reply(), Reply::Null);
+}
@@ -0,0 +1,221 @@
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use crate::common::error::{Error, ErrorKind, Result};
+
+use super::{
+    blocking::BlockRequest,
+    pubsub::{OutputBufferLimit, Subscriber},
+    resp::{self, Reply, RESP2},
+};
+
+const READ_BUF_SIZE: usize = 16 * 1024;
+
+/*
+ * 一个客户端连接：请求缓冲区、回复缓冲区以及连接级别的状态
+ */
+pub struct Client {
+    pub id: u64,
+    pub protocol: u8,
+    pub name: Option<String>,
+    // SELECT选择的db编号
+    pub db: usize,
+    // 回复QUIT或者协议错误后关闭连接
+    pub close: bool,
+    // MULTI之后入队的命令，None表示不在事务中
+    pub multi: Option<Vec<Vec<Vec<u8>>>>,
+    // 入队时出错（未知命令、参数个数错误等），EXEC时放弃整个事务
+    pub multi_error: bool,
+    // WATCH的key：(db, key, WATCH时是否存在)
+    pub watched_keys: Vec<(usize, Vec<u8>, bool)>,
+    // WATCH的key被其他连接修改时置位
+    pub dirty_cas: Arc<AtomicBool>,
+    // 订阅的频道和模式，按订阅的顺序
+    pub channels: Vec<Vec<u8>>,
+    pub patterns: Vec<Vec<u8>>,
+    // 第一次订阅之后回复都经过订阅者的输出缓冲区
+    pub subscriber: Option<Arc<Subscriber>>,
+    // 命令已经自行发送了回复（PSYNC）或者不需要回复（REPLCONF ACK），跳过这一次的回复
+    pub skip_reply: bool,
+    // 从节点通过REPLCONF listening-port告知的端口
+    pub replica_listening_port: u16,
+    // EXEC已经在AOF和复制流中传播了MULTI，脚本中的写命令不再单独用MULTI/EXEC包围
+    pub multi_propagated: bool,
+    // 阻塞命令没有取到数据时记录等待的key，命令执行完后由Server阻塞这个连接
+    pub blocked: Option<BlockRequest>,
+    // 事务、脚本以及伪客户端中的阻塞命令不阻塞，没有数据时直接返回空回复
+    pub deny_blocking: bool,
+    // 加载AOF等内部使用的伪客户端没有连接
+    stream: Option<TcpStream>,
+    query_buf: Vec<u8>,
+    reply_buf: Vec<u8>,
+}
+
+impl Client {
+    pub fn new(id: u64, stream: TcpStream) -> Self {
+        return Self {
+            id,
+            protocol: RESP2,
+            name: None,
+            db: 0,
+            close: false,
+            multi: None,
+            multi_error: false,
+            watched_keys: Vec::new(),
+            dirty_cas: Arc::new(AtomicBool::new(false)),
+            channels: Vec::new(),
+            patterns: Vec::new(),
+            subscriber: None,
+            skip_reply: false,
+            replica_listening_port: 0,
+            multi_propagated: false,
+            blocked: None,
+            deny_blocking: false,
+            stream: Some(stream),
+            query_buf: Vec::new(),
+            reply_buf: Vec::new(),
+        };
+    }
+
+    /*
+     * 没有连接的伪客户端，回复直接丢弃
+     */
+    pub fn fake() -> Self {
+        return Self {
+            id: 0,
+            protocol: RESP2,
+            name: None,
+            db: 0,
+            close: false,
+            multi: None,
+            multi_error: false,
+            watched_keys: Vec::new(),
+            dirty_cas: Arc::new(AtomicBool::new(false)),
+            channels: Vec::new(),
+            patterns: Vec::new(),
+            subscriber: None,
+            skip_reply: false,
+            replica_listening_port: 0,
+            multi_propagated: false,
+            blocked: None,
+            deny_blocking: true,
+            stream: None,
+            query_buf: Vec::new(),
+            reply_buf: Vec::new(),
+        };
+    }
+
+    /*
+     * 读取数据直到解析出至少一条完整的请求（pipeline时一次返回多条），连接关闭时返回None
+     */
+    pub fn read_requests(&mut self) -> Result<Option<Vec<Vec<Vec<u8>>>>> {
+        let mut buf = [0; READ_BUF_SIZE];
+        loop {
+            let mut requests = vec![];
+            let mut offset = 0;
+            while let Some(args) = resp::parse_request(&self.query_buf, &mut offset)? {
+                // 空行直接忽略
+                if !args.is_empty() {
+                    requests.push(args);
+                }
+            }
+            self.query_buf.drain(..offset);
+            if !requests.is_empty() {
+                return Ok(Some(requests));
+            }
+
+            let n = match &mut self.stream {
+                Some(stream) => stream.read(&mut buf)?,
+                None => 0,
+            };
+            if n == 0 {
+                return Ok(None);
+            }
+            self.query_buf.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    pub fn add_reply(&mut self, reply: &Reply) {
+        if std::mem::take(&mut self.skip_reply) || self.stream.is_none() {
+            return;
+        }
+        match &self.subscriber {
+            Some(subscriber) => subscriber.push_reply(reply),
+            None => reply.encode(self.protocol, &mut self.reply_buf),
+        }
+    }
+
+    /*
+     * 对端的ip地址，伪客户端为空
+     */
+    pub fn peer_ip(&self) -> String {
+        return self
+            .stream
+            .as_ref()
+            .and_then(|stream| stream.peer_addr().ok())
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_default();
+    }
+
+    /*
+     * 对端是否已经关闭连接，阻塞等待期间定期检查，不读取缓冲区中的数据
+     */
+    pub fn peer_closed(&self) -> bool {
+        let stream = match &self.stream {
+            Some(stream) => stream,
+            None => return false,
+        };
+        if stream.set_nonblocking(true).is_err() {
+            return true;
+        }
+        let mut buf = [0; 1];
+        let closed = match stream.peek(&mut buf) {
+            Ok(n) => n == 0,
+            Err(e) => e.kind() != std::io::ErrorKind::WouldBlock,
+        };
+        return stream.set_nonblocking(false).is_err() || closed;
+    }
+
+    /*
+     * 订阅的频道和模式的总数
+     */
+    pub fn subscriptions(&self) -> usize {
+        return self.channels.len() + self.patterns.len();
+    }
+
+    /*
+     * 第一次订阅时启动订阅者的写线程，之前还没有发送的回复先移到订阅者的缓冲区
+     */
+    pub fn subscriber(&mut self, limit: OutputBufferLimit) -> Result<Arc<Subscriber>> {
+        if let Some(subscriber) = &self.subscriber {
+            return Ok(subscriber.clone());
+        }
+        let stream = match &self.stream {
+            Some(stream) => stream.try_clone()?,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::Invalid,
+                    "fake client can't subscribe".to_string(),
+                ))
+            }
+        };
+        let subscriber = Subscriber::start(self.id, self.protocol, limit, stream)?;
+        subscriber.push(&self.reply_buf);
+        self.reply_buf.clear();
+        self.subscriber = Some(subscriber.clone());
+        return Ok(subscriber);
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        if let Some(stream) = &mut self.stream {
+            if !self.reply_buf.is_empty() {
+                stream.write_all(&self.reply_buf)?;
+            }
+        }
+        self.reply_buf.clear();
+        return Ok(());
+    }
+}
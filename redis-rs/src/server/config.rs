@@ -0,0 +1,313 @@
+use std::time::Duration;
+
+use crate::{
+    common::{
+        error::{Error, ErrorKind, Result},
+        utils,
+    },
+    db::{aof::AppendFsync, evict::EvictionPolicy, notify},
+};
+
+use super::pubsub::OutputBufferLimit;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub bind: String,
+    pub port: u16,
+    pub dir: String,
+    // 逻辑db的个数，编号从0开始
+    pub databases: usize,
+    // 每秒执行后台任务（主动过期等）的次数
+    pub hz: u64,
+    // 0表示不限制内存
+    pub maxmemory: usize,
+    pub maxmemory_policy: EvictionPolicy,
+    pub maxmemory_samples: usize,
+    // 开启后启动时优先从AOF恢复
+    pub appendonly: bool,
+    pub appendfilename: String,
+    pub appendfsync: AppendFsync,
+    // AOF末尾的命令不完整时是否截断后继续加载
+    pub aof_load_truncated: bool,
+    // 订阅者的输出缓冲区限制，超过时断开连接
+    pub client_output_buffer_limit_pubsub: OutputBufferLimit,
+    // 从节点的输出缓冲区限制，全量同步的rdb也计算在内
+    pub client_output_buffer_limit_replica: OutputBufferLimit,
+    // 键空间通知的类别，默认不发布
+    pub notify_keyspace_events: u32,
+    // 启动时作为从节点连接的主节点
+    pub replicaof: Option<(String, u16)>,
+    pub repl_backlog_size: usize,
+    // 主从连接超过这个时间没有数据时断开重连
+    pub repl_timeout: Duration,
+    // 脚本执行超过这个时间后，其它客户端的命令回复BUSY，可以用SCRIPT KILL中止脚本
+    pub busy_reply_threshold: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        return Self {
+            bind: "127.0.0.1".to_string(),
+            port: 6379,
+            dir: "store".to_string(),
+            databases: 16,
+            hz: 10,
+            maxmemory: 0,
+            maxmemory_policy: EvictionPolicy::NoEviction,
+            maxmemory_samples: 5,
+            appendonly: false,
+            appendfilename: "appendonly.aof".to_string(),
+            appendfsync: AppendFsync::EverySec,
+            aof_load_truncated: true,
+            client_output_buffer_limit_pubsub: OutputBufferLimit {
+                hard: 32 * 1024 * 1024,
+                soft: 8 * 1024 * 1024,
+                soft_seconds: 60,
+            },
+            client_output_buffer_limit_replica: OutputBufferLimit {
+                hard: 256 * 1024 * 1024,
+                soft: 64 * 1024 * 1024,
+                soft_seconds: 60,
+            },
+            notify_keyspace_events: 0,
+            replicaof: None,
+            repl_backlog_size: 1024 * 1024,
+            repl_timeout: Duration::from_secs(60),
+            busy_reply_threshold: Duration::from_millis(5000),
+        };
+    }
+}
+
+impl Config {
+    /*
+     * 命令行参数，与redis-server一致：--port 6380 --bind 0.0.0.0 --dir /data
+     */
+    pub fn from_args<I>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let name = arg.trim_start_matches("--").to_lowercase();
+            let value = args.next().ok_or(Error::new(
+                ErrorKind::Invalid,
+                format!("missing value for option: {}", arg),
+            ))?;
+            config.set(&name, &value)?;
+        }
+        return Ok(config);
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        match name {
+            "bind" => self.bind = value.to_string(),
+            "port" => {
+                self.port = utils::parse_str(&value).ok_or(Error::new(
+                    ErrorKind::Invalid,
+                    format!("invalid port: {}", value),
+                ))?
+            }
+            "dir" => self.dir = value.to_string(),
+            "databases" => {
+                self.databases = utils::parse_str::<_, usize>(&value)
+                    .filter(|databases| *databases > 0)
+                    .ok_or(Error::new(
+                        ErrorKind::Invalid,
+                        format!("invalid databases: {}", value),
+                    ))?
+            }
+            "hz" => {
+                // 与redis一致，限制在1到500之间
+                self.hz = utils::parse_str::<_, u64>(&value)
+                    .ok_or(Error::new(
+                        ErrorKind::Invalid,
+                        format!("invalid hz: {}", value),
+                    ))?
+                    .clamp(1, 500)
+            }
+            "maxmemory" => {
+                self.maxmemory = utils::parse_memory(value).ok_or(Error::new(
+                    ErrorKind::Invalid,
+                    format!("invalid maxmemory: {}", value),
+                ))?
+            }
+            "maxmemory-policy" => {
+                self.maxmemory_policy = EvictionPolicy::parse(value).ok_or(Error::new(
+                    ErrorKind::Invalid,
+                    format!("invalid maxmemory-policy: {}", value),
+                ))?
+            }
+            "maxmemory-samples" => {
+                self.maxmemory_samples = utils::parse_str::<_, usize>(&value)
+                    .filter(|samples| *samples > 0)
+                    .ok_or(Error::new(
+                        ErrorKind::Invalid,
+                        format!("invalid maxmemory-samples: {}", value),
+                    ))?
+            }
+            "appendonly" => self.appendonly = parse_bool(name, value)?,
+            "appendfilename" => self.appendfilename = value.to_string(),
+            "appendfsync" => {
+                self.appendfsync = AppendFsync::parse(value).ok_or(Error::new(
+                    ErrorKind::Invalid,
+                    format!("invalid appendfsync: {}", value),
+                ))?
+            }
+            "aof-load-truncated" => self.aof_load_truncated = parse_bool(name, value)?,
+            // 格式：<class> <hard> <soft> <soft seconds>，支持pubsub和replica（slave）
+            "client-output-buffer-limit" => {
+                let invalid = || {
+                    return Error::new(
+                        ErrorKind::Invalid,
+                        format!("invalid client-output-buffer-limit: {}", value),
+                    );
+                };
+                let (class, limit) = value.split_once(' ').ok_or_else(invalid)?;
+                let limit = OutputBufferLimit::parse(limit).ok_or_else(invalid)?;
+                match class.to_lowercase().as_str() {
+                    "pubsub" => self.client_output_buffer_limit_pubsub = limit,
+                    "replica" | "slave" => self.client_output_buffer_limit_replica = limit,
+                    _ => return Err(invalid()),
+                }
+            }
+            "notify-keyspace-events" => {
+                self.notify_keyspace_events = notify::parse_flags(value).ok_or(Error::new(
+                    ErrorKind::Invalid,
+                    format!("invalid notify-keyspace-events: {}", value),
+                ))?
+            }
+            // 格式：<host> <port>
+            "replicaof" | "slaveof" => {
+                let master = value
+                    .split_once(' ')
+                    .and_then(|(host, port)| Some((host.to_string(), port.parse().ok()?)))
+                    .ok_or(Error::new(
+                        ErrorKind::Invalid,
+                        format!("invalid replicaof: {}", value),
+                    ))?;
+                self.replicaof = Some(master);
+            }
+            "repl-backlog-size" => {
+                self.repl_backlog_size =
+                    utils::parse_memory(value)
+                        .filter(|size| *size > 0)
+                        .ok_or(Error::new(
+                            ErrorKind::Invalid,
+                            format!("invalid repl-backlog-size: {}", value),
+                        ))?
+            }
+            "repl-timeout" => {
+                self.repl_timeout = utils::parse_str::<_, u64>(&value)
+                    .filter(|timeout| *timeout > 0)
+                    .map(Duration::from_secs)
+                    .ok_or(Error::new(
+                        ErrorKind::Invalid,
+                        format!("invalid repl-timeout: {}", value),
+                    ))?
+            }
+            // 单位为毫秒，lua-time-limit是旧的名字
+            "busy-reply-threshold" | "lua-time-limit" => {
+                self.busy_reply_threshold = utils::parse_str::<_, u64>(&value)
+                    .map(Duration::from_millis)
+                    .ok_or(Error::new(
+                        ErrorKind::Invalid,
+                        format!("invalid busy-reply-threshold: {}", value),
+                    ))?
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::Invalid,
+                    format!("unknown option: {}", name),
+                ))
+            }
+        }
+        return Ok(());
+    }
+
+    pub fn aof_path(&self) -> String {
+        return format!("{}/{}", self.dir, self.appendfilename);
+    }
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool> {
+    return match value.to_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err(Error::new(
+            ErrorKind::Invalid,
+            format!("invalid {}: {}, must be yes or no", name, value),
+        )),
+    };
+}
+
+#[test]
+fn test_config() {
+    let args = vec!["--port", "6380", "--dir", "/tmp/redis", "--hz", "1000"];
+    let config = Config::from_args(args.into_iter().map(String::from)).unwrap();
+    assert_eq!(config.port, 6380);
+    assert_eq!(config.dir, "/tmp/redis");
+    assert_eq!(config.bind, "127.0.0.1");
+    assert_eq!(config.hz, 500);
+    assert_eq!(config.databases, 16);
+    assert!(Config::from_args(vec!["--databases".to_string(), "0".to_string()]).is_err());
+    let args = vec!["--maxmemory", "100mb", "--maxmemory-policy", "allkeys-lru"];
+    let config = Config::from_args(args.into_iter().map(String::from)).unwrap();
+    assert_eq!(config.maxmemory, 100 * 1024 * 1024);
+    assert_eq!(config.maxmemory_policy, EvictionPolicy::AllKeysLru);
+    assert!(Config::from_args(vec!["--maxmemory-policy".to_string(), "x".to_string()]).is_err());
+    let args = vec![
+        "--appendonly",
+        "yes",
+        "--appendfsync",
+        "always",
+        "--dir",
+        "/data",
+    ];
+    let config = Config::from_args(args.into_iter().map(String::from)).unwrap();
+    assert!(config.appendonly);
+    assert_eq!(config.appendfsync, AppendFsync::Always);
+    assert_eq!(config.aof_path(), "/data/appendonly.aof");
+    assert!(Config::from_args(vec!["--appendonly".to_string(), "1".to_string()]).is_err());
+    assert!(Config::from_args(vec!["--port".to_string()]).is_err());
+    assert!(Config::from_args(vec!["--port".to_string(), "x".to_string()]).is_err());
+    let args = vec!["--client-output-buffer-limit", "pubsub 1mb 512kb 10"];
+    let config = Config::from_args(args.into_iter().map(String::from)).unwrap();
+    assert_eq!(config.client_output_buffer_limit_pubsub.hard, 1024 * 1024);
+    assert_eq!(config.client_output_buffer_limit_pubsub.soft, 512 * 1024);
+    assert_eq!(config.client_output_buffer_limit_pubsub.soft_seconds, 10);
+    let args = vec!["--client-output-buffer-limit", "normal 0 0 0"];
+    assert!(Config::from_args(args.into_iter().map(String::from)).is_err());
+    let args = vec!["--notify-keyspace-events", "Kx"];
+    let config = Config::from_args(args.into_iter().map(String::from)).unwrap();
+    assert_eq!(
+        config.notify_keyspace_events,
+        notify::NOTIFY_KEYSPACE | notify::NOTIFY_EXPIRED
+    );
+    let args = vec!["--notify-keyspace-events", "Kw"];
+    assert!(Config::from_args(args.into_iter().map(String::from)).is_err());
+    let args = vec![
+        "--replicaof",
+        "127.0.0.1 6380",
+        "--repl-backlog-size",
+        "4mb",
+        "--client-output-buffer-limit",
+        "replica 1mb 0 0",
+    ];
+    let config = Config::from_args(args.into_iter().map(String::from)).unwrap();
+    assert_eq!(config.replicaof, Some(("127.0.0.1".to_string(), 6380)));
+    assert_eq!(config.repl_backlog_size, 4 * 1024 * 1024);
+    assert_eq!(config.client_output_buffer_limit_replica.hard, 1024 * 1024);
+    assert_eq!(
+        config.client_output_buffer_limit_pubsub.hard,
+        32 * 1024 * 1024
+    );
+    let args = vec!["--replicaof", "127.0.0.1"];
+    assert!(Config::from_args(args.into_iter().map(String::from)).is_err());
+    assert_eq!(config.busy_reply_threshold, Duration::from_secs(5));
+    let args = vec!["--lua-time-limit", "100"];
+    let config = Config::from_args(args.into_iter().map(String::from)).unwrap();
+    assert_eq!(config.busy_reply_threshold, Duration::from_millis(100));
+    let args = vec!["--busy-reply-threshold", "-1"];
+    assert!(Config::from_args(args.into_iter().map(String::from)).is_err());
+}
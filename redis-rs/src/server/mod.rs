@@ -1 +1,7 @@
+pub mod blocking;
+pub mod client;
+pub mod config;
+pub mod pubsub;
+pub mod replication;
+pub mod resp;
 pub mod server;
@@ -0,0 +1,290 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    net::{Shutdown, TcpStream},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::common::{error::Result, utils};
+
+use super::resp::Reply;
+
+/*
+ * 输出缓冲区限制，与redis的client-output-buffer-limit一致：
+ * 超过hard立即断开，持续超过soft达到soft_seconds秒后断开，0表示不限制
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputBufferLimit {
+    pub hard: usize,
+    pub soft: usize,
+    pub soft_seconds: u64,
+}
+
+impl OutputBufferLimit {
+    /*
+     * 格式：<hard> <soft> <soft seconds>，例如 32mb 8mb 60
+     */
+    pub fn parse(value: &str) -> Option<Self> {
+        let parts: Vec<&str> = value.split_whitespace().collect();
+        if parts.len() != 3 {
+            return None;
+        }
+        return Some(Self {
+            hard: utils::parse_memory(parts[0])?,
+            soft: utils::parse_memory(parts[1])?,
+            soft_seconds: utils::parse_str(&parts[2])?,
+        });
+    }
+}
+
+struct Output {
+    buf: Vec<u8>,
+    // 连接关闭后写完剩余的数据再退出
+    closed: bool,
+    // 开始超过soft限制的时间
+    soft_limit_since: Option<Instant>,
+}
+
+/*
+ * 订阅模式下的连接：其他连接发布的消息和自己的回复都追加到同一个缓冲区，
+ * 由单独的线程写入socket，慢的订阅者不会阻塞发布者
+ */
+pub struct Subscriber {
+    id: u64,
+    protocol: AtomicU8,
+    limit: OutputBufferLimit,
+    stream: TcpStream,
+    output: Mutex<Output>,
+    cond: Condvar,
+}
+
+impl Subscriber {
+    pub fn start(
+        id: u64,
+        protocol: u8,
+        limit: OutputBufferLimit,
+        stream: TcpStream,
+    ) -> Result<Arc<Self>> {
+        let writer = stream.try_clone()?;
+        let subscriber = Arc::new(Self {
+            id,
+            protocol: AtomicU8::new(protocol),
+            limit,
+            stream,
+            output: Mutex::new(Output {
+                buf: vec![],
+                closed: false,
+                soft_limit_since: None,
+            }),
+            cond: Condvar::new(),
+        });
+        let cloned = subscriber.clone();
+        thread::spawn(move || cloned.write_loop(writer));
+        return Ok(subscriber);
+    }
+
+    fn write_loop(&self, mut writer: TcpStream) {
+        loop {
+            let buf = {
+                let mut output = self.output.lock().unwrap();
+                while output.buf.is_empty() && !output.closed {
+                    output = self.cond.wait(output).unwrap();
+                }
+                if output.buf.is_empty() {
+                    return;
+                }
+                std::mem::take(&mut output.buf)
+            };
+            if writer.write_all(&buf).is_err() {
+                self.close();
+                return;
+            }
+        }
+    }
+
+    pub fn protocol(&self) -> u8 {
+        return self.protocol.load(Ordering::SeqCst);
+    }
+
+    pub fn set_protocol(&self, protocol: u8) {
+        self.protocol.store(protocol, Ordering::SeqCst);
+    }
+
+    pub fn push_reply(&self, reply: &Reply) {
+        let mut buf = vec![];
+        reply.encode(self.protocol(), &mut buf);
+        self.push(&buf);
+    }
+
+    /*
+     * 追加到缓冲区，超过限制时丢弃缓冲区并断开连接
+     */
+    pub fn push(&self, data: &[u8]) {
+        let mut output = self.output.lock().unwrap();
+        if output.closed {
+            return;
+        }
+        output.buf.extend_from_slice(data);
+        let pending = output.buf.len();
+        let limit = self.limit;
+        let mut exceeded = limit.hard > 0 && pending > limit.hard;
+        if limit.soft > 0 && pending > limit.soft {
+            let since = *output.soft_limit_since.get_or_insert_with(Instant::now);
+            exceeded |= since.elapsed() >= Duration::from_secs(limit.soft_seconds);
+        } else {
+            output.soft_limit_since = None;
+        }
+        if exceeded {
+            println!(
+                "[server] client {} closed for overcoming of output buffer limits",
+                self.id
+            );
+            output.buf.clear();
+            output.closed = true;
+            self.stream.shutdown(Shutdown::Both).unwrap_or(());
+        }
+        self.cond.notify_one();
+    }
+
+    /*
+     * 写完缓冲区中剩余的数据后结束写线程
+     */
+    pub fn close(&self) {
+        self.output.lock().unwrap().closed = true;
+        self.cond.notify_one();
+    }
+
+    /*
+     * 丢弃缓冲区并立即断开连接
+     */
+    pub fn disconnect(&self) {
+        let mut output = self.output.lock().unwrap();
+        output.buf.clear();
+        output.closed = true;
+        self.stream.shutdown(Shutdown::Both).unwrap_or(());
+        self.cond.notify_one();
+    }
+}
+
+/*
+ * 频道和模式的订阅关系，所有连接共用
+ */
+#[derive(Default)]
+pub struct PubSub {
+    channels: HashMap<Vec<u8>, Vec<Arc<Subscriber>>>,
+    patterns: HashMap<Vec<u8>, Vec<Arc<Subscriber>>>,
+}
+
+impl PubSub {
+    pub fn subscribe(&mut self, channel: &[u8], subscriber: &Arc<Subscriber>) {
+        add(&mut self.channels, channel, subscriber);
+    }
+
+    pub fn unsubscribe(&mut self, channel: &[u8], id: u64) {
+        remove(&mut self.channels, channel, id);
+    }
+
+    pub fn psubscribe(&mut self, pattern: &[u8], subscriber: &Arc<Subscriber>) {
+        add(&mut self.patterns, pattern, subscriber);
+    }
+
+    pub fn punsubscribe(&mut self, pattern: &[u8], id: u64) {
+        remove(&mut self.patterns, pattern, id);
+    }
+
+    /*
+     * 发送给频道的订阅者以及匹配的模式的订阅者，返回收到消息的次数
+     */
+    pub fn publish(&self, channel: &[u8], message: &[u8]) -> usize {
+        let mut receivers = 0;
+        if let Some(subscribers) = self.channels.get(channel) {
+            let reply = Reply::Push(vec![
+                Reply::bulk("message"),
+                Reply::bulk(channel),
+                Reply::bulk(message),
+            ]);
+            for subscriber in subscribers {
+                subscriber.push_reply(&reply);
+            }
+            receivers += subscribers.len();
+        }
+        for (pattern, subscribers) in &self.patterns {
+            if !utils::glob_match(pattern, channel) {
+                continue;
+            }
+            let reply = Reply::Push(vec![
+                Reply::bulk("pmessage"),
+                Reply::bulk(pattern),
+                Reply::bulk(channel),
+                Reply::bulk(message),
+            ]);
+            for subscriber in subscribers {
+                subscriber.push_reply(&reply);
+            }
+            receivers += subscribers.len();
+        }
+        return receivers;
+    }
+
+    /*
+     * 至少有一个订阅者的频道
+     */
+    pub fn channels(&self, pattern: Option<&[u8]>) -> Vec<Vec<u8>> {
+        return self
+            .channels
+            .keys()
+            .filter(|channel| pattern.is_none_or(|p| utils::glob_match(p, channel)))
+            .cloned()
+            .collect();
+    }
+
+    pub fn numsub(&self, channel: &[u8]) -> usize {
+        return self.channels.get(channel).map_or(0, |s| s.len());
+    }
+
+    /*
+     * 不同的模式的个数
+     */
+    pub fn numpat(&self) -> usize {
+        return self.patterns.len();
+    }
+}
+
+fn add(
+    map: &mut HashMap<Vec<u8>, Vec<Arc<Subscriber>>>,
+    name: &[u8],
+    subscriber: &Arc<Subscriber>,
+) {
+    let subscribers = map.entry(name.to_vec()).or_default();
+    if !subscribers.iter().any(|s| s.id == subscriber.id) {
+        subscribers.push(subscriber.clone());
+    }
+}
+
+fn remove(map: &mut HashMap<Vec<u8>, Vec<Arc<Subscriber>>>, name: &[u8], id: u64) {
+    if let Some(subscribers) = map.get_mut(name) {
+        subscribers.retain(|s| s.id != id);
+        if subscribers.is_empty() {
+            map.remove(name);
+        }
+    }
+}
+
+#[test]
+fn test_output_buffer_limit() {
+    assert_eq!(
+        OutputBufferLimit::parse("32mb 8mb 60"),
+        Some(OutputBufferLimit {
+            hard: 32 * 1024 * 1024,
+            soft: 8 * 1024 * 1024,
+            soft_seconds: 60
+        })
+    );
+    assert_eq!(OutputBufferLimit::parse("32mb 8mb"), None);
+    assert_eq!(OutputBufferLimit::parse("x 0 0"), None);
+}
@@ -0,0 +1,748 @@
+use std::{
+    collections::VecDeque,
+    io::{ErrorKind as IoErrorKind, Read, Write},
+    net::{Shutdown, TcpStream},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+
+use crate::{
+    common::error::{Error, ErrorKind, Result},
+    db::aof,
+};
+
+use super::{
+    pubsub::Subscriber,
+    resp::{self, Reply},
+};
+
+// 主节点向从节点发送PING的间隔，与redis的repl-ping-replica-period一致
+pub const REPL_PING_PERIOD: Duration = Duration::from_secs(10);
+// 从节点向主节点发送REPLCONF ACK的间隔
+pub const REPL_ACK_PERIOD: Duration = Duration::from_secs(1);
+
+const REPLID_LEN: usize = 40;
+const READ_BUF_SIZE: usize = 16 * 1024;
+
+/*
+ * 复制积压缓冲区：保存最近写入复制流的数据，断线重连的从节点从中补齐缺失的部分
+ */
+pub struct Backlog {
+    buf: VecDeque<u8>,
+    size: usize,
+    // 缓冲区中第一个字节的偏移量
+    start: i64,
+}
+
+impl Backlog {
+    /*
+     * offset为已经写入复制流的最后一个字节的偏移量，之后的数据从offset + 1开始
+     */
+    pub fn new(size: usize, offset: i64) -> Self {
+        return Self {
+            buf: VecDeque::new(),
+            size,
+            start: offset + 1,
+        };
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buf.extend(data);
+        if self.buf.len() > self.size {
+            let excess = self.buf.len() - self.size;
+            self.buf.drain(..excess);
+            self.start += excess as i64;
+        }
+    }
+
+    pub fn first_byte_offset(&self) -> i64 {
+        return self.start;
+    }
+
+    pub fn histlen(&self) -> usize {
+        return self.buf.len();
+    }
+
+    /*
+     * 从offset（包含）开始到末尾的数据，offset已经不在缓冲区中时返回None
+     */
+    pub fn range_from(&self, offset: i64) -> Option<Vec<u8>> {
+        if offset < self.start || offset > self.start + self.buf.len() as i64 {
+            return None;
+        }
+        let skip = (offset - self.start) as usize;
+        return Some(self.buf.iter().skip(skip).copied().collect());
+    }
+}
+
+/*
+ * 连接到主节点的从节点
+ */
+struct ReplicaInfo {
+    id: u64,
+    ip: String,
+    // REPLCONF listening-port告知的端口
+    port: u16,
+    output: Arc<Subscriber>,
+    ack_offset: i64,
+    ack_time: Instant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    // 等待复制线程连接
+    Connect,
+    // 握手以及全量同步中
+    Sync,
+    Connected,
+}
+
+/*
+ * 从节点到主节点的连接
+ */
+struct MasterLink {
+    host: String,
+    port: u16,
+    state: LinkState,
+    // 每次REPLICAOF递增，复制线程据此判断自己的连接是否已经作废
+    generation: u64,
+    // 切换主节点时关闭旧的连接
+    stream: Option<TcpStream>,
+    last_io: Instant,
+}
+
+/*
+ * 主从复制的状态，主节点和从节点共用：
+ * 复制流是写命令的RESP编码，offset为写入复制流的总字节数，
+ * 从节点按原样转发主节点的复制流，因此整条复制链上的offset一致
+ */
+pub struct Replication {
+    replid: String,
+    // 切换主节点之前的replid，offset不超过second_replid_offset时仍然可以部分重同步
+    replid2: String,
+    second_replid_offset: i64,
+    offset: i64,
+    backlog_size: usize,
+    // 第一个从节点连接时才创建
+    backlog: Option<Backlog>,
+    // 复制流中上一条命令所在的db，切换db时先写入SELECT
+    selected_db: Option<usize>,
+    replicas: Vec<ReplicaInfo>,
+    master: Option<MasterLink>,
+    // 主节点的复制流当前所在的db，部分重同步后继续使用
+    master_db: usize,
+    next_generation: u64,
+    last_ping: Instant,
+}
+
+/*
+ * 从节点请求PSYNC的结果
+ */
+#[derive(Debug, PartialEq)]
+pub enum PsyncReply {
+    // 全量同步：主节点的replid以及rdb对应的offset
+    Full(String, i64),
+    // 部分重同步，主节点的replid变化时带上新的replid
+    Continue(Option<String>),
+}
+
+impl Replication {
+    pub fn new(backlog_size: usize) -> Self {
+        return Self {
+            replid: new_replid(),
+            replid2: "0".repeat(REPLID_LEN),
+            second_replid_offset: -1,
+            offset: 0,
+            backlog_size,
+            backlog: None,
+            selected_db: None,
+            replicas: vec![],
+            master: None,
+            master_db: 0,
+            next_generation: 1,
+            last_ping: Instant::now(),
+        };
+    }
+
+    pub fn is_replica(&self) -> bool {
+        return self.master.is_some();
+    }
+
+    pub fn replid(&self) -> &str {
+        return &self.replid;
+    }
+
+    pub fn offset(&self) -> i64 {
+        return self.offset;
+    }
+
+    /*
+     * 主节点追加一条在db中执行的写命令；从节点只转发主节点的复制流
+     */
+    pub fn feed(&mut self, db: usize, args: &[Vec<u8>]) {
+        if self.is_replica() || self.backlog.is_none() {
+            return;
+        }
+        let mut buf = vec![];
+        if self.selected_db != Some(db) {
+            aof::encode_command(&[b"SELECT".to_vec(), db.to_string().into_bytes()], &mut buf);
+            self.selected_db = Some(db);
+        }
+        aof::encode_command(args, &mut buf);
+        self.feed_raw(&buf);
+    }
+
+    fn feed_raw(&mut self, data: &[u8]) {
+        self.offset += data.len() as i64;
+        if let Some(backlog) = &mut self.backlog {
+            backlog.feed(data);
+        }
+        for replica in &self.replicas {
+            replica.output.push(data);
+        }
+    }
+
+    /*
+     * 定期向从节点发送PING，从节点据此判断连接是否超时
+     */
+    pub fn ping_replicas(&mut self) {
+        if self.is_replica()
+            || self.replicas.is_empty()
+            || self.last_ping.elapsed() < REPL_PING_PERIOD
+        {
+            return;
+        }
+        self.last_ping = Instant::now();
+        let mut buf = vec![];
+        aof::encode_command(&[b"PING".to_vec()], &mut buf);
+        self.feed_raw(&buf);
+    }
+
+    /*
+     * 从节点请求从offset开始的数据：replid一致（或者是切换前的replid且offset不超过切换点）
+     * 并且数据还在积压缓冲区中时可以部分重同步，返回需要补发的数据
+     */
+    pub fn partial_data(&self, replid: &str, offset: i64) -> Option<Vec<u8>> {
+        if replid != self.replid && !(replid == self.replid2 && offset <= self.second_replid_offset)
+        {
+            return None;
+        }
+        return self.backlog.as_ref()?.range_from(offset);
+    }
+
+    /*
+     * 开始全量同步，返回rdb对应的replid和offset；之后的复制流从SELECT开始
+     */
+    pub fn prepare_full_sync(&mut self) -> (String, i64) {
+        if self.backlog.is_none() {
+            self.backlog = Some(Backlog::new(self.backlog_size, self.offset));
+        }
+        self.selected_db = None;
+        return (self.replid.clone(), self.offset);
+    }
+
+    pub fn add_replica(&mut self, id: u64, ip: String, port: u16, output: Arc<Subscriber>) {
+        self.remove_replica(id);
+        self.replicas.push(ReplicaInfo {
+            id,
+            ip,
+            port,
+            output,
+            ack_offset: 0,
+            ack_time: Instant::now(),
+        });
+    }
+
+    pub fn remove_replica(&mut self, id: u64) {
+        self.replicas.retain(|replica| replica.id != id);
+    }
+
+    pub fn ack(&mut self, id: u64, offset: i64) {
+        if let Some(replica) = self.replicas.iter_mut().find(|r| r.id == id) {
+            replica.ack_offset = offset;
+            replica.ack_time = Instant::now();
+        }
+    }
+
+    /*
+     * 断开所有从节点，让它们重新同步
+     */
+    fn disconnect_replicas(&mut self) {
+        for replica in self.replicas.drain(..) {
+            replica.output.disconnect();
+        }
+    }
+
+    /*
+     * REPLICAOF host port，已经是该主节点的从节点时返回false
+     */
+    pub fn set_master(&mut self, host: &str, port: u16) -> bool {
+        if let Some(master) = &self.master {
+            if master.host == host && master.port == port {
+                return false;
+            }
+        }
+        self.close_link();
+        self.master = Some(MasterLink {
+            host: host.to_string(),
+            port,
+            state: LinkState::Connect,
+            generation: self.next_generation,
+            stream: None,
+            last_io: Instant::now(),
+        });
+        self.next_generation += 1;
+        self.disconnect_replicas();
+        return true;
+    }
+
+    /*
+     * REPLICAOF NO ONE：成为主节点，原来的replid作为replid2，
+     * 原来同一个主节点的其它从节点仍然可以部分重同步
+     */
+    pub fn unset_master(&mut self) -> bool {
+        if self.master.is_none() {
+            return false;
+        }
+        self.close_link();
+        self.master = None;
+        self.shift_replid(new_replid());
+        self.disconnect_replicas();
+        return true;
+    }
+
+    fn shift_replid(&mut self, replid: String) {
+        self.replid2 = std::mem::replace(&mut self.replid, replid);
+        self.second_replid_offset = self.offset + 1;
+    }
+
+    fn close_link(&mut self) {
+        if let Some(stream) = self.master.as_mut().and_then(|m| m.stream.take()) {
+            stream.shutdown(Shutdown::Both).unwrap_or(());
+        }
+    }
+
+    /*
+     * 需要连接的主节点：(host, port, generation)
+     */
+    pub fn master_to_connect(&self) -> Option<(String, u16, u64)> {
+        return match &self.master {
+            Some(master) if master.state == LinkState::Connect => {
+                Some((master.host.clone(), master.port, master.generation))
+            }
+            _ => None,
+        };
+    }
+
+    pub fn is_current(&self, generation: u64) -> bool {
+        return self
+            .master
+            .as_ref()
+            .is_some_and(|m| m.generation == generation);
+    }
+
+    /*
+     * 复制线程连接上主节点，开始握手；连接已经作废时返回false
+     */
+    pub fn link_connected(&mut self, generation: u64, stream: TcpStream) -> bool {
+        match &mut self.master {
+            Some(master) if master.generation == generation => {
+                master.state = LinkState::Sync;
+                master.stream = Some(stream);
+                master.last_io = Instant::now();
+                return true;
+            }
+            _ => return false,
+        }
+    }
+
+    pub fn link_down(&mut self, generation: u64) {
+        if let Some(master) = &mut self.master {
+            if master.generation == generation {
+                master.state = LinkState::Connect;
+                master.stream = None;
+            }
+        }
+    }
+
+    pub fn link_is_up(&self) -> bool {
+        return self
+            .master
+            .as_ref()
+            .is_some_and(|m| m.state == LinkState::Connected);
+    }
+
+    /*
+     * 全量同步完成：使用主节点的replid和offset，积压缓冲区从这里重新开始
+     */
+    pub fn full_synced(&mut self, replid: String, offset: i64) {
+        self.replid = replid;
+        self.replid2 = "0".repeat(REPLID_LEN);
+        self.second_replid_offset = -1;
+        self.offset = offset;
+        self.backlog = Some(Backlog::new(self.backlog_size, offset));
+        self.master_db = 0;
+        self.disconnect_replicas();
+        self.set_link_up();
+    }
+
+    /*
+     * 部分重同步成功，主节点的replid变化时（主节点发生过切换）同样切换replid
+     */
+    pub fn partial_synced(&mut self, replid: Option<String>) {
+        if let Some(replid) = replid {
+            if replid != self.replid {
+                self.shift_replid(replid);
+                self.disconnect_replicas();
+            }
+        }
+        if self.backlog.is_none() {
+            self.backlog = Some(Backlog::new(self.backlog_size, self.offset));
+        }
+        self.set_link_up();
+    }
+
+    fn set_link_up(&mut self) {
+        if let Some(master) = &mut self.master {
+            master.state = LinkState::Connected;
+            master.last_io = Instant::now();
+        }
+    }
+
+    pub fn master_db(&self) -> usize {
+        return self.master_db;
+    }
+
+    /*
+     * 从节点执行完主节点复制流中的一条命令：推进offset并原样转发给自己的从节点
+     */
+    pub fn master_applied(&mut self, raw: &[u8], db: usize) {
+        self.feed_raw(raw);
+        self.master_db = db;
+        if let Some(master) = &mut self.master {
+            master.last_io = Instant::now();
+        }
+    }
+
+    /*
+     * INFO replication的内容
+     */
+    pub fn info(&self) -> Vec<(String, String)> {
+        let mut fields = vec![];
+        let mut field = |name: &str, value: String| fields.push((name.to_string(), value));
+        match &self.master {
+            Some(master) => {
+                field("role", "slave".to_string());
+                field("master_host", master.host.clone());
+                field("master_port", master.port.to_string());
+                let up = master.state == LinkState::Connected;
+                field(
+                    "master_link_status",
+                    if up { "up" } else { "down" }.to_string(),
+                );
+                field(
+                    "master_last_io_seconds_ago",
+                    match up {
+                        true => master.last_io.elapsed().as_secs().to_string(),
+                        false => "-1".to_string(),
+                    },
+                );
+                field(
+                    "master_sync_in_progress",
+                    ((master.state == LinkState::Sync) as u8).to_string(),
+                );
+                field("slave_repl_offset", self.offset.to_string());
+                field("slave_read_only", "1".to_string());
+            }
+            None => field("role", "master".to_string()),
+        }
+        field("connected_slaves", self.replicas.len().to_string());
+        for (i, replica) in self.replicas.iter().enumerate() {
+            field(
+                &format!("slave{}", i),
+                format!(
+                    "ip={},port={},state=online,offset={},lag={}",
+                    replica.ip,
+                    replica.port,
+                    replica.ack_offset,
+                    replica.ack_time.elapsed().as_secs()
+                ),
+            );
+        }
+        field("master_replid", self.replid.clone());
+        field("master_replid2", self.replid2.clone());
+        field("master_repl_offset", self.offset.to_string());
+        field("second_repl_offset", self.second_replid_offset.to_string());
+        field(
+            "repl_backlog_active",
+            (self.backlog.is_some() as u8).to_string(),
+        );
+        field("repl_backlog_size", self.backlog_size.to_string());
+        let (first, histlen) = match &self.backlog {
+            Some(backlog) => (backlog.first_byte_offset(), backlog.histlen()),
+            None => (0, 0),
+        };
+        field("repl_backlog_first_byte_offset", first.to_string());
+        field("repl_backlog_histlen", histlen.to_string());
+        return fields;
+    }
+}
+
+/*
+ * 40个随机的十六进制字符
+ */
+fn new_replid() -> String {
+    let mut rng = rand::thread_rng();
+    return (0..REPLID_LEN)
+        .map(|_| std::char::from_digit(rng.gen_range(0..16), 16).unwrap())
+        .collect();
+}
+
+// 复制流中的一条命令：参数以及原始数据
+pub type StreamCommand = (Vec<Vec<u8>>, Vec<u8>);
+
+/*
+ * 从节点到主节点的连接：握手、接收rdb，然后按条读取复制流
+ */
+pub struct MasterConnection {
+    stream: TcpStream,
+    buf: Vec<u8>,
+    last_io: Instant,
+}
+
+impl MasterConnection {
+    pub fn connect(host: &str, port: u16, timeout: Duration) -> Result<Self> {
+        let stream = TcpStream::connect((host, port))?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(timeout))?;
+        return Ok(Self {
+            stream,
+            buf: vec![],
+            last_io: Instant::now(),
+        });
+    }
+
+    pub fn try_clone_stream(&self) -> Result<TcpStream> {
+        return Ok(self.stream.try_clone()?);
+    }
+
+    pub fn send(&mut self, args: &[&str]) -> Result<()> {
+        let mut buf = vec![];
+        let args: Vec<Vec<u8>> = args.iter().map(|arg| arg.as_bytes().to_vec()).collect();
+        aof::encode_command(&args, &mut buf);
+        self.stream.write_all(&buf)?;
+        return Ok(());
+    }
+
+    /*
+     * 读取一次数据追加到缓冲区，超时返回false
+     */
+    fn fill(&mut self) -> Result<bool> {
+        let mut chunk = [0; READ_BUF_SIZE];
+        let n = match self.stream.read(&mut chunk) {
+            Ok(n) => n,
+            Err(e) if matches!(e.kind(), IoErrorKind::WouldBlock | IoErrorKind::TimedOut) => {
+                return Ok(false)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if n == 0 {
+            return Err(Error::new(
+                ErrorKind::IO,
+                "connection closed by master".to_string(),
+            ));
+        }
+        self.buf.extend_from_slice(&chunk[..n]);
+        self.last_io = Instant::now();
+        return Ok(true);
+    }
+
+    fn read_reply(&mut self) -> Result<Reply> {
+        loop {
+            let mut offset = 0;
+            if let Some(reply) = resp::parse_reply(&self.buf, &mut offset)? {
+                self.buf.drain(..offset);
+                return Ok(reply);
+            }
+            if !self.fill()? {
+                return Err(Error::new(
+                    ErrorKind::IO,
+                    "timeout reading from master".to_string(),
+                ));
+            }
+        }
+    }
+
+    fn expect_reply(&mut self, args: &[&str], expected: &str) -> Result<()> {
+        self.send(args)?;
+        return match self.read_reply()? {
+            Reply::Status(status) if status == expected => Ok(()),
+            reply => Err(Error::new(
+                ErrorKind::Invalid,
+                format!("unexpected reply to {}: {:?}", args[0], reply),
+            )),
+        };
+    }
+
+    /*
+     * 握手：PING，用REPLCONF告知监听端口和能力，然后用自己的replid和offset请求部分重同步
+     */
+    pub fn handshake(
+        &mut self,
+        listening_port: u16,
+        replid: &str,
+        offset: i64,
+    ) -> Result<PsyncReply> {
+        self.expect_reply(&["PING"], "PONG")?;
+        self.expect_reply(
+            &["REPLCONF", "listening-port", &listening_port.to_string()],
+            "OK",
+        )?;
+        self.expect_reply(&["REPLCONF", "capa", "psync2"], "OK")?;
+        self.send(&["PSYNC", replid, &(offset + 1).to_string()])?;
+        let reply = self.read_reply()?;
+        let status = match &reply {
+            Reply::Status(status) => status.clone(),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::Invalid,
+                    format!("unexpected reply to PSYNC: {:?}", reply),
+                ))
+            }
+        };
+        let parts: Vec<&str> = status.split_whitespace().collect();
+        return match parts.as_slice() {
+            ["FULLRESYNC", replid, offset] => match offset.parse::<i64>() {
+                Ok(offset) => Ok(PsyncReply::Full(replid.to_string(), offset)),
+                Err(_) => Err(Error::new(
+                    ErrorKind::Parser,
+                    format!("invalid FULLRESYNC reply: {}", status),
+                )),
+            },
+            ["CONTINUE"] => Ok(PsyncReply::Continue(None)),
+            ["CONTINUE", replid] => Ok(PsyncReply::Continue(Some(replid.to_string()))),
+            _ => Err(Error::new(
+                ErrorKind::Invalid,
+                format!("unexpected reply to PSYNC: {}", status),
+            )),
+        };
+    }
+
+    /*
+     * 全量同步的rdb：$<len>\r\n之后是rdb内容，末尾没有\r\n
+     */
+    pub fn read_rdb(&mut self) -> Result<Vec<u8>> {
+        let len = loop {
+            if let Some(end) = self.buf.windows(2).position(|w| w == b"\r\n") {
+                let line = String::from_utf8_lossy(&self.buf[..end]).to_string();
+                self.buf.drain(..end + 2);
+                break line
+                    .strip_prefix('$')
+                    .and_then(|len| len.parse::<usize>().ok())
+                    .ok_or_else(|| {
+                        return Error::new(
+                            ErrorKind::Parser,
+                            format!("invalid rdb length: {}", line),
+                        );
+                    })?;
+            }
+            if !self.fill()? {
+                return Err(Error::new(
+                    ErrorKind::IO,
+                    "timeout reading rdb from master".to_string(),
+                ));
+            }
+        };
+        while self.buf.len() < len {
+            if !self.fill()? {
+                return Err(Error::new(
+                    ErrorKind::IO,
+                    "timeout reading rdb from master".to_string(),
+                ));
+            }
+        }
+        return Ok(self.buf.drain(..len).collect());
+    }
+
+    /*
+     * 读取复制流中完整的命令以及各自的原始数据，超时没有数据时返回空
+     */
+    pub fn read_commands(&mut self) -> Result<Vec<StreamCommand>> {
+        if self.buf.is_empty() && !self.fill()? {
+            return Ok(vec![]);
+        }
+        let mut commands = vec![];
+        let mut offset = 0;
+        loop {
+            let start = offset;
+            match resp::parse_request(&self.buf, &mut offset)? {
+                Some(args) if args.is_empty() => {}
+                Some(args) => commands.push((args, self.buf[start..offset].to_vec())),
+                None => break,
+            }
+        }
+        self.buf.drain(..offset);
+        if commands.is_empty() && offset == 0 {
+            // 命令不完整，继续读取
+            self.fill()?;
+        }
+        return Ok(commands);
+    }
+
+    pub fn set_read_timeout(&self, timeout: Duration) -> Result<()> {
+        self.stream.set_read_timeout(Some(timeout))?;
+        return Ok(());
+    }
+
+    pub fn idle(&self) -> Duration {
+        return self.last_io.elapsed();
+    }
+}
+
+#[test]
+fn test_backlog() {
+    let mut backlog = Backlog::new(8, 100);
+    assert_eq!(backlog.first_byte_offset(), 101);
+    assert_eq!(backlog.range_from(101), Some(vec![]));
+    backlog.feed(b"abcdef");
+    assert_eq!(backlog.range_from(103), Some(b"cdef".to_vec()));
+    assert_eq!(backlog.range_from(107), Some(vec![]));
+    assert_eq!(backlog.range_from(108), None);
+    // 超出大小后丢弃最早的数据
+    backlog.feed(b"ghij");
+    assert_eq!(backlog.first_byte_offset(), 103);
+    assert_eq!(backlog.histlen(), 8);
+    assert_eq!(backlog.range_from(102), None);
+    assert_eq!(backlog.range_from(105), Some(b"efghij".to_vec()));
+}
+
+#[test]
+fn test_replication_state() {
+    let mut replication = Replication::new(1024);
+    assert!(!replication.is_replica());
+    // 没有从节点时不记录复制流
+    replication.feed(0, &[b"SET".to_vec(), b"k".to_vec(), b"v".to_vec()]);
+    assert_eq!(replication.offset(), 0);
+    let (replid, offset) = replication.prepare_full_sync();
+    assert_eq!(offset, 0);
+    replication.feed(0, &[b"SET".to_vec(), b"k".to_vec(), b"v".to_vec()]);
+    let fed = replication.offset();
+    assert!(fed > 0);
+    assert_eq!(
+        replication.partial_data(&replid, 1).unwrap().len(),
+        fed as usize
+    );
+    assert_eq!(replication.partial_data(&replid, fed + 1), Some(vec![]));
+    assert_eq!(replication.partial_data("unknown", 1), None);
+
+    // 成为从节点再切回主节点后，原来的replid在切换点之前仍然可以部分重同步
+    assert!(replication.set_master("127.0.0.1", 6379));
+    assert!(!replication.set_master("127.0.0.1", 6379));
+    assert!(replication.is_replica());
+    assert!(replication.unset_master());
+    assert_ne!(replication.replid(), replid);
+    assert!(replication.partial_data(&replid, fed + 1).is_some());
+    assert!(replication.partial_data(&replid, fed + 2).is_none());
+}
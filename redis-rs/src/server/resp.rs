@@ -0,0 +1,375 @@
+use crate::common::error::{Error, ErrorKind, Result};
+
+pub const RESP2: u8 = 2;
+pub const RESP3: u8 = 3;
+
+const MAX_MULTIBULK_LEN: i64 = 1024 * 1024;
+const MAX_BULK_LEN: i64 = 512 * 1024 * 1024;
+const MAX_INLINE_LEN: usize = 64 * 1024;
+
+/*
+ * 回复类型，按客户端协议版本编码：
+ * RESP2没有的类型会降级（Null -> $-1，Double -> 字符串，Map -> 扁平数组，Boolean -> 整数）
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Status(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Null,
+    NullArray,
+    Double(f64),
+    Boolean(bool),
+    Array(Vec<Reply>),
+    Map(Vec<(Reply, Reply)>),
+    Set(Vec<Reply>),
+    Push(Vec<Reply>),
+}
+
+impl Reply {
+    pub fn ok() -> Reply {
+        return Reply::Status("OK".to_string());
+    }
+
+    pub fn error<T>(msg: T) -> Reply
+    where
+        T: AsRef<str>,
+    {
+        return Reply::Error(format!("ERR {}", msg.as_ref()));
+    }
+
+    pub fn bulk<T>(value: T) -> Reply
+    where
+        T: AsRef<[u8]>,
+    {
+        return Reply::Bulk(value.as_ref().to_vec());
+    }
+
+    /*
+     * 命令执行失败时的回复，WRONGTYPE等自带错误码的错误不再加ERR前缀
+     */
+    pub fn from_error(e: &Error) -> Reply {
+        return match e.kind() {
+            ErrorKind::WrongType => Reply::Error(e.details().to_string()),
+            _ => Reply::error(e.details()),
+        };
+    }
+
+    pub fn encode(&self, protocol: u8, buf: &mut Vec<u8>) {
+        match self {
+            Reply::Status(s) => Self::write_line(buf, b'+', s.as_bytes()),
+            Reply::Error(e) => Self::write_line(buf, b'-', e.as_bytes()),
+            Reply::Integer(i) => Self::write_line(buf, b':', i.to_string().as_bytes()),
+            Reply::Bulk(b) => {
+                Self::write_line(buf, b'$', b.len().to_string().as_bytes());
+                buf.extend_from_slice(b);
+                buf.extend_from_slice(b"\r\n");
+            }
+            Reply::Null if protocol >= RESP3 => buf.extend_from_slice(b"_\r\n"),
+            Reply::Null => buf.extend_from_slice(b"$-1\r\n"),
+            Reply::NullArray if protocol >= RESP3 => buf.extend_from_slice(b"_\r\n"),
+            Reply::NullArray => buf.extend_from_slice(b"*-1\r\n"),
+            Reply::Double(d) if protocol >= RESP3 => {
+                Self::write_line(buf, b',', format_double(*d).as_bytes())
+            }
+            Reply::Double(d) => Reply::bulk(format_double(*d)).encode(protocol, buf),
+            Reply::Boolean(b) if protocol >= RESP3 => {
+                buf.extend_from_slice(if *b { b"#t\r\n" } else { b"#f\r\n" })
+            }
+            Reply::Boolean(b) => Reply::Integer(*b as i64).encode(protocol, buf),
+            Reply::Array(items) => Self::write_aggregate(buf, b'*', items, protocol),
+            Reply::Map(pairs) => {
+                if protocol >= RESP3 {
+                    Self::write_line(buf, b'%', pairs.len().to_string().as_bytes());
+                } else {
+                    Self::write_line(buf, b'*', (pairs.len() * 2).to_string().as_bytes());
+                }
+                for (k, v) in pairs {
+                    k.encode(protocol, buf);
+                    v.encode(protocol, buf);
+                }
+            }
+            Reply::Set(items) if protocol >= RESP3 => {
+                Self::write_aggregate(buf, b'~', items, protocol)
+            }
+            Reply::Set(items) => Self::write_aggregate(buf, b'*', items, protocol),
+            Reply::Push(items) if protocol >= RESP3 => {
+                Self::write_aggregate(buf, b'>', items, protocol)
+            }
+            Reply::Push(items) => Self::write_aggregate(buf, b'*', items, protocol),
+        }
+    }
+
+    fn write_line(buf: &mut Vec<u8>, prefix: u8, line: &[u8]) {
+        buf.push(prefix);
+        buf.extend_from_slice(line);
+        buf.extend_from_slice(b"\r\n");
+    }
+
+    fn write_aggregate(buf: &mut Vec<u8>, prefix: u8, items: &[Reply], protocol: u8) {
+        Self::write_line(buf, prefix, items.len().to_string().as_bytes());
+        for item in items {
+            item.encode(protocol, buf);
+        }
+    }
+}
+
+/*
+ * 浮点数的文本表示，与redis保持一致（inf/-inf/nan，整数不带小数点）
+ */
+pub fn format_double(d: f64) -> String {
+    if d.is_nan() {
+        return "nan".to_string();
+    } else if d.is_infinite() {
+        return if d > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    return d.to_string();
+}
+
+/*
+ * 从缓冲区中解析一条完整的请求（multibulk或inline格式），数据不完整时返回None
+ * 成功时offset指向下一条请求的开始位置，支持pipeline
+ */
+pub fn parse_request(buf: &[u8], offset: &mut usize) -> Result<Option<Vec<Vec<u8>>>> {
+    let mut pos = *offset;
+    if pos >= buf.len() {
+        return Ok(None);
+    }
+    if buf[pos] != b'*' {
+        return parse_inline(buf, offset);
+    }
+
+    let count = match read_number(buf, &mut pos, MAX_MULTIBULK_LEN, "multibulk length")? {
+        Some(count) => count,
+        None => return Ok(None),
+    };
+    let mut args: Vec<Vec<u8>> = Vec::with_capacity(count.max(0) as usize);
+    for _ in 0..count {
+        if pos >= buf.len() {
+            return Ok(None);
+        }
+        if buf[pos] != b'$' {
+            return Err(protocol_error(format!(
+                "expected '$', got '{}'",
+                buf[pos] as char
+            )));
+        }
+        let len = match read_number(buf, &mut pos, MAX_BULK_LEN, "bulk length")? {
+            Some(len) if len >= 0 => len as usize,
+            Some(_) => return Err(protocol_error("invalid bulk length".to_string())),
+            None => return Ok(None),
+        };
+        if pos + len + 2 > buf.len() {
+            return Ok(None);
+        }
+        args.push(buf[pos..pos + len].to_vec());
+        pos += len + 2;
+    }
+    *offset = pos;
+    return Ok(Some(args));
+}
+
+/*
+ * inline命令：telnet等工具直接发送的以空白分隔的一行文本
+ */
+fn parse_inline(buf: &[u8], offset: &mut usize) -> Result<Option<Vec<Vec<u8>>>> {
+    let start = *offset;
+    let end = match buf[start..].iter().position(|b| *b == b'\n') {
+        Some(i) => start + i,
+        None if buf.len() - start > MAX_INLINE_LEN => {
+            return Err(protocol_error("too big inline request".to_string()));
+        }
+        None => return Ok(None),
+    };
+    let line = String::from_utf8_lossy(&buf[start..end]);
+    *offset = end + 1;
+    return Ok(Some(
+        line.split_whitespace()
+            .map(|s| s.as_bytes().to_vec())
+            .collect(),
+    ));
+}
+
+/*
+ * 读取 <prefix><number>\r\n，pos指向前缀
+ */
+fn read_number(buf: &[u8], pos: &mut usize, max: i64, name: &str) -> Result<Option<i64>> {
+    let start = *pos + 1;
+    let end = match buf[start..].windows(2).position(|w| w == b"\r\n") {
+        Some(i) => start + i,
+        None if buf.len() - start > MAX_INLINE_LEN => {
+            return Err(protocol_error(format!("too big {}", name)));
+        }
+        None => return Ok(None),
+    };
+    let number = std::str::from_utf8(&buf[start..end])
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .filter(|n| *n <= max)
+        .ok_or(protocol_error(format!("invalid {}", name)))?;
+    *pos = end + 2;
+    return Ok(Some(number));
+}
+
+/*
+ * 解析一条回复（客户端侧，用于主从复制等场景），数据不完整时返回None
+ */
+pub fn parse_reply(buf: &[u8], offset: &mut usize) -> Result<Option<Reply>> {
+    let mut pos = *offset;
+    let reply = parse_reply_at(buf, &mut pos)?;
+    if reply.is_some() {
+        *offset = pos;
+    }
+    return Ok(reply);
+}
+
+fn parse_reply_at(buf: &[u8], pos: &mut usize) -> Result<Option<Reply>> {
+    if *pos >= buf.len() {
+        return Ok(None);
+    }
+    let prefix = buf[*pos];
+    let start = *pos + 1;
+    let end = match buf[start..].windows(2).position(|w| w == b"\r\n") {
+        Some(i) => start + i,
+        None => return Ok(None),
+    };
+    let line = String::from_utf8_lossy(&buf[start..end]).to_string();
+    let number = || {
+        return line
+            .parse::<i64>()
+            .map_err(|_| protocol_error(format!("invalid number: {}", line)));
+    };
+    *pos = end + 2;
+    let reply = match prefix {
+        b'+' => Reply::Status(line),
+        b'-' => Reply::Error(line),
+        b':' => Reply::Integer(number()?),
+        b'_' => Reply::Null,
+        b'#' => Reply::Boolean(line == "t"),
+        b',' => Reply::Double(
+            line.parse::<f64>()
+                .map_err(|_| protocol_error(format!("invalid double: {}", line)))?,
+        ),
+        b'$' => {
+            let len = number()?;
+            if len < 0 {
+                return Ok(Some(Reply::Null));
+            }
+            let len = len as usize;
+            if *pos + len + 2 > buf.len() {
+                return Ok(None);
+            }
+            let bulk = buf[*pos..*pos + len].to_vec();
+            *pos += len + 2;
+            Reply::Bulk(bulk)
+        }
+        b'*' | b'~' | b'>' | b'%' => {
+            let len = number()?;
+            if len < 0 {
+                return Ok(Some(Reply::NullArray));
+            }
+            let count = if prefix == b'%' { len * 2 } else { len };
+            let mut items = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                match parse_reply_at(buf, pos)? {
+                    Some(item) => items.push(item),
+                    None => return Ok(None),
+                }
+            }
+            match prefix {
+                b'*' => Reply::Array(items),
+                b'~' => Reply::Set(items),
+                b'>' => Reply::Push(items),
+                _ => {
+                    let mut pairs = vec![];
+                    let mut iter = items.into_iter();
+                    while let (Some(k), Some(v)) = (iter.next(), iter.next()) {
+                        pairs.push((k, v));
+                    }
+                    Reply::Map(pairs)
+                }
+            }
+        }
+        _ => {
+            return Err(protocol_error(format!(
+                "unknown reply type '{}'",
+                prefix as char
+            )))
+        }
+    };
+    return Ok(Some(reply));
+}
+
+fn protocol_error(details: String) -> Error {
+    return Error::new(ErrorKind::Parser, details);
+}
+
+#[test]
+fn test_parse_request() {
+    // pipeline中的多条multibulk请求
+    let buf = b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n*1\r\n$4\r\nPING\r\n";
+    let mut offset = 0;
+    let first = parse_request(buf, &mut offset).unwrap().unwrap();
+    assert_eq!(first, vec![b"GET".to_vec(), b"k".to_vec()]);
+    let second = parse_request(buf, &mut offset).unwrap().unwrap();
+    assert_eq!(second, vec![b"PING".to_vec()]);
+    assert_eq!(offset, buf.len());
+    assert!(parse_request(buf, &mut offset).unwrap().is_none());
+
+    // 不完整的请求不移动offset
+    let mut offset = 0;
+    assert!(parse_request(b"*2\r\n$3\r\nGET\r\n$1\r", &mut offset)
+        .unwrap()
+        .is_none());
+    assert_eq!(offset, 0);
+
+    // inline
+    let mut offset = 0;
+    let inline = parse_request(b"set  k v\r\n", &mut offset)
+        .unwrap()
+        .unwrap();
+    assert_eq!(inline, vec![b"set".to_vec(), b"k".to_vec(), b"v".to_vec()]);
+
+    // 协议错误
+    assert!(parse_request(b"*1\r\n+x\r\n", &mut 0).is_err());
+    assert!(parse_request(b"*x\r\n", &mut 0).is_err());
+}
+
+#[test]
+fn test_encode_reply() {
+    let encode = |reply: Reply, protocol: u8| {
+        let mut buf = vec![];
+        reply.encode(protocol, &mut buf);
+        return String::from_utf8(buf).unwrap();
+    };
+    assert_eq!(encode(Reply::ok(), RESP2), "+OK\r\n");
+    assert_eq!(encode(Reply::bulk("v"), RESP2), "$1\r\nv\r\n");
+    assert_eq!(encode(Reply::Null, RESP2), "$-1\r\n");
+    assert_eq!(encode(Reply::Null, RESP3), "_\r\n");
+    assert_eq!(encode(Reply::Double(1.5), RESP2), "$3\r\n1.5\r\n");
+    assert_eq!(encode(Reply::Double(f64::INFINITY), RESP3), ",inf\r\n");
+    let map = Reply::Map(vec![(Reply::bulk("k"), Reply::Integer(1))]);
+    assert_eq!(encode(map.clone(), RESP2), "*2\r\n$1\r\nk\r\n:1\r\n");
+    assert_eq!(encode(map, RESP3), "%1\r\n$1\r\nk\r\n:1\r\n");
+    assert_eq!(
+        encode(Reply::Push(vec![Reply::bulk("m")]), RESP3),
+        ">1\r\n$1\r\nm\r\n"
+    );
+
+    // 编码后再解析得到相同的回复
+    let reply = Reply::Array(vec![
+        Reply::Integer(-1),
+        Reply::Null,
+        Reply::Map(vec![(Reply::bulk("k"), Reply::Double(0.5))]),
+        Reply::Set(vec![Reply::Boolean(true)]),
+    ]);
+    let mut buf = vec![];
+    reply.encode(RESP3, &mut buf);
+    let mut offset = 0;
+    assert!(parse_reply(&buf[..buf.len() - 1], &mut offset)
+        .unwrap()
+        .is_none());
+    assert_eq!(parse_reply(&buf, &mut offset).unwrap(), Some(reply));
+    assert_eq!(offset, buf.len());
+}
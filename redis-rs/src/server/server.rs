@@ -1,8 +1,1271 @@
-use crate::common::error::Result;
-use crate::db::db::Db;
+use std::{
+    collections::HashMap,
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicU64, Ordering},
+        mpsc::{Receiver, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::command::table::{Command, CommandTable, Context, CMD_DENYOOM, CMD_WRITE};
+use crate::common::error::{Error, ErrorKind, Result};
+use crate::db::{
+    aof::{self, Aof},
+    db::Db,
+    evict,
+    notify::{self, NOTIFY_EVICTED, NOTIFY_EXPIRED, NOTIFY_KEYEVENT, NOTIFY_KEYSPACE},
+    rdb,
+};
+
+use super::{
+    blocking::Blocking,
+    client::Client,
+    config::Config,
+    pubsub::PubSub,
+    replication::{MasterConnection, PsyncReply, Replication, StreamCommand, REPL_ACK_PERIOD},
+    resp::{Reply, RESP2},
+};
+use crate::script::script::Scripts;
+
+// 兼容的redis版本，客户端会据此判断支持的特性
+pub const REDIS_VERSION: &str = "7.0.0";
+// 主动过期每次最多占用一个周期的25%，与redis的ACTIVE_EXPIRE_CYCLE_SLOW_TIME_PERC一致
+const ACTIVE_EXPIRE_CYCLE_TIME_PERC: u64 = 25;
+// 阻塞等待期间检查对端是否断开的间隔
+const BLOCKED_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+// 脚本在连接线程中递归解释执行，需要比默认更大的栈
+const CLIENT_THREAD_STACK_SIZE: usize = 8 * 1024 * 1024;
 
 pub struct Server {
-    db: Db,
+    config: Config,
+    // 按编号排列的逻辑db，所有db共用一把锁
+    dbs: Arc<Mutex<Vec<Db>>>,
+    next_client_id: AtomicU64,
+    commands: CommandTable,
+    // 开启appendonly时写命令追加到AOF
+    aof: Option<Arc<Mutex<Aof>>>,
+    // 后台保存rdb期间为true，同一时间只有一个保存任务
+    rdb_saving: Arc<AtomicBool>,
+    // 被WATCH的(db, key)以及监视它的连接，修改key时把这些连接标记为dirty_cas
+    watched_keys: Mutex<WatchedKeys>,
+    pubsub: Mutex<PubSub>,
+    replication: Mutex<Replication>,
+    // 实际监听的端口，从节点握手时告知主节点
+    listening_port: AtomicU16,
+    // 键空间通知的类别，初始值来自配置，可以用CONFIG SET修改
+    keyspace_events: AtomicU32,
+    // 脚本缓存以及正在执行的脚本
+    scripts: Scripts,
+    // 阻塞在key上的客户端
+    blocking: Mutex<Blocking>,
+}
+
+type WatchedKeys = HashMap<(usize, Vec<u8>), Vec<(u64, Arc<AtomicBool>)>>;
+
+impl Server {
+    pub fn new(config: Config) -> Result<Self> {
+        let dbs = (0..config.databases)
+            .map(|_| Db::new(config.dir.clone(), None, None))
+            .collect();
+        let mut server = Self {
+            dbs: Arc::new(Mutex::new(dbs)),
+            next_client_id: AtomicU64::new(1),
+            commands: CommandTable::default(),
+            aof: None,
+            rdb_saving: Arc::new(AtomicBool::new(false)),
+            watched_keys: Mutex::new(HashMap::new()),
+            pubsub: Mutex::new(PubSub::default()),
+            replication: Mutex::new(Replication::new(config.repl_backlog_size)),
+            listening_port: AtomicU16::new(config.port),
+            keyspace_events: AtomicU32::new(config.notify_keyspace_events),
+            scripts: Scripts::default(),
+            blocking: Mutex::new(Blocking::default()),
+            config,
+        };
+        server.load_data()?;
+        if let Some((host, port)) = &server.config.replicaof {
+            server.replication.lock().unwrap().set_master(host, *port);
+            for db in server.dbs.lock().unwrap().iter_mut() {
+                db.set_replica(true);
+            }
+        }
+        return Ok(server);
+    }
+
+    /*
+     * 开启appendonly时从AOF恢复，否则从rdb恢复。
+     * AOF不存在时先从rdb恢复再生成AOF，避免开启appendonly后丢失已有的数据
+     */
+    fn load_data(&mut self) -> Result<()> {
+        if !self.config.appendonly {
+            if let Some(dbs) = rdb::load(&self.config.dir, self.config.databases)? {
+                *self.dbs.lock().unwrap() = dbs;
+            }
+            return Ok(());
+        }
+        let path = self.config.aof_path();
+        match aof::load(&path, self.config.aof_load_truncated)? {
+            Some(commands) => {
+                let is_command =
+                    |args: &[Vec<u8>], name: &str| args[0].eq_ignore_ascii_case(name.as_bytes());
+                // 末尾没有EXEC的事务（写入时宕机）整体丢弃
+                let mut end = commands.len();
+                if let Some(multi) = commands.iter().rposition(|args| is_command(args, "multi")) {
+                    if !commands[multi..]
+                        .iter()
+                        .any(|args| is_command(args, "exec"))
+                    {
+                        println!(
+                            "[aof] discard {} commands of the incomplete transaction",
+                            end - multi
+                        );
+                        end = multi;
+                    }
+                }
+                let mut client = Client::fake();
+                let mut dbs = self.dbs.lock().unwrap();
+                for args in &commands[..end] {
+                    // 加载时只有一个连接，事务中的命令直接执行
+                    if is_command(args, "multi") || is_command(args, "exec") {
+                        continue;
+                    }
+                    let cmd = self.commands.lookup(&args[0]).ok_or_else(|| {
+                        return Error::new(
+                            ErrorKind::Parser,
+                            format!(
+                                "unknown command '{}' in aof",
+                                String::from_utf8_lossy(&args[0])
+                            ),
+                        );
+                    })?;
+                    self.call(cmd, &mut client, &mut dbs, args);
+                }
+                println!("[aof] {} commands loaded", end);
+            }
+            None => {
+                if let Some(dbs) = rdb::load(&self.config.dir, self.config.databases)? {
+                    *self.dbs.lock().unwrap() = dbs;
+                }
+                aof::rewrite(&self.dbs.lock().unwrap(), &path)?;
+            }
+        }
+        let aof = Aof::open(&path, self.config.appendfsync)?;
+        self.aof = Some(Arc::new(Mutex::new(aof)));
+        return Ok(());
+    }
+
+    pub fn listen(&self) -> Result<TcpListener> {
+        let listener = TcpListener::bind((self.config.bind.as_str(), self.config.port))?;
+        let addr = listener.local_addr()?;
+        self.listening_port.store(addr.port(), Ordering::SeqCst);
+        println!("[server] listening on {}", addr);
+        return Ok(listener);
+    }
+
+    pub fn run(self) -> Result<()> {
+        let listener = self.listen()?;
+        let server = Arc::new(self);
+        let cron = server.clone();
+        thread::spawn(move || cron.cron());
+        let replication = server.clone();
+        thread::spawn(move || replication.replication_cron());
+        return server.serve(listener);
+    }
+
+    /*
+     * 后台任务，每秒执行hz次
+     */
+    pub fn cron(&self) {
+        let period = Duration::from_millis(1000 / self.config.hz);
+        // 时间限制平均分配给每个db
+        let time_limit =
+            period * ACTIVE_EXPIRE_CYCLE_TIME_PERC as u32 / 100 / self.config.databases as u32;
+        loop {
+            thread::sleep(period);
+            let mut dbs = self.dbs.lock().unwrap();
+            for db in dbs.iter_mut() {
+                if let Err(e) = db.active_expire_cycle(time_limit) {
+                    println!("[server] active expire failed: {}", e);
+                }
+            }
+            self.notify_keyspace_events(dbs.iter_mut());
+            self.replication.lock().unwrap().ping_replicas();
+            drop(dbs);
+            if let Some(aof) = &self.aof {
+                if let Err(e) = aof.lock().unwrap().fsync_if_needed() {
+                    println!("[aof] fsync failed: {}", e);
+                }
+            }
+        }
+    }
+
+    /*
+     * 每个连接一个线程，命令在db锁内执行，保证单条命令的原子性
+     */
+    pub fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    println!("[server] accept failed: {}", e);
+                    continue;
+                }
+            };
+            let server = self.clone();
+            let res = thread::Builder::new()
+                .stack_size(CLIENT_THREAD_STACK_SIZE)
+                .spawn(move || {
+                    server.handle(stream).unwrap_or(());
+                });
+            if let Err(e) = res {
+                println!("[server] spawn client thread failed: {}", e);
+            }
+        }
+        return Ok(());
+    }
+
+    fn handle(&self, stream: TcpStream) -> Result<()> {
+        stream.set_nodelay(true)?;
+        let id = self.next_client_id.fetch_add(1, Ordering::SeqCst);
+        let mut client = Client::new(id, stream);
+        let res = self.process(&mut client);
+        self.unwatch_all(&mut client);
+        self.unsubscribe_all(&mut client);
+        self.replication.lock().unwrap().remove_replica(id);
+        return res;
+    }
+
+    fn process(&self, client: &mut Client) -> Result<()> {
+        while !client.close {
+            let requests = match client.read_requests() {
+                Ok(Some(requests)) => requests,
+                Ok(None) => break,
+                Err(e) if e.kind() == ErrorKind::Parser => {
+                    client.add_reply(&Reply::error(format!("Protocol error: {}", e)));
+                    client.close = true;
+                    vec![]
+                }
+                Err(e) => return Err(e),
+            };
+            for args in requests {
+                self.execute(client, &args);
+                if client.close {
+                    break;
+                }
+            }
+            client.flush()?;
+        }
+        return Ok(());
+    }
+
+    /*
+     * 查找命令并检查参数个数，然后在db锁内执行并加入回复：
+     * 设置了maxmemory时先尝试淘汰，仍然超出时拒绝可能增加内存的命令。
+     * 事务中除了控制事务的命令都只入队，EXEC时在同一把锁内依次执行。
+     * 从节点拒绝客户端的写命令，也不淘汰key，数据只随主节点的复制流变化
+     */
+    fn execute(&self, client: &mut Client, args: &[Vec<u8>]) {
+        let cmd = match self.commands.lookup(&args[0]) {
+            Some(cmd) => cmd,
+            None => {
+                return reject(
+                    client,
+                    Reply::error(format!(
+                        "unknown command '{}'",
+                        String::from_utf8_lossy(&args[0])
+                    )),
+                )
+            }
+        };
+        if !cmd.check_arity(args.len()) {
+            return reject(
+                client,
+                Reply::error(format!(
+                    "wrong number of arguments for '{}' command",
+                    cmd.name
+                )),
+            );
+        }
+        // RESP2的订阅模式下只能执行订阅相关的命令
+        if client.subscriptions() > 0
+            && client.protocol == RESP2
+            && !is_subscribe_command(cmd.name)
+            && !matches!(cmd.name, "ping" | "quit")
+        {
+            return reject(
+                client,
+                Reply::error(format!(
+                    "Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                    cmd.name
+                )),
+            );
+        }
+        // 脚本执行期间一直持有db锁，超过busy-reply-threshold后只接受SCRIPT KILL
+        if self.scripts.is_busy(self.config.busy_reply_threshold) {
+            if cmd.name == "script" && args.len() == 2 && args[1].eq_ignore_ascii_case(b"kill") {
+                client.add_reply(&self.scripts.kill());
+                return;
+            }
+            return reject(
+                client,
+                Reply::Error(
+                    "BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN NOSAVE."
+                        .to_string(),
+                ),
+            );
+        }
+        if let Some(queue) = &mut client.multi {
+            if is_subscribe_command(cmd.name) {
+                return reject(
+                    client,
+                    Reply::error("Command not allowed inside a transaction"),
+                );
+            }
+            if !matches!(cmd.name, "exec" | "discard" | "multi" | "watch" | "quit") {
+                queue.push(args.to_vec());
+                client.add_reply(&Reply::Status("QUEUED".to_string()));
+                return;
+            }
+        }
+        let exec = cmd.name == "exec" && client.multi.is_some();
+
+        let mut dbs = self.dbs.lock().unwrap();
+        let is_replica = self.replication.lock().unwrap().is_replica();
+        if is_replica
+            && (cmd.has_flag(CMD_WRITE)
+                || (exec
+                    && self
+                        .queued_has_flag(client.multi.as_deref().unwrap_or_default(), CMD_WRITE)))
+        {
+            if exec {
+                self.discard_transaction(client);
+            }
+            client.add_reply(&Reply::Error(
+                "READONLY You can't write against a read only replica.".to_string(),
+            ));
+            return;
+        }
+        if self.config.maxmemory > 0 && !is_replica {
+            let evicted = evict::evict(
+                &mut dbs,
+                self.config.maxmemory,
+                self.config.maxmemory_policy,
+                self.config.maxmemory_samples,
+            )
+            .unwrap_or(false);
+            self.notify_keyspace_events(dbs.iter_mut());
+            // EXEC按照队列中的命令判断
+            let deny_oom = cmd.has_flag(CMD_DENYOOM)
+                || (exec
+                    && self
+                        .queued_has_flag(client.multi.as_deref().unwrap_or_default(), CMD_DENYOOM));
+            if !evicted && deny_oom {
+                if exec {
+                    self.discard_transaction(client);
+                }
+                client.add_reply(&Reply::Error(
+                    "OOM command not allowed when used memory > 'maxmemory'.".to_string(),
+                ));
+                return;
+            }
+        }
+        let reply = match exec {
+            true => self.exec(client, &mut dbs),
+            false => self.call(cmd, client, &mut dbs, args),
+        };
+        self.serve_blocked_clients(&mut dbs);
+        // 在db锁内加入等待队列，不会错过之后其它连接推入的数据
+        if let Some(request) = client.blocked.take() {
+            let timeout_reply = request.op.timeout_reply();
+            let timeout = request.timeout;
+            let receiver =
+                self.blocking
+                    .lock()
+                    .unwrap()
+                    .block(client.id, client.db, client.protocol, request);
+            drop(dbs);
+            let reply = self.wait_blocked(client, receiver, timeout, timeout_reply);
+            client.add_reply(&reply);
+            return;
+        }
+        // PUBLISH也在db锁内执行，在锁内加入回复保证订阅的确认先于之后发布的消息
+        client.add_reply(&reply);
+    }
+
+    /*
+     * 等待其它连接唤醒，超时或者对端断开时退出队列。
+     * 唤醒总是在db锁内进行，先取得db锁再退出队列，避免已经取出的数据丢失
+     */
+    fn wait_blocked(
+        &self,
+        client: &mut Client,
+        receiver: Receiver<Reply>,
+        timeout: Option<Duration>,
+        timeout_reply: Reply,
+    ) -> Reply {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        // 阻塞之前pipeline中已经执行的命令的回复先发送出去
+        let mut closed = client.flush().is_err();
+        while !closed {
+            let wait = match deadline {
+                Some(deadline) => deadline
+                    .saturating_duration_since(Instant::now())
+                    .min(BLOCKED_CHECK_INTERVAL),
+                None => BLOCKED_CHECK_INTERVAL,
+            };
+            match receiver.recv_timeout(wait) {
+                Ok(reply) => return reply,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+            closed = client.peer_closed();
+        }
+        let _dbs = self.dbs.lock().unwrap();
+        self.blocking.lock().unwrap().unblock(client.id);
+        return receiver.try_recv().unwrap_or(timeout_reply);
+    }
+
+    /*
+     * 依次处理有了新数据的key：按阻塞的先后执行等待者对应的非阻塞命令，
+     * 直到key上没有数据或者没有等待者。BLMOVE推入的目标key可能又唤醒其它客户端
+     */
+    fn serve_blocked_clients(&self, dbs: &mut [Db]) {
+        loop {
+            let ready = self.blocking.lock().unwrap().take_ready();
+            if ready.is_empty() {
+                return;
+            }
+            for (db, key) in ready {
+                loop {
+                    let waiter = self.blocking.lock().unwrap().first_waiter(db, &key);
+                    let (id, op, protocol) = match waiter {
+                        Some(waiter) => waiter,
+                        None => break,
+                    };
+                    let args = op.command(&key);
+                    let cmd = self.commands.lookup(&args[0]).unwrap();
+                    let mut client = Client::fake();
+                    client.db = db;
+                    client.protocol = protocol;
+                    let reply = self.call(cmd, &mut client, dbs, &args);
+                    match op.reply(&key, reply) {
+                        Some(reply) => self.blocking.lock().unwrap().serve(id, reply),
+                        None => break,
+                    }
+                }
+            }
+        }
+    }
+
+    fn queued_has_flag(&self, queue: &[Vec<Vec<u8>>], flag: u32) -> bool {
+        return queue.iter().any(|args| {
+            return self
+                .commands
+                .lookup(&args[0])
+                .is_some_and(|cmd| cmd.has_flag(flag));
+        });
+    }
+
+    /*
+     * EXEC：入队出错时放弃事务，WATCH的key被修改或者已经过期时返回空回复，
+     * 否则依次执行，每条命令的错误单独返回。写命令在AOF和复制流中用MULTI/EXEC包围
+     */
+    fn exec(&self, client: &mut Client, dbs: &mut [Db]) -> Reply {
+        let queue = client.multi.take().unwrap_or_default();
+        let multi_error = std::mem::take(&mut client.multi_error);
+        let dirty = client.dirty_cas.load(Ordering::SeqCst)
+            || client.watched_keys.iter().any(|(db, key, existed)| {
+                return *existed && !dbs[*db].exist(key).unwrap_or(false);
+            });
+        self.unwatch_all(client);
+        if multi_error {
+            return Reply::Error(
+                "EXECABORT Transaction discarded because of previous errors.".to_string(),
+            );
+        }
+        if dirty {
+            return Reply::NullArray;
+        }
+
+        let propagate = self.queued_has_flag(&queue, CMD_WRITE);
+        if propagate {
+            self.propagate(client.db, &[b"MULTI".to_vec()]);
+        }
+        client.multi_propagated = propagate;
+        // 事务中的阻塞命令没有数据时直接返回
+        let deny_blocking = std::mem::replace(&mut client.deny_blocking, true);
+        let mut replies = vec![];
+        for args in &queue {
+            // 入队时已经检查过命令存在
+            let cmd = self.commands.lookup(&args[0]).unwrap();
+            replies.push(self.call(cmd, client, dbs, args));
+        }
+        client.multi_propagated = false;
+        client.deny_blocking = deny_blocking;
+        if propagate {
+            self.propagate(client.db, &[b"EXEC".to_vec()]);
+        }
+        return Reply::Array(replies);
+    }
+
+    /*
+     * 退出事务并取消所有WATCH
+     */
+    pub fn discard_transaction(&self, client: &mut Client) {
+        client.multi = None;
+        client.multi_error = false;
+        self.unwatch_all(client);
+    }
+
+    pub fn watch_key(&self, client: &mut Client, db: usize, key: &[u8], exists: bool) {
+        if client
+            .watched_keys
+            .iter()
+            .any(|(watched_db, watched_key, _)| *watched_db == db && watched_key == key)
+        {
+            return;
+        }
+        client.watched_keys.push((db, key.to_vec(), exists));
+        self.watched_keys
+            .lock()
+            .unwrap()
+            .entry((db, key.to_vec()))
+            .or_default()
+            .push((client.id, client.dirty_cas.clone()));
+    }
+
+    pub fn unwatch_all(&self, client: &mut Client) {
+        let mut watched_keys = self.watched_keys.lock().unwrap();
+        let id = client.id;
+        for (db, key, _) in client.watched_keys.drain(..) {
+            let entry = (db, key);
+            if let Some(clients) = watched_keys.get_mut(&entry) {
+                clients.retain(|(client_id, _)| *client_id != id);
+                if clients.is_empty() {
+                    watched_keys.remove(&entry);
+                }
+            }
+        }
+        client.dirty_cas.store(false, Ordering::SeqCst);
+    }
+
+    /*
+     * key被修改，监视它的连接的事务会失败
+     */
+    pub fn touch_watched_key(&self, db: usize, key: &[u8]) {
+        if let Some(clients) = self.watched_keys.lock().unwrap().get(&(db, key.to_vec())) {
+            for (_, dirty_cas) in clients {
+                dirty_cas.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /*
+     * FLUSHDB/SWAPDB等整体替换db时调用：index中被监视的key在原来的db或者替换后的db中存在时都算修改
+     */
+    pub fn touch_watched_db(
+        &self,
+        index: usize,
+        emptied: &mut Db,
+        mut replaced_with: Option<&mut Db>,
+    ) {
+        let watched_keys = self.watched_keys.lock().unwrap();
+        for ((db, key), clients) in watched_keys.iter() {
+            if *db != index {
+                continue;
+            }
+            let exists = emptied.exist(key).unwrap_or(false)
+                || match &mut replaced_with {
+                    Some(db) => db.exist(key).unwrap_or(false),
+                    None => false,
+                };
+            if exists {
+                for (_, dirty_cas) in clients {
+                    dirty_cas.store(true, Ordering::SeqCst);
+                }
+            }
+        }
+    }
+
+    /*
+     * 执行命令，写命令执行成功后重新计算涉及的key占用的内存，通知WATCH并传播到AOF和从节点。
+     * 命令中惰性过期的key先于命令本身传播DEL
+     */
+    fn call(&self, cmd: &Command, client: &mut Client, dbs: &mut [Db], args: &[Vec<u8>]) -> Reply {
+        let mut dbs: Vec<&mut Db> = dbs.iter_mut().collect();
+        return self.call_with(cmd, client, &mut dbs, args);
+    }
+
+    /*
+     * 与call相同，db以引用的形式传入，脚本中的命令借用EVAL持有的db执行
+     */
+    pub fn call_with(
+        &self,
+        cmd: &Command,
+        client: &mut Client,
+        dbs: &mut [&mut Db],
+        args: &[Vec<u8>],
+    ) -> Reply {
+        let mut ctx = Context::new(self, client, dbs, args);
+        let reply = (cmd.proc)(&mut ctx).unwrap_or_else(|e| Reply::from_error(&e));
+        let propagate = ctx.propagate.take();
+        // 只读命令也可能惰性删除过期的key
+        self.notify_keyspace_events(dbs.iter_mut().map(|db| &mut **db));
+        // 将要阻塞的命令没有修改数据
+        if !cmd.has_flag(CMD_WRITE) || matches!(reply, Reply::Error(_)) || client.blocked.is_some()
+        {
+            return reply;
+        }
+        for index in cmd.key_indexes(args.len()) {
+            dbs[client.db].update_memory(&args[index]).unwrap_or(());
+            self.touch_watched_key(client.db, &args[index]);
+        }
+        self.propagate(client.db, propagate.as_deref().unwrap_or(args));
+        return reply;
+    }
+
+    /*
+     * 取出各个db中记录的事件，按notify-keyspace-events发布到对应的频道。
+     * 过期和淘汰的key在AOF和复制流中传播为DEL，从节点自己不会删除它们
+     */
+    fn notify_keyspace_events<'d>(&self, dbs: impl Iterator<Item = &'d mut Db>) {
+        let flags = self.keyspace_events();
+        for (index, db) in dbs.enumerate() {
+            let events = db.take_events();
+            if !events.is_empty() {
+                // 被修改的key上可能有阻塞的客户端在等待
+                let mut blocking = self.blocking.lock().unwrap();
+                for event in &events {
+                    blocking.signal(index, &event.key);
+                }
+            }
+            for event in &events {
+                if event.class & (NOTIFY_EXPIRED | NOTIFY_EVICTED) != 0 {
+                    self.propagate(index, &[b"DEL".to_vec(), event.key.clone()]);
+                }
+            }
+            if events.is_empty() || flags & (NOTIFY_KEYSPACE | NOTIFY_KEYEVENT) == 0 {
+                continue;
+            }
+            let pubsub = self.pubsub.lock().unwrap();
+            for event in &events {
+                for (channel, message) in notify::channels(flags, index, event) {
+                    pubsub.publish(&channel, &message);
+                }
+            }
+        }
+    }
+
+    pub fn propagate(&self, db: usize, args: &[Vec<u8>]) {
+        self.feed_aof(db, args);
+        self.replication.lock().unwrap().feed(db, args);
+    }
+
+    fn feed_aof(&self, db: usize, args: &[Vec<u8>]) {
+        if let Some(aof) = &self.aof {
+            if let Err(e) = aof.lock().unwrap().feed(db, args) {
+                println!("[aof] write failed: {}", e);
+            }
+        }
+    }
+
+    pub fn aof(&self) -> Option<&Arc<Mutex<Aof>>> {
+        return self.aof.as_ref();
+    }
+
+    pub fn rdb_saving(&self) -> &Arc<AtomicBool> {
+        return &self.rdb_saving;
+    }
+
+    pub fn keyspace_events(&self) -> u32 {
+        return self.keyspace_events.load(Ordering::SeqCst);
+    }
+
+    pub fn set_keyspace_events(&self, flags: u32) {
+        self.keyspace_events.store(flags, Ordering::SeqCst);
+    }
+
+    pub fn pubsub(&self) -> &Mutex<PubSub> {
+        return &self.pubsub;
+    }
+
+    pub fn replication(&self) -> &Mutex<Replication> {
+        return &self.replication;
+    }
+
+    pub fn scripts(&self) -> &Scripts {
+        return &self.scripts;
+    }
+
+    pub fn blocking(&self) -> &Mutex<Blocking> {
+        return &self.blocking;
+    }
+
+    /*
+     * 复制线程：设置了主节点时连接并同步，连接断开后重连
+     */
+    pub fn replication_cron(&self) {
+        loop {
+            thread::sleep(Duration::from_millis(100));
+            let master = self.replication.lock().unwrap().master_to_connect();
+            let (host, port, generation) = match master {
+                Some(master) => master,
+                None => continue,
+            };
+            let res = self.sync_with_master(&host, port, generation);
+            self.replication.lock().unwrap().link_down(generation);
+            if let Err(e) = res {
+                println!(
+                    "[replication] link with master {}:{} lost: {}",
+                    host, port, e
+                );
+                thread::sleep(Duration::from_secs(1));
+            }
+        }
+    }
+
+    /*
+     * 握手后全量同步或者部分重同步，然后持续执行主节点的复制流，
+     * REPLICAOF切换主节点（generation变化）时返回
+     */
+    fn sync_with_master(&self, host: &str, port: u16, generation: u64) -> Result<()> {
+        let mut conn = MasterConnection::connect(host, port, self.config.repl_timeout)?;
+        let (replid, offset) = {
+            let mut replication = self.replication.lock().unwrap();
+            if !replication.link_connected(generation, conn.try_clone_stream()?) {
+                return Ok(());
+            }
+            (replication.replid().to_string(), replication.offset())
+        };
+        println!("[replication] connected to master {}:{}", host, port);
+        let listening_port = self.listening_port.load(Ordering::SeqCst);
+        match conn.handshake(listening_port, &replid, offset)? {
+            PsyncReply::Full(replid, offset) => {
+                let rdb = conn.read_rdb()?;
+                let new_dbs = rdb::restore(&rdb, &self.config.dir, self.config.databases)?;
+                let mut dbs = self.dbs.lock().unwrap();
+                let mut replication = self.replication.lock().unwrap();
+                if !replication.is_current(generation) {
+                    return Ok(());
+                }
+                for (index, (db, mut new_db)) in dbs.iter_mut().zip(new_dbs).enumerate() {
+                    new_db.set_replica(true);
+                    new_db.take_events();
+                    self.touch_watched_db(index, db, Some(&mut new_db));
+                    *db = new_db;
+                }
+                replication.full_synced(replid, offset);
+                drop(replication);
+                // 数据整体替换，AOF需要重新生成
+                if let Some(aof) = &self.aof {
+                    if !aof::rewrite_background(aof.clone(), dbs.to_vec()) {
+                        println!("[aof] rewrite after full resync skipped, already in progress");
+                    }
+                }
+                println!(
+                    "[replication] full resync with master done, {} bytes, offset {}",
+                    rdb.len(),
+                    offset
+                );
+            }
+            PsyncReply::Continue(new_replid) => {
+                let mut replication = self.replication.lock().unwrap();
+                if !replication.is_current(generation) {
+                    return Ok(());
+                }
+                replication.partial_synced(new_replid);
+                println!(
+                    "[replication] partial resync with master, offset {}",
+                    replication.offset()
+                );
+            }
+        }
+
+        conn.set_read_timeout(REPL_ACK_PERIOD)?;
+        let mut client = Client::fake();
+        client.db = self.replication.lock().unwrap().master_db();
+        // 主节点的事务收到EXEC之后在同一把锁内整体执行
+        let mut transaction: Option<Vec<StreamCommand>> = None;
+        let mut last_ack: Option<Instant> = None;
+        loop {
+            if last_ack.is_none_or(|time| time.elapsed() >= REPL_ACK_PERIOD) {
+                let offset = self.replication.lock().unwrap().offset();
+                conn.send(&["REPLCONF", "ACK", &offset.to_string()])?;
+                last_ack = Some(Instant::now());
+            }
+            if conn.idle() > self.config.repl_timeout {
+                return Err(Error::new(
+                    ErrorKind::IO,
+                    "timeout on master link".to_string(),
+                ));
+            }
+            let mut batch = vec![];
+            for (args, raw) in conn.read_commands()? {
+                let name = String::from_utf8_lossy(&args[0]).to_lowercase();
+                match (&mut transaction, name.as_str()) {
+                    (None, "multi") => transaction = Some(vec![(args, raw)]),
+                    (Some(queue), "exec") => {
+                        queue.push((args, raw));
+                        batch.append(queue);
+                        transaction = None;
+                    }
+                    (Some(queue), _) => queue.push((args, raw)),
+                    (None, _) => batch.push((args, raw)),
+                }
+            }
+            if batch.is_empty() {
+                continue;
+            }
+            let mut dbs = self.dbs.lock().unwrap();
+            // REPLICAOF也在db锁内执行，之后这一批命令不会再被切换打断
+            if !self.replication.lock().unwrap().is_current(generation) {
+                return Ok(());
+            }
+            for db in dbs.iter_mut() {
+                db.set_master_stream(true);
+            }
+            for (args, raw) in batch {
+                let name = String::from_utf8_lossy(&args[0]).to_lowercase();
+                match self.commands.lookup(&args[0]) {
+                    // 事务中的命令已经在同一把锁内，MULTI/EXEC只写入AOF
+                    Some(_) if name == "multi" || name == "exec" => self.feed_aof(client.db, &args),
+                    Some(cmd) => {
+                        self.call(cmd, &mut client, &mut dbs, &args);
+                    }
+                    None => println!("[replication] unknown command '{}' from master", name),
+                }
+                if name == "replconf"
+                    && args
+                        .get(1)
+                        .is_some_and(|arg| arg.eq_ignore_ascii_case(b"getack"))
+                {
+                    last_ack = None;
+                }
+                self.replication
+                    .lock()
+                    .unwrap()
+                    .master_applied(&raw, client.db);
+            }
+            for db in dbs.iter_mut() {
+                db.set_master_stream(false);
+            }
+        }
+    }
+
+    /*
+     * 连接关闭时取消所有订阅，写线程发送完剩余的回复后退出
+     */
+    fn unsubscribe_all(&self, client: &mut Client) {
+        let mut pubsub = self.pubsub.lock().unwrap();
+        for channel in client.channels.drain(..) {
+            pubsub.unsubscribe(&channel, client.id);
+        }
+        for pattern in client.patterns.drain(..) {
+            pubsub.punsubscribe(&pattern, client.id);
+        }
+        if let Some(subscriber) = client.subscriber.take() {
+            subscriber.close();
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        return &self.config;
+    }
+
+    pub fn commands(&self) -> &CommandTable {
+        return &self.commands;
+    }
 }
 
-impl Server {}
+/*
+ * 命令在入队之前就出错时，事务在EXEC时会被放弃
+ */
+fn reject(client: &mut Client, reply: Reply) {
+    if client.multi.is_some() {
+        client.multi_error = true;
+    }
+    client.add_reply(&reply);
+}
+
+fn is_subscribe_command(name: &str) -> bool {
+    return matches!(
+        name,
+        "subscribe" | "unsubscribe" | "psubscribe" | "punsubscribe"
+    );
+}
+
+#[cfg(test)]
+pub fn start_test_server() -> std::net::SocketAddr {
+    return start_test_server_with(Config::default());
+}
+
+/*
+ * 在给定配置的基础上启动测试服务器，端口和数据目录由测试分配
+ */
+#[cfg(test)]
+pub fn start_test_server_with(config: Config) -> std::net::SocketAddr {
+    use chrono::Local;
+
+    let dir = std::env::temp_dir().join(format!(
+        "redis-rs-test-{}-{}",
+        std::process::id(),
+        Local::now().timestamp_nanos()
+    ));
+    return serve_test_server(Config {
+        port: 0,
+        dir: dir.to_string_lossy().to_string(),
+        ..config
+    });
+}
+
+#[cfg(test)]
+fn serve_test_server(config: Config) -> std::net::SocketAddr {
+    let server = Arc::new(Server::new(config).unwrap());
+    let listener = server.listen().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let cron = server.clone();
+    thread::spawn(move || cron.cron());
+    let replication = server.clone();
+    thread::spawn(move || replication.replication_cron());
+    thread::spawn(move || server.serve(listener));
+    return addr;
+}
+
+/*
+ * 以multibulk格式发送命令并读取一条完整的回复
+ */
+#[cfg(test)]
+pub fn call(stream: &mut TcpStream, args: &[&str]) -> Reply {
+    use std::io::Write;
+
+    let mut buf = vec![];
+    Reply::Array(args.iter().map(Reply::bulk).collect()).encode(super::resp::RESP2, &mut buf);
+    stream.write_all(&buf).unwrap();
+    return read_reply(stream);
+}
+
+/*
+ * 与call相同，参数是原始字节，用于测试非UTF-8的key
+ */
+#[cfg(test)]
+pub fn call_bytes(stream: &mut TcpStream, args: &[&[u8]]) -> Reply {
+    use std::io::Write;
+
+    let mut buf = vec![];
+    Reply::Array(args.iter().map(Reply::bulk).collect()).encode(super::resp::RESP2, &mut buf);
+    stream.write_all(&buf).unwrap();
+    return read_reply(stream);
+}
+
+#[cfg(test)]
+pub fn read_reply(stream: &mut TcpStream) -> Reply {
+    return read_replies(stream, 1).pop().unwrap();
+}
+
+/*
+ * pipeline的多条回复可能在同一次read中返回，必须一起解析
+ */
+#[cfg(test)]
+pub fn read_replies(stream: &mut TcpStream, count: usize) -> Vec<Reply> {
+    use super::resp;
+    use std::io::Read;
+
+    let mut buf = vec![];
+    let mut offset = 0;
+    let mut replies = vec![];
+    let mut chunk = [0; 1024];
+    while replies.len() < count {
+        if let Some(reply) = resp::parse_reply(&buf, &mut offset).unwrap() {
+            replies.push(reply);
+            continue;
+        }
+        let n = stream.read(&mut chunk).unwrap();
+        assert!(n > 0, "connection closed");
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    return replies;
+}
+
+#[test]
+fn test_server() {
+    use std::io::{Read, Write};
+
+    let addr = start_test_server();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    assert_eq!(
+        call(&mut stream, &["PING"]),
+        Reply::Status("PONG".to_string())
+    );
+    assert_eq!(call(&mut stream, &["SET", "k", "hello"]), Reply::ok());
+    assert_eq!(call(&mut stream, &["GET", "k"]), Reply::bulk("hello"));
+    assert_eq!(call(&mut stream, &["GET", "x"]), Reply::Null);
+    assert_eq!(call(&mut stream, &["SET", "k", "world"]), Reply::ok());
+    assert_eq!(call(&mut stream, &["EXISTS", "k", "x"]), Reply::Integer(1));
+    assert_eq!(
+        call(&mut stream, &["EXPIRE", "k", "100"]),
+        Reply::Integer(1)
+    );
+    assert_eq!(call(&mut stream, &["PERSIST", "k"]), Reply::Integer(1));
+    assert_eq!(call(&mut stream, &["DEL", "k", "x"]), Reply::Integer(1));
+    assert_eq!(
+        call(&mut stream, &["GET"]),
+        Reply::error("wrong number of arguments for 'get' command")
+    );
+    assert_eq!(
+        call(&mut stream, &["FOO"]),
+        Reply::error("unknown command 'FOO'")
+    );
+
+    // pipeline和inline命令，回复按顺序返回
+    stream
+        .write_all(b"SET a 1\r\nGET a\r\n*2\r\n$3\r\nGET\r\n$1\r\nb\r\n")
+        .unwrap();
+    assert_eq!(
+        read_replies(&mut stream, 3),
+        vec![Reply::ok(), Reply::bulk("1"), Reply::Null]
+    );
+
+    // RESP3
+    match call(&mut stream, &["HELLO", "3", "SETNAME", "test"]) {
+        Reply::Map(pairs) => assert!(pairs.contains(&(Reply::bulk("proto"), Reply::Integer(3)))),
+        reply => panic!("unexpected reply: {:?}", reply),
+    }
+    stream.write_all(b"GET x\r\n").unwrap();
+    let mut buf = [0; 3];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"_\r\n");
+    assert_eq!(
+        call(&mut stream, &["HELLO", "4"]),
+        Reply::Error("NOPROTO unsupported protocol version".to_string())
+    );
+    assert_eq!(call(&mut stream, &["QUIT"]), Reply::ok());
+    assert_eq!(stream.read(&mut buf).unwrap(), 0);
+
+    // 协议错误后关闭连接
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"*1\r\n+x\r\n").unwrap();
+    assert_eq!(
+        read_reply(&mut stream),
+        Reply::error("Protocol error: expected '$', got '+'")
+    );
+    assert_eq!(stream.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn test_rdb_persistence() {
+    let dir = std::env::temp_dir().join(format!("redis-rs-rdb-server-{}", std::process::id()));
+    std::fs::remove_dir_all(&dir).unwrap_or(());
+    let config = Config {
+        port: 0,
+        dir: dir.to_string_lossy().to_string(),
+        ..Config::default()
+    };
+    let mut stream = TcpStream::connect(serve_test_server(config.clone())).unwrap();
+    call(&mut stream, &["SET", "s", "v"]);
+    call(&mut stream, &["SELECT", "2"]);
+    call(&mut stream, &["RPUSH", "l", "a", "b"]);
+    assert_eq!(
+        call(&mut stream, &["BGSAVE"]),
+        Reply::Status("Background saving started".to_string())
+    );
+    let mut waited = 0;
+    while !dir.join("db.rdb").exists() {
+        assert!(waited < 100, "bgsave not finished");
+        thread::sleep(Duration::from_millis(10));
+        waited += 1;
+    }
+
+    // 重启后从db.rdb加载所有db
+    let loaded = Server::new(config).unwrap();
+    let mut dbs = loaded.dbs.lock().unwrap();
+    assert_eq!(dbs[0].get(b"s").unwrap().unwrap().as_ref(), b"v");
+    let list = dbs[2].lookup_key(b"l").unwrap().unwrap().as_list().unwrap();
+    assert_eq!(list.range(0, -1), vec![b"a".to_vec(), b"b".to_vec()]);
+    drop(dbs);
+    std::fs::remove_dir_all(&dir).unwrap_or(());
+}
+
+#[test]
+fn test_aof_persistence() {
+    use crate::db::aof::AppendFsync;
+
+    let dir = std::env::temp_dir().join(format!("redis-rs-aof-server-{}", std::process::id()));
+    std::fs::remove_dir_all(&dir).unwrap_or(());
+    let config = Config {
+        port: 0,
+        dir: dir.to_string_lossy().to_string(),
+        appendonly: true,
+        appendfsync: AppendFsync::Always,
+        ..Config::default()
+    };
+    let mut stream = TcpStream::connect(serve_test_server(config.clone())).unwrap();
+    let mut call = |args: &[&str]| call(&mut stream, args);
+    call(&["SET", "s", "v", "EX", "100"]);
+    call(&["RPUSH", "l", "a", "b", "c"]);
+    call(&["LPOP", "l"]);
+    call(&["INCRBYFLOAT", "f", "1.5"]);
+    call(&["SADD", "set", "1", "2", "3"]);
+    call(&["SPOP", "set"]);
+    call(&["SET", "gone", "v"]);
+    call(&["EXPIRE", "gone", "-1"]);
+    call(&["ZADD", "z", "1", "a", "2", "b"]);
+    assert!(matches!(call(&["SET", "s"]), Reply::Error(_)));
+    let smembers = call(&["SMEMBERS", "set"]);
+
+    // 重放得到相同的数据，相对的过期时间和随机弹出的元素都是确定的
+    let check = |server: &Server| {
+        let mut dbs = server.dbs.lock().unwrap();
+        let db = &mut dbs[0];
+        assert!(db.get_expire(b"s").unwrap().is_some());
+        assert_eq!(db.get(b"f").unwrap().unwrap().as_ref(), b"1.5");
+        assert!(!db.exist(b"gone").unwrap());
+        let list = db.lookup_key(b"l").unwrap().unwrap().as_list().unwrap();
+        assert_eq!(list.range(0, -1), vec![b"b".to_vec(), b"c".to_vec()]);
+        let set = db.lookup_key(b"set").unwrap().unwrap().as_set().unwrap();
+        assert_eq!(set.len(), 2);
+        let zset = db.lookup_key(b"z").unwrap().unwrap().as_zset().unwrap();
+        assert_eq!(zset.len(), 2);
+    };
+    let replayed = Server::new(config.clone()).unwrap();
+    check(&replayed);
+    let mut dbs = replayed.dbs.lock().unwrap();
+    let db = &mut dbs[0];
+    let members = db
+        .lookup_key(b"set")
+        .unwrap()
+        .unwrap()
+        .as_set()
+        .unwrap()
+        .members();
+    assert_eq!(
+        Reply::Array(members.into_iter().map(Reply::Bulk).collect()),
+        smembers
+    );
+    drop(dbs);
+
+    // 重写后文件变小，重放结果不变
+    let path = config.aof_path();
+    let size = std::fs::metadata(&path).unwrap().len();
+    let mut stream = TcpStream::connect(serve_test_server(config.clone())).unwrap();
+    assert_eq!(
+        super::server::call(&mut stream, &["BGREWRITEAOF"]),
+        Reply::Status("Background append only file rewriting started".to_string())
+    );
+    let mut waited = 0;
+    while std::fs::metadata(&path).unwrap().len() >= size {
+        assert!(waited < 100, "rewrite not finished");
+        thread::sleep(Duration::from_millis(20));
+        waited += 1;
+    }
+    check(&Server::new(config).unwrap());
+    std::fs::remove_dir_all(&dir).unwrap_or(());
+}
+
+#[test]
+fn test_aof_databases() {
+    use crate::db::aof::AppendFsync;
+
+    let dir = std::env::temp_dir().join(format!("redis-rs-aof-dbs-{}", std::process::id()));
+    std::fs::remove_dir_all(&dir).unwrap_or(());
+    let config = Config {
+        port: 0,
+        dir: dir.to_string_lossy().to_string(),
+        appendonly: true,
+        appendfsync: AppendFsync::Always,
+        databases: 4,
+        ..Config::default()
+    };
+    let mut stream = TcpStream::connect(serve_test_server(config.clone())).unwrap();
+    let mut call = |args: &[&str]| call(&mut stream, args);
+    call(&["SET", "a", "0"]);
+    call(&["SELECT", "1"]);
+    call(&["SET", "a", "1"]);
+    call(&["SET", "b", "1"]);
+    call(&["MOVE", "b", "2"]);
+    call(&["SELECT", "3"]);
+    call(&["SET", "c", "3"]);
+    call(&["SWAPDB", "0", "3"]);
+    call(&["FLUSHDB"]);
+
+    // 重放时按SELECT切换db，MOVE和SWAPDB的结果不变
+    let check = |server: &Server| {
+        let mut dbs = server.dbs.lock().unwrap();
+        assert_eq!(dbs[0].get(b"c").unwrap().unwrap().as_ref(), b"3");
+        assert_eq!(dbs[1].get(b"a").unwrap().unwrap().as_ref(), b"1");
+        assert!(!dbs[1].exist(b"b").unwrap());
+        assert_eq!(dbs[2].get(b"b").unwrap().unwrap().as_ref(), b"1");
+        assert_eq!(dbs[3].dict.dict_size(), 0);
+    };
+    check(&Server::new(config.clone()).unwrap());
+
+    let mut stream = TcpStream::connect(serve_test_server(config.clone())).unwrap();
+    super::server::call(&mut stream, &["BGREWRITEAOF"]);
+    let path = config.aof_path();
+    let mut waited = 0;
+    while !std::fs::read(&path)
+        .unwrap()
+        .starts_with(b"*2\r\n$6\r\nSELECT\r\n$1\r\n0\r\n*3")
+    {
+        assert!(waited < 100, "rewrite not finished");
+        thread::sleep(Duration::from_millis(20));
+        waited += 1;
+    }
+    check(&Server::new(config).unwrap());
+    std::fs::remove_dir_all(&dir).unwrap_or(());
+}
+
+#[test]
+fn test_aof_transaction() {
+    use crate::db::aof::AppendFsync;
+    use std::io::Write;
+
+    let dir = std::env::temp_dir().join(format!("redis-rs-aof-multi-{}", std::process::id()));
+    std::fs::remove_dir_all(&dir).unwrap_or(());
+    let config = Config {
+        port: 0,
+        dir: dir.to_string_lossy().to_string(),
+        appendonly: true,
+        appendfsync: AppendFsync::Always,
+        ..Config::default()
+    };
+    let mut stream = TcpStream::connect(serve_test_server(config.clone())).unwrap();
+    let mut call = |args: &[&str]| call(&mut stream, args);
+    call(&["MULTI"]);
+    call(&["SET", "a", "1"]);
+    call(&["GET", "a"]);
+    call(&["SELECT", "1"]);
+    call(&["SET", "b", "1"]);
+    call(&["EXEC"]);
+    // 只读的事务不写入AOF
+    call(&["MULTI"]);
+    call(&["GET", "a"]);
+    call(&["EXEC"]);
+    let path = config.aof_path();
+    let commands = aof::load(&path, false).unwrap().unwrap();
+    let names: Vec<String> = commands
+        .iter()
+        .map(|args| String::from_utf8_lossy(&args[0]).to_string())
+        .collect();
+    assert_eq!(names, ["SELECT", "MULTI", "SET", "SELECT", "SET", "EXEC"]);
+
+    // 末尾不完整的事务在加载时整体丢弃
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap();
+    file.write_all(b"*1\r\n$5\r\nMULTI\r\n*3\r\n$3\r\nSET\r\n$1\r\nc\r\n$1\r\n1\r\n")
+        .unwrap();
+    let server = Server::new(config).unwrap();
+    let mut dbs = server.dbs.lock().unwrap();
+    assert!(dbs[0].exist(b"a").unwrap());
+    assert!(dbs[1].exist(b"b").unwrap());
+    assert!(!dbs[1].exist(b"c").unwrap());
+    drop(dbs);
+    std::fs::remove_dir_all(&dir).unwrap_or(());
+}
@@ -1,23 +1,140 @@
 use crate::{common::error::Error, encoding::sds::Sds};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, mem, sync::Arc};
 
 const ACTIVE_INDEX: usize = 0;
 const PASSIVE_INDEX: usize = 1;
+// 每个分段预期的元素个数，决定快照期间写时复制的粒度
+const SEGMENT_SIZE: usize = 64;
+
+// 游标迭代时按(表, 分段)缓存的虚拟桶分组
+type ScanBuckets<'a, V> = HashMap<(usize, usize), HashMap<u64, Vec<(&'a Arc<Sds>, &'a V)>>>;
 
 enum RehashType {
     Expand,
     Shrink,
 }
 
+/*
+ * 哈希表按key的哈希值分成若干个共享的分段：clone时只复制分段的指针，
+ * 修改时才复制被修改的分段（写时复制），用于BGSAVE等需要快照的场景
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Table<V>
+where
+    V: Clone,
+{
+    segments: Vec<Arc<HashMap<Arc<Sds>, V>>>,
+}
+
+impl<V> Default for Table<V>
+where
+    V: Clone,
+{
+    fn default() -> Self {
+        return Self::with_capacity(0);
+    }
+}
+
+impl<V> Table<V>
+where
+    V: Clone,
+{
+    /*
+     * 分段数为2的幂，每个分段预期SEGMENT_SIZE个元素
+     */
+    fn with_capacity(capacity: usize) -> Self {
+        let count = (capacity / SEGMENT_SIZE).max(1).next_power_of_two();
+        let per_segment = if capacity == 0 {
+            0
+        } else {
+            capacity / count + 1
+        };
+        return Self {
+            segments: (0..count)
+                .map(|_| Arc::new(HashMap::with_capacity(per_segment)))
+                .collect(),
+        };
+    }
+
+    fn segment(&self, key: &Sds) -> usize {
+        return scan_hash(key) as usize & (self.segments.len() - 1);
+    }
+
+    fn segment_mut(&mut self, key: &Sds) -> &mut HashMap<Arc<Sds>, V> {
+        let index = self.segment(key);
+        return Arc::make_mut(&mut self.segments[index]);
+    }
+
+    fn get(&self, key: &Sds) -> Option<&V> {
+        return self.segments[self.segment(key)].get(key);
+    }
+
+    /*
+     * 只有key存在时才复制分段
+     */
+    fn get_mut(&mut self, key: &Sds) -> Option<&mut V> {
+        if !self.contains_key(key) {
+            return None;
+        }
+        return self.segment_mut(key).get_mut(key);
+    }
+
+    /*
+     * 分段被快照共享时返回None，不复制分段
+     */
+    fn get_unshared_mut(&mut self, key: &Sds) -> Option<&mut V> {
+        let index = self.segment(key);
+        return Arc::get_mut(&mut self.segments[index])?.get_mut(key);
+    }
+
+    /*
+     * 游标的掩码：低位是分段的下标，再按哈希值把每个分段细分成SEGMENT_SIZE个虚拟桶
+     */
+    fn scan_mask(&self) -> u64 {
+        return ((self.segments.len() * SEGMENT_SIZE) as u64) - 1;
+    }
+
+    fn contains_key(&self, key: &Sds) -> bool {
+        return self.segments[self.segment(key)].contains_key(key);
+    }
+
+    fn insert(&mut self, key: Arc<Sds>, val: V) -> Option<V> {
+        return self.segment_mut(&key).insert(key, val);
+    }
+
+    fn remove_entry(&mut self, key: &Sds) -> Option<(Arc<Sds>, V)> {
+        if !self.contains_key(key) {
+            return None;
+        }
+        return self.segment_mut(key).remove_entry(key);
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&Arc<Sds>, &V)> {
+        return self.segments.iter().flat_map(|segment| segment.iter());
+    }
+
+    fn len(&self) -> usize {
+        return self.segments.iter().map(|segment| segment.len()).sum();
+    }
+
+    /*
+     * 按分段数计算的容量，各个分段的HashMap自己扩容，不影响分段数
+     */
+    fn capacity(&self) -> usize {
+        return self.segments.len() * SEGMENT_SIZE;
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Dict<V>
 where
     V: Clone,
 {
     load_factor: f32,
-    maps: [HashMap<Arc<Sds>, V>; 2],
-    rehashing: i8, // rehashing进度
+    maps: [Table<V>; 2],
+    rehashing: i64, // rehashing进度：下一个要迁移的分段，-1表示没有在rehash
 }
 
 impl<V> Dict<V>
@@ -27,7 +144,7 @@ where
     pub fn new(load_factor: f32) -> Self {
         Self {
             load_factor,
-            maps: [HashMap::with_capacity(4), HashMap::default()],
+            maps: [Table::with_capacity(4), Table::default()],
             rehashing: -1,
         }
     }
@@ -47,17 +164,21 @@ where
         return Ok(true);
     }
 
+    /*
+     * 新增或覆盖，返回是否为新增的key
+     */
     pub fn dict_replace(&mut self, key: Arc<Sds>, val: V) -> Result<bool, Error> {
         if self.is_rehashing() {
             self.rehash_step();
+        } else if let Some(reahsh_type) = self.need_rehash() {
+            self.resize_dict(reahsh_type)?;
         }
 
-        if self.exist(key.clone()) {
+        if let Some(v) = self.maps[PASSIVE_INDEX].get_mut(&key) {
+            *v = val;
             return Ok(false);
         }
-
-        self.add_entry(key, val);
-        return Ok(true);
+        return Ok(self.maps[ACTIVE_INDEX].insert(key, val).is_none());
     }
 
     /*
@@ -77,6 +198,39 @@ where
         return Ok(obj);
     }
 
+    /*
+     * 只读查找：不推进rehash，也不复制被快照共享的分段
+     */
+    pub fn dict_find(&self, key: &Sds) -> Option<&V> {
+        return self.maps[ACTIVE_INDEX]
+            .get(key)
+            .or_else(|| self.maps[PASSIVE_INDEX].get(key));
+    }
+
+    /*
+     * 修改value会复制被快照共享的分段
+     */
+    pub fn dict_get_mut(&mut self, key: Arc<Sds>) -> Result<Option<&mut V>, Error> {
+        if self.is_rehashing() {
+            self.rehash_step();
+        }
+
+        if self.maps[ACTIVE_INDEX].contains_key(&key) {
+            return Ok(self.maps[ACTIVE_INDEX].get_mut(&key));
+        }
+        return Ok(self.maps[PASSIVE_INDEX].get_mut(&key));
+    }
+
+    /*
+     * 只有key所在的分段没有被快照共享时才返回可变引用，用于更新可以丢弃的信息（例如访问时间）
+     */
+    pub fn dict_get_unshared_mut(&mut self, key: &Sds) -> Option<&mut V> {
+        if self.maps[ACTIVE_INDEX].contains_key(key) {
+            return self.maps[ACTIVE_INDEX].get_unshared_mut(key);
+        }
+        return self.maps[PASSIVE_INDEX].get_unshared_mut(key);
+    }
+
     pub fn dict_contanins_key(&mut self, key: Arc<Sds>) -> Result<bool, Error> {
         let is_rehashing = self.is_rehashing();
         if is_rehashing {
@@ -91,32 +245,200 @@ where
         return Ok(exists);
     }
 
-    //TODO 随机获取一个key
-    pub fn dict_get_random_key(&mut self) -> Result<Option<(Sds, V)>, Error> {
-        let is_rehashing = self.is_rehashing();
-        if is_rehashing {
+    /*
+     * 随机返回一个key：先随机选一个非空的分段（rehash期间包括旧表中还没有迁移的分段），
+     * 再在分段中随机选一个元素，与redis的dictGetRandomKey一样不是严格均匀的
+     */
+    pub fn dict_get_random_key(&mut self) -> Result<Option<(Arc<Sds>, V)>, Error> {
+        if self.is_rehashing() {
             self.rehash_step();
         }
 
-        return Ok(None);
+        if self.dict_size() == 0 {
+            return Ok(None);
+        }
+        let mut rng = rand::thread_rng();
+        let segments = self.sample_segment_count();
+        loop {
+            let segment = self.sample_segment(rng.gen_range(0..segments));
+            if !segment.is_empty() {
+                return Ok(segment
+                    .iter()
+                    .nth(rng.gen_range(0..segment.len()))
+                    .map(|(k, v)| (k.clone(), v.clone())));
+            }
+        }
+    }
+
+    /*
+     * 从随机的分段开始依次取出最多count个key（不重复），用于过期和淘汰时的采样。
+     * 与redis的dictGetSomeKeys一致，最多访问count*10个分段，表很稀疏时返回的key可能不到count个
+     */
+    pub fn dict_get_some_keys(&mut self, count: usize) -> Vec<(Arc<Sds>, V)> {
+        if self.is_rehashing() {
+            self.rehash_step();
+        }
+
+        let mut keys = vec![];
+        if self.dict_size() == 0 {
+            return keys;
+        }
+        let segments = self.sample_segment_count();
+        let start = rand::thread_rng().gen_range(0..segments);
+        for step in 0..segments.min(count * 10) {
+            let segment = self.sample_segment((start + step) % segments);
+            keys.extend(
+                segment
+                    .iter()
+                    .take(count - keys.len())
+                    .map(|(k, v)| (k.clone(), v.clone())),
+            );
+            if keys.len() >= count {
+                break;
+            }
+        }
+        return keys;
+    }
+
+    /*
+     * 可以采样的分段数：新表的全部分段，加上旧表中还没有迁移的分段
+     */
+    fn sample_segment_count(&self) -> usize {
+        let mut count = self.maps[ACTIVE_INDEX].segments.len();
+        if self.is_rehashing() {
+            count += self.maps[PASSIVE_INDEX]
+                .segments
+                .len()
+                .saturating_sub(self.rehashing as usize);
+        }
+        return count;
+    }
+
+    fn sample_segment(&self, index: usize) -> &HashMap<Arc<Sds>, V> {
+        let active = &self.maps[ACTIVE_INDEX].segments;
+        if index < active.len() {
+            return &active[index];
+        }
+        return &self.maps[PASSIVE_INDEX].segments[self.rehashing as usize + index - active.len()];
     }
 
     pub fn dict_delete(&mut self, key: Arc<Sds>) -> Result<Option<(Arc<Sds>, V)>, Error> {
-        let is_rehashing = self.is_rehashing();
-        if is_rehashing {
+        if self.is_rehashing() {
             self.rehash_step();
         } else if let Some(reahsh_type) = self.need_rehash() {
             self.resize_dict(reahsh_type)?;
         }
 
+        // 可能刚刚开始rehash，旧表中还有全部的key
         let mut entry = self.remove_entry(key.clone(), ACTIVE_INDEX);
-        if entry.is_none() && is_rehashing {
+        if entry.is_none() {
             entry = self.remove_entry(key, PASSIVE_INDEX);
         }
 
         return Ok(entry);
     }
 
+    pub fn dict_iter(&self) -> impl Iterator<Item = (&Arc<Sds>, &V)> {
+        return self.maps[ACTIVE_INDEX]
+            .iter()
+            .chain(self.maps[PASSIVE_INDEX].iter());
+    }
+
+    /*
+     * 游标迭代，与redis的dictScan一致：游标是虚拟桶的下标，按反向二进制递增，
+     * 表的大小改变或者正在rehash时，迭代期间一直存在的key至少会被返回一次。
+     * 每次大约返回count个元素，同一个虚拟桶中的元素一起返回，返回0表示迭代结束；
+     * 与redis一致，连续访问10*count个空桶后也会返回，避免稀疏的表一次访问太多桶
+     */
+    pub fn dict_scan(&self, cursor: u64, count: usize) -> (u64, Vec<(Arc<Sds>, V)>) {
+        let mut buckets: ScanBuckets<V> = HashMap::new();
+        let mut result = vec![];
+        let mut empty_visits = count.max(1) * 10;
+        let mut v = cursor;
+        loop {
+            let visited = result.len();
+            if !self.is_rehashing() {
+                let mask = self.maps[ACTIVE_INDEX].scan_mask();
+                self.scan_bucket(&mut buckets, ACTIVE_INDEX, v & mask, &mut result);
+                v = next_cursor(v, mask);
+            } else {
+                // 先访问小表的桶，再访问大表中由它展开的所有桶
+                let (small, large) = if self.maps[ACTIVE_INDEX].segments.len()
+                    <= self.maps[PASSIVE_INDEX].segments.len()
+                {
+                    (ACTIVE_INDEX, PASSIVE_INDEX)
+                } else {
+                    (PASSIVE_INDEX, ACTIVE_INDEX)
+                };
+                let m0 = self.maps[small].scan_mask();
+                let m1 = self.maps[large].scan_mask();
+                self.scan_bucket(&mut buckets, small, v & m0, &mut result);
+                loop {
+                    self.scan_bucket(&mut buckets, large, v & m1, &mut result);
+                    // 只递增大表多出的位（同样按反向二进制），从游标所在的位置访问到最后，
+                    // 游标来自缩容前的大表时，之前的位置已经访问过
+                    v = (next_cursor(v | m0, m1) & (m0 ^ m1)) | (v & m0);
+                    if v & (m0 ^ m1) == 0 {
+                        break;
+                    }
+                }
+                v = next_cursor(v, m0);
+            }
+            if result.len() == visited {
+                empty_visits -= 1;
+            }
+            if v == 0 || result.len() >= count.max(1) || empty_visits == 0 {
+                return (v, result);
+            }
+        }
+    }
+
+    /*
+     * 返回虚拟桶中的元素，同一次迭代中每个分段只按虚拟桶分组一次
+     */
+    fn scan_bucket<'a>(
+        &'a self,
+        buckets: &mut ScanBuckets<'a, V>,
+        index: usize,
+        bucket: u64,
+        result: &mut Vec<(Arc<Sds>, V)>,
+    ) {
+        let table = &self.maps[index];
+        let segment = bucket as usize & (table.segments.len() - 1);
+        let mask = table.scan_mask();
+        let grouped = buckets.entry((index, segment)).or_insert_with(|| {
+            let mut grouped: HashMap<u64, Vec<(&Arc<Sds>, &V)>> = HashMap::new();
+            for (k, v) in table.segments[segment].iter() {
+                grouped.entry(scan_hash(k) & mask).or_default().push((k, v));
+            }
+            return grouped;
+        });
+        if let Some(entries) = grouped.get(&bucket) {
+            result.extend(entries.iter().map(|(k, v)| ((*k).clone(), (*v).clone())));
+        }
+    }
+
+    /*
+     * 估算占用的内存：与redis的MEMORY USAGE一致，只采样前samples个元素，按元素个数推算
+     */
+    pub fn dict_memory_usage<F>(&self, samples: usize, value_size: F) -> usize
+    where
+        F: Fn(&V) -> usize,
+    {
+        // 每个元素除了key和value之外还有Arc的引用计数和哈希表的开销
+        let entry_size = mem::size_of::<(Arc<Sds>, V)>() + 2 * mem::size_of::<usize>();
+        let sampled: Vec<usize> = self
+            .dict_iter()
+            .take(samples)
+            .map(|(k, v)| k.alloc_size() + value_size(v) + entry_size)
+            .collect();
+        let size = mem::size_of::<Self>();
+        if sampled.is_empty() {
+            return size;
+        }
+        return size + sampled.iter().sum::<usize>() * self.dict_size() / sampled.len();
+    }
+
     pub fn dict_size(&self) -> usize {
         let mut size = self.maps[ACTIVE_INDEX].len();
         if self.is_rehashing() {
@@ -125,56 +447,72 @@ where
         return size;
     }
 
+    #[allow(dead_code)]
     pub fn dict_release(&mut self) -> Result<bool, Error> {
-        drop(self);
+        *self = Self::new(self.load_factor);
         return Ok(true);
     }
 
+    /*
+     * 与快照相比被复制的分段数
+     */
+    #[cfg(test)]
+    pub fn copied_segments(&self, snapshot: &Dict<V>) -> usize {
+        return self.maps[ACTIVE_INDEX]
+            .segments
+            .iter()
+            .zip(snapshot.maps[ACTIVE_INDEX].segments.iter())
+            .filter(|(a, b)| !Arc::ptr_eq(a, b))
+            .count();
+    }
+
     fn is_rehashing(&self) -> bool {
         return self.rehashing != -1;
     }
 
+    /*
+     * 每一步迁移旧表的一个分段（与redis每次迁移一个桶类似）
+     */
     fn rehash_step(&mut self) {
-        // 借用规则必须得clone一份key后再删除
-        let remove_key = self.maps[PASSIVE_INDEX]
-            .keys()
-            .into_iter()
-            .next()
-            .map(|k| k.clone());
-        if let Some(key) = remove_key {
-            let (k, v) = self.maps[PASSIVE_INDEX].remove_entry(&key).unwrap();
-            self.add_entry(k, v);
+        let index = self.rehashing as usize;
+        let passive = &mut self.maps[PASSIVE_INDEX];
+        if index < passive.segments.len() {
+            let segment = mem::take(&mut passive.segments[index]);
+            self.rehashing += 1;
+            // 分段被快照共享时只能复制
+            let entries = Arc::try_unwrap(segment).unwrap_or_else(|shared| (*shared).clone());
+            for (k, v) in entries {
+                self.add_entry(k, v);
+            }
         } else {
             // 结束rehash
-            self.maps[PASSIVE_INDEX].shrink_to_fit();
+            self.maps[PASSIVE_INDEX] = Table::default();
             self.rehashing = -1;
         }
     }
 
     fn need_rehash(&self) -> Option<RehashType> {
-        let factor =
-            self.maps[ACTIVE_INDEX].len() as f32 / (self.maps[ACTIVE_INDEX].capacity()) as f32;
+        let table = &self.maps[ACTIVE_INDEX];
+        let factor = table.len() as f32 / table.capacity() as f32;
         if factor > self.load_factor {
             return Some(RehashType::Expand);
-        } else if factor < 0.1 {
+        } else if factor < 0.1 && table.segments.len() > 1 {
             return Some(RehashType::Shrink);
         }
         return None;
     }
 
+    /*
+     * 扩容和缩容都创建新表并渐进式rehash
+     */
     fn resize_dict(&mut self, rehash_type: RehashType) -> Result<(), Error> {
-        match rehash_type {
-            RehashType::Expand => {
-                self.maps[PASSIVE_INDEX] =
-                    HashMap::with_capacity(self.maps[ACTIVE_INDEX].capacity() * 2);
-                self.maps.swap(ACTIVE_INDEX, PASSIVE_INDEX);
-                self.rehashing = 0;
-            }
-            // FIXME 缩容没有使用渐进式rehash（需要考虑如何与扩容时的渐进式rehash冲突）
-            RehashType::Shrink => {
-                self.maps[ACTIVE_INDEX].shrink_to_fit();
-            }
+        let capacity = match rehash_type {
+            RehashType::Expand => self.maps[ACTIVE_INDEX].capacity() * 2,
+            RehashType::Shrink => self.maps[ACTIVE_INDEX].len() * 2,
         };
+        self.maps[PASSIVE_INDEX] = Table::with_capacity(capacity);
+        self.maps.swap(ACTIVE_INDEX, PASSIVE_INDEX);
+        self.rehashing = 0;
         return Ok(());
     }
 
@@ -183,7 +521,7 @@ where
     }
 
     fn get_value(&self, key: Arc<Sds>, index: usize) -> Option<V> {
-        return self.maps[index].get(&key).map(|v| v.clone());
+        return self.maps[index].get(&key).cloned();
     }
 
     fn remove_entry(&mut self, key: Arc<Sds>, index: usize) -> Option<(Arc<Sds>, V)> {
@@ -196,9 +534,28 @@ where
     }
 }
 
+/*
+ * 反向二进制递增：把掩码以外的位置1后反转，加1再反转回来，
+ * 表变大或者变小时都不会跳过已经存在的桶
+ */
+fn next_cursor(cursor: u64, mask: u64) -> u64 {
+    return ((cursor | !mask).reverse_bits().wrapping_add(1)).reverse_bits();
+}
+
+/*
+ * 稳定的哈希（FNV-1a），只使用63位
+ */
+fn scan_hash(key: &Sds) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in key.as_bytes() {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return hash >> 1;
+}
+
 #[test]
 fn test_dict() {
-    use rand::Rng;
     use std::time::Instant;
 
     let mut dict: Dict<Sds> = Dict::new(0.8);
@@ -239,7 +596,7 @@ fn test_dict() {
     // missing get
     let time = Instant::now();
     for _ in 0..count {
-        let key = Arc::new(Sds::new(&"X".as_bytes()));
+        let key = Arc::new(Sds::new("X".as_bytes()));
         assert!(dict.dict_get(key).unwrap().is_none());
     }
     println!("missing get {} time: {:?}", count, time.elapsed());
@@ -262,3 +619,169 @@ fn test_dict() {
 
     dict.dict_release().unwrap();
 }
+
+#[test]
+fn test_dict_scan() {
+    use std::collections::HashSet;
+
+    let key = |i: i32| Arc::new(Sds::new(i.to_string().as_bytes()));
+    // 每次调用后执行f，返回迭代到的值和调用次数
+    let scan = |dict: &mut Dict<i32>, f: &mut dyn FnMut(&mut Dict<i32>, u64)| {
+        let mut seen = HashSet::new();
+        let mut calls = 0;
+        let mut cursor = 0;
+        loop {
+            let (next, entries) = dict.dict_scan(cursor, 10);
+            // 虚拟桶平均不到一个元素，每次只比count多出最后一步访问的几个桶
+            assert!(entries.len() < 20);
+            seen.extend(entries.into_iter().map(|(_, v)| v));
+            calls += 1;
+            f(dict, cursor);
+            if next == 0 {
+                return (seen, calls);
+            }
+            cursor = next;
+        }
+    };
+
+    // 迭代过程中新增的key触发扩容，一直存在的key不会遗漏
+    let mut dict: Dict<i32> = Dict::new(0.8);
+    for i in 0..100 {
+        dict.dict_add(key(i), i).unwrap();
+    }
+    let (seen, _) = scan(&mut dict, &mut |dict, cursor| {
+        dict.dict_add(Arc::new(Sds::new(format!("new-{}", cursor).as_bytes())), -1)
+            .unwrap();
+    });
+    assert!((0..100).all(|i| seen.contains(&i)));
+
+    // 每次调用只访问大约count个元素
+    let mut dict: Dict<i32> = Dict::new(0.8);
+    for i in 0..5000 {
+        dict.dict_add(key(i), i).unwrap();
+    }
+    while dict.is_rehashing() {
+        dict.rehash_step();
+    }
+    let (seen, calls) = scan(&mut dict, &mut |_, _| {});
+    assert_eq!(seen.len(), 5000);
+    assert!(calls > 5000 / 20);
+
+    // 迭代过程中删除大部分key触发缩容，一直存在的key不会遗漏
+    let mut next = 100;
+    let (seen, _) = scan(&mut dict, &mut |dict, _| {
+        for _ in 0..100 {
+            if next < 5000 {
+                dict.dict_delete(key(next)).unwrap();
+                next += 1;
+            }
+        }
+    });
+    assert!(dict.maps[ACTIVE_INDEX].segments.len() < 5000 / SEGMENT_SIZE);
+    assert!((0..100).all(|i| seen.contains(&i)));
+}
+
+#[test]
+fn test_dict_random_key() {
+    use std::collections::HashSet;
+
+    let mut dict: Dict<i32> = Dict::new(0.8);
+    assert!(dict.dict_get_random_key().unwrap().is_none());
+    assert!(dict.dict_get_some_keys(5).is_empty());
+
+    // 插入过程中会发生rehash，两张表中的key都要能被采样到
+    for i in 0..100 {
+        dict.dict_add(Arc::new(Sds::new(i.to_string().as_bytes())), i)
+            .unwrap();
+    }
+    let mut seen = HashSet::new();
+    for _ in 0..2000 {
+        let (key, value) = dict.dict_get_random_key().unwrap().unwrap();
+        assert_eq!(key.to_string(), value.to_string());
+        seen.insert(value);
+    }
+    assert_eq!(seen.len(), 100);
+
+    let sampled = dict.dict_get_some_keys(20);
+    assert_eq!(sampled.len(), 20);
+    let distinct: HashSet<i32> = sampled.iter().map(|(_, v)| *v).collect();
+    assert_eq!(distinct.len(), 20);
+    assert_eq!(dict.dict_get_some_keys(500).len(), 100);
+
+    // 刚开始rehash时几乎所有的key都在旧表中，也都能被采样到
+    let mut dict: Dict<i32> = Dict::new(0.8);
+    for i in 0..1000 {
+        dict.dict_add(Arc::new(Sds::new(i.to_string().as_bytes())), i)
+            .unwrap();
+    }
+    while dict.is_rehashing() {
+        dict.rehash_step();
+    }
+    dict.resize_dict(RehashType::Expand).unwrap();
+    let (key, _) = dict.dict_get_random_key().unwrap().unwrap();
+    assert!(dict.rehashing > 0 && dict.exist(key));
+    let distinct: HashSet<i32> = dict
+        .dict_get_some_keys(1000)
+        .iter()
+        .map(|(_, v)| *v)
+        .collect();
+    assert!(dict.is_rehashing());
+    assert_eq!(distinct.len(), 1000);
+}
+
+#[test]
+fn test_dict_snapshot() {
+    let mut dict: Dict<i32> = Dict::new(0.8);
+    let count = 10000;
+    for i in 0..count {
+        dict.dict_add(Arc::new(Sds::new(i.to_string().as_bytes())), i)
+            .unwrap();
+    }
+    while dict.is_rehashing() {
+        dict.rehash_step();
+    }
+    let segments = dict.maps[ACTIVE_INDEX].segments.len();
+    assert!(segments >= count as usize / SEGMENT_SIZE / 2);
+
+    // 快照只共享分段，修改一个key只复制它所在的分段
+    let snapshot = dict.clone();
+    let copied = |dict: &Dict<i32>| dict.copied_segments(&snapshot);
+    assert_eq!(copied(&dict), 0);
+    dict.dict_replace(Arc::new(Sds::new(b"1")), -1).unwrap();
+    dict.dict_get(Arc::new(Sds::new(b"2"))).unwrap();
+    dict.dict_get_mut(Arc::new(Sds::new(b"missing"))).unwrap();
+    assert_eq!(dict.dict_find(&Sds::new(b"4")), Some(&4));
+    assert_eq!(copied(&dict), 1);
+    // 被快照共享的分段不返回可变引用
+    assert!(dict.dict_get_unshared_mut(&Sds::new(b"1")).is_some());
+    assert!(snapshot
+        .clone()
+        .dict_get_unshared_mut(&Sds::new(b"1"))
+        .is_none());
+    dict.dict_delete(Arc::new(Sds::new(b"3"))).unwrap();
+    assert!(copied(&dict) <= 2);
+
+    let mut snapshot = snapshot;
+    assert_eq!(snapshot.dict_size(), count as usize);
+    assert_eq!(
+        snapshot.dict_get(Arc::new(Sds::new(b"1"))).unwrap(),
+        Some(1)
+    );
+    assert_eq!(
+        snapshot.dict_get(Arc::new(Sds::new(b"3"))).unwrap(),
+        Some(3)
+    );
+    assert_eq!(dict.dict_get(Arc::new(Sds::new(b"1"))).unwrap(), Some(-1));
+    assert_eq!(dict.dict_size(), count as usize - 1);
+
+    // 删除大部分key后缩容
+    for i in 0..count - 10 {
+        dict.dict_delete(Arc::new(Sds::new(i.to_string().as_bytes())))
+            .unwrap();
+    }
+    while dict.is_rehashing() {
+        dict.rehash_step();
+    }
+    assert_eq!(dict.dict_size(), 10);
+    assert_eq!(dict.maps[ACTIVE_INDEX].segments.len(), 1);
+}
@@ -0,0 +1,257 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::error::{Error, ErrorKind, Result},
+    encoding::{listpack::Listpack, sds::Sds},
+};
+
+use super::{dict::Dict, object::MEMORY_SAMPLES, strings};
+
+// 与redis的hash-max-listpack-entries和hash-max-listpack-value一致
+const HASH_MAX_LISTPACK_ENTRIES: usize = 128;
+const HASH_MAX_LISTPACK_VALUE: usize = 64;
+const THRESH_HOLD: f32 = 0.9;
+
+// (field, value)
+pub type HashEntry = (Vec<u8>, Vec<u8>);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum HashValue {
+    // field和value交替存放
+    Listpack(Listpack),
+    Dict(Dict<Sds>),
+}
+
+/*
+ * 哈希对象：字段较少并且都较短时使用listpack，超过阈值后转换为dict，不会再转换回来
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HashObject {
+    value: HashValue,
+}
+
+impl Default for HashObject {
+    fn default() -> Self {
+        return Self {
+            value: HashValue::Listpack(Listpack::new()),
+        };
+    }
+}
+
+impl HashObject {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn get_encoding(&self) -> &str {
+        return match self.value {
+            HashValue::Listpack(_) => "listpack",
+            HashValue::Dict(_) => "hashtable",
+        };
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        return match &self.value {
+            HashValue::Listpack(lp) => lp.alloc_size(),
+            HashValue::Dict(dict) => dict.dict_memory_usage(MEMORY_SAMPLES, |v| v.alloc_size()),
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        return match &self.value {
+            HashValue::Listpack(lp) => lp.len() / 2,
+            HashValue::Dict(dict) => dict.dict_size(),
+        };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<Vec<u8>> {
+        return match &self.value {
+            HashValue::Listpack(lp) => {
+                let index = Self::find(lp, field)?;
+                lp.get(index + 1)
+            }
+            HashValue::Dict(dict) => dict
+                .dict_find(&Sds::new(field))
+                .map(|v| v.as_bytes().to_vec()),
+        };
+    }
+
+    pub fn contains(&self, field: &[u8]) -> bool {
+        return match &self.value {
+            HashValue::Listpack(lp) => Self::find(lp, field).is_some(),
+            HashValue::Dict(dict) => dict.dict_find(&Sds::new(field)).is_some(),
+        };
+    }
+
+    /*
+     * 新增或覆盖，返回是否为新增的字段
+     */
+    pub fn set(&mut self, field: &[u8], value: &[u8]) -> bool {
+        if field.len() > HASH_MAX_LISTPACK_VALUE
+            || value.len() > HASH_MAX_LISTPACK_VALUE
+            || self.len() >= HASH_MAX_LISTPACK_ENTRIES
+        {
+            self.convert_to_dict();
+        }
+        return match &mut self.value {
+            HashValue::Listpack(lp) => match Self::find(lp, field) {
+                Some(index) => {
+                    lp.replace(index + 1, value);
+                    false
+                }
+                None => {
+                    lp.push_back(field);
+                    lp.push_back(value);
+                    true
+                }
+            },
+            HashValue::Dict(dict) => dict
+                .dict_replace(Arc::new(Sds::new(field)), Sds::new(value))
+                .unwrap_or(false),
+        };
+    }
+
+    pub fn remove(&mut self, field: &[u8]) -> bool {
+        return match &mut self.value {
+            HashValue::Listpack(lp) => match Self::find(lp, field) {
+                Some(index) => {
+                    lp.remove(index);
+                    lp.remove(index);
+                    true
+                }
+                None => false,
+            },
+            HashValue::Dict(dict) => dict
+                .dict_delete(Arc::new(Sds::new(field)))
+                .map(|entry| entry.is_some())
+                .unwrap_or(false),
+        };
+    }
+
+    pub fn incr_by(&mut self, field: &[u8], delta: i64) -> Result<i64> {
+        let current = match self.get(field) {
+            Some(v) => strings::parse_integer(&v).ok_or_else(|| {
+                return Error::new(
+                    ErrorKind::Invalid,
+                    "hash value is not an integer".to_string(),
+                );
+            })?,
+            None => 0,
+        };
+        let value = current.checked_add(delta).ok_or_else(|| {
+            return Error::new(
+                ErrorKind::Invalid,
+                "increment or decrement would overflow".to_string(),
+            );
+        })?;
+        self.set(field, value.to_string().as_bytes());
+        return Ok(value);
+    }
+
+    pub fn incr_by_float(&mut self, field: &[u8], delta: f64) -> Result<f64> {
+        let current = match self.get(field) {
+            Some(v) => strings::parse_float(&v).ok_or_else(|| {
+                return Error::new(ErrorKind::Invalid, "hash value is not a float".to_string());
+            })?,
+            None => 0.0,
+        };
+        let value = current + delta;
+        if !value.is_finite() {
+            return Err(Error::new(
+                ErrorKind::Invalid,
+                "increment would produce NaN or Infinity".to_string(),
+            ));
+        }
+        self.set(field, value.to_string().as_bytes());
+        return Ok(value);
+    }
+
+    pub fn entries(&self) -> Vec<HashEntry> {
+        return match &self.value {
+            HashValue::Listpack(lp) => {
+                let values: Vec<Vec<u8>> = lp.iter().collect();
+                values
+                    .chunks(2)
+                    .map(|pair| (pair[0].clone(), pair[1].clone()))
+                    .collect()
+            }
+            HashValue::Dict(dict) => dict
+                .dict_iter()
+                .map(|(k, v)| (k.as_bytes().to_vec(), v.as_bytes().to_vec()))
+                .collect(),
+        };
+    }
+
+    /*
+     * listpack编码时一次返回全部字段，游标为0
+     */
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<HashEntry>) {
+        return match &self.value {
+            HashValue::Listpack(_) => (0, self.entries()),
+            HashValue::Dict(dict) => {
+                let (next, entries) = dict.dict_scan(cursor, count);
+                let entries = entries
+                    .into_iter()
+                    .map(|(k, v)| (k.as_bytes().to_vec(), v.as_bytes().to_vec()))
+                    .collect();
+                (next, entries)
+            }
+        };
+    }
+
+    /*
+     * field在listpack中的下标
+     */
+    fn find(lp: &Listpack, field: &[u8]) -> Option<usize> {
+        return lp.iter().step_by(2).position(|f| f == field).map(|i| i * 2);
+    }
+
+    fn convert_to_dict(&mut self) {
+        if let HashValue::Listpack(_) = self.value {
+            let mut dict = Dict::new(THRESH_HOLD);
+            for (field, value) in self.entries() {
+                dict.dict_add(Arc::new(Sds::new(&field)), Sds::new(&value))
+                    .unwrap();
+            }
+            self.value = HashValue::Dict(dict);
+        }
+    }
+}
+
+#[test]
+fn test_hash() {
+    let mut hash = HashObject::new();
+    assert!(hash.set(b"name", b"redis"));
+    assert!(!hash.set(b"name", b"redis-rs"));
+    assert!(hash.set(b"count", b"1"));
+    assert_eq!(hash.get_encoding(), "listpack");
+    assert_eq!(hash.len(), 2);
+    assert_eq!(hash.get(b"name").unwrap(), b"redis-rs");
+    assert_eq!(hash.incr_by(b"count", 9).unwrap(), 10);
+    assert!(hash.incr_by(b"name", 1).is_err());
+    assert_eq!(hash.incr_by_float(b"count", 0.5).unwrap(), 10.5);
+    assert!(hash.remove(b"name"));
+    assert!(!hash.remove(b"name"));
+    assert!(!hash.contains(b"name"));
+    assert_eq!(hash.entries(), vec![(b"count".to_vec(), b"10.5".to_vec())]);
+
+    // 超过阈值后转换为dict
+    let long = "v".repeat(HASH_MAX_LISTPACK_VALUE + 1);
+    hash.set(b"long", long.as_bytes());
+    assert_eq!(hash.get_encoding(), "hashtable");
+    assert_eq!(hash.get(b"long").unwrap(), long.as_bytes());
+    assert_eq!(hash.get(b"count").unwrap(), b"10.5");
+
+    let mut hash = HashObject::new();
+    for i in 0..=HASH_MAX_LISTPACK_ENTRIES {
+        hash.set(i.to_string().as_bytes(), b"v");
+    }
+    assert_eq!(hash.get_encoding(), "hashtable");
+    assert_eq!(hash.len(), HASH_MAX_LISTPACK_ENTRIES + 1);
+}
@@ -0,0 +1,268 @@
+use serde::{Deserialize, Serialize};
+
+use crate::encoding::{listpack::Listpack, quicklist::Quicklist};
+
+// 与redis的list-max-listpack-size -2一致：单个listpack不超过8KB
+const LIST_MAX_LISTPACK_SIZE: usize = 8 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ListValue {
+    Listpack(Listpack),
+    Quicklist(Quicklist),
+}
+
+/*
+ * 列表对象：元素较少时使用单个listpack，超过大小限制后转换为quicklist，
+ * quicklist缩小到限制的一半以下并且只剩一个节点时再转换回listpack
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListObject {
+    value: ListValue,
+}
+
+impl Default for ListObject {
+    fn default() -> Self {
+        return Self {
+            value: ListValue::Listpack(Listpack::new()),
+        };
+    }
+}
+
+impl ListObject {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn get_encoding(&self) -> &str {
+        return match self.value {
+            ListValue::Listpack(_) => "listpack",
+            ListValue::Quicklist(_) => "quicklist",
+        };
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        return match &self.value {
+            ListValue::Listpack(lp) => lp.alloc_size(),
+            ListValue::Quicklist(ql) => ql.alloc_size(),
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        return match &self.value {
+            ListValue::Listpack(lp) => lp.len(),
+            ListValue::Quicklist(ql) => ql.len(),
+        };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    /*
+     * 负数下标从末尾开始计数
+     */
+    pub fn get(&self, index: i64) -> Option<Vec<u8>> {
+        let index = self.index(index)?;
+        return match &self.value {
+            ListValue::Listpack(lp) => lp.get(index),
+            ListValue::Quicklist(ql) => ql.get(index),
+        };
+    }
+
+    pub fn set(&mut self, index: i64, value: &[u8]) -> bool {
+        let index = match self.index(index) {
+            Some(index) => index,
+            None => return false,
+        };
+        let replaced = match &mut self.value {
+            ListValue::Listpack(lp) => lp.replace(index, value),
+            ListValue::Quicklist(ql) => ql.replace(index, value),
+        };
+        self.convert();
+        return replaced;
+    }
+
+    pub fn push(&mut self, value: &[u8], front: bool) {
+        match (&mut self.value, front) {
+            (ListValue::Listpack(lp), true) => lp.push_front(value),
+            (ListValue::Listpack(lp), false) => lp.push_back(value),
+            (ListValue::Quicklist(ql), true) => ql.push_front(value),
+            (ListValue::Quicklist(ql), false) => ql.push_back(value),
+        }
+        self.convert();
+    }
+
+    pub fn pop(&mut self, front: bool) -> Option<Vec<u8>> {
+        let len = self.len();
+        if len == 0 {
+            return None;
+        }
+        let index = if front { 0 } else { len - 1 };
+        return self.remove(index);
+    }
+
+    /*
+     * LINSERT：在第一个等于pivot的元素前（后）插入，返回插入后的长度，找不到pivot时返回None
+     */
+    pub fn insert(&mut self, pivot: &[u8], value: &[u8], before: bool) -> Option<usize> {
+        let position = self.iter().position(|v| v == pivot)?;
+        let index = if before { position } else { position + 1 };
+        match &mut self.value {
+            ListValue::Listpack(lp) => lp.insert(index, value),
+            ListValue::Quicklist(ql) => ql.insert(index, value),
+        }
+        self.convert();
+        return Some(self.len());
+    }
+
+    /*
+     * LREM：count大于0从头部开始删除，小于0从尾部开始删除，等于0删除全部，返回删除的个数
+     */
+    pub fn remove_value(&mut self, count: i64, value: &[u8]) -> usize {
+        let mut indexes: Vec<usize> = self
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v == value)
+            .map(|(i, _)| i)
+            .collect();
+        if count < 0 {
+            indexes.reverse();
+        }
+        if count != 0 {
+            indexes.truncate(count.unsigned_abs() as usize);
+        }
+        // 从后往前删除，前面元素的下标不受影响
+        indexes.sort_unstable();
+        for index in indexes.iter().rev() {
+            self.remove(*index);
+        }
+        return indexes.len();
+    }
+
+    /*
+     * LRANGE：闭区间，负数下标从末尾开始计数，超出范围的部分被截断
+     */
+    pub fn range(&self, start: i64, stop: i64) -> Vec<Vec<u8>> {
+        return match self.range_indexes(start, stop) {
+            Some((start, stop)) => self.iter().skip(start).take(stop - start + 1).collect(),
+            None => vec![],
+        };
+    }
+
+    /*
+     * LTRIM：只保留区间内的元素
+     */
+    pub fn trim(&mut self, start: i64, stop: i64) {
+        let kept = self.range(start, stop);
+        if kept.len() == self.len() {
+            return;
+        }
+        let mut list = Self::new();
+        for value in kept.iter() {
+            list.push(value, false);
+        }
+        *self = list;
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Vec<u8>> + '_> {
+        return match &self.value {
+            ListValue::Listpack(lp) => Box::new(lp.iter()),
+            ListValue::Quicklist(ql) => Box::new(ql.iter()),
+        };
+    }
+
+    fn remove(&mut self, index: usize) -> Option<Vec<u8>> {
+        let value = match &mut self.value {
+            ListValue::Listpack(lp) => lp.remove(index),
+            ListValue::Quicklist(ql) => ql.remove(index),
+        };
+        self.convert();
+        return value;
+    }
+
+    fn index(&self, index: i64) -> Option<usize> {
+        let len = self.len() as i64;
+        let index = if index < 0 { len + index } else { index };
+        if index < 0 || index >= len {
+            return None;
+        }
+        return Some(index as usize);
+    }
+
+    fn range_indexes(&self, start: i64, stop: i64) -> Option<(usize, usize)> {
+        let len = self.len() as i64;
+        let start = if start < 0 {
+            (len + start).max(0)
+        } else {
+            start
+        };
+        let stop = if stop < 0 { len + stop } else { stop }.min(len - 1);
+        if start > stop || start >= len {
+            return None;
+        }
+        return Some((start as usize, stop as usize));
+    }
+
+    /*
+     * 根据大小在listpack和quicklist之间转换编码
+     */
+    fn convert(&mut self) {
+        let value = std::mem::replace(&mut self.value, ListValue::Listpack(Listpack::new()));
+        self.value = match value {
+            ListValue::Listpack(lp) if lp.bytes() > LIST_MAX_LISTPACK_SIZE => {
+                ListValue::Quicklist(Quicklist::from_listpack(lp, LIST_MAX_LISTPACK_SIZE))
+            }
+            ListValue::Quicklist(ql)
+                if ql.node_count() <= 1 && ql.bytes() <= LIST_MAX_LISTPACK_SIZE / 2 =>
+            {
+                ListValue::Listpack(ql.into_listpack().unwrap())
+            }
+            value => value,
+        };
+    }
+}
+
+#[test]
+fn test_list() {
+    let mut list = ListObject::new();
+    for i in 0..5 {
+        list.push(i.to_string().as_bytes(), false);
+    }
+    list.push(b"head", true);
+    assert_eq!(list.len(), 6);
+    assert_eq!(list.get_encoding(), "listpack");
+    assert_eq!(list.get(0).unwrap(), b"head");
+    assert_eq!(list.get(-1).unwrap(), b"4");
+    assert!(list.get(6).is_none());
+    assert_eq!(list.range(1, 2), vec![b"0".to_vec(), b"1".to_vec()]);
+    assert_eq!(list.range(-2, 100), vec![b"3".to_vec(), b"4".to_vec()]);
+    assert!(list.range(4, 2).is_empty());
+
+    assert_eq!(list.insert(b"2", b"x", true), Some(7));
+    assert_eq!(list.insert(b"2", b"x", false), Some(8));
+    assert_eq!(list.insert(b"y", b"x", false), None);
+    assert_eq!(list.remove_value(-1, b"x"), 1);
+    assert_eq!(list.get(3).unwrap(), b"x");
+    assert_eq!(list.get(5).unwrap(), b"3");
+    assert_eq!(list.remove_value(0, b"x"), 1);
+    assert!(list.set(0, b"first"));
+    assert_eq!(list.pop(true).unwrap(), b"first");
+    assert_eq!(list.pop(false).unwrap(), b"4");
+    list.trim(1, -2);
+    assert_eq!(list.range(0, -1), vec![b"1".to_vec(), b"2".to_vec()]);
+    list.trim(5, 10);
+    assert!(list.is_empty());
+
+    // 超过大小限制后转换为quicklist，缩小后转换回listpack
+    let value = "v".repeat(100);
+    for _ in 0..200 {
+        list.push(value.as_bytes(), false);
+    }
+    assert_eq!(list.get_encoding(), "quicklist");
+    assert_eq!(list.len(), 200);
+    while list.len() > 10 {
+        list.pop(true);
+    }
+    assert_eq!(list.get_encoding(), "listpack");
+    assert_eq!(list.get(9).unwrap(), value.as_bytes());
+}
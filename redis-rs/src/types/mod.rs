@@ -1,3 +1,7 @@
-pub mod strings;
 pub mod dict;
+pub mod hash;
+pub mod list;
 pub mod object;
+pub mod set;
+pub mod strings;
+pub mod zset;
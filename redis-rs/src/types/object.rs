@@ -1,30 +1,57 @@
 use std::sync::Arc;
 
 use chrono::Local;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
-use crate::common::{error::Error, utils};
+use crate::common::{
+    error::{Error, ErrorKind, Result},
+    utils,
+};
 
-use super::strings::StringObject;
+use super::{
+    hash::HashObject, list::ListObject, set::SetObject, strings::StringObject, zset::ZSetObject,
+};
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+// 估算集合类型的内存时采样的元素个数，与redis的OBJ_COMPUTE_SIZE_DEF_SAMPLES一致
+pub const MEMORY_SAMPLES: usize = 5;
+// LFU计数器，与redis的LFU_INIT_VAL、lfu-log-factor和lfu-decay-time（分钟）一致
+const LFU_INIT_VAL: u8 = 5;
+const LFU_LOG_FACTOR: f64 = 10.0;
+const LFU_DECAY_TIME: i64 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ObjectValue {
     Null,
     Strings(Arc<StringObject>),
+    List(Arc<ListObject>),
+    Hash(Arc<HashObject>),
+    Set(Arc<SetObject>),
+    ZSet(Arc<ZSetObject>),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Object {
     value: ObjectValue,
     active_time: i64,
+    // 对数访问计数器以及上次衰减的时间（分钟）
+    lfu_counter: u8,
+    lfu_decr_time: i64,
+    // 已经计入db的used_memory的大小
+    #[serde(skip)]
+    pub accounted_memory: usize,
 }
 
 impl Object {
     // object method
-    pub fn new(value: ObjectValue) -> Result<Self, Error> {
+    pub fn new(value: ObjectValue) -> Result<Self> {
+        let now = Local::now().timestamp_millis();
         let obj = Self {
             value,
-            active_time: Local::now().timestamp_millis(),
+            active_time: now,
+            lfu_counter: LFU_INIT_VAL,
+            lfu_decr_time: now / 60_000,
+            accounted_memory: 0,
         };
         return Ok(obj);
     }
@@ -33,6 +60,10 @@ impl Object {
         return match &self.value {
             ObjectValue::Null => "Null",
             ObjectValue::Strings(_) => "String",
+            ObjectValue::List(_) => "List",
+            ObjectValue::Hash(_) => "Hash",
+            ObjectValue::Set(_) => "Set",
+            ObjectValue::ZSet(_) => "ZSet",
         };
     }
 
@@ -40,6 +71,10 @@ impl Object {
         return match &self.value {
             ObjectValue::Null => "Null",
             ObjectValue::Strings(s) => s.get_encoding(),
+            ObjectValue::List(l) => l.get_encoding(),
+            ObjectValue::Hash(h) => h.get_encoding(),
+            ObjectValue::Set(s) => s.get_encoding(),
+            ObjectValue::ZSet(z) => z.get_encoding(),
         };
     }
 
@@ -47,24 +82,173 @@ impl Object {
         return utils::elasped(self.active_time);
     }
 
-    fn refresh_active_time(&mut self) {
+    pub fn refresh_active_time(&mut self) {
         self.active_time = Local::now().timestamp_millis();
     }
 
+    /*
+     * 访问对象：刷新访问时间（LRU）并更新访问计数（LFU）
+     */
+    pub fn touch(&mut self) {
+        self.refresh_active_time();
+        self.lfu_counter = self.lfu_counter();
+        self.lfu_decr_time = self.active_time / 60_000;
+        self.lfu_log_incr();
+    }
+
+    /*
+     * 衰减后的访问计数：每LFU_DECAY_TIME分钟没有访问减1
+     */
+    pub fn lfu_counter(&self) -> u8 {
+        let elapsed = Local::now().timestamp_millis() / 60_000 - self.lfu_decr_time;
+        let periods = (elapsed / LFU_DECAY_TIME).clamp(0, u8::MAX as i64) as u8;
+        return self.lfu_counter.saturating_sub(periods);
+    }
+
+    /*
+     * 估算占用的内存
+     */
+    pub fn memory_usage(&self) -> usize {
+        let size = std::mem::size_of::<Self>();
+        return size
+            + match &self.value {
+                ObjectValue::Null => 0,
+                ObjectValue::Strings(s) => s.memory_usage(),
+                ObjectValue::List(l) => l.memory_usage(),
+                ObjectValue::Hash(h) => h.memory_usage(),
+                ObjectValue::Set(s) => s.memory_usage(),
+                ObjectValue::ZSet(z) => z.memory_usage(),
+            };
+    }
+
+    /*
+     * 计数越大增加的概率越小，与redis的LFULogIncr一致
+     */
+    fn lfu_log_incr(&mut self) {
+        if self.lfu_counter == u8::MAX {
+            return;
+        }
+        let base = self.lfu_counter.saturating_sub(LFU_INIT_VAL) as f64;
+        let p = 1.0 / (base * LFU_LOG_FACTOR + 1.0);
+        if rand::thread_rng().gen::<f64>() < p {
+            self.lfu_counter += 1;
+        }
+    }
+
     // string method
+    #[allow(dead_code)]
     pub fn get(&mut self) -> Option<Box<[u8]>> {
         self.refresh_active_time();
         return match &self.value {
             ObjectValue::Strings(s) => Some(s.get()),
-            ObjectValue::Null => None,
+            _ => None,
+        };
+    }
+
+    pub fn as_string(&self) -> Result<&StringObject> {
+        return match &self.value {
+            ObjectValue::Strings(s) => Ok(s),
+            _ => Err(wrong_type()),
+        };
+    }
+
+    /*
+     * 共享对象在修改前会被复制（copy on write）
+     */
+    pub fn as_string_mut(&mut self) -> Result<&mut StringObject> {
+        return match &mut self.value {
+            ObjectValue::Strings(s) => Ok(Arc::make_mut(s)),
+            _ => Err(wrong_type()),
+        };
+    }
+
+    pub fn as_list(&self) -> Result<&ListObject> {
+        return match &self.value {
+            ObjectValue::List(l) => Ok(l),
+            _ => Err(wrong_type()),
+        };
+    }
+
+    pub fn as_list_mut(&mut self) -> Result<&mut ListObject> {
+        return match &mut self.value {
+            ObjectValue::List(l) => Ok(Arc::make_mut(l)),
+            _ => Err(wrong_type()),
+        };
+    }
+
+    pub fn as_hash(&self) -> Result<&HashObject> {
+        return match &self.value {
+            ObjectValue::Hash(h) => Ok(h),
+            _ => Err(wrong_type()),
+        };
+    }
+
+    pub fn as_hash_mut(&mut self) -> Result<&mut HashObject> {
+        return match &mut self.value {
+            ObjectValue::Hash(h) => Ok(Arc::make_mut(h)),
+            _ => Err(wrong_type()),
+        };
+    }
+
+    pub fn as_set(&self) -> Result<&SetObject> {
+        return match &self.value {
+            ObjectValue::Set(s) => Ok(s),
+            _ => Err(wrong_type()),
+        };
+    }
+
+    pub fn as_set_mut(&mut self) -> Result<&mut SetObject> {
+        return match &mut self.value {
+            ObjectValue::Set(s) => Ok(Arc::make_mut(s)),
+            _ => Err(wrong_type()),
         };
     }
+
+    pub fn as_zset(&self) -> Result<&ZSetObject> {
+        return match &self.value {
+            ObjectValue::ZSet(z) => Ok(z),
+            _ => Err(wrong_type()),
+        };
+    }
+
+    pub fn as_zset_mut(&mut self) -> Result<&mut ZSetObject> {
+        return match &mut self.value {
+            ObjectValue::ZSet(z) => Ok(Arc::make_mut(z)),
+            _ => Err(wrong_type()),
+        };
+    }
+}
+
+pub fn wrong_type() -> Error {
+    return Error::new(
+        ErrorKind::WrongType,
+        "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+    );
 }
 
 #[test]
 fn test_object() {
     // base
-    let mut obj = Object::new(ObjectValue::Null).unwrap();
+    let obj = Object::new(ObjectValue::Null).unwrap();
     assert_eq!(obj.get_type(), "Null");
     assert_eq!(obj.get_encoding(), "Null");
+    assert_eq!(obj.as_string().unwrap_err().kind(), ErrorKind::WrongType);
+
+    // 修改共享的字符串对象不影响其他引用
+    let shared = Arc::new(StringObject::new("1"));
+    let mut obj = Object::new(ObjectValue::Strings(shared.clone())).unwrap();
+    obj.as_string_mut().unwrap().append(b"0");
+    assert_eq!(obj.as_string().unwrap().get().as_ref(), b"10");
+    assert_eq!(shared.get().as_ref(), b"1");
+
+    // 访问计数增长越来越慢
+    assert_eq!(obj.lfu_counter(), LFU_INIT_VAL);
+    for _ in 0..1000 {
+        obj.touch();
+    }
+    let counter = obj.lfu_counter();
+    assert!(counter > LFU_INIT_VAL + 5 && counter < 100);
+    obj.lfu_decr_time -= 3;
+    assert_eq!(obj.lfu_counter(), counter - 3);
+    assert!(obj.memory_usage() > std::mem::size_of::<Object>());
 }
@@ -0,0 +1,229 @@
+use std::sync::Arc;
+
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::encoding::{intset::Intset, sds::Sds};
+
+use super::{dict::Dict, object::MEMORY_SAMPLES, strings};
+
+// 与redis的set-max-intset-entries一致
+const SET_MAX_INTSET_ENTRIES: usize = 512;
+const THRESH_HOLD: f32 = 0.9;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SetValue {
+    Intset(Intset),
+    // 只使用dict的key
+    Dict(Dict<()>),
+}
+
+/*
+ * 集合对象：元素全部是整数并且数量较少时使用intset，
+ * 加入非整数元素或者超过数量限制后转换为dict，不会再转换回来
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetObject {
+    value: SetValue,
+}
+
+impl Default for SetObject {
+    fn default() -> Self {
+        return Self {
+            value: SetValue::Intset(Intset::new()),
+        };
+    }
+}
+
+impl SetObject {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn get_encoding(&self) -> &str {
+        return match self.value {
+            SetValue::Intset(_) => "intset",
+            SetValue::Dict(_) => "hashtable",
+        };
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        return match &self.value {
+            SetValue::Intset(is) => is.alloc_size(),
+            SetValue::Dict(dict) => dict.dict_memory_usage(MEMORY_SAMPLES, |_| 0),
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        return match &self.value {
+            SetValue::Intset(is) => is.len(),
+            SetValue::Dict(dict) => dict.dict_size(),
+        };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        return match &self.value {
+            SetValue::Intset(is) => match strings::parse_integer(member) {
+                Some(value) => is.contains(value),
+                None => false,
+            },
+            SetValue::Dict(dict) => dict.dict_find(&Sds::new(member)).is_some(),
+        };
+    }
+
+    /*
+     * 返回是否为新增的元素
+     */
+    pub fn add(&mut self, member: &[u8]) -> bool {
+        if let SetValue::Intset(is) = &mut self.value {
+            match strings::parse_integer(member) {
+                Some(value) if is.contains(value) => return false,
+                Some(value) if is.len() < SET_MAX_INTSET_ENTRIES => return is.add(value),
+                _ => self.convert_to_dict(),
+            }
+        }
+        return match &mut self.value {
+            SetValue::Dict(dict) => dict
+                .dict_add(Arc::new(Sds::new(member)), ())
+                .unwrap_or(false),
+            SetValue::Intset(_) => unreachable!(),
+        };
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        return match &mut self.value {
+            SetValue::Intset(is) => match strings::parse_integer(member) {
+                Some(value) => is.remove(value),
+                None => false,
+            },
+            SetValue::Dict(dict) => dict
+                .dict_delete(Arc::new(Sds::new(member)))
+                .map(|entry| entry.is_some())
+                .unwrap_or(false),
+        };
+    }
+
+    /*
+     * intset编码时按整数从小到大排列
+     */
+    pub fn members(&self) -> Vec<Vec<u8>> {
+        return match &self.value {
+            SetValue::Intset(is) => is.iter().map(|v| v.to_string().into_bytes()).collect(),
+            SetValue::Dict(dict) => dict
+                .dict_iter()
+                .map(|(k, _)| k.as_bytes().to_vec())
+                .collect(),
+        };
+    }
+
+    pub fn random_member(&self) -> Option<Vec<u8>> {
+        if self.is_empty() {
+            return None;
+        }
+        let index = rand::thread_rng().gen_range(0..self.len());
+        return match &self.value {
+            SetValue::Intset(is) => is.get(index).map(|v| v.to_string().into_bytes()),
+            SetValue::Dict(dict) => dict
+                .dict_iter()
+                .nth(index)
+                .map(|(k, _)| k.as_bytes().to_vec()),
+        };
+    }
+
+    /*
+     * SRANDMEMBER：count为正数时返回不重复的元素，为负数时元素可能重复
+     */
+    pub fn random_members(&self, count: i64) -> Vec<Vec<u8>> {
+        if count < 0 {
+            return (0..count.unsigned_abs())
+                .filter_map(|_| self.random_member())
+                .collect();
+        }
+        let mut members = self.members();
+        if count as usize >= members.len() {
+            return members;
+        }
+        members.shuffle(&mut rand::thread_rng());
+        members.truncate(count as usize);
+        return members;
+    }
+
+    /*
+     * SPOP：随机删除并返回一个元素
+     */
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let member = self.random_member()?;
+        self.remove(&member);
+        return Some(member);
+    }
+
+    /*
+     * intset编码时一次返回全部元素，游标为0
+     */
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<Vec<u8>>) {
+        return match &self.value {
+            SetValue::Intset(_) => (0, self.members()),
+            SetValue::Dict(dict) => {
+                let (next, entries) = dict.dict_scan(cursor, count);
+                let members = entries
+                    .into_iter()
+                    .map(|(k, _)| k.as_bytes().to_vec())
+                    .collect();
+                (next, members)
+            }
+        };
+    }
+
+    fn convert_to_dict(&mut self) {
+        if let SetValue::Intset(_) = self.value {
+            let mut dict = Dict::new(THRESH_HOLD);
+            for member in self.members() {
+                dict.dict_add(Arc::new(Sds::new(&member)), ()).unwrap();
+            }
+            self.value = SetValue::Dict(dict);
+        }
+    }
+}
+
+#[test]
+fn test_set() {
+    let mut set = SetObject::new();
+    assert!(set.add(b"3"));
+    assert!(set.add(b"-1"));
+    assert!(!set.add(b"3"));
+    assert_eq!(set.get_encoding(), "intset");
+    assert_eq!(set.members(), vec![b"-1".to_vec(), b"3".to_vec()]);
+    assert!(set.contains(b"3"));
+    assert!(!set.contains(b"a"));
+    assert!(!set.remove(b"a"));
+    assert!(set.remove(b"-1"));
+    assert_eq!(set.len(), 1);
+
+    // 非规范的整数按字符串处理
+    assert!(set.add(b"03"));
+    assert_eq!(set.get_encoding(), "hashtable");
+    assert!(set.contains(b"3"));
+    assert!(set.contains(b"03"));
+    assert_eq!(set.random_members(5).len(), 2);
+    assert_eq!(set.random_members(1).len(), 1);
+    assert_eq!(set.random_members(-5).len(), 5);
+    let member = set.pop().unwrap();
+    assert!(!set.contains(&member));
+    assert_eq!(set.len(), 1);
+
+    // 超过数量限制后转换为dict
+    let mut set = SetObject::new();
+    for i in 0..SET_MAX_INTSET_ENTRIES {
+        set.add(i.to_string().as_bytes());
+    }
+    assert_eq!(set.get_encoding(), "intset");
+    assert!(!set.add(b"0"));
+    assert_eq!(set.get_encoding(), "intset");
+    set.add(b"-1");
+    assert_eq!(set.get_encoding(), "hashtable");
+    assert_eq!(set.len(), SET_MAX_INTSET_ENTRIES + 1);
+}
@@ -1,5 +1,12 @@
-use crate::{common::utils, encoding::sds::Sds};
+use crate::{
+    common::error::{Error, ErrorKind, Result},
+    encoding::sds::Sds,
+};
 use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+// 与redis的OBJ_ENCODING_EMBSTR_SIZE_LIMIT一致
+const EMBSTR_SIZE_LIMIT: usize = 44;
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum StringValue {
@@ -20,11 +27,11 @@ impl Default for StringObject {
     }
 }
 
-impl ToString for StringObject {
-    fn to_string(&self) -> String {
+impl Display for StringObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self.value {
-            StringValue::Integer(i) => i.to_string(),
-            StringValue::Raw(s) => s.to_string(),
+            StringValue::Integer(i) => write!(f, "{}", i),
+            StringValue::Raw(s) => write!(f, "{}", s),
         }
     }
 }
@@ -34,18 +41,42 @@ impl StringObject {
     where
         T: AsRef<str> + AsRef<[u8]>,
     {
+        return Self::from_bytes(AsRef::<[u8]>::as_ref(&value));
+    }
+
+    /*
+     * 能无损表示为整数的值（"10"，而不是"010"或"+10"）使用整数编码
+     */
+    pub fn from_bytes(value: &[u8]) -> Self {
         return StringObject {
-            value: match utils::parse_str::<T, i64>(&value) {
+            value: match parse_integer(value) {
                 Some(i) => StringValue::Integer(i),
-                _ => StringValue::Raw(Sds::new(value.as_ref())),
+                _ => StringValue::Raw(Sds::new(value)),
             },
         };
     }
 
+    pub fn from_integer(value: i64) -> Self {
+        return StringObject {
+            value: StringValue::Integer(value),
+        };
+    }
+
+    /*
+     * 与redis的编码名称一致：短字符串为embstr
+     */
     pub fn get_encoding(&self) -> &str {
-        return match self.value {
-            StringValue::Integer(_) => "Integer",
-            StringValue::Raw(_) => "Raw",
+        return match &self.value {
+            StringValue::Integer(_) => "int",
+            StringValue::Raw(s) if s.len() <= EMBSTR_SIZE_LIMIT => "embstr",
+            StringValue::Raw(_) => "raw",
+        };
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        return match &self.value {
+            StringValue::Integer(_) => std::mem::size_of::<Self>(),
+            StringValue::Raw(s) => std::mem::size_of::<Self>() + s.alloc_size(),
         };
     }
 
@@ -55,6 +86,126 @@ impl StringObject {
             StringValue::Raw(s) => s.as_bytes().into(),
         };
     }
+
+    pub fn len(&self) -> usize {
+        return match &self.value {
+            StringValue::Integer(i) => i.to_string().len(),
+            StringValue::Raw(s) => s.len(),
+        };
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    /*
+     * 追加到末尾，返回追加后的长度
+     */
+    pub fn append(&mut self, value: &[u8]) -> usize {
+        let sds = self.raw_mut();
+        sds.push(value);
+        return sds.len();
+    }
+
+    /*
+     * GETRANGE语义：负数下标从末尾开始，超出范围的部分被截断
+     */
+    pub fn get_range(&self, start: i64, end: i64) -> Vec<u8> {
+        let value = self.get();
+        let len = value.len() as i64;
+        let start = if start < 0 {
+            (len + start).max(0)
+        } else {
+            start
+        };
+        let end = if end < 0 { (len + end).max(0) } else { end }.min(len - 1);
+        if len == 0 || start > end {
+            return vec![];
+        }
+        return value[start as usize..=end as usize].to_vec();
+    }
+
+    /*
+     * 从offset开始覆盖写入，返回写入后的长度
+     */
+    pub fn set_range(&mut self, offset: usize, value: &[u8]) -> usize {
+        let sds = self.raw_mut();
+        sds.set_range(offset, value);
+        return sds.len();
+    }
+
+    pub fn incr_by(&mut self, delta: i64) -> Result<i64> {
+        let current = match &self.value {
+            StringValue::Integer(i) => *i,
+            StringValue::Raw(s) => parse_integer(s.as_bytes()).ok_or_else(not_integer)?,
+        };
+        let value = current.checked_add(delta).ok_or_else(|| {
+            return Error::new(
+                ErrorKind::Invalid,
+                "increment or decrement would overflow".to_string(),
+            );
+        })?;
+        self.value = StringValue::Integer(value);
+        return Ok(value);
+    }
+
+    pub fn incr_by_float(&mut self, delta: f64) -> Result<f64> {
+        let current = match &self.value {
+            StringValue::Integer(i) => *i as f64,
+            StringValue::Raw(s) => parse_float(s.as_bytes()).ok_or_else(|| {
+                return Error::new(ErrorKind::Invalid, "value is not a valid float".to_string());
+            })?,
+        };
+        let value = current + delta;
+        if !value.is_finite() {
+            return Err(Error::new(
+                ErrorKind::Invalid,
+                "increment would produce NaN or Infinity".to_string(),
+            ));
+        }
+        *self = Self::from_bytes(value.to_string().as_bytes());
+        return Ok(value);
+    }
+
+    /*
+     * 修改内容前转换为Raw编码
+     */
+    fn raw_mut(&mut self) -> &mut Sds {
+        if let StringValue::Integer(i) = self.value {
+            self.value = StringValue::Raw(Sds::new(i.to_string().as_bytes()));
+        }
+        return match &mut self.value {
+            StringValue::Raw(s) => s,
+            StringValue::Integer(_) => unreachable!(),
+        };
+    }
+}
+
+pub fn parse_integer(value: &[u8]) -> Option<i64> {
+    let i = std::str::from_utf8(value).ok()?.parse::<i64>().ok()?;
+    if i.to_string().as_bytes() != value {
+        return None;
+    }
+    return Some(i);
+}
+
+/*
+ * 与redis一致，不接受nan和inf
+ */
+pub fn parse_float(value: &[u8]) -> Option<f64> {
+    return std::str::from_utf8(value)
+        .ok()?
+        .parse::<f64>()
+        .ok()
+        .filter(|f| f.is_finite());
+}
+
+fn not_integer() -> Error {
+    return Error::new(
+        ErrorKind::Invalid,
+        "value is not an integer or out of range".to_string(),
+    );
 }
 
 #[test]
@@ -71,3 +222,36 @@ fn test_strings() {
     assert!(matches!(integer_obj.value, StringValue::Integer(_)));
     assert_eq!(integer_obj.get().as_ref(), integer_str.as_bytes());
 }
+
+#[test]
+fn test_strings_modify() {
+    // 非规范的整数字符串保持Raw编码
+    assert!(matches!(
+        StringObject::new("010").value,
+        StringValue::Raw(_)
+    ));
+    assert!(matches!(StringObject::new("+1").value, StringValue::Raw(_)));
+
+    let mut obj = StringObject::new("10");
+    assert_eq!(obj.incr_by(5).unwrap(), 15);
+    assert!(matches!(obj.value, StringValue::Integer(15)));
+    assert!(obj.incr_by(i64::MAX).is_err());
+    assert_eq!(obj.append(b"x"), 3);
+    assert_eq!(obj.get().as_ref(), b"15x");
+    assert!(obj.incr_by(1).is_err());
+
+    let mut obj = StringObject::new("Hello World");
+    assert_eq!(obj.get_range(0, 4), b"Hello");
+    assert_eq!(obj.get_range(-5, -1), b"World");
+    assert_eq!(obj.get_range(5, 3), b"");
+    assert_eq!(obj.get_range(0, 100), b"Hello World");
+    assert_eq!(obj.set_range(6, b"Redis"), 11);
+    assert_eq!(obj.get().as_ref(), b"Hello Redis");
+
+    let mut obj = StringObject::new("10.5");
+    assert_eq!(obj.incr_by_float(0.1).unwrap(), 10.6);
+    assert_eq!(obj.get().as_ref(), b"10.6");
+    assert_eq!(obj.incr_by_float(-5.6).unwrap(), 5.0);
+    assert!(matches!(obj.value, StringValue::Integer(5)));
+    assert!(StringObject::new("abc").incr_by_float(1.0).is_err());
+}
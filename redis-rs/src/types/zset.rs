@@ -0,0 +1,365 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::error::{Error, ErrorKind, Result},
+    encoding::{
+        sds::Sds,
+        skiplist::{Skiplist, SkiplistRange},
+    },
+};
+
+use super::{dict::Dict, object::MEMORY_SAMPLES};
+
+const THRESH_HOLD: f32 = 0.9;
+
+// (member, score)
+pub type ZSetEntry = (Vec<u8>, f64);
+
+/*
+ * 分数区间，"("开头表示开区间，支持-inf和+inf
+ */
+pub struct ScoreRange {
+    min: f64,
+    max: f64,
+    min_exclusive: bool,
+    max_exclusive: bool,
+}
+
+impl ScoreRange {
+    pub fn parse(min: &[u8], max: &[u8]) -> Result<Self> {
+        let (min, min_exclusive) = Self::parse_bound(min)?;
+        let (max, max_exclusive) = Self::parse_bound(max)?;
+        return Ok(Self {
+            min,
+            max,
+            min_exclusive,
+            max_exclusive,
+        });
+    }
+
+    fn parse_bound(bound: &[u8]) -> Result<(f64, bool)> {
+        let (value, exclusive) = match bound.first() {
+            Some(b'(') => (&bound[1..], true),
+            _ => (bound, false),
+        };
+        let value = parse_score(value).ok_or_else(|| {
+            return Error::new(ErrorKind::Invalid, "min or max is not a float".to_string());
+        })?;
+        return Ok((value, exclusive));
+    }
+}
+
+impl SkiplistRange for ScoreRange {
+    fn below_min(&self, score: f64, _: &[u8]) -> bool {
+        return if self.min_exclusive {
+            score <= self.min
+        } else {
+            score < self.min
+        };
+    }
+
+    fn above_max(&self, score: f64, _: &[u8]) -> bool {
+        return if self.max_exclusive {
+            score >= self.max
+        } else {
+            score > self.max
+        };
+    }
+}
+
+enum LexBound {
+    NegativeInfinity,
+    PositiveInfinity,
+    Inclusive(Vec<u8>),
+    Exclusive(Vec<u8>),
+}
+
+/*
+ * 字典序区间："-"和"+"表示无穷，"["和"("分别表示闭区间和开区间，
+ * 只在所有元素分数相同时有意义
+ */
+pub struct LexRange {
+    min: LexBound,
+    max: LexBound,
+}
+
+impl LexRange {
+    pub fn parse(min: &[u8], max: &[u8]) -> Result<Self> {
+        return Ok(Self {
+            min: Self::parse_bound(min)?,
+            max: Self::parse_bound(max)?,
+        });
+    }
+
+    fn parse_bound(bound: &[u8]) -> Result<LexBound> {
+        return match bound {
+            b"-" => Ok(LexBound::NegativeInfinity),
+            b"+" => Ok(LexBound::PositiveInfinity),
+            [b'[', value @ ..] => Ok(LexBound::Inclusive(value.to_vec())),
+            [b'(', value @ ..] => Ok(LexBound::Exclusive(value.to_vec())),
+            _ => Err(Error::new(
+                ErrorKind::Invalid,
+                "min or max not valid string range item".to_string(),
+            )),
+        };
+    }
+}
+
+impl SkiplistRange for LexRange {
+    fn below_min(&self, _: f64, member: &[u8]) -> bool {
+        return match &self.min {
+            LexBound::NegativeInfinity => false,
+            LexBound::PositiveInfinity => true,
+            LexBound::Inclusive(value) => member < value.as_slice(),
+            LexBound::Exclusive(value) => member <= value.as_slice(),
+        };
+    }
+
+    fn above_max(&self, _: f64, member: &[u8]) -> bool {
+        return match &self.max {
+            LexBound::NegativeInfinity => true,
+            LexBound::PositiveInfinity => false,
+            LexBound::Inclusive(value) => member > value.as_slice(),
+            LexBound::Exclusive(value) => member >= value.as_slice(),
+        };
+    }
+}
+
+/*
+ * 有序集合对象：跳表按分数排序，dict保存member到分数的映射
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZSetObject {
+    dict: Dict<f64>,
+    zsl: Skiplist,
+}
+
+impl Default for ZSetObject {
+    fn default() -> Self {
+        return Self {
+            dict: Dict::new(THRESH_HOLD),
+            zsl: Skiplist::new(),
+        };
+    }
+}
+
+impl ZSetObject {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn get_encoding(&self) -> &str {
+        return "skiplist";
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        return self.dict.dict_memory_usage(MEMORY_SAMPLES, |_| 0)
+            + self.zsl.alloc_size(MEMORY_SAMPLES);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.zsl.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.zsl.is_empty();
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        return self.dict.dict_find(&Sds::new(member)).copied();
+    }
+
+    /*
+     * 新增或更新分数，返回是否为新增的元素
+     */
+    pub fn insert(&mut self, member: &[u8], score: f64) -> bool {
+        return match self.score(member) {
+            Some(current) => {
+                if current != score {
+                    self.zsl.delete(current, member);
+                    self.zsl.insert(score, member);
+                    self.dict
+                        .dict_replace(Arc::new(Sds::new(member)), score)
+                        .unwrap();
+                }
+                false
+            }
+            None => {
+                self.zsl.insert(score, member);
+                self.dict
+                    .dict_add(Arc::new(Sds::new(member)), score)
+                    .unwrap();
+                true
+            }
+        };
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        return match self.dict.dict_delete(Arc::new(Sds::new(member))) {
+            Ok(Some((_, score))) => self.zsl.delete(score, member),
+            _ => false,
+        };
+    }
+
+    /*
+     * 排名从0开始，reverse时按分数从大到小计算
+     */
+    pub fn rank(&self, member: &[u8], reverse: bool) -> Option<usize> {
+        let score = self.score(member)?;
+        let rank = self.zsl.rank(score, member)?;
+        return Some(if reverse { self.len() - 1 - rank } else { rank });
+    }
+
+    /*
+     * 按排名的闭区间，负数下标从末尾开始计数
+     */
+    pub fn range_by_rank(&self, start: i64, stop: i64, reverse: bool) -> Vec<ZSetEntry> {
+        let len = self.len() as i64;
+        let start = if start < 0 {
+            (len + start).max(0)
+        } else {
+            start
+        };
+        let stop = if stop < 0 { len + stop } else { stop }.min(len - 1);
+        if start > stop || start >= len {
+            return vec![];
+        }
+        let rank = if reverse { len - 1 - start } else { start };
+        return self
+            .zsl
+            .iter(rank as usize, reverse)
+            .take((stop - start + 1) as usize)
+            .map(|(m, s)| (m.to_vec(), s))
+            .collect();
+    }
+
+    /*
+     * 按分数或字典序的区间，跳过offset个元素后最多返回limit个
+     */
+    pub fn range(
+        &self,
+        range: &dyn SkiplistRange,
+        reverse: bool,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Vec<ZSetEntry> {
+        let rank = match reverse {
+            false => self.zsl.first_in_range(range),
+            true => self.zsl.last_in_range(range),
+        };
+        let rank = match rank {
+            Some(rank) => rank,
+            None => return vec![],
+        };
+        return self
+            .zsl
+            .iter(rank, reverse)
+            .take_while(|(m, s)| match reverse {
+                false => !range.above_max(*s, m),
+                true => !range.below_min(*s, m),
+            })
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|(m, s)| (m.to_vec(), s))
+            .collect();
+    }
+
+    pub fn count(&self, range: &dyn SkiplistRange) -> usize {
+        return match (
+            self.zsl.first_in_range(range),
+            self.zsl.last_in_range(range),
+        ) {
+            (Some(first), Some(last)) => last - first + 1,
+            _ => 0,
+        };
+    }
+
+    /*
+     * 弹出分数最小（最大）的元素
+     */
+    pub fn pop(&mut self, max: bool) -> Option<ZSetEntry> {
+        let rank = if max { self.len().checked_sub(1)? } else { 0 };
+        let (member, score) = self.zsl.iter(rank, false).next()?;
+        let entry = (member.to_vec(), score);
+        self.remove(&entry.0);
+        return Some(entry);
+    }
+
+    pub fn entries(&self) -> Vec<ZSetEntry> {
+        return self
+            .zsl
+            .iter(0, false)
+            .map(|(m, s)| (m.to_vec(), s))
+            .collect();
+    }
+}
+
+/*
+ * 分数可以是inf和-inf，但不能是nan
+ */
+pub fn parse_score(value: &[u8]) -> Option<f64> {
+    return std::str::from_utf8(value)
+        .ok()?
+        .parse::<f64>()
+        .ok()
+        .filter(|f| !f.is_nan());
+}
+
+#[test]
+fn test_zset() {
+    let mut zset = ZSetObject::new();
+    assert!(zset.insert(b"a", 1.0));
+    assert!(zset.insert(b"b", 2.0));
+    assert!(zset.insert(b"c", 3.0));
+    assert!(!zset.insert(b"a", 4.0));
+    assert_eq!(zset.len(), 3);
+    assert_eq!(zset.score(b"a"), Some(4.0));
+    assert_eq!(zset.rank(b"a", false), Some(2));
+    assert_eq!(zset.rank(b"a", true), Some(0));
+    assert_eq!(zset.rank(b"x", false), None);
+
+    let members = |entries: Vec<ZSetEntry>| {
+        return entries.into_iter().map(|(m, _)| m).collect::<Vec<_>>();
+    };
+    assert_eq!(
+        members(zset.range_by_rank(0, -1, false)),
+        vec![b"b".to_vec(), b"c".to_vec(), b"a".to_vec()]
+    );
+    assert_eq!(
+        members(zset.range_by_rank(0, 1, true)),
+        vec![b"a".to_vec(), b"c".to_vec()]
+    );
+    assert!(zset.range_by_rank(5, 10, false).is_empty());
+
+    let range = ScoreRange::parse(b"(2", b"+inf").unwrap();
+    assert_eq!(zset.count(&range), 2);
+    assert_eq!(
+        members(zset.range(&range, true, 1, None)),
+        vec![b"c".to_vec()]
+    );
+    assert!(ScoreRange::parse(b"x", b"1").is_err());
+    assert!(ScoreRange::parse(b"nan", b"1").is_err());
+
+    // 分数相同时按字典序
+    let mut lex = ZSetObject::new();
+    for member in ["a", "b", "c", "d"] {
+        lex.insert(member.as_bytes(), 0.0);
+    }
+    let range = LexRange::parse(b"[b", b"(d").unwrap();
+    assert_eq!(
+        members(lex.range(&range, false, 0, Some(5))),
+        vec![b"b".to_vec(), b"c".to_vec()]
+    );
+    let range = LexRange::parse(b"-", b"+").unwrap();
+    assert_eq!(lex.count(&range), 4);
+    assert!(LexRange::parse(b"b", b"+").is_err());
+
+    assert_eq!(zset.pop(false), Some((b"b".to_vec(), 2.0)));
+    assert_eq!(zset.pop(true), Some((b"a".to_vec(), 4.0)));
+    assert!(zset.remove(b"c"));
+    assert!(!zset.remove(b"c"));
+    assert!(zset.is_empty());
+    assert_eq!(zset.pop(true), None);
+}